@@ -0,0 +1,433 @@
+//! Import of native PDF annotations
+//!
+//! Converts annotations read by [`crate::pdf::annotation_extractor`] into
+//! stored [`Annotation`]s (with `PdfTextQuote` and `PdfRegion` selectors) or
+//! into PDF [`Highlight`](crate::db::Highlight)s.
+//!
+//! Imports are idempotent: every imported record gets an ID derived from a
+//! hash of its page, quads and text, so extracting the same PDF again (for
+//! example after it was edited in another reader) only adds the annotations
+//! that weren't imported before.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+use super::store::AnnotationRepository;
+use super::types::{
+    Annotation, AnnotationBody, AnnotationTarget, AnnotationType, BodyType, PdfRect,
+};
+use crate::db::{CreateHighlight, HighlightRepository, PdfRegion};
+use crate::error::{AppError, Result};
+use crate::pdf::annotation_extractor::{
+    ExtractedAnnotation, ExtractedAnnotationType, NormalizedRect,
+};
+
+/// Where imported annotations are stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportTarget {
+    /// Multi-selector annotations (`annotations` table)
+    #[default]
+    Annotations,
+    /// Legacy highlights (`highlights` table)
+    Highlights,
+}
+
+/// Result of an import
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    /// IDs of the records created by this import
+    pub imported: Vec<String>,
+    /// Annotations that weren't imported
+    pub skipped: Vec<SkippedAnnotation>,
+}
+
+/// An extracted annotation that wasn't imported
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedAnnotation {
+    /// Page number (1-indexed)
+    pub page: u32,
+    /// Annotation type
+    #[serde(rename = "type")]
+    pub annotation_type: ExtractedAnnotationType,
+    /// Text under the annotation (if any)
+    pub text: Option<String>,
+    /// Why the annotation was skipped
+    pub reason: String,
+}
+
+impl SkippedAnnotation {
+    fn new(annotation: &ExtractedAnnotation, reason: &str) -> Self {
+        Self {
+            page: annotation.page,
+            annotation_type: annotation.annotation_type,
+            text: annotation.text.clone(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// Import extracted annotations for a book
+///
+/// Annotations without a stored equivalent (strike-outs, ink) and
+/// annotations imported before are listed in [`ImportSummary::skipped`].
+pub async fn import_annotations(
+    pool: &SqlitePool,
+    book_id: &str,
+    user_id: Option<&str>,
+    target: ImportTarget,
+    annotations: &[ExtractedAnnotation],
+) -> Result<ImportSummary> {
+    let mut summary = ImportSummary::default();
+
+    for extracted in annotations {
+        if import_type(extracted.annotation_type).is_none() {
+            summary
+                .skipped
+                .push(SkippedAnnotation::new(extracted, "no equivalent type"));
+            continue;
+        }
+
+        let id = import_id(book_id, user_id, extracted);
+        let exists = match target {
+            ImportTarget::Annotations => AnnotationRepository::new(pool)
+                .get(&id)
+                .await
+                .map_err(store_error)?
+                .is_some(),
+            ImportTarget::Highlights => HighlightRepository::new(pool).get(&id).await?.is_some(),
+        };
+        if exists {
+            summary
+                .skipped
+                .push(SkippedAnnotation::new(extracted, "already imported"));
+            continue;
+        }
+
+        match target {
+            ImportTarget::Annotations => {
+                if let Some(annotation) = to_annotation(&id, book_id, user_id, extracted) {
+                    AnnotationRepository::new(pool)
+                        .save(&annotation)
+                        .await
+                        .map_err(store_error)?;
+                }
+            }
+            ImportTarget::Highlights => {
+                if let Some(highlight) = to_highlight(extracted) {
+                    HighlightRepository::new(pool)
+                        .create_with_id(&id, book_id, user_id, &highlight)
+                        .await?;
+                }
+            }
+        }
+        summary.imported.push(id);
+    }
+
+    Ok(summary)
+}
+
+/// Stored type for an extracted annotation type
+fn import_type(annotation_type: ExtractedAnnotationType) -> Option<AnnotationType> {
+    match annotation_type {
+        ExtractedAnnotationType::Highlight => Some(AnnotationType::Highlight),
+        ExtractedAnnotationType::Underline | ExtractedAnnotationType::Squiggly => {
+            Some(AnnotationType::Underline)
+        }
+        ExtractedAnnotationType::Text | ExtractedAnnotationType::FreeText => {
+            Some(AnnotationType::Note)
+        }
+        ExtractedAnnotationType::StrikeOut
+        | ExtractedAnnotationType::Ink
+        | ExtractedAnnotationType::Unknown => None,
+    }
+}
+
+/// Deterministic ID for an imported annotation
+///
+/// The ID is a UUID built from a SHA-256 hash of the book, user, page,
+/// quads (or the rect, for annotations without quads) and text.
+/// Coordinates are rounded so that re-saving a PDF doesn't change the key.
+pub fn import_id(book_id: &str, user_id: Option<&str>, annotation: &ExtractedAnnotation) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(book_id.as_bytes());
+    hasher.update([0]);
+    hasher.update(user_id.unwrap_or_default().as_bytes());
+    hasher.update([0]);
+    hasher.update(annotation.page.to_be_bytes());
+
+    let quads = if annotation.quads.is_empty() {
+        std::slice::from_ref(&annotation.rect)
+    } else {
+        annotation.quads.as_slice()
+    };
+    for quad in quads {
+        let key = format!(
+            "{:.4},{:.4},{:.4},{:.4};",
+            quad.x, quad.y, quad.width, quad.height
+        );
+        hasher.update(key.as_bytes());
+    }
+
+    let text = annotation
+        .text
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    hasher.update(text.as_bytes());
+
+    let digest = hasher.finalize();
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    uuid::Builder::from_custom_bytes(bytes)
+        .into_uuid()
+        .to_string()
+}
+
+/// Convert an extracted annotation into a stored annotation
+///
+/// The target has a `PdfTextQuote` selector when the text under the
+/// annotation is known, and a `PdfRegion` selector for its rect.
+pub fn to_annotation(
+    id: &str,
+    book_id: &str,
+    user_id: Option<&str>,
+    extracted: &ExtractedAnnotation,
+) -> Option<Annotation> {
+    let annotation_type = import_type(extracted.annotation_type)?;
+    let page = extracted.page as usize;
+
+    let mut target = AnnotationTarget::with_selectors(book_id, Vec::new());
+    if let Some(text) = extracted.text.as_deref().filter(|t| !t.trim().is_empty()) {
+        target.add_pdf_text_quote(page, text, None, None);
+    }
+    target.add_pdf_region(page, pdf_rect(&extracted.rect));
+
+    let mut annotation = match annotation_type {
+        AnnotationType::Note => Annotation::new_note(
+            book_id,
+            target,
+            extracted.comment.as_deref().unwrap_or_default(),
+        ),
+        _ => {
+            let mut annotation = Annotation::new_highlight(book_id, target);
+            annotation.annotation_type = annotation_type;
+            annotation.body = extracted.comment.as_ref().map(|comment| AnnotationBody {
+                body_type: BodyType::TextualBody,
+                value: Some(comment.clone()),
+                format: Some("text/plain".to_string()),
+            });
+            annotation
+        }
+    };
+
+    annotation.id = id.to_string();
+    if let Some(user_id) = user_id {
+        annotation = annotation.with_user(user_id);
+    }
+    if let Some(color) = extracted.color.as_deref() {
+        annotation = annotation.with_color(&color.to_ascii_lowercase());
+    }
+    if let Some(created) = parse_date(extracted.created_date.as_deref()) {
+        annotation.created_at = created;
+    }
+    if let Some(modified) = parse_date(extracted.modified_date.as_deref()) {
+        annotation.updated_at = modified.max(annotation.created_at);
+    }
+
+    Some(annotation)
+}
+
+/// Convert an extracted annotation into a PDF highlight
+///
+/// Quads are stored as the highlight's rects, and the annotation rect as
+/// its region.
+pub fn to_highlight(extracted: &ExtractedAnnotation) -> Option<CreateHighlight> {
+    let annotation_type = match import_type(extracted.annotation_type)? {
+        AnnotationType::Highlight => "highlight",
+        AnnotationType::Underline => "underline",
+        _ => "note",
+    };
+    let rect = &extracted.rect;
+
+    Some(CreateHighlight {
+        document_format: Some("pdf".to_string()),
+        annotation_type: Some(annotation_type.to_string()),
+        cfi: None,
+        page: Some(extracted.page as i32),
+        text: extracted.text.clone().unwrap_or_default(),
+        chapter: Some(format!("Page {}", extracted.page)),
+        page_percent: None,
+        color: extracted.color.as_deref().map(str::to_ascii_lowercase),
+        annotation: extracted.comment.clone(),
+        text_prefix: None,
+        text_suffix: None,
+        region: Some(PdfRegion {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+        }),
+        rects: (!extracted.quads.is_empty()).then(|| {
+            extracted
+                .quads
+                .iter()
+                .map(|quad| crate::db::PdfRect {
+                    x: quad.x,
+                    y: quad.y,
+                    width: quad.width,
+                    height: quad.height,
+                })
+                .collect()
+        }),
+    })
+}
+
+fn pdf_rect(rect: &NormalizedRect) -> PdfRect {
+    PdfRect {
+        x: rect.x,
+        y: rect.y,
+        width: rect.width,
+        height: rect.height,
+    }
+}
+
+fn store_error(e: anyhow::Error) -> AppError {
+    AppError::Internal(format!("Failed to import annotation: {}", e))
+}
+
+fn parse_date(date: Option<&str>) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(date?)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> NormalizedRect {
+        NormalizedRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn extracted(annotation_type: ExtractedAnnotationType) -> ExtractedAnnotation {
+        ExtractedAnnotation {
+            annotation_type,
+            page: 3,
+            text: Some("The quick brown fox".to_string()),
+            comment: None,
+            color: Some("#FFFF00".to_string()),
+            rect: rect(0.1, 0.2, 0.5, 0.05),
+            quads: vec![rect(0.1, 0.2, 0.5, 0.02), rect(0.1, 0.23, 0.3, 0.02)],
+            created_date: Some("2024-01-15T10:30:00Z".to_string()),
+            modified_date: None,
+            author: None,
+            ink_list: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_import_id_is_stable() {
+        let a = extracted(ExtractedAnnotationType::Highlight);
+        let mut b = a.clone();
+        b.quads[0].x += 0.000001;
+        b.text = Some("The  quick\nbrown fox".to_string());
+        b.comment = Some("added later".to_string());
+
+        let id = import_id("book-1", Some("user-1"), &a);
+        assert_eq!(id, import_id("book-1", Some("user-1"), &b));
+        assert!(Uuid::parse_str(&id).is_ok());
+
+        assert_ne!(id, import_id("book-1", Some("user-2"), &a));
+        b.page = 4;
+        assert_ne!(id, import_id("book-1", Some("user-1"), &b));
+    }
+
+    #[test]
+    fn test_import_id_uses_rect_without_quads() {
+        let mut a = extracted(ExtractedAnnotationType::Text);
+        a.quads.clear();
+        a.text = None;
+        let mut b = a.clone();
+        b.rect = rect(0.6, 0.7, 0.05, 0.05);
+
+        assert_ne!(import_id("book-1", None, &a), import_id("book-1", None, &b));
+    }
+
+    #[test]
+    fn test_to_annotation_highlight() {
+        let mut source = extracted(ExtractedAnnotationType::Squiggly);
+        source.comment = Some("Remember this".to_string());
+        let annotation = to_annotation("id-1", "book-1", Some("user-1"), &source).unwrap();
+
+        assert_eq!(annotation.id, "id-1");
+        assert_eq!(annotation.annotation_type, AnnotationType::Underline);
+        assert_eq!(annotation.user_id.as_deref(), Some("user-1"));
+        assert_eq!(annotation.pdf_page(), Some(3));
+        assert_eq!(annotation.pdf_text_quote(), Some("The quick brown fox"));
+        assert_eq!(annotation.pdf_region().unwrap().width, 0.5);
+        assert_eq!(annotation.style.unwrap().color, "#ffff00");
+        assert_eq!(
+            annotation.body.unwrap().value.as_deref(),
+            Some("Remember this")
+        );
+        assert_eq!(
+            annotation.created_at.to_rfc3339(),
+            "2024-01-15T10:30:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_to_annotation_note_without_text() {
+        let mut source = extracted(ExtractedAnnotationType::Text);
+        source.text = None;
+        source.comment = Some("Check this page".to_string());
+        let annotation = to_annotation("id-1", "book-1", None, &source).unwrap();
+
+        assert_eq!(annotation.annotation_type, AnnotationType::Note);
+        assert_eq!(annotation.pdf_text_quote(), None);
+        assert!(annotation.pdf_region().is_some());
+        assert_eq!(
+            annotation.body.unwrap().value.as_deref(),
+            Some("Check this page")
+        );
+    }
+
+    #[test]
+    fn test_unsupported_types_are_not_converted() {
+        for annotation_type in [
+            ExtractedAnnotationType::StrikeOut,
+            ExtractedAnnotationType::Ink,
+            ExtractedAnnotationType::Unknown,
+        ] {
+            let source = extracted(annotation_type);
+            assert!(to_annotation("id-1", "book-1", None, &source).is_none());
+            assert!(to_highlight(&source).is_none());
+        }
+    }
+
+    #[test]
+    fn test_to_highlight() {
+        let highlight = to_highlight(&extracted(ExtractedAnnotationType::Highlight)).unwrap();
+
+        assert_eq!(highlight.document_format.as_deref(), Some("pdf"));
+        assert_eq!(highlight.annotation_type.as_deref(), Some("highlight"));
+        assert_eq!(highlight.page, Some(3));
+        assert_eq!(highlight.chapter.as_deref(), Some("Page 3"));
+        assert_eq!(highlight.color.as_deref(), Some("#ffff00"));
+        assert_eq!(highlight.region.unwrap().height, 0.05);
+        assert_eq!(highlight.rects.unwrap().len(), 2);
+    }
+}
@@ -17,10 +17,14 @@
 //!   - Bookmarks
 //!
 //! - SQLite persistence with sync metadata
+//!
+//! - Idempotent import of native PDF annotations
 
+mod import;
 mod store;
 mod types;
 
+pub use import::{import_annotations, import_id, ImportSummary, ImportTarget};
 pub use store::{AnnotationQuery, AnnotationRepository};
 pub use types::{
     Annotation, AnnotationBody, AnnotationStyle, AnnotationTarget, AnnotationType, BodyType,
@@ -118,6 +118,22 @@ impl Cfi {
         self.range.is_some()
     }
 
+    /// The location this CFI starts at: the start of a range, or the CFI
+    /// itself
+    pub fn start(&self) -> Cfi {
+        match &self.range {
+            Some(range) => {
+                let mut path = self.path.clone();
+                path.steps.extend(range.start.steps.iter().cloned());
+                path.character_offset = range.start.character_offset.clone();
+                path.temporal_offset = range.start.temporal_offset.clone();
+                path.spatial_offset = range.start.spatial_offset.clone();
+                Cfi::new(path)
+            }
+            None => self.clone(),
+        }
+    }
+
     /// Get the spine index if this CFI references a spine item
     /// The spine index is typically at position 2 in the path (after /6/N)
     pub fn spine_index(&self) -> Option<u32> {
@@ -318,6 +334,21 @@ mod tests {
         assert_eq!(cfi.to_string(), "epubcfi(/6/4[chapter1]!/4)");
     }
 
+    #[test]
+    fn test_range_start() {
+        let mut start = CfiPath::with_steps(vec![CfiStep::element(1)]);
+        start.set_character_offset(5);
+        let mut end = CfiPath::with_steps(vec![CfiStep::element(3)]);
+        end.set_character_offset(2);
+        let range = Cfi::with_range(
+            CfiPath::with_steps(vec![CfiStep::element(6), CfiStep::element(4)]),
+            CfiRange { start, end },
+        );
+
+        assert_eq!(range.start().to_string(), "epubcfi(/6/4/1:5)");
+        assert_eq!(range.start().start(), range.start());
+    }
+
     #[test]
     fn test_cfi_with_character_offset() {
         let mut path = CfiPath::with_steps(vec![
@@ -8,6 +8,10 @@ pub struct Config {
     pub server: ServerConfig,
     pub storage: StorageConfig,
     pub database: DatabaseConfig,
+    #[serde(default)]
+    pub library: LibraryConfig,
+    #[serde(default)]
+    pub pdf: PdfConfig,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -24,6 +28,15 @@ pub struct StorageConfig {
     pub access_key: String,
     pub secret_key: String,
     pub region: Option<String>,
+    /// Library directory for the `local` provider
+    #[serde(default = "default_local_path")]
+    pub local_path: String,
+    /// Hand clients presigned URLs so file bytes bypass the server
+    #[serde(default)]
+    pub presigned_urls: bool,
+    /// Lifetime of presigned URLs in seconds (S3 allows up to 7 days)
+    #[serde(default = "default_presign_expiry_secs")]
+    pub presign_expiry_secs: u64,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -33,6 +46,16 @@ pub enum StorageProvider {
     R2,
     S3,
     B2,
+    /// Local directory (Calibre library layout), no object store needed
+    Local,
+}
+
+fn default_local_path() -> String {
+    "./library".to_string()
+}
+
+fn default_presign_expiry_secs() -> u64 {
+    3600
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -40,6 +63,40 @@ pub struct DatabaseConfig {
     pub url: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct LibraryConfig {
+    /// Seconds between background catalog rescans (0 disables them)
+    #[serde(default = "default_scan_interval_secs")]
+    pub scan_interval_secs: u64,
+    /// Bearer token required on bucket event webhooks, if set
+    #[serde(default)]
+    pub webhook_token: Option<String>,
+    /// Server directory Calibre `metadata.db` files may be imported from
+    #[serde(default)]
+    pub import_dir: Option<String>,
+}
+
+impl Default for LibraryConfig {
+    fn default() -> Self {
+        LibraryConfig {
+            scan_interval_secs: default_scan_interval_secs(),
+            webhook_token: None,
+            import_dir: None,
+        }
+    }
+}
+
+fn default_scan_interval_secs() -> u64 {
+    300
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PdfConfig {
+    /// Directory of trusted certificates (PEM or DER) for signature validation
+    #[serde(default)]
+    pub signature_trust_dir: Option<String>,
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
@@ -54,16 +111,38 @@ impl Default for Config {
                 access_key: "admin".to_string(),
                 secret_key: "password123".to_string(),
                 region: Some("us-east-1".to_string()),
+                local_path: default_local_path(),
+                presigned_urls: false,
+                presign_expiry_secs: default_presign_expiry_secs(),
             },
             database: DatabaseConfig {
                 url: "sqlite:./libros.db".to_string(),
             },
+            library: LibraryConfig::default(),
+            pdf: PdfConfig::default(),
         }
     }
 }
 
 impl Config {
     pub fn from_env() -> Result<Self, env::VarError> {
+        let provider_name = env::var("STORAGE_PROVIDER")
+            .or_else(|_| env::var("S3_PROVIDER"))
+            .unwrap_or_else(|_| "minio".to_string());
+        let provider = match provider_name.as_str() {
+            "r2" => StorageProvider::R2,
+            "s3" => StorageProvider::S3,
+            "b2" => StorageProvider::B2,
+            "local" => StorageProvider::Local,
+            _ => StorageProvider::Minio,
+        };
+
+        // S3 settings are only required for S3-compatible providers
+        let s3_var = |name: &str| match env::var(name) {
+            Err(_) if matches!(provider, StorageProvider::Local) => Ok(String::new()),
+            result => result,
+        };
+
         Ok(Config {
             server: ServerConfig {
                 host: env::var("SERVER_HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
@@ -73,21 +152,39 @@ impl Config {
                     .unwrap_or(3000),
             },
             storage: StorageConfig {
-                provider: match env::var("S3_PROVIDER").unwrap_or_else(|_| "minio".to_string()).as_str() {
-                    "r2" => StorageProvider::R2,
-                    "s3" => StorageProvider::S3,
-                    "b2" => StorageProvider::B2,
-                    _ => StorageProvider::Minio,
-                },
-                endpoint: env::var("S3_ENDPOINT")?,
-                bucket: env::var("S3_BUCKET")?,
-                access_key: env::var("S3_ACCESS_KEY")?,
-                secret_key: env::var("S3_SECRET_KEY")?,
+                provider: provider.clone(),
+                endpoint: s3_var("S3_ENDPOINT")?,
+                bucket: s3_var("S3_BUCKET")?,
+                access_key: s3_var("S3_ACCESS_KEY")?,
+                secret_key: s3_var("S3_SECRET_KEY")?,
                 region: env::var("S3_REGION").ok(),
+                local_path: env::var("STORAGE_PATH").unwrap_or_else(|_| default_local_path()),
+                presigned_urls: env::var("S3_PRESIGNED_URLS")
+                    .map(|v| matches!(v.as_str(), "1" | "true" | "yes"))
+                    .unwrap_or(false),
+                presign_expiry_secs: env::var("S3_PRESIGN_EXPIRY_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(default_presign_expiry_secs),
             },
             database: DatabaseConfig {
                 url: env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:./libros.db".to_string()),
             },
+            library: LibraryConfig {
+                scan_interval_secs: env::var("LIBRARY_SCAN_INTERVAL_SECS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(default_scan_interval_secs),
+                webhook_token: env::var("LIBRARY_WEBHOOK_TOKEN").ok().filter(|t| !t.is_empty()),
+                import_dir: env::var("LIBRARY_IMPORT_DIR")
+                    .ok()
+                    .filter(|dir| !dir.is_empty()),
+            },
+            pdf: PdfConfig {
+                signature_trust_dir: env::var("PDF_SIGNATURE_TRUST_DIR")
+                    .ok()
+                    .filter(|dir| !dir.is_empty()),
+            },
         })
     }
 }
@@ -0,0 +1,219 @@
+//! Books database operations
+//!
+//! Persistent records for uploaded documents. Each row points at the
+//! original file in object storage (`storage_key`) so documents can be
+//! re-hydrated after a restart or on another replica.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::error::Result;
+
+/// Book record
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct BookRecord {
+    pub id: String,
+    pub title: String,
+    /// Authors joined with ", "
+    pub authors: Option<String>,
+    pub file_name: String,
+    pub file_size: i64,
+    /// SHA-256 of the stored file (used for deduplication)
+    pub file_hash: Option<String>,
+    pub mime_type: String,
+    /// Object storage key of the original file
+    pub storage_key: String,
+    pub cover_key: Option<String>,
+    /// JSON-encoded `ParsedDocument` captured at upload time
+    pub metadata: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Create book request
+#[derive(Debug, Clone)]
+pub struct CreateBook {
+    pub id: String,
+    pub title: String,
+    pub authors: Option<String>,
+    pub file_name: String,
+    pub file_size: i64,
+    pub file_hash: Option<String>,
+    pub mime_type: String,
+    pub storage_key: String,
+    pub cover_key: Option<String>,
+    pub metadata: Option<String>,
+}
+
+/// Books repository
+pub struct BookRepository<'a> {
+    pool: &'a SqlitePool,
+}
+
+impl<'a> BookRepository<'a> {
+    pub fn new(pool: &'a SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Get a book by ID
+    pub async fn get(&self, id: &str) -> Result<Option<BookRecord>> {
+        let book = sqlx::query_as::<_, BookRecord>(
+            r#"
+            SELECT id, title, authors, file_name, file_size, file_hash, mime_type,
+                   storage_key, cover_key, metadata, created_at, updated_at
+            FROM books
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(book)
+    }
+
+    /// List all books, most recently added first
+    pub async fn list(&self) -> Result<Vec<BookRecord>> {
+        let books = sqlx::query_as::<_, BookRecord>(
+            r#"
+            SELECT id, title, authors, file_name, file_size, file_hash, mime_type,
+                   storage_key, cover_key, metadata, created_at, updated_at
+            FROM books
+            ORDER BY created_at DESC
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(books)
+    }
+
+    /// Check whether a book with the given ID exists
+    pub async fn exists(&self, id: &str) -> Result<bool> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM books WHERE id = ?")
+            .bind(id)
+            .fetch_one(self.pool)
+            .await?;
+
+        Ok(count > 0)
+    }
+
+    /// Insert a new book
+    pub async fn create(&self, book: &CreateBook) -> Result<BookRecord> {
+        self.insert(book, "INSERT").await?;
+
+        self.get(&book.id)
+            .await?
+            .ok_or_else(|| crate::error::AppError::Internal("Failed to fetch created book".to_string()))
+    }
+
+    /// Insert a new book unless its ID is taken
+    ///
+    /// Returns `None` if a book with the same ID exists. Claims the ID
+    /// atomically, so concurrent uploads can't both succeed.
+    pub async fn try_create(&self, book: &CreateBook) -> Result<Option<BookRecord>> {
+        if !self.insert(book, "INSERT OR IGNORE").await? {
+            return Ok(None);
+        }
+        self.get(&book.id).await
+    }
+
+    /// Run an insert statement, returning whether a row was inserted
+    async fn insert(&self, book: &CreateBook, verb: &str) -> Result<bool> {
+        let now = Utc::now().to_rfc3339();
+
+        let result = sqlx::query(&format!(
+            r#"
+            {} INTO books (id, title, authors, file_name, file_size, file_hash, mime_type,
+                               storage_key, cover_key, metadata, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            verb
+        ))
+        .bind(&book.id)
+        .bind(&book.title)
+        .bind(&book.authors)
+        .bind(&book.file_name)
+        .bind(book.file_size)
+        .bind(&book.file_hash)
+        .bind(&book.mime_type)
+        .bind(&book.storage_key)
+        .bind(&book.cover_key)
+        .bind(&book.metadata)
+        .bind(&now)
+        .bind(&now)
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete a book record
+    pub async fn delete(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM books WHERE id = ?")
+            .bind(id)
+            .execute(self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::initialize_schema;
+
+    async fn test_pool() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        initialize_schema(&pool).await.unwrap();
+        pool
+    }
+
+    fn sample_book(id: &str) -> CreateBook {
+        CreateBook {
+            id: id.to_string(),
+            title: "Dune".to_string(),
+            authors: Some("Frank Herbert".to_string()),
+            file_name: "dune.epub".to_string(),
+            file_size: 1024,
+            file_hash: Some("abc123".to_string()),
+            mime_type: "application/epub+zip".to_string(),
+            storage_key: format!("books/{}/dune.epub", id),
+            cover_key: None,
+            metadata: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_get_delete() {
+        let pool = test_pool().await;
+        let repo = BookRepository::new(&pool);
+
+        let created = repo.create(&sample_book("dune")).await.unwrap();
+        assert_eq!(created.title, "Dune");
+        assert!(repo.exists("dune").await.unwrap());
+        assert_eq!(repo.list().await.unwrap().len(), 1);
+
+        assert!(repo.delete("dune").await.unwrap());
+        assert!(repo.get("dune").await.unwrap().is_none());
+        assert!(!repo.delete("dune").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_try_create_claims_id() {
+        let pool = test_pool().await;
+        let repo = BookRepository::new(&pool);
+
+        let created = repo.try_create(&sample_book("dune")).await.unwrap();
+        assert!(created.is_some());
+
+        let mut duplicate = sample_book("dune");
+        duplicate.title = "Dune Messiah".to_string();
+        assert!(repo.try_create(&duplicate).await.unwrap().is_none());
+        assert_eq!(repo.get("dune").await.unwrap().unwrap().title, "Dune");
+        assert!(repo.create(&duplicate).await.is_err());
+    }
+}
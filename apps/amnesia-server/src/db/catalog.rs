@@ -0,0 +1,1028 @@
+//! Library catalog database operations
+//!
+//! Scanned `LibraryBook`s live in `library_books`, and the storage objects
+//! each scan saw (with ETag and last-modified time) in `library_objects`.
+//! Refreshes diff storage against `library_objects` and apply only the
+//! changes, so the catalog survives restarts without rescanning the bucket.
+//!
+//! Book IDs are stable: re-scanning a folder updates its row in place.
+//! Metadata edits are kept in `library_book_edits` so they can be reverted.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::error::{AppError, Result};
+use crate::library::{BookEdit, BookFormat, LibraryBook, LibraryChanges, LibraryScanner, MetadataSnapshot};
+use crate::storage::ObjectMetadata;
+
+/// Columns selected for `LibraryBookRow`
+const BOOK_COLUMNS: &str = "id, s3_prefix, title, author, author_sort, authors, publisher, \
+     pubdate, language, description, series, series_index, tags, identifiers, formats, \
+     cover_key, rating, custom_columns, added_at, updated_at";
+
+/// Catalog repository
+pub struct CatalogRepository<'a> {
+    pool: &'a SqlitePool,
+}
+
+impl<'a> CatalogRepository<'a> {
+    pub fn new(pool: &'a SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Get a book by ID
+    pub async fn get(&self, id: &str) -> Result<Option<LibraryBook>> {
+        let row = sqlx::query_as::<_, LibraryBookRow>(&format!(
+            "SELECT {} FROM library_books WHERE id = ?",
+            BOOK_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(row.map(LibraryBookRow::into_book))
+    }
+
+    /// One page of the books matching `filter`, in `order`
+    pub async fn list(
+        &self,
+        filter: &CatalogFilter,
+        order: CatalogOrder,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<LibraryBook>> {
+        let (conditions, params) = filter.conditions();
+        let sql = format!(
+            "SELECT {} FROM library_books {} ORDER BY {} LIMIT ? OFFSET ?",
+            BOOK_COLUMNS,
+            conditions,
+            order.sql()
+        );
+        let mut query = sqlx::query_as::<_, LibraryBookRow>(&sql);
+        for param in &params {
+            query = query.bind(param);
+        }
+
+        let rows = query.bind(limit).bind(offset).fetch_all(self.pool).await?;
+        Ok(rows.into_iter().map(LibraryBookRow::into_book).collect())
+    }
+
+    /// Number of books in the catalog
+    pub async fn count(&self) -> Result<i64> {
+        self.count_matching(&CatalogFilter::default()).await
+    }
+
+    /// Number of books matching `filter`
+    pub async fn count_matching(&self, filter: &CatalogFilter) -> Result<i64> {
+        let (conditions, params) = filter.conditions();
+        let sql = format!("SELECT COUNT(*) FROM library_books {}", conditions);
+        let mut query = sqlx::query_scalar(&sql);
+        for param in &params {
+            query = query.bind(param);
+        }
+
+        Ok(query.fetch_one(self.pool).await?)
+    }
+
+    /// The most common values of `facet` among books matching `filter`,
+    /// with their book counts
+    pub async fn facet_counts(
+        &self,
+        filter: &CatalogFilter,
+        facet: CatalogFacet,
+        limit: i64,
+    ) -> Result<Vec<(String, i64)>> {
+        let (conditions, params) = filter.conditions();
+        let (value, join) = match facet {
+            CatalogFacet::Language => ("language", ""),
+            CatalogFacet::Publisher => ("publisher", ""),
+            CatalogFacet::Tag => ("facet.value", ", json_each(library_books.tags) AS facet"),
+            CatalogFacet::Format => (
+                "json_extract(facet.value, '$.format')",
+                ", json_each(library_books.formats) AS facet",
+            ),
+        };
+        let not_null = if conditions.is_empty() {
+            "WHERE"
+        } else {
+            "AND"
+        };
+        let sql = format!(
+            "SELECT {value}, COUNT(DISTINCT library_books.id) AS books \
+             FROM library_books{join} {conditions} {not_null} {value} IS NOT NULL \
+             GROUP BY {value} ORDER BY books DESC, {value} LIMIT ?"
+        );
+        let mut query = sqlx::query_as(&sql);
+        for param in &params {
+            query = query.bind(param);
+        }
+
+        Ok(query.bind(limit).fetch_all(self.pool).await?)
+    }
+
+    /// Books with a format in `formats` whose page count isn't recorded
+    pub async fn missing_page_counts(&self, formats: &[&str]) -> Result<Vec<LibraryBook>> {
+        if formats.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let placeholders = vec!["?"; formats.len()].join(", ");
+        let sql = format!(
+            "SELECT {} FROM library_books WHERE EXISTS (\
+                 SELECT 1 FROM json_each(library_books.formats) \
+                 WHERE json_extract(value, '$.pages') IS NULL \
+                 AND json_extract(value, '$.format') IN ({}))",
+            BOOK_COLUMNS, placeholders
+        );
+        let mut query = sqlx::query_as::<_, LibraryBookRow>(&sql);
+        for format in formats {
+            query = query.bind(*format);
+        }
+
+        let rows = query.fetch_all(self.pool).await?;
+        Ok(rows.into_iter().map(LibraryBookRow::into_book).collect())
+    }
+
+    /// Most recently added books
+    pub async fn recent(&self, limit: i64) -> Result<Vec<LibraryBook>> {
+        self.list(&CatalogFilter::default(), CatalogOrder::Added, limit, 0)
+            .await
+    }
+
+    /// Primary authors with their book counts, by name
+    pub async fn author_counts(&self) -> Result<Vec<(String, i64)>> {
+        let counts = sqlx::query_as(
+            r#"
+            SELECT author, COUNT(*) FROM library_books
+            WHERE author IS NOT NULL
+            GROUP BY author
+            ORDER BY author
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(counts)
+    }
+
+    /// Series with their book counts, by name
+    pub async fn series_counts(&self) -> Result<Vec<(String, i64)>> {
+        let counts = sqlx::query_as(
+            r#"
+            SELECT series, COUNT(*) FROM library_books
+            WHERE series IS NOT NULL
+            GROUP BY series
+            ORDER BY series
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(counts)
+    }
+
+    /// Book folders that have a catalog entry
+    pub async fn prefixes(&self) -> Result<HashSet<String>> {
+        let prefixes: Vec<String> = sqlx::query_scalar("SELECT s3_prefix FROM library_books")
+            .fetch_all(self.pool)
+            .await?;
+
+        Ok(prefixes.into_iter().collect())
+    }
+
+    /// Storage objects recorded by previous scans, by key
+    pub async fn known_objects(&self) -> Result<HashMap<String, ObjectMetadata>> {
+        let rows: Vec<(String, i64, Option<String>, Option<String>)> =
+            sqlx::query_as("SELECT key, size, etag, last_modified FROM library_objects")
+                .fetch_all(self.pool)
+                .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(key, size, etag, last_modified)| {
+                let metadata = ObjectMetadata {
+                    key: key.clone(),
+                    size,
+                    last_modified: last_modified.as_deref().and_then(parse_time),
+                    content_type: None,
+                    etag,
+                };
+                (key, metadata)
+            })
+            .collect())
+    }
+
+    /// Insert or update a book, keyed by its storage folder
+    ///
+    /// An existing entry keeps its ID and `added_at`.
+    pub async fn upsert(&self, book: &LibraryBook) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        upsert_book(&mut conn, book).await
+    }
+
+    /// Scan storage for changes since the last refresh and apply them
+    pub async fn refresh(&self, scanner: &LibraryScanner) -> Result<LibraryChanges> {
+        let known_objects = self.known_objects().await?;
+        let known_books = self.prefixes().await?;
+
+        // Applied even without book changes, to record new non-book objects
+        let changes = scanner.scan_changes(&known_objects, &known_books).await?;
+        self.apply_changes(&changes).await?;
+
+        Ok(changes)
+    }
+
+    /// Rescan the given book folders and apply their changes
+    pub async fn refresh_folders(
+        &self,
+        scanner: &LibraryScanner,
+        folders: &HashSet<String>,
+    ) -> Result<LibraryChanges> {
+        let known_objects = self.known_objects().await?;
+        let known_books = self.prefixes().await?;
+
+        let changes = scanner
+            .scan_folders(folders, &known_objects, &known_books)
+            .await?;
+        self.apply_changes(&changes).await?;
+
+        Ok(changes)
+    }
+
+    /// Apply the result of `LibraryScanner::scan_changes` in one transaction
+    pub async fn apply_changes(&self, changes: &LibraryChanges) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for prefix in &changes.removed {
+            sqlx::query("DELETE FROM library_books WHERE s3_prefix = ?")
+                .bind(prefix)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM library_objects WHERE prefix = ?")
+                .bind(prefix)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for book in changes.added.iter().chain(&changes.updated) {
+            upsert_book(&mut tx, book).await?;
+        }
+
+        for (prefix, objects) in &changes.objects {
+            replace_objects(&mut tx, prefix, objects).await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Update a book's row by ID, including its storage folder
+    pub async fn update(&self, book: &LibraryBook) -> Result<()> {
+        let to_json = |value: serde_json::Result<String>| value.unwrap_or_default();
+
+        let result = sqlx::query(
+            r#"
+            UPDATE library_books SET
+                s3_prefix = ?, title = ?, author = ?, author_sort = ?, authors = ?,
+                publisher = ?, pubdate = ?, language = ?, description = ?, series = ?,
+                series_index = ?, tags = ?, identifiers = ?, formats = ?, cover_key = ?,
+                rating = ?, custom_columns = ?, updated_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&book.s3_prefix)
+        .bind(&book.title)
+        .bind(&book.author)
+        .bind(&book.author_sort)
+        .bind(to_json(serde_json::to_string(&book.authors)))
+        .bind(&book.publisher)
+        .bind(&book.pubdate)
+        .bind(&book.language)
+        .bind(&book.description)
+        .bind(&book.series)
+        .bind(book.series_index.map(f64::from))
+        .bind(to_json(serde_json::to_string(&book.tags)))
+        .bind(to_json(serde_json::to_string(&book.identifiers)))
+        .bind(to_json(serde_json::to_string(&book.formats)))
+        .bind(&book.cover_key)
+        .bind(book.rating.map(f64::from))
+        .bind(to_json(serde_json::to_string(&book.custom_columns)))
+        .bind(book.updated_at.to_rfc3339())
+        .bind(&book.id)
+        .execute(self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("Book not found: {}", book.id)));
+        }
+        Ok(())
+    }
+
+    /// Set a book's cover image key
+    pub async fn set_cover(&self, id: &str, cover_key: Option<&str>) -> Result<()> {
+        let result = sqlx::query("UPDATE library_books SET cover_key = ? WHERE id = ?")
+            .bind(cover_key)
+            .bind(id)
+            .execute(self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound(format!("Book not found: {}", id)));
+        }
+        Ok(())
+    }
+
+    /// Record the page count of the format stored at `s3_key`
+    ///
+    /// Other formats are left alone. Returns false if the book or the
+    /// format is gone.
+    pub async fn set_page_count(&self, id: &str, s3_key: &str, pages: usize) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE library_books
+            SET formats = json_set(
+                formats,
+                (SELECT '$[' || key || '].pages' FROM json_each(library_books.formats)
+                 WHERE json_extract(value, '$.s3_key') = ?1 LIMIT 1),
+                ?2
+            )
+            WHERE id = ?3
+              AND EXISTS (
+                SELECT 1 FROM json_each(library_books.formats)
+                WHERE json_extract(value, '$.s3_key') = ?1
+              )
+            "#,
+        )
+        .bind(s3_key)
+        .bind(pages as i64)
+        .bind(id)
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Record the current objects of a book folder, replacing earlier ones
+    pub async fn record_objects(&self, prefix: &str, objects: &[ObjectMetadata]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        replace_objects(&mut tx, prefix, objects).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Record a metadata edit
+    pub async fn record_edit(
+        &self,
+        book_id: &str,
+        fields: &[String],
+        before: &MetadataSnapshot,
+        after: &MetadataSnapshot,
+        reverts: Option<i64>,
+    ) -> Result<BookEdit> {
+        let created_at = Utc::now();
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO library_book_edits (book_id, fields, before, after, reverts, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(book_id)
+        .bind(serde_json::to_string(fields).unwrap_or_default())
+        .bind(serde_json::to_string(before).unwrap_or_default())
+        .bind(serde_json::to_string(after).unwrap_or_default())
+        .bind(reverts)
+        .bind(created_at.to_rfc3339())
+        .execute(self.pool)
+        .await?;
+
+        Ok(BookEdit {
+            id: result.last_insert_rowid(),
+            book_id: book_id.to_string(),
+            fields: fields.to_vec(),
+            before: before.clone(),
+            after: after.clone(),
+            reverts,
+            created_at,
+        })
+    }
+
+    /// A book's metadata edits, newest first
+    pub async fn edits(&self, book_id: &str) -> Result<Vec<BookEdit>> {
+        let rows = sqlx::query_as::<_, BookEditRow>(
+            r#"
+            SELECT id, book_id, fields, before, after, reverts, created_at
+            FROM library_book_edits
+            WHERE book_id = ?
+            ORDER BY id DESC
+            "#,
+        )
+        .bind(book_id)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(BookEditRow::into_edit).collect())
+    }
+
+    /// Get a metadata edit by ID
+    pub async fn get_edit(&self, id: i64) -> Result<Option<BookEdit>> {
+        let row = sqlx::query_as::<_, BookEditRow>(
+            r#"
+            SELECT id, book_id, fields, before, after, reverts, created_at
+            FROM library_book_edits
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(self.pool)
+        .await?;
+
+        Ok(row.map(BookEditRow::into_edit))
+    }
+}
+
+/// Listing filters; unset fields match every book
+#[derive(Debug, Clone, Default)]
+pub struct CatalogFilter {
+    /// Primary author
+    pub author: Option<String>,
+    pub series: Option<String>,
+    /// Substring search over title, author, series and tags
+    ///
+    /// `author:` and `title:` qualifiers (quoted for several words, as in
+    /// `author:"Frank Herbert" dune`) restrict a term to that field. All
+    /// parts must match.
+    pub search: Option<String>,
+    pub language: Option<String>,
+    pub tag: Option<String>,
+    /// Format name, such as `epub`
+    pub format: Option<String>,
+    pub publisher: Option<String>,
+}
+
+impl CatalogFilter {
+    /// `WHERE` clause (empty without filters) and its parameters
+    fn conditions(&self) -> (String, Vec<String>) {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+
+        let mut equals = |column: &'static str, value: &Option<String>| {
+            if let Some(value) = value {
+                conditions.push(format!("{} = ?", column));
+                params.push(value.clone());
+            }
+        };
+        equals("author", &self.author);
+        equals("series", &self.series);
+        equals("language", &self.language);
+        equals("publisher", &self.publisher);
+
+        if let Some(ref tag) = self.tag {
+            conditions.push(
+                "EXISTS (SELECT 1 FROM json_each(library_books.tags) WHERE value = ?)".to_string(),
+            );
+            params.push(tag.clone());
+        }
+        if let Some(ref format) = self.format {
+            conditions.push(
+                "EXISTS (SELECT 1 FROM json_each(library_books.formats) \
+                 WHERE json_extract(value, '$.format') = ?)"
+                    .to_string(),
+            );
+            params.push(format.clone());
+        }
+
+        let terms = self
+            .search
+            .as_deref()
+            .map(SearchTerms::parse)
+            .unwrap_or_default();
+        if let Some(ref text) = terms.text {
+            conditions.push(
+                r"(title LIKE ? ESCAPE '\' OR author LIKE ? ESCAPE '\'
+                   OR series LIKE ? ESCAPE '\' OR tags LIKE ? ESCAPE '\')"
+                    .to_string(),
+            );
+            params.extend(std::iter::repeat(like_pattern(text)).take(4));
+        }
+        if let Some(ref author) = terms.author {
+            conditions.push(r"(author LIKE ? ESCAPE '\' OR authors LIKE ? ESCAPE '\')".to_string());
+            params.extend(std::iter::repeat(like_pattern(author)).take(2));
+        }
+        if let Some(ref title) = terms.title {
+            conditions.push(r"title LIKE ? ESCAPE '\'".to_string());
+            params.push(like_pattern(title));
+        }
+
+        if conditions.is_empty() {
+            return (String::new(), params);
+        }
+        (format!("WHERE {}", conditions.join(" AND ")), params)
+    }
+}
+
+/// Listing order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatalogOrder {
+    Title,
+    /// By author sort name, then title
+    Author,
+    /// Newest first
+    Added,
+    /// By series index, then title
+    Series,
+}
+
+impl CatalogOrder {
+    fn sql(&self) -> &'static str {
+        match self {
+            CatalogOrder::Title => "title COLLATE NOCASE",
+            CatalogOrder::Author => {
+                "COALESCE(author_sort, author) COLLATE NOCASE, title COLLATE NOCASE"
+            }
+            CatalogOrder::Added => "added_at DESC",
+            CatalogOrder::Series => "COALESCE(series_index, 0), title COLLATE NOCASE",
+        }
+    }
+}
+
+/// Field counted by [`CatalogRepository::facet_counts`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CatalogFacet {
+    Language,
+    Tag,
+    /// Format name; a book counts once per format
+    Format,
+    Publisher,
+}
+
+async fn upsert_book(conn: &mut SqliteConnection, book: &LibraryBook) -> Result<()> {
+    let to_json = |value: serde_json::Result<String>| value.unwrap_or_default();
+
+    sqlx::query(
+        r#"
+        INSERT INTO library_books (id, s3_prefix, title, author, author_sort, authors, publisher,
+                                   pubdate, language, description, series, series_index, tags,
+                                   identifiers, formats, cover_key, rating, custom_columns,
+                                   added_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(s3_prefix) DO UPDATE SET
+            title = excluded.title,
+            author = excluded.author,
+            author_sort = excluded.author_sort,
+            authors = excluded.authors,
+            publisher = excluded.publisher,
+            pubdate = excluded.pubdate,
+            language = excluded.language,
+            description = excluded.description,
+            series = excluded.series,
+            series_index = excluded.series_index,
+            tags = excluded.tags,
+            identifiers = excluded.identifiers,
+            formats = excluded.formats,
+            cover_key = excluded.cover_key,
+            rating = excluded.rating,
+            custom_columns = excluded.custom_columns,
+            updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(&book.id)
+    .bind(&book.s3_prefix)
+    .bind(&book.title)
+    .bind(&book.author)
+    .bind(&book.author_sort)
+    .bind(to_json(serde_json::to_string(&book.authors)))
+    .bind(&book.publisher)
+    .bind(&book.pubdate)
+    .bind(&book.language)
+    .bind(&book.description)
+    .bind(&book.series)
+    .bind(book.series_index.map(f64::from))
+    .bind(to_json(serde_json::to_string(&book.tags)))
+    .bind(to_json(serde_json::to_string(&book.identifiers)))
+    .bind(to_json(serde_json::to_string(&book.formats)))
+    .bind(&book.cover_key)
+    .bind(book.rating.map(f64::from))
+    .bind(to_json(serde_json::to_string(&book.custom_columns)))
+    .bind(book.added_at.to_rfc3339())
+    .bind(book.updated_at.to_rfc3339())
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+async fn replace_objects(
+    conn: &mut SqliteConnection,
+    prefix: &str,
+    objects: &[ObjectMetadata],
+) -> Result<()> {
+    sqlx::query("DELETE FROM library_objects WHERE prefix = ?")
+        .bind(prefix)
+        .execute(&mut *conn)
+        .await?;
+
+    for obj in objects {
+        sqlx::query(
+            r#"
+            INSERT INTO library_objects (key, prefix, size, etag, last_modified)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&obj.key)
+        .bind(prefix)
+        .bind(obj.size)
+        .bind(&obj.etag)
+        .bind(obj.last_modified.map(|t| t.to_rfc3339()))
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Row in the `library_book_edits` table
+#[derive(sqlx::FromRow)]
+struct BookEditRow {
+    id: i64,
+    book_id: String,
+    fields: String,
+    before: String,
+    after: String,
+    reverts: Option<i64>,
+    created_at: String,
+}
+
+impl BookEditRow {
+    fn into_edit(self) -> BookEdit {
+        BookEdit {
+            id: self.id,
+            book_id: self.book_id,
+            fields: serde_json::from_str(&self.fields).unwrap_or_default(),
+            before: serde_json::from_str(&self.before).unwrap_or_default(),
+            after: serde_json::from_str(&self.after).unwrap_or_default(),
+            reverts: self.reverts,
+            created_at: parse_time(&self.created_at).unwrap_or_else(Utc::now),
+        }
+    }
+}
+
+/// Row in the `library_books` table
+#[derive(sqlx::FromRow)]
+struct LibraryBookRow {
+    id: String,
+    s3_prefix: String,
+    title: String,
+    author: Option<String>,
+    author_sort: Option<String>,
+    authors: String,
+    publisher: Option<String>,
+    pubdate: Option<String>,
+    language: Option<String>,
+    description: Option<String>,
+    series: Option<String>,
+    series_index: Option<f64>,
+    tags: String,
+    identifiers: String,
+    formats: String,
+    cover_key: Option<String>,
+    rating: Option<f64>,
+    custom_columns: String,
+    added_at: String,
+    updated_at: String,
+}
+
+impl LibraryBookRow {
+    fn into_book(self) -> LibraryBook {
+        LibraryBook {
+            id: self.id,
+            title: self.title,
+            author: self.author,
+            author_sort: self.author_sort,
+            authors: serde_json::from_str(&self.authors).unwrap_or_default(),
+            publisher: self.publisher,
+            pubdate: self.pubdate,
+            language: self.language,
+            description: self.description,
+            series: self.series,
+            series_index: self.series_index.map(|i| i as f32),
+            tags: serde_json::from_str(&self.tags).unwrap_or_default(),
+            identifiers: serde_json::from_str(&self.identifiers).unwrap_or_default(),
+            formats: serde_json::from_str(&self.formats).unwrap_or_default(),
+            cover_key: self.cover_key,
+            rating: self.rating.map(|r| r as f32),
+            custom_columns: serde_json::from_str(&self.custom_columns).unwrap_or_default(),
+            s3_prefix: self.s3_prefix,
+            added_at: parse_time(&self.added_at).unwrap_or_else(Utc::now),
+            updated_at: parse_time(&self.updated_at).unwrap_or_else(Utc::now),
+        }
+    }
+}
+
+fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Escape `%`, `_` and `\` for a `LIKE ... ESCAPE '\'` pattern
+/// Catalog search query split into free text and field qualifiers
+#[derive(Debug, Default, PartialEq)]
+pub struct SearchTerms {
+    /// Unqualified words, matched against title, author, series and tags
+    pub text: Option<String>,
+    pub author: Option<String>,
+    pub title: Option<String>,
+}
+
+impl SearchTerms {
+    /// Parse `author:` and `title:` qualifiers out of a query
+    ///
+    /// Double quotes group words into one term. Repeated qualifiers are
+    /// joined with spaces.
+    pub fn parse(query: &str) -> Self {
+        let mut text = Vec::new();
+        let mut author = Vec::new();
+        let mut title = Vec::new();
+
+        for token in tokenize(query) {
+            let (field, value) = match token.split_once(':') {
+                Some((field, value)) if field.eq_ignore_ascii_case("author") => (&mut author, value),
+                Some((field, value)) if field.eq_ignore_ascii_case("title") => (&mut title, value),
+                _ => (&mut text, token.as_str()),
+            };
+            if !value.is_empty() {
+                field.push(value.to_string());
+            }
+        }
+
+        let join = |words: Vec<String>| (!words.is_empty()).then(|| words.join(" "));
+        Self {
+            text: join(text),
+            author: join(author),
+            title: join(title),
+        }
+    }
+}
+
+/// Split a query on whitespace outside double quotes, dropping the quotes
+fn tokenize(query: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for ch in query.chars() {
+        match ch {
+            '"' => quoted = !quoted,
+            ch if ch.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            ch => current.push(ch),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// `LIKE` pattern matching `value` anywhere
+fn like_pattern(value: &str) -> String {
+    format!("%{}%", escape_like(value))
+}
+
+fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        if matches!(ch, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::initialize_schema;
+    use crate::library::{BookFormat, FormatType};
+
+    async fn test_pool() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        initialize_schema(&pool).await.unwrap();
+        pool
+    }
+
+    fn sample_book(title: &str, author: &str) -> LibraryBook {
+        let prefix = format!("{}/{}", author, title);
+        let mut book = LibraryBook::new(title.to_string(), prefix.clone());
+        book.author = Some(author.to_string());
+        book.authors = vec![author.to_string()];
+        book.tags = vec!["Science Fiction".to_string()];
+        book.formats = vec![BookFormat {
+            format: FormatType::Epub,
+            s3_key: format!("{}/{}.epub", prefix, title),
+            size: 1024,
+            pages: None,
+        }];
+        book
+    }
+
+    fn object(key: &str, etag: &str) -> ObjectMetadata {
+        ObjectMetadata {
+            key: key.to_string(),
+            size: 1024,
+            last_modified: Some(Utc::now()),
+            content_type: None,
+            etag: Some(etag.to_string()),
+        }
+    }
+
+    async fn search(repo: &CatalogRepository<'_>, query: &str) -> Vec<LibraryBook> {
+        let filter = CatalogFilter {
+            search: Some(query.to_string()),
+            ..Default::default()
+        };
+        repo.list(&filter, CatalogOrder::Title, 10, 0)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_upsert_keeps_id() {
+        let pool = test_pool().await;
+        let repo = CatalogRepository::new(&pool);
+
+        let book = sample_book("Dune", "Frank Herbert");
+        repo.upsert(&book).await.unwrap();
+
+        // A rescan produces a new ID for the same folder
+        let mut rescanned = sample_book("Dune", "Frank Herbert");
+        rescanned.series = Some("Dune".to_string());
+        repo.upsert(&rescanned).await.unwrap();
+
+        assert_eq!(repo.count().await.unwrap(), 1);
+        let stored = repo.get(&book.id).await.unwrap().unwrap();
+        assert_eq!(stored.series.as_deref(), Some("Dune"));
+        assert_eq!(stored.tags, vec!["Science Fiction".to_string()]);
+        assert_eq!(stored.formats.len(), 1);
+        assert!(repo.get(&rescanned.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_apply_changes() {
+        let pool = test_pool().await;
+        let repo = CatalogRepository::new(&pool);
+
+        let dune = sample_book("Dune", "Frank Herbert");
+        let emma = sample_book("Emma", "Jane Austen");
+        let mut changes = LibraryChanges {
+            added: vec![dune.clone(), emma.clone()],
+            ..Default::default()
+        };
+        changes.objects.insert(
+            dune.s3_prefix.clone(),
+            vec![object("Frank Herbert/Dune/Dune.epub", "a")],
+        );
+        changes.objects.insert(
+            emma.s3_prefix.clone(),
+            vec![object("Jane Austen/Emma/Emma.epub", "b")],
+        );
+        repo.apply_changes(&changes).await.unwrap();
+
+        assert_eq!(repo.count().await.unwrap(), 2);
+        assert_eq!(repo.known_objects().await.unwrap().len(), 2);
+        assert_eq!(
+            repo.author_counts().await.unwrap(),
+            vec![("Frank Herbert".to_string(), 1), ("Jane Austen".to_string(), 1)]
+        );
+        assert_eq!(search(&repo, "dun").await.len(), 1);
+        assert_eq!(search(&repo, "science").await.len(), 2);
+        assert!(search(&repo, "100%").await.is_empty());
+        assert_eq!(search(&repo, "author:austen").await.len(), 1);
+        assert_eq!(search(&repo, "author:austen title:dune").await.len(), 0);
+        assert_eq!(
+            search(&repo, r#"author:"frank herbert" science"#).await[0].title,
+            "Dune"
+        );
+
+        let removal = LibraryChanges {
+            removed: vec![emma.s3_prefix.clone()],
+            ..Default::default()
+        };
+        repo.apply_changes(&removal).await.unwrap();
+
+        assert!(repo.get(&emma.id).await.unwrap().is_none());
+        let known = repo.known_objects().await.unwrap();
+        assert_eq!(known.len(), 1);
+        assert_eq!(known["Frank Herbert/Dune/Dune.epub"].etag.as_deref(), Some("a"));
+    }
+
+    #[tokio::test]
+    async fn test_filtered_listing() {
+        let pool = test_pool().await;
+        let repo = CatalogRepository::new(&pool);
+
+        let mut dune = sample_book("Dune", "Frank Herbert");
+        dune.language = Some("en".to_string());
+        let mut solaris = sample_book("Solaris", "Stanislaw Lem");
+        solaris.language = Some("pl".to_string());
+        solaris.formats.push(BookFormat {
+            format: FormatType::Pdf,
+            s3_key: "Stanislaw Lem/Solaris/Solaris.pdf".to_string(),
+            size: 1024,
+            pages: None,
+        });
+        let mut emma = sample_book("Emma", "Jane Austen");
+        emma.tags = vec!["Classics".to_string()];
+        for book in [&dune, &solaris, &emma] {
+            repo.upsert(book).await.unwrap();
+        }
+
+        let filter = CatalogFilter {
+            tag: Some("Science Fiction".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(repo.count_matching(&filter).await.unwrap(), 2);
+        let page = repo.list(&filter, CatalogOrder::Title, 1, 1).await.unwrap();
+        assert_eq!(page[0].title, "Solaris");
+
+        let formats = repo
+            .facet_counts(&filter, CatalogFacet::Format, 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            formats,
+            vec![("epub".to_string(), 2), ("pdf".to_string(), 1)]
+        );
+        let languages = repo
+            .facet_counts(&CatalogFilter::default(), CatalogFacet::Language, 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            languages,
+            vec![("en".to_string(), 1), ("pl".to_string(), 1)]
+        );
+
+        let filter = CatalogFilter {
+            format: Some("pdf".to_string()),
+            ..Default::default()
+        };
+        let books = repo
+            .list(&filter, CatalogOrder::Author, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(books.len(), 1);
+        assert_eq!(books[0].title, "Solaris");
+    }
+
+    #[tokio::test]
+    async fn test_missing_page_counts() {
+        let pool = test_pool().await;
+        let repo = CatalogRepository::new(&pool);
+
+        let mut solaris = sample_book("Solaris", "Stanislaw Lem");
+        solaris.formats[0].format = FormatType::Pdf;
+        repo.upsert(&solaris).await.unwrap();
+        let dune = sample_book("Dune", "Frank Herbert");
+        repo.upsert(&dune).await.unwrap();
+
+        let missing = repo.missing_page_counts(&["pdf", "cbz"]).await.unwrap();
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].title, "Solaris");
+
+        assert!(repo
+            .set_page_count(&solaris.id, &solaris.formats[0].s3_key, 300)
+            .await
+            .unwrap());
+        assert!(repo.missing_page_counts(&["pdf"]).await.unwrap().is_empty());
+        let stored = repo.get(&solaris.id).await.unwrap().unwrap();
+        assert_eq!(stored.formats[0].pages, Some(300));
+
+        // A removed book or renamed file is skipped
+        assert!(!repo.set_page_count("missing", "x.pdf", 1).await.unwrap());
+        assert!(!repo
+            .set_page_count(&solaris.id, "renamed.pdf", 1)
+            .await
+            .unwrap());
+    }
+
+    #[test]
+    fn test_search_terms() {
+        let terms = SearchTerms::parse(r#"author:"Frank Herbert" Title:Dune  messiah"#);
+        assert_eq!(terms.author.as_deref(), Some("Frank Herbert"));
+        assert_eq!(terms.title.as_deref(), Some("Dune"));
+        assert_eq!(terms.text.as_deref(), Some("messiah"));
+
+        // Unknown qualifiers are plain text
+        let terms = SearchTerms::parse("isbn:123 author:");
+        assert_eq!(terms.text.as_deref(), Some("isbn:123"));
+        assert!(terms.author.is_none());
+        assert_eq!(SearchTerms::parse("  "), SearchTerms::default());
+    }
+}
@@ -316,6 +316,17 @@ impl<'a> HighlightRepository<'a> {
         data: &CreateHighlight,
     ) -> Result<Highlight> {
         let id = Uuid::new_v4().to_string();
+        self.create_with_id(&id, book_id, user_id, data).await
+    }
+
+    /// Create a new highlight with a caller-chosen ID
+    pub async fn create_with_id(
+        &self,
+        id: &str,
+        book_id: &str,
+        user_id: Option<&str>,
+        data: &CreateHighlight,
+    ) -> Result<Highlight> {
         let now = Utc::now().to_rfc3339();
         let color = data.color.as_deref().unwrap_or("yellow");
         let format = data.document_format.as_deref().unwrap_or("epub");
@@ -344,7 +355,7 @@ impl<'a> HighlightRepository<'a> {
             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
-        .bind(&id)
+        .bind(id)
         .bind(book_id)
         .bind(user_id)
         .bind(format)
@@ -368,7 +379,7 @@ impl<'a> HighlightRepository<'a> {
         .execute(self.pool)
         .await?;
 
-        self.get(&id)
+        self.get(id)
             .await?
             .ok_or_else(|| crate::error::AppError::Internal("Failed to fetch created highlight".to_string()))
     }
@@ -3,11 +3,15 @@
 //! Handles reading progress, highlights, library metadata storage,
 //! and full-text search via FTS5.
 
+mod books;
+mod catalog;
 mod highlights;
 mod progress;
 mod schema;
 pub mod search;
 
+pub use books::*;
+pub use catalog::*;
 pub use highlights::*;
 pub use progress::*;
 pub use schema::*;
@@ -101,6 +101,45 @@ async fn run_migrations(pool: &SqlitePool) -> Result<()> {
             .await?;
     }
 
+    // Migration: Add upload_offset to upload_sessions (tus uploads)
+    let columns: Vec<(String,)> = sqlx::query_as(
+        "SELECT name FROM pragma_table_info('upload_sessions')"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    if !columns.iter().any(|(n,)| n == "upload_offset") {
+        sqlx::query("ALTER TABLE upload_sessions ADD COLUMN upload_offset INTEGER NOT NULL DEFAULT 0")
+            .execute(pool)
+            .await?;
+    }
+
+    // Migration: Add direct_upload_id to upload_sessions (presigned multipart uploads)
+    if !columns.iter().any(|(n,)| n == "direct_upload_id") {
+        sqlx::query("ALTER TABLE upload_sessions ADD COLUMN direct_upload_id TEXT")
+            .execute(pool)
+            .await?;
+    }
+
+    // Migration: Add rating and custom columns to library_books (Calibre import)
+    let columns: Vec<(String,)> = sqlx::query_as(
+        "SELECT name FROM pragma_table_info('library_books')"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    if !columns.iter().any(|(n,)| n == "rating") {
+        sqlx::query("ALTER TABLE library_books ADD COLUMN rating REAL")
+            .execute(pool)
+            .await?;
+    }
+
+    if !columns.iter().any(|(n,)| n == "custom_columns") {
+        sqlx::query("ALTER TABLE library_books ADD COLUMN custom_columns TEXT NOT NULL DEFAULT '{}'")
+            .execute(pool)
+            .await?;
+    }
+
     Ok(())
 }
 
@@ -122,6 +161,58 @@ CREATE TABLE IF NOT EXISTS books (
     updated_at TEXT NOT NULL DEFAULT (datetime('now'))
 );
 
+-- Library catalog (books found by scanning storage, plus ingested uploads)
+CREATE TABLE IF NOT EXISTS library_books (
+    id TEXT PRIMARY KEY,
+    -- Book folder in storage (Author/Title)
+    s3_prefix TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    author TEXT,
+    author_sort TEXT,
+    -- JSON arrays/objects
+    authors TEXT NOT NULL DEFAULT '[]',
+    publisher TEXT,
+    pubdate TEXT,
+    language TEXT,
+    description TEXT,
+    series TEXT,
+    series_index REAL,
+    tags TEXT NOT NULL DEFAULT '[]',
+    identifiers TEXT NOT NULL DEFAULT '{}',
+    formats TEXT NOT NULL DEFAULT '[]',
+    cover_key TEXT,
+    -- Stars out of 5
+    rating REAL,
+    -- JSON object of Calibre custom columns by lookup name
+    custom_columns TEXT NOT NULL DEFAULT '{}',
+    added_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Metadata edits to catalog books (history and revert)
+CREATE TABLE IF NOT EXISTS library_book_edits (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_id TEXT NOT NULL,
+    -- JSON array of changed field names
+    fields TEXT NOT NULL,
+    -- JSON metadata snapshots
+    before TEXT NOT NULL,
+    after TEXT NOT NULL,
+    -- Edit this one reverted, if any
+    reverts INTEGER,
+    created_at TEXT NOT NULL
+);
+
+-- Storage objects seen by the last library scan (for incremental refresh)
+CREATE TABLE IF NOT EXISTS library_objects (
+    key TEXT PRIMARY KEY,
+    -- Book folder the object belongs to
+    prefix TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    etag TEXT,
+    last_modified TEXT
+);
+
 -- Upload sessions table (for resumable uploads)
 CREATE TABLE IF NOT EXISTS upload_sessions (
     id TEXT PRIMARY KEY,
@@ -135,7 +226,9 @@ CREATE TABLE IF NOT EXISTS upload_sessions (
     status TEXT NOT NULL DEFAULT 'pending',
     user_id TEXT,
     created_at TEXT NOT NULL DEFAULT (datetime('now')),
-    expires_at TEXT NOT NULL
+    expires_at TEXT NOT NULL,
+    upload_offset INTEGER NOT NULL DEFAULT 0,
+    direct_upload_id TEXT
 );
 
 -- Reading progress table
@@ -245,6 +338,12 @@ const SCHEMA_INDEXES_SQL: &str = r#"
 CREATE INDEX IF NOT EXISTS idx_books_file_hash ON books(file_hash);
 CREATE INDEX IF NOT EXISTS idx_books_title ON books(title);
 
+CREATE INDEX IF NOT EXISTS idx_library_books_author ON library_books(author);
+CREATE INDEX IF NOT EXISTS idx_library_books_series ON library_books(series);
+CREATE INDEX IF NOT EXISTS idx_library_books_added_at ON library_books(added_at);
+CREATE INDEX IF NOT EXISTS idx_library_objects_prefix ON library_objects(prefix);
+CREATE INDEX IF NOT EXISTS idx_library_book_edits_book ON library_book_edits(book_id);
+
 CREATE INDEX IF NOT EXISTS idx_upload_sessions_file_hash ON upload_sessions(file_hash);
 CREATE INDEX IF NOT EXISTS idx_upload_sessions_status ON upload_sessions(status);
 CREATE INDEX IF NOT EXISTS idx_upload_sessions_expires ON upload_sessions(expires_at);
@@ -1,6 +1,7 @@
 //! FTS5 Full-Text Search for Books and Highlights
 //!
-//! Provides fast full-text search using SQLite's FTS5 extension.
+//! Provides fast full-text search using SQLite's FTS5 extension. Books
+//! are indexed from the library catalog (`library_books`).
 //! Performance: ~50x faster than LIKE queries on large datasets.
 //!
 //! # Usage
@@ -23,6 +24,13 @@ use sqlx::SqlitePool;
 
 use crate::error::Result;
 
+/// Comma-separated authors of a `library_books` row aliased `new`
+const FTS_AUTHORS: &str =
+    "COALESCE((SELECT group_concat(value, ', ') FROM json_each(new.authors)), new.author)";
+
+/// Comma-separated tags of a `library_books` row aliased `new`
+const FTS_TAGS: &str = "(SELECT group_concat(value, ', ') FROM json_each(new.tags))";
+
 /// FTS5 search result for books
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 #[serde(rename_all = "camelCase")]
@@ -77,15 +85,26 @@ impl<'a> FTS5Search<'a> {
 
     /// Initialize FTS5 virtual tables
     pub async fn initialize(&self) -> Result<()> {
-        // Create FTS5 table for books
+        // The books index used to mirror the `books` table; search now
+        // covers the library catalog instead
+        for statement in [
+            "DROP TRIGGER IF EXISTS books_fts_delete",
+            "DROP TRIGGER IF EXISTS books_fts_insert",
+            "DROP TRIGGER IF EXISTS books_fts_update",
+            "DROP TABLE IF EXISTS books_fts",
+        ] {
+            sqlx::query(statement).execute(self.pool).await?;
+        }
+
+        // Create FTS5 table for catalog books. Authors and tags are stored
+        // as JSON arrays, so the index keeps its own flattened copy.
         sqlx::query(
             r#"
-            CREATE VIRTUAL TABLE IF NOT EXISTS books_fts USING fts5(
+            CREATE VIRTUAL TABLE IF NOT EXISTS library_fts USING fts5(
                 title,
                 authors,
-                metadata,
-                content='books',
-                content_rowid='rowid',
+                series,
+                tags,
                 tokenize='unicode61 remove_diacritics 2'
             )
             "#,
@@ -109,7 +128,7 @@ impl<'a> FTS5Search<'a> {
         .execute(self.pool)
         .await?;
 
-        // Create triggers to keep FTS in sync with books table
+        // Create triggers to keep FTS in sync with the catalog
         self.create_books_triggers().await?;
 
         // Create triggers for highlights
@@ -118,14 +137,13 @@ impl<'a> FTS5Search<'a> {
         Ok(())
     }
 
-    /// Create triggers for books FTS synchronization
+    /// Create triggers for catalog FTS synchronization
     async fn create_books_triggers(&self) -> Result<()> {
         // Delete trigger
         sqlx::query(
             r#"
-            CREATE TRIGGER IF NOT EXISTS books_fts_delete AFTER DELETE ON books BEGIN
-                INSERT INTO books_fts(books_fts, rowid, title, authors, metadata)
-                VALUES('delete', old.rowid, old.title, old.authors, old.metadata);
+            CREATE TRIGGER IF NOT EXISTS library_fts_delete AFTER DELETE ON library_books BEGIN
+                DELETE FROM library_fts WHERE rowid = old.rowid;
             END
             "#,
         )
@@ -133,28 +151,29 @@ impl<'a> FTS5Search<'a> {
         .await?;
 
         // Insert trigger
-        sqlx::query(
+        sqlx::query(&format!(
             r#"
-            CREATE TRIGGER IF NOT EXISTS books_fts_insert AFTER INSERT ON books BEGIN
-                INSERT INTO books_fts(rowid, title, authors, metadata)
-                VALUES(new.rowid, new.title, new.authors, new.metadata);
+            CREATE TRIGGER IF NOT EXISTS library_fts_insert AFTER INSERT ON library_books BEGIN
+                INSERT INTO library_fts(rowid, title, authors, series, tags)
+                VALUES(new.rowid, new.title, {}, new.series, {});
             END
             "#,
-        )
+            FTS_AUTHORS, FTS_TAGS
+        ))
         .execute(self.pool)
         .await?;
 
         // Update trigger
-        sqlx::query(
+        sqlx::query(&format!(
             r#"
-            CREATE TRIGGER IF NOT EXISTS books_fts_update AFTER UPDATE ON books BEGIN
-                INSERT INTO books_fts(books_fts, rowid, title, authors, metadata)
-                VALUES('delete', old.rowid, old.title, old.authors, old.metadata);
-                INSERT INTO books_fts(rowid, title, authors, metadata)
-                VALUES(new.rowid, new.title, new.authors, new.metadata);
+            CREATE TRIGGER IF NOT EXISTS library_fts_update AFTER UPDATE ON library_books BEGIN
+                DELETE FROM library_fts WHERE rowid = old.rowid;
+                INSERT INTO library_fts(rowid, title, authors, series, tags)
+                VALUES(new.rowid, new.title, {}, new.series, {});
             END
             "#,
-        )
+            FTS_AUTHORS, FTS_TAGS
+        ))
         .execute(self.pool)
         .await?;
 
@@ -204,18 +223,21 @@ impl<'a> FTS5Search<'a> {
         Ok(())
     }
 
-    /// Rebuild the books FTS index from existing data
+    /// Rebuild the books FTS index from the library catalog
     pub async fn rebuild_books_index(&self) -> Result<usize> {
         // Clear existing index
-        sqlx::query("DELETE FROM books_fts").execute(self.pool).await?;
+        sqlx::query("DELETE FROM library_fts")
+            .execute(self.pool)
+            .await?;
 
-        // Rebuild from books table
-        let result = sqlx::query(
+        // Rebuild from library_books table
+        let result = sqlx::query(&format!(
             r#"
-            INSERT INTO books_fts(rowid, title, authors, metadata)
-            SELECT rowid, title, authors, metadata FROM books
+            INSERT INTO library_fts(rowid, title, authors, series, tags)
+            SELECT new.rowid, new.title, {}, new.series, {} FROM library_books new
             "#,
-        )
+            FTS_AUTHORS, FTS_TAGS
+        ))
         .execute(self.pool)
         .await?;
 
@@ -251,14 +273,14 @@ impl<'a> FTS5Search<'a> {
             SELECT
                 b.id,
                 b.title,
-                b.authors,
-                highlight(books_fts, 0, '<mark>', '</mark>') as title_highlight,
-                highlight(books_fts, 1, '<mark>', '</mark>') as authors_highlight,
-                books_fts.rank as rank
-            FROM books b
-            INNER JOIN books_fts ON b.rowid = books_fts.rowid
-            WHERE books_fts MATCH ?
-            ORDER BY books_fts.rank
+                library_fts.authors,
+                highlight(library_fts, 0, '<mark>', '</mark>') as title_highlight,
+                highlight(library_fts, 1, '<mark>', '</mark>') as authors_highlight,
+                library_fts.rank as rank
+            FROM library_books b
+            INNER JOIN library_fts ON b.rowid = library_fts.rowid
+            WHERE library_fts MATCH ?
+            ORDER BY library_fts.rank
             LIMIT ?
             "#,
         )
@@ -300,14 +322,14 @@ impl<'a> FTS5Search<'a> {
             SELECT
                 b.id,
                 b.title,
-                b.authors,
-                highlight(books_fts, 0, '<mark>', '</mark>') as title_highlight,
-                highlight(books_fts, 1, '<mark>', '</mark>') as authors_highlight,
-                books_fts.rank as rank
-            FROM books b
-            INNER JOIN books_fts ON b.rowid = books_fts.rowid
-            WHERE books_fts MATCH ?
-            ORDER BY books_fts.rank
+                library_fts.authors,
+                highlight(library_fts, 0, '<mark>', '</mark>') as title_highlight,
+                highlight(library_fts, 1, '<mark>', '</mark>') as authors_highlight,
+                library_fts.rank as rank
+            FROM library_books b
+            INNER JOIN library_fts ON b.rowid = library_fts.rowid
+            WHERE library_fts MATCH ?
+            ORDER BY library_fts.rank
             LIMIT ?
             "#,
         )
@@ -448,7 +470,7 @@ impl<'a> FTS5Search<'a> {
     /// Check if FTS5 tables exist
     pub async fn is_initialized(&self) -> Result<bool> {
         let result: Option<(String,)> = sqlx::query_as(
-            "SELECT name FROM sqlite_master WHERE type='table' AND name='books_fts'",
+            "SELECT name FROM sqlite_master WHERE type='table' AND name='library_fts'",
         )
         .fetch_optional(self.pool)
         .await?;
@@ -458,7 +480,7 @@ impl<'a> FTS5Search<'a> {
 
     /// Get FTS5 index statistics
     pub async fn get_stats(&self) -> Result<FTS5Stats> {
-        let books_count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM books_fts")
+        let books_count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM library_fts")
             .fetch_one(self.pool)
             .await?;
 
@@ -523,4 +545,36 @@ mod tests {
         assert_eq!(sanitize_fts5_query("test:value"), "testvalue");
         assert_eq!(sanitize_fts5_query("test\"quote"), "test\"\"quote");
     }
+
+    #[tokio::test]
+    async fn test_search_catalog_books() {
+        use crate::db::{initialize_schema, CatalogRepository};
+        use crate::library::LibraryBook;
+
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        initialize_schema(&pool).await.unwrap();
+        let fts = FTS5Search::new(&pool);
+        fts.initialize().await.unwrap();
+
+        let mut book = LibraryBook::new("Dune".to_string(), "Frank Herbert/Dune".to_string());
+        book.author = Some("Frank Herbert".to_string());
+        book.authors = vec!["Frank Herbert".to_string(), "Brian Herbert".to_string()];
+        let catalog = CatalogRepository::new(&pool);
+        catalog.upsert(&book).await.unwrap();
+
+        let results = fts.search_books("brian", 10).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, book.id);
+        assert_eq!(
+            results[0].authors.as_deref(),
+            Some("Frank Herbert, Brian Herbert")
+        );
+
+        // Updates replace the indexed row rather than adding another
+        book.title = "Dune Messiah".to_string();
+        catalog.upsert(&book).await.unwrap();
+        assert_eq!(fts.search_books("messiah", 10).await.unwrap().len(), 1);
+        assert_eq!(fts.get_stats().await.unwrap().books_indexed, 1);
+        assert_eq!(fts.rebuild_books_index().await.unwrap(), 1);
+    }
 }
@@ -3,6 +3,11 @@
 //! Provides a single caching layer for all document formats (PDF, EPUB).
 //! Uses LRU eviction for bounded memory usage.
 //!
+//! Reflowable documents can also be cached laid out with other
+//! [`LayoutConfig`]s than their own. Each layout is a separate variant with
+//! its own page count, parser and renderer; requests name the variant they
+//! want by passing the layout.
+//!
 //! # Thread Safety
 //!
 //! All caches use `tokio::sync::RwLock` for async-safe access.
@@ -17,8 +22,9 @@ use tokio::sync::RwLock;
 use tokio::time::{timeout, Duration};
 
 use super::{
-    DocumentError, DocumentParser, DocumentRenderer, DocumentResult, ImageFormat, ParsedDocument,
-    RenderRequest, RenderResult, SearchOptions, SearchResult, StructuredText,
+    ContentDocument, ContentPosition, DocumentError, DocumentParser, DocumentRenderer,
+    DocumentResult, ImageFormat, LayoutConfig, ParsedDocument, RenderRequest, RenderResult,
+    SearchOptions, SearchResult, StructuredText,
 };
 
 /// Timeout for document parsing operations
@@ -41,6 +47,8 @@ pub struct CacheConfig {
     pub max_renders: usize,
     /// Maximum number of structured text entries to cache
     pub max_stext: usize,
+    /// Maximum number of layout variants of reflowable documents to keep
+    pub max_layouts: usize,
 }
 
 impl Default for CacheConfig {
@@ -50,6 +58,7 @@ impl Default for CacheConfig {
             max_renderers: 50,
             max_renders: 500,
             max_stext: 1000,
+            max_layouts: 20,
         }
     }
 }
@@ -67,6 +76,8 @@ pub struct RenderCacheKey {
     pub rotation: u16,
     /// Output format
     pub format: ImageFormat,
+    /// Layout of a reflowable document (see [`LayoutConfig::key`])
+    pub layout: Option<[u32; 3]>,
 }
 
 impl RenderCacheKey {
@@ -78,21 +89,42 @@ impl RenderCacheKey {
             scale: (request.scale * 100.0) as u32,
             rotation: request.rotation as u16,
             format: request.format,
+            layout: request.layout.as_ref().map(LayoutConfig::key),
         }
     }
 
     /// Create a cache key for a thumbnail
-    pub fn thumbnail(doc_id: &str, item_index: usize, max_size: u32) -> Self {
+    pub fn thumbnail(
+        doc_id: &str,
+        item_index: usize,
+        max_size: u32,
+        layout: Option<&LayoutConfig>,
+    ) -> Self {
         Self {
             doc_id: doc_id.to_string(),
             item_index,
             scale: max_size,
             rotation: 0,
             format: ImageFormat::Jpeg,
+            layout: layout.map(LayoutConfig::key),
         }
     }
 }
 
+/// Key of a document laid out with a given layout
+type LayoutKey = (String, [u32; 3]);
+
+/// Key of cached structured text: document, item and layout
+type StextKey = (String, usize, Option<[u32; 3]>);
+
+/// A reflowable document laid out with a non-default layout
+#[derive(Clone)]
+struct LayoutVariant {
+    parsed: ParsedDocument,
+    parser: Arc<dyn DocumentParser>,
+    renderer: Arc<dyn DocumentRenderer>,
+}
+
 /// Unified document cache for all formats
 ///
 /// Stores parsed metadata, parser/renderer instances, and cached outputs
@@ -112,7 +144,10 @@ pub struct DocumentCache {
     render_cache: Arc<RwLock<LruCache<RenderCacheKey, Vec<u8>>>>,
 
     /// Structured text cache
-    stext_cache: Arc<RwLock<LruCache<(String, usize), StructuredText>>>,
+    stext_cache: Arc<RwLock<LruCache<StextKey, StructuredText>>>,
+
+    /// Layout variants of reflowable documents with LRU eviction
+    layouts: Arc<RwLock<LruCache<LayoutKey, LayoutVariant>>>,
 
     /// Configuration
     config: CacheConfig,
@@ -135,6 +170,8 @@ impl DocumentCache {
             .unwrap_or(NonZeroUsize::new(500).unwrap());
         let stext_size = NonZeroUsize::new(config.max_stext)
             .unwrap_or(NonZeroUsize::new(1000).unwrap());
+        let layouts_size = NonZeroUsize::new(config.max_layouts)
+            .unwrap_or(NonZeroUsize::new(20).unwrap());
 
         Self {
             documents: Arc::new(RwLock::new(HashMap::new())),
@@ -142,6 +179,7 @@ impl DocumentCache {
             renderers: Arc::new(RwLock::new(LruCache::new(renderers_size))),
             render_cache: Arc::new(RwLock::new(LruCache::new(renders_size))),
             stext_cache: Arc::new(RwLock::new(LruCache::new(stext_size))),
+            layouts: Arc::new(RwLock::new(LruCache::new(layouts_size))),
             config,
         }
     }
@@ -223,14 +261,77 @@ impl DocumentCache {
         renderers.get(doc_id).cloned()
     }
 
+    /// Store a document laid out with a non-default layout
+    pub async fn store_layout(
+        &self,
+        doc_id: String,
+        layout: LayoutConfig,
+        parsed: ParsedDocument,
+        parser: Arc<dyn DocumentParser>,
+        renderer: Arc<dyn DocumentRenderer>,
+    ) {
+        let mut layouts = self.layouts.write().await;
+        layouts.put(
+            (doc_id, layout.key()),
+            LayoutVariant {
+                parsed,
+                parser,
+                renderer,
+            },
+        );
+    }
+
+    /// Get a document laid out with `layout`: its parsed metadata, parser
+    /// and renderer
+    pub async fn get_layout(
+        &self,
+        doc_id: &str,
+        layout: &LayoutConfig,
+    ) -> Option<(ParsedDocument, Arc<dyn DocumentParser>, Arc<dyn DocumentRenderer>)> {
+        self.get_variant(doc_id, layout)
+            .await
+            .map(|variant| (variant.parsed, variant.parser, variant.renderer))
+    }
+
+    async fn get_variant(&self, doc_id: &str, layout: &LayoutConfig) -> Option<LayoutVariant> {
+        let mut layouts = self.layouts.write().await;
+        layouts.get(&(doc_id.to_string(), layout.key())).cloned()
+    }
+
+    /// Parser for a document, laid out with `layout` if given
+    async fn parser_for(
+        &self,
+        doc_id: &str,
+        layout: Option<&LayoutConfig>,
+    ) -> DocumentResult<Arc<dyn DocumentParser>> {
+        let parser = match layout {
+            Some(layout) => self.get_variant(doc_id, layout).await.map(|variant| variant.parser),
+            None => self.get_parser(doc_id).await,
+        };
+        parser.ok_or_else(|| not_cached(doc_id, layout))
+    }
+
+    /// Renderer for a document, laid out with `layout` if given
+    async fn renderer_for(
+        &self,
+        doc_id: &str,
+        layout: Option<&LayoutConfig>,
+    ) -> DocumentResult<Arc<dyn DocumentRenderer>> {
+        let renderer = match layout {
+            Some(layout) => self.get_variant(doc_id, layout).await.map(|variant| variant.renderer),
+            None => self.get_renderer(doc_id).await,
+        };
+        renderer.ok_or_else(|| not_cached(doc_id, layout))
+    }
+
     /// Extract text from a document item with caching
     pub async fn extract_text(
         &self,
         doc_id: &str,
         item_index: usize,
+        layout: Option<&LayoutConfig>,
     ) -> DocumentResult<String> {
-        let parser = self.get_parser(doc_id).await
-            .ok_or_else(|| DocumentError::NotFound(format!("Document {} not cached", doc_id)))?;
+        let parser = self.parser_for(doc_id, layout).await?;
 
         let result = timeout(
             Duration::from_secs(TEXT_TIMEOUT_SECS),
@@ -247,8 +348,9 @@ impl DocumentCache {
         &self,
         doc_id: &str,
         item_index: usize,
+        layout: Option<&LayoutConfig>,
     ) -> DocumentResult<StructuredText> {
-        let cache_key = (doc_id.to_string(), item_index);
+        let cache_key = (doc_id.to_string(), item_index, layout.map(LayoutConfig::key));
 
         // Check cache first
         {
@@ -259,8 +361,7 @@ impl DocumentCache {
         }
 
         // Get parser and extract
-        let parser = self.get_parser(doc_id).await
-            .ok_or_else(|| DocumentError::NotFound(format!("Document {} not cached", doc_id)))?;
+        let parser = self.parser_for(doc_id, layout).await?;
 
         let result = timeout(
             Duration::from_secs(TEXT_TIMEOUT_SECS),
@@ -284,9 +385,9 @@ impl DocumentCache {
         doc_id: &str,
         query: &str,
         options: SearchOptions,
+        layout: Option<&LayoutConfig>,
     ) -> DocumentResult<Vec<SearchResult>> {
-        let parser = self.get_parser(doc_id).await
-            .ok_or_else(|| DocumentError::NotFound(format!("Document {} not cached", doc_id)))?;
+        let parser = self.parser_for(doc_id, layout).await?;
 
         let result = timeout(
             Duration::from_secs(SEARCH_TIMEOUT_SECS),
@@ -298,7 +399,48 @@ impl DocumentCache {
         result
     }
 
+    /// Where each laid-out page starts in the document's content
+    pub async fn content_positions(
+        &self,
+        doc_id: &str,
+        layout: Option<&LayoutConfig>,
+    ) -> DocumentResult<Vec<ContentPosition>> {
+        let parser = self.parser_for(doc_id, layout).await?;
+
+        let result = timeout(
+            Duration::from_secs(PARSE_TIMEOUT_SECS),
+            parser.content_positions(),
+        )
+        .await
+        .map_err(|_| DocumentError::Timeout(PARSE_TIMEOUT_SECS))?;
+
+        result
+    }
+
+    /// Content document of a spine item
+    ///
+    /// Content documents don't depend on the layout, so the document's own
+    /// parser is used.
+    pub async fn content_document(
+        &self,
+        doc_id: &str,
+        spine_index: usize,
+    ) -> DocumentResult<ContentDocument> {
+        let parser = self.parser_for(doc_id, None).await?;
+
+        let result = timeout(
+            Duration::from_secs(PARSE_TIMEOUT_SECS),
+            parser.content_document(spine_index),
+        )
+        .await
+        .map_err(|_| DocumentError::Timeout(PARSE_TIMEOUT_SECS))?;
+
+        result
+    }
+
     /// Render an item with caching
+    ///
+    /// Uses the layout variant named by `request.layout`, if any.
     pub async fn render(
         &self,
         doc_id: &str,
@@ -320,8 +462,7 @@ impl DocumentCache {
         }
 
         // Get renderer and render
-        let renderer = self.get_renderer(doc_id).await
-            .ok_or_else(|| DocumentError::NotFound(format!("Document {} not cached", doc_id)))?;
+        let renderer = self.renderer_for(doc_id, request.layout.as_ref()).await?;
 
         let result = timeout(
             Duration::from_secs(RENDER_TIMEOUT_SECS),
@@ -345,8 +486,9 @@ impl DocumentCache {
         doc_id: &str,
         item_index: usize,
         max_size: u32,
+        layout: Option<&LayoutConfig>,
     ) -> DocumentResult<RenderResult> {
-        let cache_key = RenderCacheKey::thumbnail(doc_id, item_index, max_size);
+        let cache_key = RenderCacheKey::thumbnail(doc_id, item_index, max_size, layout);
 
         // Check cache first
         {
@@ -362,8 +504,7 @@ impl DocumentCache {
         }
 
         // Get renderer and render
-        let renderer = self.get_renderer(doc_id).await
-            .ok_or_else(|| DocumentError::NotFound(format!("Document {} not cached", doc_id)))?;
+        let renderer = self.renderer_for(doc_id, layout).await?;
 
         let result = timeout(
             Duration::from_secs(RENDER_TIMEOUT_SECS),
@@ -417,15 +558,28 @@ impl DocumentCache {
         // Remove cached structured text
         {
             let mut cache = self.stext_cache.write().await;
-            let keys_to_remove: Vec<(String, usize)> = cache
+            let keys_to_remove: Vec<StextKey> = cache
                 .iter()
-                .filter(|((id, _), _)| id == doc_id)
+                .filter(|((id, _, _), _)| id == doc_id)
                 .map(|(k, _)| k.clone())
                 .collect();
             for key in keys_to_remove {
                 cache.pop(&key);
             }
         }
+
+        // Remove layout variants
+        {
+            let mut layouts = self.layouts.write().await;
+            let keys_to_remove: Vec<LayoutKey> = layouts
+                .iter()
+                .filter(|((id, _), _)| id == doc_id)
+                .map(|(k, _)| k.clone())
+                .collect();
+            for key in keys_to_remove {
+                layouts.pop(&key);
+            }
+        }
     }
 
     /// Clear all caches
@@ -450,6 +604,10 @@ impl DocumentCache {
             let mut cache = self.stext_cache.write().await;
             cache.clear();
         }
+        {
+            let mut layouts = self.layouts.write().await;
+            layouts.clear();
+        }
     }
 
     /// Get the number of cached documents
@@ -501,6 +659,16 @@ impl DocumentCache {
     }
 }
 
+fn not_cached(doc_id: &str, layout: Option<&LayoutConfig>) -> DocumentError {
+    match layout {
+        Some(layout) => DocumentError::NotFound(format!(
+            "Document {} not cached with layout {}x{} at {}em",
+            doc_id, layout.width, layout.height, layout.em
+        )),
+        None => DocumentError::NotFound(format!("Document {} not cached", doc_id)),
+    }
+}
+
 /// Cache statistics
 #[derive(Debug, Clone)]
 pub struct CacheStats {
@@ -542,6 +710,7 @@ mod tests {
             max_renderers: 10,
             max_renders: 100,
             max_stext: 200,
+            max_layouts: 5,
         };
         let cache = DocumentCache::new(config);
         let stats = cache.stats().await;
@@ -561,6 +730,7 @@ mod tests {
             format: ImageFormat::Png,
             clip: None,
             background: None,
+            layout: None,
         };
         let key = RenderCacheKey::new("doc-123", &request);
 
@@ -568,11 +738,27 @@ mod tests {
         assert_eq!(key.item_index, 5);
         assert_eq!(key.scale, 150); // 1.5 * 100
         assert_eq!(key.rotation, 90);
+        assert_eq!(key.layout, None);
+
+        let layout = LayoutConfig {
+            width: 390.5,
+            height: 844.0,
+            em: 16.0,
+        };
+        let laid_out = RenderCacheKey::new(
+            "doc-123",
+            &RenderRequest {
+                layout: Some(layout),
+                ..request
+            },
+        );
+        assert_eq!(laid_out.layout, Some([39050, 84400, 1600]));
+        assert_ne!(laid_out, key);
     }
 
     #[tokio::test]
     async fn test_thumbnail_cache_key() {
-        let key = RenderCacheKey::thumbnail("doc-456", 0, 256);
+        let key = RenderCacheKey::thumbnail("doc-456", 0, 256, None);
 
         assert_eq!(key.doc_id, "doc-456");
         assert_eq!(key.item_index, 0);
@@ -70,6 +70,10 @@ pub enum DocumentError {
     /// Image processing error
     #[error("Image error: {0}")]
     ImageError(String),
+
+    /// Format conversion error
+    #[error("Conversion error: {0}")]
+    ConversionError(String),
 }
 
 /// Result type alias for document operations
@@ -45,7 +45,7 @@
 //! }).await?;
 //!
 //! // Get structured text
-//! let stext = cache.get_structured_text(&doc.id, 0).await?;
+//! let stext = cache.get_structured_text(&doc.id, 0, None).await?;
 //! ```
 
 mod cache;
@@ -57,7 +57,7 @@ pub use cache::{CacheConfig, CacheStats, DocumentCache, RenderCacheKey as CacheR
 pub use error::{DocumentError, DocumentResult, Result};
 pub use traits::{Document, DocumentParser, DocumentRenderer, RenderCacheKey};
 pub use types::{
-    BoundingBox, CharPosition, Creator, DocumentFormat, DocumentMetadata, ImageFormat,
-    ParsedDocument, Rect, RenderRequest, RenderResult, Resource, SearchOptions, SearchResult,
+    BoundingBox, CharPosition, ContentDocument, ContentPosition, Creator, DocumentFormat, DocumentMetadata,
+    ImageFormat, LayoutConfig, ParsedDocument, Rect, RenderRequest, RenderResult, Resource, SearchOptions, SearchResult,
     StructuredText, TextBlock, TextDirection, TextLine, TocEntry,
 };
@@ -4,9 +4,9 @@
 
 use async_trait::async_trait;
 
-use super::error::Result;
+use super::error::{DocumentError, Result};
 use super::types::{
-    ParsedDocument, RenderRequest, RenderResult, Resource, SearchOptions, SearchResult,
+    ContentDocument, ContentPosition, ParsedDocument, RenderRequest, RenderResult, Resource, SearchOptions, SearchResult,
     StructuredText, TocEntry,
 };
 
@@ -36,6 +36,24 @@ pub trait DocumentParser: Send + Sync {
 
     /// Get item dimensions (page size)
     fn get_item_dimensions(&self, item_index: usize) -> Result<(f32, f32)>;
+
+    /// Where each laid-out page starts in the source content
+    ///
+    /// Only reflowable documents with addressable content (EPUB) have
+    /// positions; other formats return none.
+    async fn content_positions(&self) -> Result<Vec<ContentPosition>> {
+        Ok(Vec::new())
+    }
+
+    /// Content document of a spine item
+    ///
+    /// Only formats whose source is a package of XHTML documents (EPUB)
+    /// have content documents.
+    async fn content_document(&self, _spine_index: usize) -> Result<ContentDocument> {
+        Err(DocumentError::UnsupportedFormat(
+            "document has no content documents".to_string(),
+        ))
+    }
 }
 
 /// Format-agnostic document renderer
@@ -10,6 +10,18 @@ use serde::{Deserialize, Serialize};
 pub enum DocumentFormat {
     Pdf,
     Epub,
+    /// FictionBook 2 (XML)
+    Fb2,
+    /// Mobipocket, including Kindle AZW/AZW3
+    Mobi,
+    /// XPS and OpenXPS
+    Xps,
+    /// Comic book ZIP archive
+    Cbz,
+    /// Comic book RAR archive
+    Cbr,
+    /// Comic book 7-Zip archive
+    Cb7,
 }
 
 impl DocumentFormat {
@@ -18,6 +30,12 @@ impl DocumentFormat {
         match ext.to_lowercase().as_str() {
             "pdf" => Some(Self::Pdf),
             "epub" => Some(Self::Epub),
+            "fb2" => Some(Self::Fb2),
+            "mobi" | "azw" | "azw3" | "prc" => Some(Self::Mobi),
+            "xps" | "oxps" => Some(Self::Xps),
+            "cbz" => Some(Self::Cbz),
+            "cbr" => Some(Self::Cbr),
+            "cb7" => Some(Self::Cb7),
             _ => None,
         }
     }
@@ -27,16 +45,68 @@ impl DocumentFormat {
         match mime {
             "application/pdf" => Some(Self::Pdf),
             "application/epub+zip" => Some(Self::Epub),
+            "application/x-fictionbook+xml" | "application/x-fictionbook" => Some(Self::Fb2),
+            "application/x-mobipocket-ebook" | "application/vnd.amazon.mobi8-ebook" => {
+                Some(Self::Mobi)
+            }
+            "application/vnd.ms-xpsdocument" | "application/oxps" => Some(Self::Xps),
+            "application/vnd.comicbook+zip" | "application/x-cbz" => Some(Self::Cbz),
+            "application/vnd.comicbook-rar" | "application/x-cbr" => Some(Self::Cbr),
+            "application/x-cb7" => Some(Self::Cb7),
             _ => None,
         }
     }
 
+    /// Canonical MIME type
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Pdf => "application/pdf",
+            Self::Epub => "application/epub+zip",
+            Self::Fb2 => "application/x-fictionbook+xml",
+            Self::Mobi => "application/x-mobipocket-ebook",
+            Self::Xps => "application/vnd.ms-xpsdocument",
+            Self::Cbz => "application/vnd.comicbook+zip",
+            Self::Cbr => "application/vnd.comicbook-rar",
+            Self::Cb7 => "application/x-cb7",
+        }
+    }
+
+    /// File extension, without the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Pdf => "pdf",
+            Self::Epub => "epub",
+            Self::Fb2 => "fb2",
+            Self::Mobi => "mobi",
+            Self::Xps => "xps",
+            Self::Cbz => "cbz",
+            Self::Cbr => "cbr",
+            Self::Cb7 => "cb7",
+        }
+    }
+
+    /// Whether this is a comic book archive (one image per page)
+    pub fn is_comic(&self) -> bool {
+        matches!(self, Self::Cbz | Self::Cbr | Self::Cb7)
+    }
+
+    /// Whether content reflows into pages of a chosen size (EPUB, FB2,
+    /// MOBI) rather than having fixed pages
+    pub fn is_reflowable(&self) -> bool {
+        matches!(self, Self::Epub | Self::Fb2 | Self::Mobi)
+    }
+
     /// Detect format from magic bytes
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
         Self::from_magic_bytes(bytes)
     }
 
     /// Detect format from magic bytes (alias for from_bytes)
+    ///
+    /// RAR and 7-Zip archives are taken to be comics. ZIPs are told apart
+    /// by their entries (EPUB `mimetype`, XPS `.fdseq` document sequence);
+    /// any other ZIP is not recognized, since the bytes alone can't tell a
+    /// CBZ from any other ZIP; pair this with the file extension.
     pub fn from_magic_bytes(bytes: &[u8]) -> Option<Self> {
         if bytes.len() < 4 {
             return None;
@@ -47,16 +117,43 @@ impl DocumentFormat {
             return Some(Self::Pdf);
         }
 
-        // EPUB magic: PK (ZIP) with mimetype containing "epub"
-        // Note: We don't assume all ZIPs are EPUBs to avoid false positives
-        // with .docx, .xlsx, .apk, .jar and other ZIP-based formats
+        // RAR magic: Rar!\x1a\x07 (RAR 4 and 5)
+        if bytes.starts_with(b"Rar!\x1a\x07") {
+            return Some(Self::Cbr);
+        }
+
+        // 7-Zip magic: 7z\xbc\xaf\x27\x1c
+        if bytes.starts_with(b"7z\xbc\xaf\x27\x1c") {
+            return Some(Self::Cb7);
+        }
+
+        // MOBI/AZW3: PalmDB header with type and creator "BOOKMOBI" at
+        // offset 60
+        if bytes.get(60..68) == Some(b"BOOKMOBI".as_slice()) {
+            return Some(Self::Mobi);
+        }
+
+        // FB2: XML with a <FictionBook> root near the start. The prolog is
+        // ASCII whatever the declared encoding (often windows-1251), so a
+        // lossy look at the first bytes is enough.
+        let head = String::from_utf8_lossy(&bytes[..bytes.len().min(FB2_SNIFF_LENGTH)]);
+        let xml = head.trim_start_matches('\u{feff}').trim_start();
+        if xml.starts_with('<') && head.contains("<FictionBook") {
+            return Some(Self::Fb2);
+        }
+
         if bytes.starts_with(b"PK") && bytes.len() > 30 {
-            // Check for "application/epub+zip" or "epub" in first 58 bytes
+            // EPUB magic: PK (ZIP) with mimetype containing "epub"
+            // Note: We don't assume all ZIPs are EPUBs to avoid false positives
+            // with .docx, .xlsx, .apk, .jar and other ZIP-based formats
             // (EPUB files have a mimetype file at offset 30 with "application/epub+zip")
-            if let Ok(s) = std::str::from_utf8(&bytes[..bytes.len().min(58)]) {
-                if s.contains("epub") {
-                    return Some(Self::Epub);
-                }
+            // (the local header before it holds a binary CRC and timestamp)
+            if bytes[..bytes.len().min(58)].windows(4).any(|w| w == b"epub") {
+                return Some(Self::Epub);
+            }
+
+            if is_xps(bytes) {
+                return Some(Self::Xps);
             }
             // Don't assume - return None for unknown ZIP files
         }
@@ -65,6 +162,20 @@ impl DocumentFormat {
     }
 }
 
+/// Bytes searched for an FB2 root element (past the XML declaration and
+/// any comments or stylesheet instructions)
+const FB2_SNIFF_LENGTH: usize = 1024;
+
+/// Whether a ZIP is an XPS document: XPS packages have a fixed document
+/// sequence part (`.fdseq`)
+fn is_xps(bytes: &[u8]) -> bool {
+    zip::ZipArchive::new(std::io::Cursor::new(bytes)).is_ok_and(|archive| {
+        archive
+            .file_names()
+            .any(|name| name.to_ascii_lowercase().ends_with(".fdseq"))
+    })
+}
+
 /// Parsed document metadata and structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -77,7 +188,7 @@ pub struct ParsedDocument {
     pub metadata: DocumentMetadata,
     /// Table of contents
     pub toc: Vec<TocEntry>,
-    /// Number of items (pages for PDF, chapters for EPUB)
+    /// Number of items (pages for PDF and comics, chapters for EPUB)
     pub item_count: usize,
     /// Item labels (page numbers, chapter titles)
     pub item_labels: Option<Vec<String>>,
@@ -109,6 +220,12 @@ pub struct DocumentMetadata {
     pub rights: Option<String>,
     /// Subject tags
     pub subjects: Vec<String>,
+    /// Series name (comics' ComicInfo.xml)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series: Option<String>,
+    /// Position in the series
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series_index: Option<f32>,
 }
 
 /// Document creator (author, editor, etc.)
@@ -309,6 +426,77 @@ impl SearchOptions {
     }
 }
 
+/// Default layout width for reflowable documents (points)
+const DEFAULT_LAYOUT_WIDTH: f32 = 800.0;
+
+/// Default layout height for reflowable documents (points)
+const DEFAULT_LAYOUT_HEIGHT: f32 = 600.0;
+
+/// Default em size for reflowable text layout (points)
+const DEFAULT_EM_SIZE: f32 = 12.0;
+
+/// Layout configuration for reflowable documents
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LayoutConfig {
+    /// Page width in points
+    pub width: f32,
+    /// Page height in points
+    pub height: f32,
+    /// Em size for font scaling
+    pub em: f32,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            width: DEFAULT_LAYOUT_WIDTH,
+            height: DEFAULT_LAYOUT_HEIGHT,
+            em: DEFAULT_EM_SIZE,
+        }
+    }
+}
+
+impl LayoutConfig {
+    /// Width, height and em in hundredths of a point, for hashing
+    pub fn key(&self) -> [u32; 3] {
+        [
+            (self.width * 100.0) as u32,
+            (self.height * 100.0) as u32,
+            (self.em * 100.0) as u32,
+        ]
+    }
+}
+
+/// Where a laid-out page of a reflowable document starts in its content
+///
+/// The path and offset locate the first text on the page inside the spine
+/// item's content document, in EPUB CFI terms.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentPosition {
+    /// Laid-out page index
+    pub item_index: usize,
+    /// Spine item the page starts in
+    pub spine_index: usize,
+    /// CFI steps from the content document's root element to the text node
+    pub path: Vec<u32>,
+    /// Offset into the text node, in UTF-16 code units
+    pub offset: u32,
+    /// How far through the spine item's text the page starts (0.0 to 1.0)
+    pub progression: f32,
+}
+
+/// A spine item's content document, as stored in the package
+#[derive(Debug, Clone)]
+pub struct ContentDocument {
+    /// Spine index of the item
+    pub spine_index: usize,
+    /// Path of the content document inside the package
+    pub href: String,
+    /// The document's markup (XHTML)
+    pub content: String,
+}
+
 /// Render request
 #[derive(Debug, Clone)]
 pub struct RenderRequest {
@@ -324,6 +512,8 @@ pub struct RenderRequest {
     pub clip: Option<Rect>,
     /// Background color (RGBA)
     pub background: Option<[u8; 4]>,
+    /// Layout of a reflowable document (the handler's own when `None`)
+    pub layout: Option<LayoutConfig>,
 }
 
 impl Default for RenderRequest {
@@ -335,6 +525,7 @@ impl Default for RenderRequest {
             rotation: 0,
             clip: None,
             background: None,
+            layout: None,
         }
     }
 }
@@ -409,3 +600,89 @@ impl ImageFormat {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            // Stored, as an EPUB's mimetype must be
+            let options = SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Stored);
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    /// PalmDB header: 32-byte name, then attributes, dates and counters up
+    /// to the type/creator at offset 60
+    fn palm_db(type_creator: &[u8; 8]) -> Vec<u8> {
+        let mut data = vec![0u8; 78];
+        data[..8].copy_from_slice(b"The_Book");
+        data[60..68].copy_from_slice(type_creator);
+        data
+    }
+
+    #[test]
+    fn test_sniff_fb2() {
+        let utf8 = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+            <FictionBook xmlns=\"http://www.gribuser.ru/xml/fictionbook/2.0\">\
+            <description><title-info><book-title>Мастер и Маргарита</book-title>";
+        assert_eq!(
+            DocumentFormat::from_magic_bytes(utf8.as_bytes()),
+            Some(DocumentFormat::Fb2)
+        );
+
+        // windows-1251 body behind a BOM-less prolog, and a BOM'd UTF-8 file
+        let mut cp1251 = b"<?xml version=\"1.0\" encoding=\"windows-1251\"?>\r\n<FictionBook>".to_vec();
+        cp1251.extend_from_slice(&[0xcc, 0xe0, 0xf1, 0xf2, 0xe5, 0xf0]);
+        assert_eq!(DocumentFormat::from_magic_bytes(&cp1251), Some(DocumentFormat::Fb2));
+        let bom = [b"\xef\xbb\xbf".as_slice(), utf8.as_bytes()].concat();
+        assert_eq!(DocumentFormat::from_magic_bytes(&bom), Some(DocumentFormat::Fb2));
+
+        // Other XML isn't FB2
+        let svg = b"<?xml version=\"1.0\"?><svg xmlns=\"http://www.w3.org/2000/svg\"/>";
+        assert_eq!(DocumentFormat::from_magic_bytes(svg), None);
+    }
+
+    #[test]
+    fn test_sniff_mobi() {
+        assert_eq!(
+            DocumentFormat::from_magic_bytes(&palm_db(b"BOOKMOBI")),
+            Some(DocumentFormat::Mobi)
+        );
+        // Other PalmDB databases aren't
+        assert_eq!(DocumentFormat::from_magic_bytes(&palm_db(b"DATAPALM")), None);
+        assert_eq!(DocumentFormat::from_extension("azw3"), Some(DocumentFormat::Mobi));
+    }
+
+    #[test]
+    fn test_sniff_xps() {
+        let xps = zip(&[
+            ("[Content_Types].xml", b"<Types/>"),
+            ("_rels/.rels", b"<Relationships/>"),
+            ("FixedDocSeq.fdseq", b"<FixedDocumentSequence/>"),
+            ("Documents/1/FixedDoc.fdoc", b"<FixedDocument/>"),
+        ]);
+        assert_eq!(DocumentFormat::from_magic_bytes(&xps), Some(DocumentFormat::Xps));
+
+        // A ZIP of images is left to the extension (CBZ)
+        let cbz = zip(&[("001.jpg", b"\xff\xd8\xff"), ("002.jpg", b"\xff\xd8\xff")]);
+        assert_eq!(DocumentFormat::from_magic_bytes(&cbz), None);
+    }
+
+    #[test]
+    fn test_sniff_pdf_and_epub() {
+        assert_eq!(
+            DocumentFormat::from_magic_bytes(b"%PDF-1.7\n"),
+            Some(DocumentFormat::Pdf)
+        );
+        let epub = zip(&[("mimetype", b"application/epub+zip")]);
+        assert_eq!(DocumentFormat::from_magic_bytes(&epub), Some(DocumentFormat::Epub));
+    }
+}
@@ -59,6 +59,12 @@ pub enum StorageError {
 
     #[error("S3 SDK error: {0}")]
     SdkError(String),
+
+    #[error("Invalid object key: {0}")]
+    InvalidKey(String),
+
+    #[error("Not supported by this storage backend: {0}")]
+    Unsupported(String),
 }
 
 /// Error response body
@@ -97,6 +103,14 @@ impl IntoResponse for AppError {
                         "access_denied",
                         "Access denied".to_string(),
                     ),
+                    StorageError::InvalidKey(key) => {
+                        (StatusCode::BAD_REQUEST, "bad_request", format!("Invalid object key: {}", key))
+                    }
+                    StorageError::Unsupported(operation) => (
+                        StatusCode::NOT_IMPLEMENTED,
+                        "not_implemented",
+                        format!("Not supported by this storage backend: {}", operation),
+                    ),
                     _ => (
                         StatusCode::INTERNAL_SERVER_ERROR,
                         "storage_error",
@@ -0,0 +1,391 @@
+//! Comic book archive access
+//!
+//! Pages are the archive's image entries in natural order (`page2.jpg`
+//! before `page10.jpg`), skipping macOS resource forks and hidden files.
+//! The archive type is sniffed from the bytes rather than taken from the
+//! extension, since plenty of `.cbr` files are really ZIPs. Real RAR
+//! archives need the `cbr` feature.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+
+use sevenz_rust::{Password, SevenZReader};
+use zip::ZipArchive;
+
+use crate::document::{DocumentError, DocumentResult};
+
+/// Image extensions treated as pages
+const PAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "bmp"];
+
+/// ComicInfo.xml, the ComicRack metadata file
+const COMIC_INFO: &str = "comicinfo.xml";
+
+/// Largest entry read out of an archive; sizes in headers can't be trusted
+const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Largest buffer reserved up front from an entry's declared size
+const PREALLOCATE_LIMIT: u64 = 8 * 1024 * 1024;
+
+enum ArchiveKind {
+    Zip,
+    /// Entries decompressed up front: 7-Zip and RAR archives are usually
+    /// solid, so single entries can't be read without decoding everything
+    /// before them
+    Unpacked(HashMap<String, Vec<u8>>),
+}
+
+/// A comic archive's pages and ComicInfo.xml
+pub struct ComicArchive {
+    data: Arc<Vec<u8>>,
+    kind: ArchiveKind,
+    pages: Vec<String>,
+    comic_info: Option<Vec<u8>>,
+}
+
+impl ComicArchive {
+    /// Open a ZIP (CBZ), 7-Zip (CB7) or RAR (CBR) archive
+    ///
+    /// RAR archives can only be read with the `cbr` feature; without it
+    /// they need repacking as CBZ.
+    pub fn from_bytes(data: Vec<u8>) -> DocumentResult<Self> {
+        let data = Arc::new(data);
+        if data.starts_with(b"Rar!\x1a\x07") {
+            Self::open_rar(data)
+        } else if data.starts_with(b"7z\xbc\xaf\x27\x1c") {
+            Self::open_7z(data)
+        } else if data.starts_with(b"PK") {
+            Self::open_zip(data)
+        } else {
+            Err(DocumentError::InvalidContent(
+                "Not a ZIP or 7-Zip comic archive".to_string(),
+            ))
+        }
+    }
+
+    fn open_zip(data: Arc<Vec<u8>>) -> DocumentResult<Self> {
+        let mut zip = ZipArchive::new(Cursor::new(data.as_slice()))
+            .map_err(|e| DocumentError::ParseError(format!("Invalid ZIP archive: {}", e)))?;
+        let names: Vec<String> = zip.file_names().map(str::to_string).collect();
+
+        let comic_info = match names.iter().find(|name| is_comic_info(name)) {
+            Some(name) => Some(read_zip_entry(&mut zip, name)?),
+            None => None,
+        };
+
+        Ok(Self {
+            pages: page_names(names),
+            comic_info,
+            kind: ArchiveKind::Zip,
+            data,
+        })
+    }
+
+    fn open_7z(data: Arc<Vec<u8>>) -> DocumentResult<Self> {
+        let mut reader = SevenZReader::new(
+            Cursor::new(data.as_slice()),
+            data.len() as u64,
+            Password::empty(),
+        )
+        .map_err(|e| DocumentError::ParseError(format!("Invalid 7-Zip archive: {}", e)))?;
+
+        let mut entries = HashMap::new();
+        let mut comic_info = None;
+        let mut failed = None;
+        reader
+            .for_each_entries(|entry, content| {
+                let name = entry.name();
+                if entry.is_directory() || !(is_page(name) || is_comic_info(name)) {
+                    // Solid blocks still have to be read through
+                    std::io::copy(content, &mut std::io::sink())?;
+                    return Ok(true);
+                }
+
+                let buffer = match read_limited(content, name, entry.size(), MAX_ENTRY_SIZE) {
+                    Ok(buffer) => buffer,
+                    Err(e) => {
+                        failed = Some(e);
+                        return Ok(false);
+                    }
+                };
+                if is_comic_info(name) {
+                    comic_info.get_or_insert(buffer);
+                } else {
+                    entries.insert(name.to_string(), buffer);
+                }
+                Ok(true)
+            })
+            .map_err(|e| DocumentError::ParseError(format!("Failed to read 7-Zip archive: {}", e)))?;
+        if let Some(e) = failed {
+            return Err(e);
+        }
+
+        Ok(Self {
+            pages: page_names(entries.keys().cloned().collect()),
+            comic_info,
+            kind: ArchiveKind::Unpacked(entries),
+            data,
+        })
+    }
+
+    #[cfg(feature = "cbr")]
+    fn open_rar(data: Arc<Vec<u8>>) -> DocumentResult<Self> {
+        use std::io::Write;
+
+        // unrar only reads from the file system
+        let mut file = tempfile::NamedTempFile::new()?;
+        file.write_all(&data)?;
+        file.flush()?;
+
+        let rar_error = |e: unrar::error::UnrarError| {
+            DocumentError::ParseError(format!("Failed to read RAR archive: {}", e))
+        };
+        let mut archive = unrar::Archive::new(file.path())
+            .open_for_processing()
+            .map_err(rar_error)?;
+
+        let mut entries = HashMap::new();
+        let mut comic_info = None;
+        while let Some(header) = archive.read_header().map_err(rar_error)? {
+            // RAR 4 archives made on Windows separate paths with backslashes
+            let name = header.entry().filename.to_string_lossy().replace('\\', "/");
+            archive = if header.entry().is_file() && (is_page(&name) || is_comic_info(&name)) {
+                // unrar reads whole entries, so check the declared size first
+                if header.entry().unpacked_size > MAX_ENTRY_SIZE {
+                    return Err(too_large(&name));
+                }
+                let (buffer, rest) = header.read().map_err(rar_error)?;
+                if is_comic_info(&name) {
+                    comic_info.get_or_insert(buffer);
+                } else {
+                    entries.insert(name, buffer);
+                }
+                rest
+            } else {
+                header.skip().map_err(rar_error)?
+            };
+        }
+
+        Ok(Self {
+            pages: page_names(entries.keys().cloned().collect()),
+            comic_info,
+            kind: ArchiveKind::Unpacked(entries),
+            data,
+        })
+    }
+
+    #[cfg(not(feature = "cbr"))]
+    fn open_rar(_data: Arc<Vec<u8>>) -> DocumentResult<Self> {
+        Err(DocumentError::UnsupportedFormat(
+            "RAR comic archives need the `cbr` feature; repack as CBZ or CB7".to_string(),
+        ))
+    }
+
+    /// Number of pages
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Archive path of a page's image
+    pub fn page_name(&self, index: usize) -> Option<&str> {
+        self.pages.get(index).map(String::as_str)
+    }
+
+    /// Raw image data of a page
+    pub fn read_page(&self, index: usize) -> DocumentResult<Vec<u8>> {
+        let name = self
+            .page_name(index)
+            .ok_or(DocumentError::ItemNotFound(index))?;
+        self.read_entry(name)
+    }
+
+    /// Raw data of an entry, by archive path
+    pub fn read_entry(&self, name: &str) -> DocumentResult<Vec<u8>> {
+        match &self.kind {
+            ArchiveKind::Zip => {
+                let mut zip = ZipArchive::new(Cursor::new(self.data.as_slice()))
+                    .map_err(|e| DocumentError::ParseError(format!("Invalid ZIP archive: {}", e)))?;
+                read_zip_entry(&mut zip, name)
+            }
+            ArchiveKind::Unpacked(entries) => entries
+                .get(name)
+                .cloned()
+                .ok_or_else(|| DocumentError::ResourceNotFound(name.to_string())),
+        }
+    }
+
+    /// Contents of ComicInfo.xml, if the archive has one
+    pub fn comic_info(&self) -> Option<&[u8]> {
+        self.comic_info.as_deref()
+    }
+}
+
+fn read_zip_entry(zip: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> DocumentResult<Vec<u8>> {
+    let mut entry = zip
+        .by_name(name)
+        .map_err(|_| DocumentError::ResourceNotFound(name.to_string()))?;
+    let size = entry.size();
+    read_limited(&mut entry, name, size, MAX_ENTRY_SIZE)
+}
+
+/// Read an entry of `size` declared bytes, failing past `limit`
+fn read_limited(reader: impl Read, name: &str, size: u64, limit: u64) -> DocumentResult<Vec<u8>> {
+    let mut buffer = Vec::with_capacity(size.min(limit).min(PREALLOCATE_LIMIT) as usize);
+    reader.take(limit + 1).read_to_end(&mut buffer)?;
+    if buffer.len() as u64 > limit {
+        return Err(too_large(name));
+    }
+    Ok(buffer)
+}
+
+fn too_large(name: &str) -> DocumentError {
+    DocumentError::InvalidContent(format!(
+        "Archive entry {} is larger than {} MiB",
+        name,
+        MAX_ENTRY_SIZE / (1024 * 1024)
+    ))
+}
+
+/// Page images among archive entries, in natural order
+fn page_names(names: Vec<String>) -> Vec<String> {
+    let mut pages: Vec<String> = names.into_iter().filter(|name| is_page(name)).collect();
+    pages.sort_by(|a, b| natord::compare_ignore_case(a, b));
+    pages
+}
+
+fn is_page(name: &str) -> bool {
+    let hidden = name
+        .split('/')
+        .any(|part| part.starts_with('.') || part == "__MACOSX");
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    !hidden && PAGE_EXTENSIONS.contains(&extension.as_str())
+}
+
+fn is_comic_info(name: &str) -> bool {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    file_name.eq_ignore_ascii_case(COMIC_INFO) && !name.contains("__MACOSX")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_names_natural_order() {
+        let names = vec![
+            "Issue/page10.jpg".to_string(),
+            "Issue/page2.JPG".to_string(),
+            "Issue/page1.png".to_string(),
+            "__MACOSX/Issue/._page1.png".to_string(),
+            "Issue/.thumbs/page1.jpg".to_string(),
+            "ComicInfo.xml".to_string(),
+            "Issue/".to_string(),
+        ];
+
+        assert_eq!(
+            page_names(names),
+            vec!["Issue/page1.png", "Issue/page2.JPG", "Issue/page10.jpg"]
+        );
+    }
+
+    #[test]
+    fn test_read_limited() {
+        // Declared sizes only bound the preallocation
+        let data = read_limited(&b"page"[..], "p1.jpg", u64::MAX, 4).unwrap();
+        assert_eq!(data, b"page");
+
+        let result = read_limited(&b"pages"[..], "p1.jpg", 1, 4);
+        assert!(matches!(result, Err(DocumentError::InvalidContent(_))));
+    }
+
+    #[test]
+    fn test_7z_archive() {
+        let mut writer = sevenz_rust::SevenZWriter::new(Cursor::new(Vec::new())).unwrap();
+        for (name, data) in [
+            ("p10.jpg", &b"ten"[..]),
+            ("p9.jpg", b"nine"),
+            ("notes.txt", b"skip"),
+            ("ComicInfo.xml", b"<ComicInfo/>"),
+        ] {
+            let mut entry = sevenz_rust::SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            entry.has_stream = true;
+            writer.push_archive_entry(entry, Some(data)).unwrap();
+        }
+        let data = writer.finish().unwrap().into_inner();
+
+        let archive = ComicArchive::from_bytes(data).unwrap();
+        assert_eq!(archive.page_count(), 2);
+        assert_eq!(archive.page_name(0), Some("p9.jpg"));
+        assert_eq!(archive.read_page(1).unwrap(), b"ten");
+        assert_eq!(archive.comic_info(), Some(&b"<ComicInfo/>"[..]));
+    }
+
+    /// RAR 4 archive of uncompressed entries
+    #[cfg(feature = "cbr")]
+    fn stored_rar(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        fn crc32(data: &[u8]) -> u32 {
+            let mut crc = !0u32;
+            for byte in data {
+                crc ^= *byte as u32;
+                for _ in 0..8 {
+                    crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+                }
+            }
+            !crc
+        }
+        // Header CRCs are the low 16 bits of the CRC32 of the rest of the header
+        fn block(out: &mut Vec<u8>, header: &[u8]) {
+            out.extend_from_slice(&(crc32(header) as u16).to_le_bytes());
+            out.extend_from_slice(header);
+        }
+
+        let mut out = b"Rar!\x1a\x07\x00".to_vec();
+        block(&mut out, &[0x73, 0, 0, 13, 0, 0, 0, 0, 0, 0, 0]);
+        for (name, data) in entries {
+            let mut header = vec![0x74];
+            header.extend_from_slice(&0x8000u16.to_le_bytes()); // long block
+            header.extend_from_slice(&(32 + name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&(data.len() as u32).to_le_bytes()); // packed
+            header.extend_from_slice(&(data.len() as u32).to_le_bytes()); // unpacked
+            header.push(0); // MS-DOS
+            header.extend_from_slice(&crc32(data).to_le_bytes());
+            header.extend_from_slice(&0x2100_0000u32.to_le_bytes()); // DOS time
+            header.extend_from_slice(&[20, 0x30]); // version 2.0, stored
+            header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&0x20u32.to_le_bytes()); // archive attribute
+            header.extend_from_slice(name.as_bytes());
+            block(&mut out, &header);
+            out.extend_from_slice(data);
+        }
+        block(&mut out, &[0x7b, 0x00, 0x40, 7, 0]);
+        out
+    }
+
+    #[test]
+    #[cfg(feature = "cbr")]
+    fn test_rar_archive() {
+        let data = stored_rar(&[
+            ("Issue\\p10.jpg", b"ten"),
+            ("Issue\\p9.jpg", b"nine"),
+            ("notes.txt", b"skip"),
+            ("ComicInfo.xml", b"<ComicInfo/>"),
+        ]);
+
+        let archive = ComicArchive::from_bytes(data).unwrap();
+        assert_eq!(archive.page_count(), 2);
+        assert_eq!(archive.page_name(0), Some("Issue/p9.jpg"));
+        assert_eq!(archive.read_page(1).unwrap(), b"ten");
+        assert_eq!(archive.comic_info(), Some(&b"<ComicInfo/>"[..]));
+    }
+
+    #[test]
+    #[cfg(not(feature = "cbr"))]
+    fn test_rar_unsupported() {
+        let result = ComicArchive::from_bytes(b"Rar!\x1a\x07\x01\x00rest".to_vec());
+        assert!(matches!(result, Err(DocumentError::UnsupportedFormat(_))));
+    }
+}
@@ -0,0 +1,22 @@
+//! Comic book archive implementation
+//!
+//! This module provides `DocumentParser` and `DocumentRenderer`
+//! implementations for comic archives (CBZ, CB7, CBR), without MuPDF.
+//!
+//! # Architecture
+//!
+//! - `ComicArchive`: Page images in natural order, and ComicInfo.xml
+//! - [`ComicDocumentHandler`]: Unified handler implementing both traits
+//!
+//! # Limitations
+//!
+//! There is no pure-Rust RAR decoder, so RAR archives are only read with
+//! the `cbr` feature, which builds the bundled C++ unrar library. Without
+//! it they are detected and rejected. CBR files that are really ZIP or
+//! 7-Zip archives (a common mislabeling) open either way.
+
+mod archive;
+mod parser;
+mod renderer;
+
+pub use parser::ComicDocumentHandler;
@@ -0,0 +1,394 @@
+//! Comic DocumentParser implementation
+//!
+//! Implements the unified `DocumentParser` trait for comic archives. Each
+//! page is one image; metadata comes from ComicInfo.xml when present, and
+//! its page bookmarks make up the table of contents. Comics have no text
+//! layer, so text extraction and search come back empty.
+
+use std::io::Cursor;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+use serde::Deserialize;
+
+use crate::document::{
+    Creator, DocumentError, DocumentFormat, DocumentMetadata, DocumentParser, DocumentResult,
+    ParsedDocument, SearchOptions, SearchResult, StructuredText, TocEntry,
+};
+
+use super::archive::ComicArchive;
+
+/// ComicInfo.xml (ComicRack schema), the fields we use
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct ComicInfo {
+    pub title: Option<String>,
+    pub series: Option<String>,
+    pub number: Option<String>,
+    pub summary: Option<String>,
+    pub year: Option<i32>,
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+    /// Comma-separated names
+    pub writer: Option<String>,
+    /// Comma-separated names
+    pub penciller: Option<String>,
+    pub publisher: Option<String>,
+    /// Comma-separated genres
+    pub genre: Option<String>,
+    #[serde(rename = "LanguageISO")]
+    pub language_iso: Option<String>,
+    pub pages: Option<ComicPages>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ComicPages {
+    #[serde(rename = "Page", default)]
+    pub pages: Vec<ComicPage>,
+}
+
+/// Per-page details: `Image` is the 0-based page index
+#[derive(Debug, Default, Deserialize)]
+pub struct ComicPage {
+    #[serde(rename = "@Image")]
+    pub image: usize,
+    #[serde(rename = "@Type")]
+    pub page_type: Option<String>,
+    #[serde(rename = "@Bookmark")]
+    pub bookmark: Option<String>,
+}
+
+impl ComicInfo {
+    /// Parse ComicInfo.xml
+    pub fn parse(xml: &[u8]) -> DocumentResult<Self> {
+        let xml = String::from_utf8_lossy(xml);
+        quick_xml::de::from_str(&xml)
+            .map_err(|e| DocumentError::ParseError(format!("Invalid ComicInfo.xml: {}", e)))
+    }
+
+    /// Issue number as a series position, when it is numeric
+    pub fn series_index(&self) -> Option<f32> {
+        self.number.as_deref()?.trim().parse().ok()
+    }
+
+    /// Publication date as `YYYY`, `YYYY-MM` or `YYYY-MM-DD`
+    pub fn date(&self) -> Option<String> {
+        let year = self.year.filter(|year| *year > 0)?;
+        Some(match (self.month, self.day) {
+            (Some(month), Some(day)) => format!("{:04}-{:02}-{:02}", year, month, day),
+            (Some(month), None) => format!("{:04}-{:02}", year, month),
+            _ => format!("{:04}", year),
+        })
+    }
+
+    /// Title to show: the issue title, else series and number
+    fn display_title(&self) -> Option<String> {
+        if let Some(title) = non_empty(&self.title) {
+            return Some(title.to_string());
+        }
+        let series = non_empty(&self.series)?;
+        Some(match non_empty(&self.number) {
+            Some(number) => format!("{} #{}", series, number),
+            None => series.to_string(),
+        })
+    }
+
+    fn metadata(&self, fallback_title: &str, cover_href: Option<String>) -> DocumentMetadata {
+        let mut creators: Vec<Creator> = split_names(&self.writer)
+            .map(|name| Creator {
+                name,
+                role: Some("author".to_string()),
+                file_as: None,
+            })
+            .collect();
+        creators.extend(split_names(&self.penciller).map(|name| Creator {
+            name,
+            role: Some("illustrator".to_string()),
+            file_as: None,
+        }));
+
+        DocumentMetadata {
+            title: self
+                .display_title()
+                .unwrap_or_else(|| fallback_title.to_string()),
+            creators,
+            publisher: non_empty(&self.publisher).map(str::to_string),
+            language: non_empty(&self.language_iso).map(str::to_string),
+            identifier: None,
+            description: non_empty(&self.summary).map(str::to_string),
+            cover_href,
+            date: self.date(),
+            rights: None,
+            subjects: split_names(&self.genre).collect(),
+            series: non_empty(&self.series).map(str::to_string),
+            series_index: self.series_index(),
+        }
+    }
+
+    /// Table of contents from page bookmarks
+    fn toc(&self, page_count: usize) -> Vec<TocEntry> {
+        let Some(ref pages) = self.pages else {
+            return Vec::new();
+        };
+        pages
+            .pages
+            .iter()
+            .filter(|page| page.image < page_count)
+            .filter_map(|page| {
+                let label = page.bookmark.as_deref().map(str::trim).filter(|b| !b.is_empty())?;
+                Some(TocEntry {
+                    label: label.to_string(),
+                    href: format!("page:{}", page.image + 1),
+                    item_index: Some(page.image),
+                    children: Vec::new(),
+                    play_order: Some((page.image + 1) as u32),
+                })
+            })
+            .collect()
+    }
+
+    /// Index of the page marked as the front cover
+    fn cover_page(&self) -> Option<usize> {
+        self.pages.as_ref()?.pages.iter().find_map(|page| {
+            page.page_type
+                .as_deref()
+                .filter(|t| t.eq_ignore_ascii_case("FrontCover"))
+                .map(|_| page.image)
+        })
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+fn split_names(value: &Option<String>) -> impl Iterator<Item = String> + '_ {
+    value
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+/// Comic archive implementation of DocumentParser and DocumentRenderer
+pub struct ComicDocumentHandler {
+    id: String,
+    format: DocumentFormat,
+    pub(super) archive: Arc<ComicArchive>,
+    info: Option<ComicInfo>,
+    /// Page sizes in pixels, read on first use
+    dimensions: Mutex<Vec<Option<(f32, f32)>>>,
+}
+
+impl ComicDocumentHandler {
+    /// Create a comic handler from archive bytes
+    ///
+    /// A missing or malformed ComicInfo.xml is not an error; the comic just
+    /// has no metadata beyond its pages.
+    pub fn from_bytes(data: Vec<u8>, id: String, format: DocumentFormat) -> DocumentResult<Self> {
+        let archive = ComicArchive::from_bytes(data)?;
+        if archive.page_count() == 0 {
+            return Err(DocumentError::InvalidContent(
+                "Comic archive has no page images".to_string(),
+            ));
+        }
+
+        let info = archive.comic_info().and_then(|xml| match ComicInfo::parse(xml) {
+            Ok(info) => Some(info),
+            Err(e) => {
+                tracing::warn!(document_id = %id, "Ignoring ComicInfo.xml: {}", e);
+                None
+            }
+        });
+
+        Ok(Self {
+            dimensions: Mutex::new(vec![None; archive.page_count()]),
+            archive: Arc::new(archive),
+            id,
+            format,
+            info,
+        })
+    }
+
+    pub(super) fn validate_item_index(&self, item_index: usize) -> DocumentResult<()> {
+        if item_index >= self.archive.page_count() {
+            return Err(DocumentError::ItemNotFound(item_index));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DocumentParser for ComicDocumentHandler {
+    async fn parse(&self) -> DocumentResult<ParsedDocument> {
+        let page_count = self.archive.page_count();
+        let info = self.info.as_ref();
+
+        let cover_page = info.and_then(ComicInfo::cover_page).unwrap_or(0);
+        let cover_href = self.archive.page_name(cover_page).map(str::to_string);
+        let metadata = match info {
+            Some(info) => info.metadata(&self.id, cover_href),
+            None => DocumentMetadata {
+                title: self.id.clone(),
+                cover_href,
+                ..Default::default()
+            },
+        };
+
+        Ok(ParsedDocument {
+            id: self.id.clone(),
+            format: self.format,
+            metadata,
+            toc: info.map(|info| info.toc(page_count)).unwrap_or_default(),
+            item_count: page_count,
+            item_labels: Some((1..=page_count).map(|n| n.to_string()).collect()),
+            has_text_layer: false,
+        })
+    }
+
+    fn item_count(&self) -> usize {
+        self.archive.page_count()
+    }
+
+    async fn extract_toc(&self) -> DocumentResult<Vec<TocEntry>> {
+        let page_count = self.archive.page_count();
+        Ok(self
+            .info
+            .as_ref()
+            .map(|info| info.toc(page_count))
+            .unwrap_or_default())
+    }
+
+    async fn extract_text(&self, item_index: usize) -> DocumentResult<String> {
+        self.validate_item_index(item_index)?;
+        Ok(String::new())
+    }
+
+    async fn get_structured_text(&self, item_index: usize) -> DocumentResult<StructuredText> {
+        let (width, height) = self.get_item_dimensions(item_index)?;
+        Ok(StructuredText {
+            item_index,
+            width,
+            height,
+            blocks: Vec::new(),
+        })
+    }
+
+    async fn search(
+        &self,
+        _query: &str,
+        _options: SearchOptions,
+    ) -> DocumentResult<Vec<SearchResult>> {
+        Ok(Vec::new())
+    }
+
+    fn get_item_dimensions(&self, item_index: usize) -> DocumentResult<(f32, f32)> {
+        self.validate_item_index(item_index)?;
+        if let Some(dimensions) = self.dimensions.lock()[item_index] {
+            return Ok(dimensions);
+        }
+
+        let data = self.archive.read_page(item_index)?;
+        let (width, height) = image::ImageReader::new(Cursor::new(&data))
+            .with_guessed_format()?
+            .into_dimensions()
+            .map_err(|e| DocumentError::ImageError(e.to_string()))?;
+        let dimensions = (width as f32, height as f32);
+
+        self.dimensions.lock()[item_index] = Some(dimensions);
+        Ok(dimensions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    const COMIC_INFO: &str = r#"<?xml version="1.0"?>
+<ComicInfo xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <Series>Saga</Series>
+  <Number>7</Number>
+  <Summary>The family lands on a new world.</Summary>
+  <Writer>Brian K. Vaughan</Writer>
+  <Penciller>Fiona Staples</Penciller>
+  <Year>2014</Year>
+  <Month>1</Month>
+  <LanguageISO>en</LanguageISO>
+  <Pages>
+    <Page Image="0" Type="FrontCover" />
+    <Page Image="2" Bookmark="Chapter Seven" />
+  </Pages>
+</ComicInfo>"#;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::new(width, height));
+        let mut data = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    fn cbz(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_comic_info() {
+        let info = ComicInfo::parse(COMIC_INFO.as_bytes()).unwrap();
+        assert_eq!(info.display_title().as_deref(), Some("Saga #7"));
+        assert_eq!(info.series_index(), Some(7.0));
+        assert_eq!(info.date().as_deref(), Some("2014-01"));
+        assert_eq!(info.cover_page(), Some(0));
+
+        let toc = info.toc(3);
+        assert_eq!(toc.len(), 1);
+        assert_eq!(toc[0].label, "Chapter Seven");
+        assert_eq!(toc[0].item_index, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_parse_cbz() {
+        let data = cbz(&[
+            ("page10.png", &png(4, 6)),
+            ("page2.png", &png(2, 3)),
+            ("page1.png", &png(1, 1)),
+            ("ComicInfo.xml", COMIC_INFO.as_bytes()),
+        ]);
+
+        let handler =
+            ComicDocumentHandler::from_bytes(data, "saga-7".to_string(), DocumentFormat::Cbz)
+                .unwrap();
+        let parsed = handler.parse().await.unwrap();
+        assert_eq!(parsed.format, DocumentFormat::Cbz);
+        assert_eq!(parsed.item_count, 3);
+        assert_eq!(parsed.metadata.title, "Saga #7");
+        assert_eq!(parsed.metadata.series.as_deref(), Some("Saga"));
+        assert_eq!(parsed.metadata.creators[0].name, "Brian K. Vaughan");
+        assert_eq!(parsed.metadata.creators[1].role.as_deref(), Some("illustrator"));
+        assert_eq!(parsed.metadata.cover_href.as_deref(), Some("page1.png"));
+        assert!(!parsed.has_text_layer);
+
+        // Natural order: page1, page2, page10
+        assert_eq!(handler.get_item_dimensions(1).unwrap(), (2.0, 3.0));
+        assert_eq!(handler.get_item_dimensions(2).unwrap(), (4.0, 6.0));
+        assert!(handler.get_item_dimensions(3).is_err());
+    }
+
+    #[test]
+    fn test_cbz_without_pages() {
+        let data = cbz(&[("ComicInfo.xml", COMIC_INFO.as_bytes())]);
+        let result = ComicDocumentHandler::from_bytes(data, "empty".to_string(), DocumentFormat::Cbz);
+        assert!(matches!(result, Err(DocumentError::InvalidContent(_))));
+    }
+}
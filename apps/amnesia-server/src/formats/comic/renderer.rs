@@ -0,0 +1,181 @@
+//! Comic DocumentRenderer implementation
+//!
+//! Pages are decoded from the archive and resampled: scale 1.0 is the
+//! page image's own size. Thumbnails are never enlarged.
+
+use std::io::Cursor;
+
+use async_trait::async_trait;
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, GenericImageView};
+
+use crate::document::{
+    DocumentError, DocumentRenderer, DocumentResult, ImageFormat, Rect, RenderRequest,
+    RenderResult, Resource,
+};
+
+use super::ComicDocumentHandler;
+
+/// JPEG quality of rendered pages
+const JPEG_QUALITY: u8 = 85;
+
+#[async_trait]
+impl DocumentRenderer for ComicDocumentHandler {
+    async fn render_item(&self, request: &RenderRequest) -> DocumentResult<RenderResult> {
+        self.validate_item_index(request.item_index)?;
+
+        let archive = self.archive.clone();
+        let request = request.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let data = archive.read_page(request.item_index)?;
+            let mut page = decode(&data)?;
+
+            if let Some(clip) = request.clip {
+                page = crop(&page, &clip);
+            }
+
+            let scale = request.scale.clamp(0.1, 4.0);
+            if (scale - 1.0).abs() > f32::EPSILON {
+                let (width, height) = page.dimensions();
+                let width = ((width as f32 * scale).round() as u32).max(1);
+                let height = ((height as f32 * scale).round() as u32).max(1);
+                page = page.resize_exact(width, height, FilterType::Lanczos3);
+            }
+
+            page = match request.rotation {
+                90 => page.rotate90(),
+                180 => page.rotate180(),
+                270 => page.rotate270(),
+                _ => page,
+            };
+
+            encode_page(&page, request.format)
+        })
+        .await
+        .map_err(|e| DocumentError::RenderError(format!("Task join error: {}", e)))?
+    }
+
+    async fn render_thumbnail(
+        &self,
+        item_index: usize,
+        max_size: u32,
+    ) -> DocumentResult<RenderResult> {
+        self.validate_item_index(item_index)?;
+
+        let archive = self.archive.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let data = archive.read_page(item_index)?;
+            let page = decode(&data)?;
+
+            let (width, height) = page.dimensions();
+            let page = if width.max(height) > max_size {
+                page.resize(max_size, max_size, FilterType::Lanczos3)
+            } else {
+                page
+            };
+
+            // JPEG for smaller thumbnails
+            encode_page(&page, ImageFormat::Jpeg)
+        })
+        .await
+        .map_err(|e| DocumentError::RenderError(format!("Task join error: {}", e)))?
+    }
+
+    /// Page images by archive path, as stored
+    async fn get_resource(&self, href: &str) -> DocumentResult<Resource> {
+        let content = self.archive.read_entry(href)?;
+        Ok(Resource {
+            href: href.to_string(),
+            mime_type: mime_guess::from_path(href)
+                .first_or_octet_stream()
+                .to_string(),
+            content,
+        })
+    }
+}
+
+fn decode(data: &[u8]) -> DocumentResult<DynamicImage> {
+    image::load_from_memory(data).map_err(|e| DocumentError::ImageError(e.to_string()))
+}
+
+/// Crop to a clip rectangle in page pixels, kept within the page
+fn crop(page: &DynamicImage, clip: &Rect) -> DynamicImage {
+    let (width, height) = page.dimensions();
+    let x = (clip.x.max(0.0) as u32).min(width.saturating_sub(1));
+    let y = (clip.y.max(0.0) as u32).min(height.saturating_sub(1));
+    let clip_width = (clip.width.max(1.0) as u32).min(width - x);
+    let clip_height = (clip.height.max(1.0) as u32).min(height - y);
+    page.crop_imm(x, y, clip_width, clip_height)
+}
+
+fn encode_page(page: &DynamicImage, format: ImageFormat) -> DocumentResult<RenderResult> {
+    let mut data = Vec::new();
+    let result = match format {
+        // JPEG has no alpha channel
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(page.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY)),
+        ImageFormat::Png => page.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png),
+        ImageFormat::Webp => DynamicImage::ImageRgba8(page.to_rgba8())
+            .write_to(&mut Cursor::new(&mut data), image::ImageFormat::WebP),
+    };
+    result.map_err(|e| DocumentError::ImageError(e.to_string()))?;
+
+    let (width, height) = page.dimensions();
+    Ok(RenderResult {
+        data,
+        format,
+        width,
+        height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::DocumentFormat;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    fn handler() -> ComicDocumentHandler {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::new(400, 600));
+        let mut page = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut page), image::ImageFormat::Png)
+            .unwrap();
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("01.png", SimpleFileOptions::default()).unwrap();
+        zip.write_all(&page).unwrap();
+        let data = zip.finish().unwrap().into_inner();
+
+        ComicDocumentHandler::from_bytes(data, "comic".to_string(), DocumentFormat::Cbz).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_render_page() {
+        let handler = handler();
+        let request = RenderRequest {
+            scale: 0.5,
+            format: ImageFormat::Jpeg,
+            rotation: 90,
+            ..Default::default()
+        };
+
+        let result = handler.render_item(&request).await.unwrap();
+        assert_eq!((result.width, result.height), (300, 200));
+        assert_eq!(image::guess_format(&result.data).unwrap(), image::ImageFormat::Jpeg);
+    }
+
+    #[tokio::test]
+    async fn test_render_thumbnail() {
+        let handler = handler();
+
+        let thumbnail = handler.render_thumbnail(0, 150).await.unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (100, 150));
+
+        // Never enlarged
+        let large = handler.render_thumbnail(0, 1200).await.unwrap();
+        assert_eq!((large.width, large.height), (400, 600));
+    }
+}
@@ -0,0 +1,446 @@
+//! Reflowable EPUB from structured text
+//!
+//! Each source item (a PDF page, say) becomes one XHTML file, its text
+//! blocks paragraphs. Blocks set noticeably larger than the body text
+//! become headings, and words hyphenated across lines are rejoined. The
+//! navigation document follows the source's table of contents.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+
+use html_escape::{encode_double_quoted_attribute, encode_text};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::document::{DocumentError, DocumentResult, ParsedDocument, StructuredText, TextBlock, TocEntry};
+
+/// Blocks at least this much larger than the body text are headings
+const HEADING_SCALE: f32 = 1.3;
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+/// A paragraph of the converted text
+#[derive(Debug, PartialEq)]
+enum Paragraph {
+    Heading(String),
+    Text(String),
+}
+
+/// Build an EPUB 3 (with an EPUB 2 NCX) from a document's structured text
+///
+/// `items` holds the structured text of every item, in order.
+pub fn build_epub(parsed: &ParsedDocument, items: &[StructuredText]) -> DocumentResult<Vec<u8>> {
+    let body_size = body_font_size(items);
+    let metadata = &parsed.metadata;
+    let title = if metadata.title.is_empty() {
+        parsed.id.as_str()
+    } else {
+        metadata.title.as_str()
+    };
+    let language = metadata.language.as_deref().unwrap_or("en");
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default();
+
+    // The mimetype must come first, uncompressed
+    add_file(&mut zip, "mimetype", "application/epub+zip", stored)?;
+    add_file(&mut zip, "META-INF/container.xml", CONTAINER_XML, deflated)?;
+
+    for (index, item) in items.iter().enumerate() {
+        let paragraphs = paragraphs(item, body_size);
+        let xhtml = item_xhtml(title, language, &paragraphs);
+        add_file(&mut zip, &format!("OEBPS/{}", item_href(index)), &xhtml, deflated)?;
+    }
+
+    let toc = if parsed.toc.is_empty() {
+        vec![TocEntry {
+            label: title.to_string(),
+            href: item_href(0),
+            item_index: Some(0),
+            children: Vec::new(),
+            play_order: None,
+        }]
+    } else {
+        parsed.toc.clone()
+    };
+
+    add_file(&mut zip, "OEBPS/content.opf", &package(parsed, title, language, items.len()), deflated)?;
+    add_file(&mut zip, "OEBPS/nav.xhtml", &nav(title, language, &toc), deflated)?;
+    add_file(&mut zip, "OEBPS/toc.ncx", &ncx(&parsed.id, title, &toc), deflated)?;
+
+    let data = zip
+        .finish()
+        .map_err(|e| DocumentError::ConversionError(format!("Failed to write EPUB: {}", e)))?
+        .into_inner();
+    Ok(data)
+}
+
+fn add_file(
+    zip: &mut ZipWriter<Cursor<Vec<u8>>>,
+    name: &str,
+    content: &str,
+    options: SimpleFileOptions,
+) -> DocumentResult<()> {
+    zip.start_file(name, options)
+        .map_err(|e| DocumentError::ConversionError(format!("Failed to write EPUB: {}", e)))?;
+    zip.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+/// Path of an item's XHTML file, relative to the package document
+fn item_href(index: usize) -> String {
+    format!("text/item-{:04}.xhtml", index + 1)
+}
+
+/// The most common font size, by character
+fn body_font_size(items: &[StructuredText]) -> Option<f32> {
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    let sizes = items
+        .iter()
+        .flat_map(|item| &item.blocks)
+        .flat_map(|block| &block.lines)
+        .flat_map(|line| &line.chars)
+        .filter_map(|c| c.font_size);
+    for size in sizes {
+        *counts.entry((size * 2.0).round() as u32).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|&(size, count)| (count, size))
+        .map(|(size, _)| size as f32 / 2.0)
+}
+
+fn paragraphs(item: &StructuredText, body_size: Option<f32>) -> Vec<Paragraph> {
+    item.blocks
+        .iter()
+        .filter_map(|block| {
+            let text = block_text(block);
+            if text.is_empty() {
+                return None;
+            }
+            let heading = match (block_font_size(block), body_size) {
+                (Some(size), Some(body)) => size >= body * HEADING_SCALE,
+                _ => false,
+            };
+            Some(if heading {
+                Paragraph::Heading(text)
+            } else {
+                Paragraph::Text(text)
+            })
+        })
+        .collect()
+}
+
+/// A block's lines joined with spaces, rejoining hyphenated words
+fn block_text(block: &TextBlock) -> String {
+    let mut text = String::new();
+    for line in &block.lines {
+        let line_text = match &line.text {
+            Some(text) => text.clone(),
+            None => line.chars.iter().map(|c| c.char).collect(),
+        };
+        let line_text = line_text.trim();
+        if line_text.is_empty() {
+            continue;
+        }
+
+        if text.ends_with('-') && line_text.starts_with(char::is_lowercase) {
+            text.pop();
+        } else if !text.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(line_text);
+    }
+    text
+}
+
+fn block_font_size(block: &TextBlock) -> Option<f32> {
+    let sizes: Vec<f32> = block
+        .lines
+        .iter()
+        .flat_map(|line| &line.chars)
+        .filter_map(|c| c.font_size)
+        .collect();
+    (!sizes.is_empty()).then(|| sizes.iter().sum::<f32>() / sizes.len() as f32)
+}
+
+fn item_xhtml(title: &str, language: &str, paragraphs: &[Paragraph]) -> String {
+    let mut body = String::new();
+    for paragraph in paragraphs {
+        match paragraph {
+            Paragraph::Heading(text) => body.push_str(&format!("<h2>{}</h2>\n", encode_text(text))),
+            Paragraph::Text(text) => body.push_str(&format!("<p>{}</p>\n", encode_text(text))),
+        }
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="{lang}" lang="{lang}">
+<head><title>{title}</title></head>
+<body>
+{body}</body>
+</html>
+"#,
+        lang = encode_double_quoted_attribute(language),
+        title = encode_text(title),
+        body = body,
+    )
+}
+
+fn package(parsed: &ParsedDocument, title: &str, language: &str, item_count: usize) -> String {
+    let metadata = &parsed.metadata;
+    let mut dc = format!(
+        "    <dc:identifier id=\"uid\">{}</dc:identifier>\n    <dc:title>{}</dc:title>\n    <dc:language>{}</dc:language>\n",
+        encode_text(metadata.identifier.as_deref().unwrap_or(&format!("urn:amnesia:{}", parsed.id))),
+        encode_text(title),
+        encode_text(language),
+    );
+    for creator in &metadata.creators {
+        dc.push_str(&format!("    <dc:creator>{}</dc:creator>\n", encode_text(&creator.name)));
+    }
+    if let Some(publisher) = &metadata.publisher {
+        dc.push_str(&format!("    <dc:publisher>{}</dc:publisher>\n", encode_text(publisher)));
+    }
+    if let Some(description) = &metadata.description {
+        dc.push_str(&format!("    <dc:description>{}</dc:description>\n", encode_text(description)));
+    }
+    if let Some(date) = &metadata.date {
+        dc.push_str(&format!("    <dc:date>{}</dc:date>\n", encode_text(date)));
+    }
+
+    let mut manifest = String::from(
+        "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n    <item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\n",
+    );
+    let mut spine = String::new();
+    for index in 0..item_count {
+        manifest.push_str(&format!(
+            "    <item id=\"item{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+            index + 1,
+            item_href(index)
+        ));
+        spine.push_str(&format!("    <itemref idref=\"item{}\"/>\n", index + 1));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+{dc}    <meta property="dcterms:modified">{modified}</meta>
+  </metadata>
+  <manifest>
+{manifest}  </manifest>
+  <spine toc="ncx">
+{spine}  </spine>
+</package>
+"#,
+        dc = dc,
+        modified = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+        manifest = manifest,
+        spine = spine,
+    )
+}
+
+/// Href of a TOC entry's item, falling back to the first item
+fn entry_href(entry: &TocEntry) -> String {
+    item_href(entry.item_index.unwrap_or(0))
+}
+
+fn nav(title: &str, language: &str, toc: &[TocEntry]) -> String {
+    fn list(entries: &[TocEntry], out: &mut String) {
+        out.push_str("<ol>\n");
+        for entry in entries {
+            out.push_str(&format!(
+                "<li><a href=\"{}\">{}</a>",
+                entry_href(entry),
+                encode_text(&entry.label)
+            ));
+            if !entry.children.is_empty() {
+                out.push('\n');
+                list(&entry.children, out);
+            }
+            out.push_str("</li>\n");
+        }
+        out.push_str("</ol>\n");
+    }
+
+    let mut entries = String::new();
+    list(toc, &mut entries);
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{lang}" lang="{lang}">
+<head><title>{title}</title></head>
+<body>
+<nav epub:type="toc" id="toc">
+<h1>{title}</h1>
+{entries}</nav>
+</body>
+</html>
+"#,
+        lang = encode_double_quoted_attribute(language),
+        title = encode_text(title),
+        entries = entries,
+    )
+}
+
+fn ncx(id: &str, title: &str, toc: &[TocEntry]) -> String {
+    fn points(entries: &[TocEntry], order: &mut usize, out: &mut String) {
+        for entry in entries {
+            *order += 1;
+            out.push_str(&format!(
+                "<navPoint id=\"nav{order}\" playOrder=\"{order}\"><navLabel><text>{}</text></navLabel><content src=\"{}\"/>\n",
+                encode_text(&entry.label),
+                entry_href(entry),
+                order = order,
+            ));
+            points(&entry.children, order, out);
+            out.push_str("</navPoint>\n");
+        }
+    }
+
+    let mut nav_points = String::new();
+    points(toc, &mut 0, &mut nav_points);
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+<head><meta name="dtb:uid" content="{uid}"/></head>
+<docTitle><text>{title}</text></docTitle>
+<navMap>
+{nav_points}</navMap>
+</ncx>
+"#,
+        uid = encode_double_quoted_attribute(&format!("urn:amnesia:{}", id)),
+        title = encode_text(title),
+        nav_points = nav_points,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{BoundingBox, CharPosition, DocumentFormat, DocumentMetadata, TextLine};
+    use std::io::Read;
+
+    fn line(text: &str, size: f32) -> TextLine {
+        TextLine {
+            bbox: BoundingBox::default(),
+            dir: None,
+            chars: text
+                .chars()
+                .map(|c| CharPosition {
+                    char: c,
+                    x: 0.0,
+                    y: 0.0,
+                    width: 1.0,
+                    height: 1.0,
+                    font_size: Some(size),
+                    font_name: None,
+                    font_flags: None,
+                    color: None,
+                })
+                .collect(),
+            text: None,
+        }
+    }
+
+    fn block(lines: Vec<TextLine>) -> TextBlock {
+        TextBlock {
+            bbox: BoundingBox::default(),
+            lines,
+        }
+    }
+
+    fn page(index: usize, blocks: Vec<TextBlock>) -> StructuredText {
+        StructuredText {
+            item_index: index,
+            width: 612.0,
+            height: 792.0,
+            blocks,
+        }
+    }
+
+    #[test]
+    fn test_paragraphs() {
+        let items = vec![page(
+            0,
+            vec![
+                block(vec![line("Chapter One", 18.0)]),
+                block(vec![
+                    line("It was a bright cold day, and the clocks were strik-", 10.0),
+                    line("ing thirteen.", 10.0),
+                ]),
+                block(vec![line("  ", 10.0)]),
+            ],
+        )];
+
+        let paragraphs = paragraphs(&items[0], body_font_size(&items));
+        assert_eq!(
+            paragraphs,
+            vec![
+                Paragraph::Heading("Chapter One".to_string()),
+                Paragraph::Text(
+                    "It was a bright cold day, and the clocks were striking thirteen.".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_epub() {
+        let parsed = ParsedDocument {
+            id: "report".to_string(),
+            format: DocumentFormat::Pdf,
+            metadata: DocumentMetadata {
+                title: "Q3 <Report>".to_string(),
+                ..Default::default()
+            },
+            toc: Vec::new(),
+            item_count: 2,
+            item_labels: None,
+            has_text_layer: true,
+        };
+        let items = vec![
+            page(0, vec![block(vec![line("Fish & chips", 10.0)])]),
+            page(1, vec![block(vec![line("Page two", 10.0)])]),
+        ];
+
+        let data = build_epub(&parsed, &items).unwrap();
+        assert_eq!(DocumentFormat::from_magic_bytes(&data), Some(DocumentFormat::Epub));
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(data)).unwrap();
+        assert_eq!(zip.by_index(0).unwrap().name(), "mimetype");
+
+        let mut item = String::new();
+        zip.by_name("OEBPS/text/item-0001.xhtml")
+            .unwrap()
+            .read_to_string(&mut item)
+            .unwrap();
+        assert!(item.contains("<p>Fish &amp; chips</p>"));
+        assert!(item.contains("<title>Q3 &lt;Report&gt;</title>"));
+
+        let mut opf = String::new();
+        zip.by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut opf)
+            .unwrap();
+        assert!(opf.contains("<itemref idref=\"item2\"/>"));
+
+        // Without a source TOC, the title links to the first item
+        let mut nav = String::new();
+        zip.by_name("OEBPS/nav.xhtml")
+            .unwrap()
+            .read_to_string(&mut nav)
+            .unwrap();
+        assert!(nav.contains("<a href=\"text/item-0001.xhtml\">Q3 &lt;Report&gt;</a>"));
+    }
+}
@@ -0,0 +1,273 @@
+//! KEPUB markup for Kobo readers
+//!
+//! A KEPUB is an EPUB whose content documents wrap every sentence in a
+//! `<span class="koboSpan" id="kobo.{paragraph}.{sentence}">`, which Kobo
+//! firmware uses for reading position, highlights and page counts. The body
+//! is also wrapped in the `book-columns`/`book-inner` divs Kobo's renderer
+//! lays out pages with. Everything else is copied through unchanged.
+
+use std::cell::{Cell, RefCell};
+use std::io::{Cursor, Read, Write};
+use std::rc::Rc;
+
+use lol_html::html_content::ContentType;
+use lol_html::{element, end_tag, rewrite_str, text, RewriteStrSettings};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::document::{DocumentError, DocumentResult};
+
+/// Elements that start a new Kobo paragraph
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p", "h1", "h2", "h3", "h4", "h5", "h6", "li", "blockquote", "dd", "dt", "td", "th",
+    "figcaption", "pre",
+];
+
+/// Elements whose text isn't prose and is left alone
+const SKIPPED_ELEMENTS: &[&str] = &["script", "style", "svg", "math"];
+
+/// Characters that end a sentence
+const SENTENCE_ENDINGS: &[char] = &['.', '!', '?', '…'];
+
+/// Closing punctuation that stays with the sentence before it
+const CLOSING_PUNCTUATION: &[char] = &['"', '\'', '”', '’', '»', ')', ']'];
+
+const KOBO_STYLE: &str =
+    r#"<style type="text/css">div#book-inner { margin-top: 0; margin-bottom: 0; }</style>"#;
+
+/// Add Kobo markup to every content document of an EPUB
+pub fn kepubify(epub: &[u8]) -> DocumentResult<Vec<u8>> {
+    let mut archive = ZipArchive::new(Cursor::new(epub))
+        .map_err(|e| DocumentError::ParseError(format!("Invalid EPUB archive: {}", e)))?;
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|e| DocumentError::ParseError(format!("Invalid EPUB entry: {}", e)))?;
+
+        if !is_content_document(entry.name()) {
+            zip.raw_copy_file(entry).map_err(write_error)?;
+            continue;
+        }
+
+        let name = entry.name().to_string();
+        let mut html = String::new();
+        entry.read_to_string(&mut html).map_err(|e| {
+            DocumentError::InvalidContent(format!("{} is not UTF-8: {}", name, e))
+        })?;
+
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.start_file(name, options).map_err(write_error)?;
+        zip.write_all(add_kobo_spans(&html)?.as_bytes())?;
+    }
+
+    Ok(zip.finish().map_err(write_error)?.into_inner())
+}
+
+fn write_error(e: zip::result::ZipError) -> DocumentError {
+    DocumentError::ConversionError(format!("Failed to write KEPUB: {}", e))
+}
+
+fn is_content_document(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    [".xhtml", ".html", ".htm"].iter().any(|ext| name.ends_with(ext))
+}
+
+/// Wrap each sentence of a content document in a Kobo span
+///
+/// Documents that already have Kobo spans are returned as they are.
+fn add_kobo_spans(html: &str) -> DocumentResult<String> {
+    if html.contains("koboSpan") {
+        return Ok(html.to_string());
+    }
+
+    let paragraph = Rc::new(Cell::new(0usize));
+    let sentence = Rc::new(Cell::new(0usize));
+    let skip_depth = Rc::new(Cell::new(0usize));
+    let buffer = RefCell::new(String::new());
+
+    let element_counters = (paragraph.clone(), sentence.clone(), skip_depth.clone());
+    let text_counters = (paragraph, sentence, skip_depth);
+
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("head", |el| {
+                el.append(KOBO_STYLE, ContentType::Html);
+                Ok(())
+            }),
+            element!("body", |el| {
+                el.prepend(r#"<div id="book-columns"><div id="book-inner">"#, ContentType::Html);
+                el.append("</div></div>", ContentType::Html);
+                Ok(())
+            }),
+            element!("body *", move |el| {
+                let (paragraph, sentence, skip_depth) = &element_counters;
+                let tag = el.tag_name().to_ascii_lowercase();
+
+                if SKIPPED_ELEMENTS.contains(&tag.as_str()) && el.can_have_content() {
+                    skip_depth.set(skip_depth.get() + 1);
+                    let skip_depth = skip_depth.clone();
+                    el.on_end_tag(end_tag!(move |_| {
+                        skip_depth.set(skip_depth.get().saturating_sub(1));
+                        Ok(())
+                    }))?;
+                } else if BLOCK_ELEMENTS.contains(&tag.as_str()) {
+                    paragraph.set(paragraph.get() + 1);
+                    sentence.set(0);
+                }
+                Ok(())
+            }),
+            text!("body", |chunk| {
+                let (paragraph, sentence, skip_depth) = &text_counters;
+                if skip_depth.get() > 0 {
+                    return Ok(());
+                }
+
+                // Text nodes can arrive in several chunks; spans are built
+                // once the whole node is in
+                let mut buffer = buffer.borrow_mut();
+                buffer.push_str(chunk.as_str());
+                if !chunk.last_in_text_node() {
+                    chunk.remove();
+                    return Ok(());
+                }
+
+                let text = std::mem::take(&mut *buffer);
+                if text.trim().is_empty() {
+                    chunk.replace(&text, ContentType::Html);
+                    return Ok(());
+                }
+
+                paragraph.set(paragraph.get().max(1));
+                let mut spans = String::new();
+                let content = text.trim_start();
+                spans.push_str(&text[..text.len() - content.len()]);
+                for segment in sentences(content) {
+                    sentence.set(sentence.get() + 1);
+                    spans.push_str(&format!(
+                        r#"<span class="koboSpan" id="kobo.{}.{}">{}</span>"#,
+                        paragraph.get(),
+                        sentence.get(),
+                        segment
+                    ));
+                }
+                chunk.replace(&spans, ContentType::Html);
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    };
+
+    rewrite_str(html, settings)
+        .map_err(|e| DocumentError::ConversionError(format!("Failed to add Kobo spans: {}", e)))
+}
+
+/// Split text into sentences, each keeping its trailing whitespace
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((_, c)) = chars.next() {
+        if !SENTENCE_ENDINGS.contains(&c) {
+            continue;
+        }
+        while let Some(&(_, next)) = chars.peek() {
+            if SENTENCE_ENDINGS.contains(&next) || CLOSING_PUNCTUATION.contains(&next) {
+                chars.next();
+            } else {
+                break;
+            }
+        }
+
+        let mut end = None;
+        while let Some(&(i, next)) = chars.peek() {
+            if !next.is_whitespace() {
+                end = Some(i);
+                break;
+            }
+            chars.next();
+        }
+        match end {
+            // Only a sentence if whitespace followed
+            Some(end) if text[..end].ends_with(char::is_whitespace) => {
+                sentences.push(&text[start..end]);
+                start = end;
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+
+    if start < text.len() {
+        sentences.push(&text[start..]);
+    }
+    sentences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sentences() {
+        assert_eq!(
+            sentences("Is it? “Yes!” she said... Fine.x.  Done. "),
+            vec!["Is it? ", "“Yes!” ", "she said... ", "Fine.x.  ", "Done. "]
+        );
+        assert_eq!(sentences("No ending"), vec!["No ending"]);
+    }
+
+    #[test]
+    fn test_add_kobo_spans() {
+        let html = r#"<html><head><title>T</title></head><body>
+<h1>One</h1>
+<p>First. Second &amp; <em>third.</em></p>
+<script>var a = 1. b;</script>
+</body></html>"#;
+
+        let result = add_kobo_spans(html).unwrap();
+        assert!(result.contains(r#"<body><div id="book-columns"><div id="book-inner">"#));
+        assert!(result.contains(r#"</div></div></body>"#));
+        assert!(result.contains(KOBO_STYLE));
+        assert!(result.contains(r#"<h1><span class="koboSpan" id="kobo.1.1">One</span></h1>"#));
+        assert!(result.contains(
+            r#"<p><span class="koboSpan" id="kobo.2.1">First. </span><span class="koboSpan" id="kobo.2.2">Second &amp; </span><em><span class="koboSpan" id="kobo.2.3">third.</span></em></p>"#
+        ));
+        assert!(result.contains("<script>var a = 1. b;</script>"));
+        assert!(!result.contains("<title><span"));
+
+        // Already converted
+        assert_eq!(add_kobo_spans(&result).unwrap(), result);
+    }
+
+    #[test]
+    fn test_kepubify() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        zip.start_file("mimetype", stored).unwrap();
+        zip.write_all(b"application/epub+zip").unwrap();
+        zip.start_file("OEBPS/ch1.xhtml", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"<html><head></head><body><p>Hi.</p></body></html>")
+            .unwrap();
+        let epub = zip.finish().unwrap().into_inner();
+
+        let kepub = kepubify(&epub).unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(kepub)).unwrap();
+
+        let mimetype = archive.by_index(0).unwrap();
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), CompressionMethod::Stored);
+        drop(mimetype);
+
+        let mut chapter = String::new();
+        archive
+            .by_name("OEBPS/ch1.xhtml")
+            .unwrap()
+            .read_to_string(&mut chapter)
+            .unwrap();
+        assert!(chapter.contains(r#"<span class="koboSpan" id="kobo.1.1">Hi.</span>"#));
+    }
+}
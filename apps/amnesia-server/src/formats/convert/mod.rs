@@ -0,0 +1,207 @@
+//! Format conversion
+//!
+//! Converts stored documents into the formats readers ask for:
+//!
+//! - **PDF**: reflowable documents (EPUB, FB2, MOBI) are laid out into pages
+//!   by MuPDF with a [`LayoutConfig`], then written as PDF. XPS converts
+//!   page for page.
+//! - **EPUB**: a simple reflowable EPUB built from the structured text of a
+//!   PDF, XPS, FB2 or MOBI.
+//! - **KEPUB**: an EPUB with Kobo sentence spans. Other formats are
+//!   converted to EPUB first.
+//! - **Text**: the plain text of every item.
+//!
+//! Comics have no text and can't be converted.
+
+mod epub;
+mod kepub;
+
+use crate::document::{DocumentError, DocumentFormat, DocumentResult, LayoutConfig};
+use crate::formats::open_document;
+use crate::mupdf::SafeDocument;
+
+/// Output format of a conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConversionTarget {
+    Pdf,
+    Epub,
+    Kepub,
+    Text,
+}
+
+impl ConversionTarget {
+    /// Parse a target name as used in the `to` query parameter
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "pdf" => Some(Self::Pdf),
+            "epub" => Some(Self::Epub),
+            "kepub" => Some(Self::Kepub),
+            "txt" | "text" => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    /// Name of the target, as accepted by [`ConversionTarget::from_name`]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pdf => "pdf",
+            Self::Epub => "epub",
+            Self::Kepub => "kepub",
+            Self::Text => "txt",
+        }
+    }
+
+    /// File extension for converted files, without the leading dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Pdf => "pdf",
+            Self::Epub => "epub",
+            // Kobo only treats ".kepub.epub" files as KEPUBs
+            Self::Kepub => "kepub.epub",
+            Self::Text => "txt",
+        }
+    }
+
+    /// MIME type of converted files
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Pdf => "application/pdf",
+            Self::Epub => "application/epub+zip",
+            Self::Kepub => "application/kepub+zip",
+            Self::Text => "text/plain; charset=utf-8",
+        }
+    }
+
+    /// Whether a source format can be converted to this target
+    pub fn accepts(&self, source: DocumentFormat) -> bool {
+        match self {
+            Self::Pdf => matches!(
+                source,
+                DocumentFormat::Epub | DocumentFormat::Fb2 | DocumentFormat::Mobi | DocumentFormat::Xps
+            ),
+            Self::Epub => !source.is_comic() && source != DocumentFormat::Epub,
+            Self::Kepub | Self::Text => !source.is_comic(),
+        }
+    }
+}
+
+/// Convert a document to another format
+///
+/// `layout` sets the page size and font size reflowable documents are laid
+/// out with when converting to PDF.
+pub async fn convert(
+    data: Vec<u8>,
+    id: String,
+    source: DocumentFormat,
+    target: ConversionTarget,
+    layout: LayoutConfig,
+) -> DocumentResult<Vec<u8>> {
+    if !target.accepts(source) {
+        return Err(DocumentError::UnsupportedFormat(format!(
+            "Can't convert {:?} to {}",
+            source,
+            target.name()
+        )));
+    }
+
+    match target {
+        ConversionTarget::Pdf => tokio::task::spawn_blocking(move || to_pdf(data, id, layout))
+            .await
+            .map_err(|e| DocumentError::ConversionError(format!("Task join error: {}", e)))?,
+        ConversionTarget::Epub => to_epub(data, id, source).await,
+        ConversionTarget::Kepub => {
+            let epub = if source == DocumentFormat::Epub {
+                data
+            } else {
+                to_epub(data, id, source).await?
+            };
+            tokio::task::spawn_blocking(move || kepub::kepubify(&epub))
+                .await
+                .map_err(|e| DocumentError::ConversionError(format!("Task join error: {}", e)))?
+        }
+        ConversionTarget::Text => to_text(data, id, source).await,
+    }
+}
+
+/// Lay out (if reflowable) and write every page as PDF
+fn to_pdf(data: Vec<u8>, id: String, layout: LayoutConfig) -> DocumentResult<Vec<u8>> {
+    let doc = SafeDocument::from_bytes(data, id)?;
+    doc.with_doc_mut(|mupdf_doc| {
+        if mupdf_doc.is_reflowable().unwrap_or(false) {
+            mupdf_doc.layout(layout.width, layout.height, layout.em)?;
+        }
+
+        let pdf = mupdf_doc.convert_to_pdf(0, -1, 0)?;
+        let mut output = Vec::new();
+        pdf.write_to(&mut output)?;
+        Ok(output)
+    })
+}
+
+async fn to_epub(data: Vec<u8>, id: String, source: DocumentFormat) -> DocumentResult<Vec<u8>> {
+    let (parser, _, parsed) = open_document(data, id, source).await?;
+
+    let mut items = Vec::with_capacity(parsed.item_count);
+    for index in 0..parsed.item_count {
+        items.push(parser.get_structured_text(index).await?);
+    }
+
+    tokio::task::spawn_blocking(move || epub::build_epub(&parsed, &items))
+        .await
+        .map_err(|e| DocumentError::ConversionError(format!("Task join error: {}", e)))?
+}
+
+async fn to_text(data: Vec<u8>, id: String, source: DocumentFormat) -> DocumentResult<Vec<u8>> {
+    let (parser, _, parsed) = open_document(data, id, source).await?;
+
+    let mut text = String::new();
+    for index in 0..parsed.item_count {
+        let item = parser.extract_text(index).await?;
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+        if !text.is_empty() {
+            text.push_str("\n\n");
+        }
+        text.push_str(item);
+    }
+    text.push('\n');
+    Ok(text.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_names() {
+        assert_eq!(ConversionTarget::from_name("KEPUB"), Some(ConversionTarget::Kepub));
+        assert_eq!(ConversionTarget::from_name("text"), Some(ConversionTarget::Text));
+        assert_eq!(ConversionTarget::from_name("docx"), None);
+        assert_eq!(ConversionTarget::Kepub.extension(), "kepub.epub");
+    }
+
+    #[test]
+    fn test_accepted_sources() {
+        assert!(ConversionTarget::Pdf.accepts(DocumentFormat::Epub));
+        assert!(!ConversionTarget::Pdf.accepts(DocumentFormat::Pdf));
+        assert!(ConversionTarget::Epub.accepts(DocumentFormat::Pdf));
+        assert!(!ConversionTarget::Epub.accepts(DocumentFormat::Epub));
+        assert!(ConversionTarget::Kepub.accepts(DocumentFormat::Epub));
+        assert!(!ConversionTarget::Kepub.accepts(DocumentFormat::Cbz));
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_conversion() {
+        let result = convert(
+            Vec::new(),
+            "comic".to_string(),
+            DocumentFormat::Cbz,
+            ConversionTarget::Text,
+            LayoutConfig::default(),
+        )
+        .await;
+        assert!(matches!(result, Err(DocumentError::UnsupportedFormat(_))));
+    }
+}
@@ -9,17 +9,22 @@
 //!
 //! MuPDF treats EPUBs as reflowable documents. The `layout()` method is used
 //! to set virtual page dimensions before rendering or text extraction.
+//! Laid-out pages are mapped back to the content documents (as the parts of
+//! an EPUB CFI) by matching their text against the spine's XHTML.
 //!
 //! # Note on Raw XHTML Access
 //!
 //! The MuPDF Rust bindings (v0.5) don't expose the fz_archive API for direct
-//! access to raw EPUB XHTML content. For now, content is accessed via MuPDF's
-//! page rendering and text extraction APIs. Raw XHTML access would require
-//! custom FFI bindings or using rbook as a fallback.
+//! access to raw EPUB XHTML content. Rendering and text extraction go through
+//! MuPDF; the package document and spine XHTML are read from the ZIP archive
+//! directly.
 
+mod package;
 mod parser;
+mod positions;
 mod renderer;
 
+pub use package::EpubPackage;
 pub use parser::EpubDocumentHandler;
 pub use parser::EpubDocumentParser;
 pub use renderer::EpubDocumentRenderer;
@@ -0,0 +1,298 @@
+//! EPUB package document and spine
+//!
+//! Reads `META-INF/container.xml`, the package document it points at, and
+//! the spine's content documents straight from the ZIP archive, without
+//! going through MuPDF. The package document also names the cover image:
+//! the manifest item with the `cover-image` property, or the one EPUB 2's
+//! `<meta name="cover" content="...">` points at.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use zip::ZipArchive;
+
+use crate::document::{DocumentError, DocumentResult};
+
+/// An EPUB's spine, in reading order, and its cover image
+pub struct EpubPackage {
+    data: Arc<Vec<u8>>,
+    /// Archive paths of the spine's content documents
+    spine: Vec<String>,
+    /// Archive path and media type of the cover image
+    cover: Option<(String, String)>,
+}
+
+impl EpubPackage {
+    /// Read the package document of an EPUB
+    pub fn from_bytes(data: Arc<Vec<u8>>) -> DocumentResult<Self> {
+        let container = read_entry(&data, "META-INF/container.xml")?;
+        let opf_path = rootfile_path(&String::from_utf8_lossy(&container)).ok_or_else(|| {
+            DocumentError::ParseError("container.xml names no package document".to_string())
+        })?;
+        let opf = read_entry(&data, &opf_path)?;
+
+        let base = opf_path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+        let package = PackageDocument::parse(&String::from_utf8_lossy(&opf))?;
+        let spine = package
+            .spine_hrefs()
+            .map(|href| resolve_href(base, href))
+            .collect();
+        let cover = package
+            .cover()
+            .map(|(href, media_type)| (resolve_href(base, href), media_type.to_string()));
+
+        Ok(Self { data, spine, cover })
+    }
+
+    /// Archive paths of the spine's content documents
+    pub fn spine(&self) -> &[String] {
+        &self.spine
+    }
+
+    /// Markup of a spine item's content document
+    pub fn content(&self, spine_index: usize) -> DocumentResult<String> {
+        let path = self
+            .spine
+            .get(spine_index)
+            .ok_or(DocumentError::ItemNotFound(spine_index))?;
+        let data = read_entry(&self.data, path)?;
+        Ok(String::from_utf8_lossy(&data).into_owned())
+    }
+
+    /// Archive path and media type of the cover image, if one is declared
+    pub fn cover(&self) -> Option<(&str, &str)> {
+        self.cover
+            .as_ref()
+            .map(|(path, media_type)| (path.as_str(), media_type.as_str()))
+    }
+
+    /// Raw data of an entry, by archive path
+    pub fn read(&self, path: &str) -> DocumentResult<Vec<u8>> {
+        read_entry(&self.data, path)
+    }
+}
+
+fn read_entry(data: &[u8], name: &str) -> DocumentResult<Vec<u8>> {
+    let mut archive = ZipArchive::new(Cursor::new(data))
+        .map_err(|e| DocumentError::ParseError(format!("Invalid EPUB archive: {}", e)))?;
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|_| DocumentError::ResourceNotFound(name.to_string()))?;
+    let mut buffer = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Attribute values of an element, keyed by local name
+fn attributes(element: &BytesStart) -> HashMap<String, String> {
+    element
+        .attributes()
+        .flatten()
+        .filter_map(|attr| {
+            let key = String::from_utf8_lossy(attr.key.local_name().as_ref()).into_owned();
+            attr.unescape_value()
+                .ok()
+                .map(|value| (key, value.into_owned()))
+        })
+        .collect()
+}
+
+/// Path of the package document named by `META-INF/container.xml`
+fn rootfile_path(container: &str) -> Option<String> {
+    let mut reader = Reader::from_str(container);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) if e.local_name().as_ref() == b"rootfile" => {
+                if let Some(path) = attributes(&e).remove("full-path") {
+                    return Some(path);
+                }
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => {}
+        }
+    }
+}
+
+/// Manifest items and spine of a package document
+struct PackageDocument {
+    /// Attributes of each manifest item
+    items: Vec<HashMap<String, String>>,
+    /// Spine idrefs, in reading order
+    idrefs: Vec<String>,
+    /// Manifest id named by `<meta name="cover">`
+    meta_cover: Option<String>,
+}
+
+impl PackageDocument {
+    fn parse(opf: &str) -> DocumentResult<Self> {
+        let mut reader = Reader::from_str(opf);
+        let mut package = Self {
+            items: Vec::new(),
+            idrefs: Vec::new(),
+            meta_cover: None,
+        };
+
+        loop {
+            match reader.read_event() {
+                Ok(Event::Start(e)) | Ok(Event::Empty(e)) => match e.local_name().as_ref() {
+                    b"item" => package.items.push(attributes(&e)),
+                    b"itemref" => package.idrefs.extend(attributes(&e).remove("idref")),
+                    b"meta" => {
+                        let mut attrs = attributes(&e);
+                        if attrs.get("name").map(String::as_str) == Some("cover") {
+                            package.meta_cover = attrs.remove("content");
+                        }
+                    }
+                    _ => {}
+                },
+                Ok(Event::Eof) => break,
+                Err(e) => {
+                    return Err(DocumentError::ParseError(format!(
+                        "Invalid package document: {}",
+                        e
+                    )))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(package)
+    }
+
+    fn item(&self, id: &str) -> Option<&HashMap<String, String>> {
+        self.items
+            .iter()
+            .find(|item| item.get("id").map(String::as_str) == Some(id))
+    }
+
+    /// Manifest hrefs of the spine's items, in spine order
+    fn spine_hrefs(&self) -> impl Iterator<Item = &str> {
+        self.idrefs
+            .iter()
+            .filter_map(|idref| self.item(idref)?.get("href").map(String::as_str))
+    }
+
+    /// Href and media type of the manifest item marked as the cover image
+    fn cover(&self) -> Option<(&str, &str)> {
+        let is_image = |item: &&HashMap<String, String>| {
+            item.get("media-type")
+                .is_some_and(|media_type| media_type.starts_with("image/"))
+        };
+
+        let cover = self
+            .items
+            .iter()
+            .filter(is_image)
+            .find(|item| {
+                item.get("properties")
+                    .is_some_and(|props| props.split_whitespace().any(|p| p == "cover-image"))
+            })
+            .or_else(|| self.item(self.meta_cover.as_deref()?).filter(is_image))?;
+
+        Some((cover.get("href")?, cover.get("media-type")?))
+    }
+}
+
+/// Resolve a manifest href against the package document's directory
+fn resolve_href(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or(href);
+    let href = urlencoding::decode(href)
+        .map(|h| h.into_owned())
+        .unwrap_or_else(|_| href.to_string());
+
+    let mut parts: Vec<&str> = if href.starts_with('/') {
+        Vec::new()
+    } else {
+        base.split('/').filter(|p| !p.is_empty()).collect()
+    };
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Write;
+
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+
+    /// Build an EPUB whose spine is the given chapters, in order
+    pub(crate) fn epub(chapters: &[&str]) -> Vec<u8> {
+        let mut manifest = String::new();
+        let mut spine = String::new();
+        for index in 0..chapters.len() {
+            manifest.push_str(&format!(
+                r#"<item id="c{0}" href="text/ch%20{0}.xhtml" media-type="application/xhtml+xml"/>"#,
+                index
+            ));
+            spine.push_str(&format!(r#"<itemref idref="c{}"/>"#, index));
+        }
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+        zip.start_file("mimetype", options).unwrap();
+        zip.write_all(b"application/epub+zip").unwrap();
+        zip.start_file("META-INF/container.xml", options).unwrap();
+        zip.write_all(
+            br#"<container><rootfiles><rootfile full-path="OEBPS/content.opf"/></rootfiles></container>"#,
+        )
+        .unwrap();
+        zip.start_file("OEBPS/content.opf", options).unwrap();
+        zip.write_all(
+            format!(
+                r#"<package><manifest>{}</manifest><spine>{}</spine></package>"#,
+                manifest, spine
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        for (index, chapter) in chapters.iter().enumerate() {
+            zip.start_file(format!("OEBPS/text/ch {}.xhtml", index), options)
+                .unwrap();
+            zip.write_all(chapter.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_spine() {
+        let package =
+            EpubPackage::from_bytes(Arc::new(epub(&["<html/>", "<html><body/></html>"]))).unwrap();
+
+        assert_eq!(
+            package.spine(),
+            ["OEBPS/text/ch 0.xhtml", "OEBPS/text/ch 1.xhtml"]
+        );
+        assert_eq!(package.content(1).unwrap(), "<html><body/></html>");
+        assert!(matches!(
+            package.content(2),
+            Err(DocumentError::ItemNotFound(2))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_href() {
+        assert_eq!(
+            resolve_href("OEBPS", "text/ch1.xhtml#start"),
+            "OEBPS/text/ch1.xhtml"
+        );
+        assert_eq!(
+            resolve_href("OEBPS/content", "../ch1.xhtml"),
+            "OEBPS/ch1.xhtml"
+        );
+        assert_eq!(resolve_href("", "ch1.xhtml"), "ch1.xhtml");
+    }
+}
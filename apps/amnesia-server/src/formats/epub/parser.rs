@@ -1,7 +1,8 @@
 //! EPUB DocumentParser implementation using MuPDF
 //!
 //! Implements the unified `DocumentParser` trait for EPUB documents.
-//! Uses MuPDF via `SafeDocument` for thread-safe access.
+//! Uses MuPDF via `SafeDocument` for thread-safe access. FB2 and MOBI/AZW3
+//! are reflowable in MuPDF as well and go through the same handler.
 //!
 //! # MuPDF EPUB Handling
 //!
@@ -22,41 +23,14 @@ use mupdf::{MetadataName, TextPageOptions};
 use parking_lot::RwLock;
 
 use crate::document::{
-    BoundingBox, CharPosition, Creator, DocumentError, DocumentFormat, DocumentMetadata,
-    DocumentParser, DocumentResult, ParsedDocument, SearchOptions, SearchResult, StructuredText,
-    TextBlock, TextDirection, TextLine, TocEntry,
+    BoundingBox, CharPosition, ContentDocument, ContentPosition, Creator, DocumentError, DocumentFormat,
+    DocumentMetadata, DocumentParser, DocumentResult, LayoutConfig, ParsedDocument, SearchOptions,
+    SearchResult, StructuredText, TextBlock, TextDirection, TextLine, TocEntry,
 };
 use crate::mupdf::SafeDocument;
 
-/// Default layout width for EPUB rendering (points)
-const DEFAULT_LAYOUT_WIDTH: f32 = 800.0;
-
-/// Default layout height for EPUB rendering (points)
-const DEFAULT_LAYOUT_HEIGHT: f32 = 600.0;
-
-/// Default em size for EPUB text layout (points)
-const DEFAULT_EM_SIZE: f32 = 12.0;
-
-/// Layout configuration for reflowable documents
-#[derive(Debug, Clone, Copy)]
-pub struct LayoutConfig {
-    /// Page width in points
-    pub width: f32,
-    /// Page height in points
-    pub height: f32,
-    /// Em size for font scaling
-    pub em: f32,
-}
-
-impl Default for LayoutConfig {
-    fn default() -> Self {
-        Self {
-            width: DEFAULT_LAYOUT_WIDTH,
-            height: DEFAULT_LAYOUT_HEIGHT,
-            em: DEFAULT_EM_SIZE,
-        }
-    }
-}
+use super::package::EpubPackage;
+use super::positions::locate_pages;
 
 /// EPUB implementation of DocumentParser and DocumentRenderer
 ///
@@ -82,6 +56,9 @@ pub struct EpubDocumentHandler {
 
     /// Cached page count after initial layout
     page_count: RwLock<Option<usize>>,
+
+    /// Page start positions for the current layout, computed on first use
+    positions: RwLock<Option<Arc<Vec<ContentPosition>>>>,
 }
 
 impl EpubDocumentHandler {
@@ -92,6 +69,7 @@ impl EpubDocumentHandler {
             doc: Arc::new(doc),
             layout_config: RwLock::new(LayoutConfig::default()),
             page_count: RwLock::new(None),
+            positions: RwLock::new(None),
         };
 
         // Perform initial layout to cache page count
@@ -111,6 +89,7 @@ impl EpubDocumentHandler {
             doc: Arc::new(doc),
             layout_config: RwLock::new(layout_config),
             page_count: RwLock::new(None),
+            positions: RwLock::new(None),
         };
 
         handler.perform_initial_layout()?;
@@ -125,6 +104,7 @@ impl EpubDocumentHandler {
             doc: Arc::new(doc),
             layout_config: RwLock::new(LayoutConfig::default()),
             page_count: RwLock::new(None),
+            positions: RwLock::new(None),
         };
 
         handler.perform_initial_layout()?;
@@ -174,6 +154,7 @@ impl EpubDocumentHandler {
     pub fn relayout(&self, config: LayoutConfig) -> DocumentResult<()> {
         // Update configuration (interior mutable)
         *self.layout_config.write() = config;
+        *self.positions.write() = None;
 
         // Re-run layout to update page count cache
         self.doc.with_doc_mut(|mupdf_doc| {
@@ -204,12 +185,7 @@ impl DocumentParser for EpubDocumentHandler {
         let layout_config = self.layout_config();
 
         tokio::task::spawn_blocking(move || {
-            doc.with_doc_mut(|mupdf_doc| {
-                // Ensure layout before accessing pages
-                if mupdf_doc.is_reflowable().unwrap_or(false) {
-                    mupdf_doc.layout(layout_config.width, layout_config.height, layout_config.em)?;
-                }
-
+            with_layout(&doc, layout_config, |mupdf_doc| {
                 // Extract metadata
                 let get_meta = |name: MetadataName| -> Option<String> {
                     mupdf_doc.metadata(name).ok().filter(|s| !s.is_empty())
@@ -245,6 +221,8 @@ impl DocumentParser for EpubDocumentHandler {
                     date: creation_date,
                     rights: None,
                     subjects: Vec::new(),
+                    series: None,
+                    series_index: None,
                 };
 
                 // Extract table of contents
@@ -258,7 +236,7 @@ impl DocumentParser for EpubDocumentHandler {
 
                 Ok(ParsedDocument {
                     id: doc.id().to_string(),
-                    format: DocumentFormat::Epub,
+                    format: doc.format(),
                     metadata,
                     toc,
                     item_count,
@@ -277,8 +255,11 @@ impl DocumentParser for EpubDocumentHandler {
 
     async fn extract_toc(&self) -> DocumentResult<Vec<TocEntry>> {
         let doc = self.doc.clone();
+        let layout_config = self.layout_config();
 
-        tokio::task::spawn_blocking(move || doc.with_doc(|mupdf_doc| extract_toc(mupdf_doc)))
+        tokio::task::spawn_blocking(move || {
+            with_layout(&doc, layout_config, |mupdf_doc| extract_toc(mupdf_doc))
+        })
             .await
             .map_err(|e| DocumentError::ParseError(format!("Task join error: {}", e)))?
     }
@@ -286,9 +267,10 @@ impl DocumentParser for EpubDocumentHandler {
     async fn extract_text(&self, item_index: usize) -> DocumentResult<String> {
         self.validate_item_index(item_index)?;
         let doc = self.doc.clone();
+        let layout_config = self.layout_config();
 
         tokio::task::spawn_blocking(move || {
-            doc.with_doc(|mupdf_doc| {
+            with_layout(&doc, layout_config, |mupdf_doc| {
                 let page = mupdf_doc.load_page(item_index as i32)?;
                 page.to_text().map_err(Into::into)
             })
@@ -300,9 +282,10 @@ impl DocumentParser for EpubDocumentHandler {
     async fn get_structured_text(&self, item_index: usize) -> DocumentResult<StructuredText> {
         self.validate_item_index(item_index)?;
         let doc = self.doc.clone();
+        let layout_config = self.layout_config();
 
         tokio::task::spawn_blocking(move || {
-            doc.with_doc(|mupdf_doc| {
+            with_layout(&doc, layout_config, |mupdf_doc| {
                 let page = mupdf_doc.load_page(item_index as i32)?;
                 let bounds = page.bounds()?;
                 let width = bounds.x1 - bounds.x0;
@@ -333,9 +316,10 @@ impl DocumentParser for EpubDocumentHandler {
         let limit = if options.limit == 0 { 100 } else { options.limit };
         let include_context = options.include_context;
         let context_length = options.context_length;
+        let layout_config = self.layout_config();
 
         tokio::task::spawn_blocking(move || {
-            doc.with_doc(|mupdf_doc| {
+            with_layout(&doc, layout_config, |mupdf_doc| {
                 let mut results = Vec::new();
                 let page_count = mupdf_doc.page_count()? as usize;
 
@@ -389,12 +373,66 @@ impl DocumentParser for EpubDocumentHandler {
     fn get_item_dimensions(&self, item_index: usize) -> DocumentResult<(f32, f32)> {
         self.validate_item_index(item_index)?;
 
-        self.doc.with_doc(|mupdf_doc| {
+        with_layout(&self.doc, self.layout_config(), |mupdf_doc| {
             let page = mupdf_doc.load_page(item_index as i32)?;
             let bounds = page.bounds()?;
             Ok((bounds.x1 - bounds.x0, bounds.y1 - bounds.y0))
         })
     }
+
+    async fn content_positions(&self) -> DocumentResult<Vec<ContentPosition>> {
+        // FB2 and MOBI have no spine to point into
+        if self.doc.format() != DocumentFormat::Epub {
+            return Ok(Vec::new());
+        }
+        if let Some(positions) = self.positions.read().clone() {
+            return Ok(positions.as_ref().clone());
+        }
+
+        let doc = self.doc.clone();
+        let layout_config = self.layout_config();
+
+        let positions = tokio::task::spawn_blocking(move || {
+            let package = EpubPackage::from_bytes(doc.get_bytes()?)?;
+            let pages = with_layout(&doc, layout_config, |mupdf_doc| {
+                let page_count = mupdf_doc.page_count()? as usize;
+                let mut pages = Vec::with_capacity(page_count);
+                for page_idx in 0..page_count {
+                    pages.push(mupdf_doc.load_page(page_idx as i32)?.to_text()?);
+                }
+                Ok(pages)
+            })?;
+            Ok::<_, DocumentError>(locate_pages(&package, &pages))
+        })
+        .await
+        .map_err(|e| DocumentError::ParseError(format!("Task join error: {}", e)))??;
+
+        let positions = Arc::new(positions);
+        *self.positions.write() = Some(positions.clone());
+        Ok(positions.as_ref().clone())
+    }
+
+    async fn content_document(&self, spine_index: usize) -> DocumentResult<ContentDocument> {
+        if self.doc.format() != DocumentFormat::Epub {
+            return Err(DocumentError::UnsupportedFormat(format!(
+                "{:?} has no content documents",
+                self.doc.format()
+            )));
+        }
+
+        let doc = self.doc.clone();
+        tokio::task::spawn_blocking(move || {
+            let package = EpubPackage::from_bytes(doc.get_bytes()?)?;
+            let content = package.content(spine_index)?;
+            Ok(ContentDocument {
+                spine_index,
+                href: package.spine()[spine_index].clone(),
+                content,
+            })
+        })
+        .await
+        .map_err(|e| DocumentError::ParseError(format!("Task join error: {}", e)))?
+    }
 }
 
 impl EpubDocumentHandler {
@@ -408,6 +446,26 @@ impl EpubDocumentHandler {
 
 // Helper functions
 
+/// Run an operation on a fresh document laid out with `config`
+///
+/// SafeDocument opens a new document per operation, so page numbers only
+/// match the handler's layout if every page access lays out first.
+pub(crate) fn with_layout<F, R>(
+    doc: &SafeDocument,
+    config: LayoutConfig,
+    f: F,
+) -> DocumentResult<R>
+where
+    F: FnOnce(&mut mupdf::Document) -> DocumentResult<R>,
+{
+    doc.with_doc_mut(|mupdf_doc| {
+        if mupdf_doc.is_reflowable().unwrap_or(false) {
+            mupdf_doc.layout(config.width, config.height, config.em)?;
+        }
+        f(mupdf_doc)
+    })
+}
+
 fn extract_toc(doc: &mupdf::Document) -> DocumentResult<Vec<TocEntry>> {
     let outlines = doc.outlines()?;
     Ok(convert_outlines_to_toc(&outlines))
@@ -591,9 +649,10 @@ mod tests {
     #[test]
     fn test_layout_config_default() {
         let config = LayoutConfig::default();
-        assert_eq!(config.width, DEFAULT_LAYOUT_WIDTH);
-        assert_eq!(config.height, DEFAULT_LAYOUT_HEIGHT);
-        assert_eq!(config.em, DEFAULT_EM_SIZE);
+        assert_eq!(config.width, 800.0);
+        assert_eq!(config.height, 600.0);
+        assert_eq!(config.em, 12.0);
+        assert_eq!(config.key(), [80000, 60000, 1200]);
     }
 
     #[test]
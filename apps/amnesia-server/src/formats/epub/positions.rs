@@ -0,0 +1,312 @@
+//! Page start positions in EPUB content documents
+//!
+//! MuPDF lays an EPUB out into pages but can't say where in the XHTML a page
+//! starts. Positions are recovered by matching text: the spine's content
+//! documents are read in order into one stream of lowercase alphanumeric
+//! characters, each remembering its text node and offset, and the start of
+//! every laid-out page's text is searched for from where the previous page
+//! matched. Pages without text, or whose text can't be found, start where
+//! the previous page is expected to end.
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use crate::document::ContentPosition;
+
+use super::package::EpubPackage;
+
+/// Characters of a page's text searched for in the content documents
+const PROBE_LENGTH: usize = 40;
+
+/// Elements whose text MuPDF doesn't lay out
+const SKIPPED_ELEMENTS: &[&[u8]] = &[b"script", b"style"];
+
+/// A text node of a content document
+#[derive(Debug, PartialEq)]
+struct TextNode {
+    /// CFI steps from the root element
+    path: Vec<u32>,
+    text: String,
+}
+
+/// A character of the normalized book text
+struct StreamChar {
+    c: char,
+    spine_index: usize,
+    node: usize,
+    /// Offset into the text node, in UTF-16 code units
+    offset: u32,
+}
+
+/// The normalized text of every spine item, in reading order
+#[derive(Default)]
+struct BookText {
+    chars: Vec<StreamChar>,
+    /// CFI steps of each text node
+    nodes: Vec<Vec<u32>>,
+    /// Range of `chars` belonging to each spine item
+    spine_ranges: Vec<(usize, usize)>,
+}
+
+impl BookText {
+    fn read(package: &EpubPackage) -> Self {
+        let mut book = Self::default();
+        for spine_index in 0..package.spine().len() {
+            let start = book.chars.len();
+            // Unreadable content documents count as empty
+            if let Ok(xhtml) = package.content(spine_index) {
+                for node in text_nodes(&xhtml) {
+                    book.push_node(spine_index, node);
+                }
+            }
+            book.spine_ranges.push((start, book.chars.len()));
+        }
+        book
+    }
+
+    fn push_node(&mut self, spine_index: usize, node: TextNode) {
+        let node_index = self.nodes.len();
+        let mut offset = 0u32;
+        for c in node.text.chars() {
+            for c in c.to_lowercase().filter(|c| c.is_alphanumeric()) {
+                self.chars.push(StreamChar {
+                    c,
+                    spine_index,
+                    node: node_index,
+                    offset,
+                });
+            }
+            offset += c.len_utf16() as u32;
+        }
+        self.nodes.push(node.path);
+    }
+
+    /// First occurrence of `probe` at or after `from`
+    fn find(&self, probe: &[char], from: usize) -> Option<usize> {
+        if probe.is_empty() || probe.len() > self.chars.len() {
+            return None;
+        }
+        (from..=self.chars.len() - probe.len()).find(|&start| {
+            self.chars[start..start + probe.len()]
+                .iter()
+                .zip(probe)
+                .all(|(a, b)| a.c == *b)
+        })
+    }
+
+    fn position(&self, item_index: usize, index: usize) -> ContentPosition {
+        let at = &self.chars[index];
+        let (start, end) = self.spine_ranges[at.spine_index];
+        ContentPosition {
+            item_index,
+            spine_index: at.spine_index,
+            path: self.nodes[at.node].clone(),
+            offset: at.offset,
+            progression: (index - start) as f32 / (end - start) as f32,
+        }
+    }
+}
+
+/// Positions of the pages with the given texts, in page order
+///
+/// Returns no positions if the content documents have no text.
+pub fn locate_pages(package: &EpubPackage, pages: &[String]) -> Vec<ContentPosition> {
+    let book = BookText::read(package);
+    if book.chars.is_empty() {
+        return Vec::new();
+    }
+    let last = book.chars.len() - 1;
+
+    let mut positions = Vec::with_capacity(pages.len());
+    // Where the next search starts, just past the last match
+    let mut cursor = 0;
+    // Where the next page should start if its text isn't found
+    let mut expected = 0;
+
+    for (item_index, text) in pages.iter().enumerate() {
+        let page = normalize(text);
+        let probe = &page[..page.len().min(PROBE_LENGTH)];
+
+        let start = match book.find(probe, cursor) {
+            Some(start) => {
+                cursor = start + 1;
+                start
+            }
+            None => expected.min(last),
+        };
+        expected = expected.max(start + page.len());
+        positions.push(book.position(item_index, start));
+    }
+
+    positions
+}
+
+/// Lowercase alphanumeric characters of a text
+fn normalize(text: &str) -> Vec<char> {
+    text.chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+/// Text nodes inside the body of a content document, with their CFI paths
+///
+/// Adjacent text (split by entities, CDATA or comments) is one node, as in
+/// the DOM. Malformed markup ends the walk early.
+fn text_nodes(xhtml: &str) -> Vec<TextNode> {
+    struct Frame {
+        name: Vec<u8>,
+        step: u32,
+        /// Element children seen so far
+        children: u32,
+    }
+
+    let mut reader = Reader::from_str(xhtml);
+    reader.check_end_names(false);
+
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut nodes: Vec<TextNode> = Vec::new();
+
+    loop {
+        let text = match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let step = match stack.last_mut() {
+                    Some(parent) => {
+                        parent.children += 1;
+                        parent.children * 2
+                    }
+                    // The root element isn't a step
+                    None => 0,
+                };
+                stack.push(Frame {
+                    name: e.local_name().as_ref().to_ascii_lowercase(),
+                    step,
+                    children: 0,
+                });
+                continue;
+            }
+            Ok(Event::Empty(_)) => {
+                if let Some(parent) = stack.last_mut() {
+                    parent.children += 1;
+                }
+                continue;
+            }
+            Ok(Event::End(_)) => {
+                stack.pop();
+                continue;
+            }
+            Ok(Event::Text(e)) => {
+                html_escape::decode_html_entities(&String::from_utf8_lossy(&e)).into_owned()
+            }
+            Ok(Event::CData(e)) => String::from_utf8_lossy(&e).into_owned(),
+            Ok(Event::Eof) | Err(_) => break,
+            _ => continue,
+        };
+
+        let in_body = stack.iter().any(|frame| frame.name == b"body");
+        let skipped = stack
+            .iter()
+            .any(|frame| SKIPPED_ELEMENTS.contains(&frame.name.as_slice()));
+        let Some(parent) = stack.last() else {
+            continue;
+        };
+        if !in_body || skipped {
+            continue;
+        }
+
+        let mut path: Vec<u32> = stack.iter().skip(1).map(|frame| frame.step).collect();
+        path.push(parent.children * 2 + 1);
+        match nodes.last_mut() {
+            Some(last) if last.path == path => last.text.push_str(&text),
+            _ => nodes.push(TextNode { path, text }),
+        }
+    }
+
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::super::package::tests::epub;
+    use super::*;
+
+    #[test]
+    fn test_text_nodes() {
+        let xhtml = r#"<?xml version="1.0"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><head><title>Skip</title><style>p {}</style></head>
+<body><p>One &amp; <em>two</em> three<br/>four</p><script>skip()</script><p>caf&eacute;<!-- c --> again</p></body></html>"#;
+
+        let nodes = text_nodes(xhtml);
+        let found: Vec<(&[u32], &str)> = nodes
+            .iter()
+            .filter(|node| !node.text.trim().is_empty())
+            .map(|node| (node.path.as_slice(), node.text.as_str()))
+            .collect();
+
+        assert_eq!(
+            found,
+            vec![
+                (&[4, 2, 1][..], "One & "),
+                (&[4, 2, 2, 1][..], "two"),
+                (&[4, 2, 3][..], " three"),
+                (&[4, 2, 5][..], "four"),
+                (&[4, 6, 1][..], "café again"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_locate_pages() {
+        let package = EpubPackage::from_bytes(Arc::new(epub(&[
+            "<html><head/><body><h1>Chapter One</h1><p>It was a dark and stormy night; the rain fell.</p></body></html>",
+            "<html><head/><body><p>\u{1F600} Chapter Two begins here.</p><p>And ends.</p></body></html>",
+        ])))
+        .unwrap();
+
+        let pages = vec![
+            "CHAPTER ONE\nIt was a dark and".to_string(),
+            "stormy night; the rain fell.".to_string(),
+            String::new(),
+            "Chapter two begins here.\nAnd ends.".to_string(),
+        ];
+        let positions = locate_pages(&package, &pages);
+
+        assert_eq!(positions.len(), 4);
+        assert_eq!(
+            (positions[0].spine_index, &positions[0].path[..]),
+            (0, &[4, 2, 1][..])
+        );
+        assert_eq!(positions[0].offset, 0);
+        assert_eq!(positions[0].progression, 0.0);
+
+        assert_eq!(
+            (positions[1].spine_index, &positions[1].path[..]),
+            (0, &[4, 4, 1][..])
+        );
+        assert_eq!(positions[1].offset, 18);
+        assert!(positions[1].progression > 0.5);
+
+        // Empty pages start where the previous page ends
+        assert_eq!(positions[2].spine_index, 1);
+        assert_eq!(positions[2].offset, 3);
+
+        // The emoji is two UTF-16 code units
+        assert_eq!(
+            (positions[3].spine_index, &positions[3].path[..]),
+            (1, &[4, 2, 1][..])
+        );
+        assert_eq!(positions[3].offset, 3);
+        assert_eq!(positions[3].item_index, 3);
+    }
+
+    #[test]
+    fn test_locate_pages_without_text() {
+        let package = EpubPackage::from_bytes(Arc::new(epub(&[
+            "<html><body><img src=\"a.png\"/></body></html>",
+        ])))
+        .unwrap();
+        assert!(locate_pages(&package, &["".to_string()]).is_empty());
+    }
+}
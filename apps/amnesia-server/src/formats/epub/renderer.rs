@@ -21,11 +21,11 @@ use mupdf::{Colorspace, Matrix};
 use zip::ZipArchive;
 
 use crate::document::{
-    DocumentError, DocumentParser, DocumentRenderer, DocumentResult, ImageFormat, RenderRequest,
+    DocumentError, DocumentFormat, DocumentParser, DocumentRenderer, DocumentResult, ImageFormat, RenderRequest,
     RenderResult, Resource,
 };
 
-use super::parser::EpubDocumentHandler;
+use super::parser::{with_layout, EpubDocumentHandler};
 
 #[async_trait]
 impl DocumentRenderer for EpubDocumentHandler {
@@ -42,12 +42,7 @@ impl DocumentRenderer for EpubDocumentHandler {
         let layout_config = self.layout_config();
 
         tokio::task::spawn_blocking(move || {
-            doc.with_doc_mut(|mupdf_doc| {
-                // Ensure document is laid out
-                if mupdf_doc.is_reflowable().unwrap_or(false) {
-                    mupdf_doc.layout(layout_config.width, layout_config.height, layout_config.em)?;
-                }
-
+            with_layout(&doc, layout_config, |mupdf_doc| {
                 let page = mupdf_doc.load_page(item_index as i32)?;
 
                 // Build transformation matrix with scale and rotation
@@ -89,12 +84,7 @@ impl DocumentRenderer for EpubDocumentHandler {
         let layout_config = self.layout_config();
 
         tokio::task::spawn_blocking(move || {
-            doc.with_doc_mut(|mupdf_doc| {
-                // Ensure document is laid out
-                if mupdf_doc.is_reflowable().unwrap_or(false) {
-                    mupdf_doc.layout(layout_config.width, layout_config.height, layout_config.em)?;
-                }
-
+            with_layout(&doc, layout_config, |mupdf_doc| {
                 let page = mupdf_doc.load_page(item_index as i32)?;
                 let bounds = page.bounds()?;
 
@@ -125,6 +115,10 @@ impl DocumentRenderer for EpubDocumentHandler {
     async fn get_resource(&self, href: &str) -> DocumentResult<Resource> {
         // Extract resource from EPUB ZIP archive with fuzzy path matching
         let doc = self.document();
+        if doc.format() != DocumentFormat::Epub {
+            // FB2 and MOBI aren't ZIP archives
+            return Err(DocumentError::ResourceNotFound(href.to_string()));
+        }
         let bytes = doc.get_bytes()?;
 
         let href = href.to_string();
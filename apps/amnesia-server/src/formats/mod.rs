@@ -1,7 +1,13 @@
 //! Format-specific document implementations
 //!
 //! This module contains implementations of the document abstraction traits
-//! for specific formats (PDF, EPUB).
+//! for specific formats (PDF, EPUB, comic archives).
+//!
+//! MuPDF also opens XPS, FB2 and MOBI/AZW3: XPS is fixed-layout and uses the
+//! PDF handler, FB2 and MOBI are reflowable and use the EPUB handler.
+//!
+//! The `convert` module converts between formats (EPUB to PDF, PDF to EPUB
+//! or text, anything to KEPUB).
 //!
 //! # Architecture
 //!
@@ -9,8 +15,59 @@
 //! - `Parser`: Implements `DocumentParser` for metadata and text extraction
 //! - `Renderer`: Implements `DocumentRenderer` for page/chapter rendering
 //!
-//! These implementations wrap the lower-level MuPDF bindings and provide
-//! the unified interface defined in the `document` module.
+//! The PDF and EPUB implementations wrap the lower-level MuPDF bindings;
+//! comics are read directly from their archives. All provide the unified
+//! interface defined in the `document` module.
 
+pub mod comic;
+pub mod convert;
 pub mod epub;
 pub mod pdf;
+
+use std::sync::Arc;
+
+use crate::document::{
+    DocumentFormat, DocumentParser, DocumentRenderer, DocumentResult, LayoutConfig, ParsedDocument,
+};
+
+/// Open a document from bytes with the handler for its format
+///
+/// Returns the parser and renderer (both backed by the same handler) along
+/// with the parsed metadata.
+pub async fn open_document(
+    data: Vec<u8>,
+    id: String,
+    format: DocumentFormat,
+) -> DocumentResult<(Arc<dyn DocumentParser>, Arc<dyn DocumentRenderer>, ParsedDocument)> {
+    open_document_with_layout(data, id, format, LayoutConfig::default()).await
+}
+
+/// Open a document from bytes, laying reflowable formats out with `layout`
+///
+/// Fixed-layout formats ignore the layout.
+pub async fn open_document_with_layout(
+    data: Vec<u8>,
+    id: String,
+    format: DocumentFormat,
+    layout: LayoutConfig,
+) -> DocumentResult<(Arc<dyn DocumentParser>, Arc<dyn DocumentRenderer>, ParsedDocument)> {
+    match format {
+        DocumentFormat::Pdf | DocumentFormat::Xps => {
+            let handler = Arc::new(pdf::PdfDocumentHandler::from_bytes(data, id)?);
+            let parsed = handler.parse().await?;
+            Ok((handler.clone(), handler, parsed))
+        }
+        DocumentFormat::Epub | DocumentFormat::Fb2 | DocumentFormat::Mobi => {
+            let handler = Arc::new(epub::EpubDocumentHandler::from_bytes_with_layout(
+                data, id, layout,
+            )?);
+            let parsed = handler.parse().await?;
+            Ok((handler.clone(), handler, parsed))
+        }
+        DocumentFormat::Cbz | DocumentFormat::Cbr | DocumentFormat::Cb7 => {
+            let handler = Arc::new(comic::ComicDocumentHandler::from_bytes(data, id, format)?);
+            let parsed = handler.parse().await?;
+            Ok((handler.clone(), handler, parsed))
+        }
+    }
+}
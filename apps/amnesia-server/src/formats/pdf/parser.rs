@@ -1,7 +1,8 @@
 //! PDF DocumentParser implementation
 //!
 //! Implements the unified `DocumentParser` trait for PDF documents.
-//! Uses MuPDF via `SafeDocument` for thread-safe access.
+//! Uses MuPDF via `SafeDocument` for thread-safe access. XPS documents
+//! are fixed-layout too and go through the same handler.
 
 use std::sync::Arc;
 
@@ -9,7 +10,7 @@ use async_trait::async_trait;
 use mupdf::{MetadataName, TextPageOptions};
 
 use crate::document::{
-    BoundingBox, CharPosition, Creator, DocumentError, DocumentFormat, DocumentMetadata,
+    BoundingBox, CharPosition, Creator, DocumentError, DocumentMetadata,
     DocumentParser, DocumentRenderer, DocumentResult, ParsedDocument, RenderRequest, RenderResult,
     Resource, SearchOptions, SearchResult, StructuredText, TextBlock, TextDirection, TextLine,
     TocEntry,
@@ -88,6 +89,8 @@ impl DocumentParser for PdfDocumentHandler {
                     date,
                     rights: None,
                     subjects: Vec::new(),
+                    series: None,
+                    series_index: None,
                 };
 
                 // Extract table of contents
@@ -105,7 +108,7 @@ impl DocumentParser for PdfDocumentHandler {
 
                 Ok(ParsedDocument {
                     id: doc.id().to_string(),
-                    format: DocumentFormat::Pdf,
+                    format: doc.format(),
                     metadata,
                     toc,
                     item_count: doc.item_count(),
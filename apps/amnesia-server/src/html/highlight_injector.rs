@@ -3,10 +3,10 @@
 //! This module handles injecting highlight spans into EPUB chapter HTML
 //! based on annotation selectors.
 
+use html_escape::encode_double_quoted_attribute;
 use lol_html::{element, rewrite_str, RewriteStrSettings};
-use std::collections::HashMap;
 
-use crate::annotations::Annotation;
+use crate::annotations::{Annotation, Selector};
 
 /// Configuration for highlight injection
 #[derive(Debug, Clone)]
@@ -45,67 +45,87 @@ pub struct InjectionResult {
 
 /// Inject highlight spans into HTML content
 ///
-/// This uses a marker-based approach:
-/// 1. Pre-process annotations to find text positions
-/// 2. Inject highlight spans at those positions using lol_html
+/// Annotations are anchored by their text quote selector: the quote is
+/// searched for in the document's text (entities decoded, whitespace
+/// ignored, text in `<head>`, `<script>` and `<style>` skipped), and the
+/// prefix and suffix pick between repeated occurrences. A highlight that
+/// crosses element boundaries is split into one span per text node, and
+/// overlapping highlights nest, so the markup stays well-formed.
 ///
-/// Note: Full CFI resolution requires DOM context. For server-side injection,
-/// we rely on text quote matching when CFI resolution isn't possible.
+/// Note: Full CFI resolution requires DOM context, so annotations without a
+/// text quote, or whose quote isn't found, are reported as failed.
 pub fn inject_highlights(
     html: &str,
     annotations: &[Annotation],
     config: &HighlightConfig,
 ) -> Result<InjectionResult, InjectError> {
-    if annotations.is_empty() {
-        return Ok(InjectionResult {
-            html: html.to_string(),
-            injected_count: 0,
-            failed_annotations: vec![],
-        });
+    let runs = text_runs(html);
+    let text = document_text(html, &runs);
+
+    // (text run, start, end, annotation) in raw byte offsets
+    let mut marks: Vec<(usize, usize, usize, usize)> = Vec::new();
+    let mut failed_annotations = Vec::new();
+
+    for (index, annotation) in annotations.iter().enumerate() {
+        let Some((start, end)) = anchor(&text, annotation) else {
+            failed_annotations.push(annotation.id.clone());
+            continue;
+        };
+        for chunk in text[start..end].chunk_by(|a, b| a.run == b.run) {
+            let (first, last) = (&chunk[0], &chunk[chunk.len() - 1]);
+            marks.push((first.run, first.start, last.end, index));
+        }
     }
 
-    // Build a map of text quotes to annotations for matching
-    let quote_map: HashMap<&str, &Annotation> = annotations
-        .iter()
-        .filter_map(|a| a.text_quote().map(|q| (q, a)))
-        .collect();
-
-    if quote_map.is_empty() {
-        // No text quotes to match - can't inject server-side without CFI resolution
+    let injected_count = annotations.len() - failed_annotations.len();
+    if marks.is_empty() {
         return Ok(InjectionResult {
             html: html.to_string(),
-            injected_count: 0,
-            failed_annotations: annotations.iter().map(|a| a.id.clone()).collect(),
+            injected_count,
+            failed_annotations,
         });
     }
 
-    let mut injected_count = 0;
-    let mut output = html.to_string();
-
-    // Simple text replacement approach for now
-    // A production implementation would use proper DOM position tracking
-    for (quote, annotation) in &quote_map {
-        if let Some(pos) = output.find(*quote) {
-            let highlight_span = format_highlight_span(annotation, quote, config);
-            output = format!(
-                "{}{}{}",
-                &output[..pos],
-                highlight_span,
-                &output[pos + quote.len()..]
-            );
-            injected_count += 1;
+    // Split each highlighted text run at every highlight boundary, and wrap
+    // each piece in the spans of the highlights covering it
+    let mut pieces: Vec<(usize, usize, String)> = Vec::new();
+    marks.sort_by_key(|&(run, start, _, _)| (run, start));
+    for run_marks in marks.chunk_by(|a, b| a.0 == b.0) {
+        let mut bounds: Vec<usize> = run_marks
+            .iter()
+            .flat_map(|&(_, start, end, _)| [start, end])
+            .collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        for piece in bounds.windows(2) {
+            let (start, end) = (piece[0], piece[1]);
+            let mut covering: Vec<usize> = run_marks
+                .iter()
+                .filter(|&&(_, s, e, _)| s <= start && e >= end)
+                .map(|&(_, _, _, index)| index)
+                .collect();
+            if covering.is_empty() {
+                continue;
+            }
+            covering.sort_unstable();
+
+            let mut wrapped = html[start..end].to_string();
+            for &index in covering.iter().rev() {
+                wrapped = format_highlight_span(&annotations[index], &wrapped, config);
+            }
+            pieces.push((start, end, wrapped));
         }
     }
 
-    let failed_annotations: Vec<String> = annotations
-        .iter()
-        .filter(|a| {
-            a.text_quote()
-                .map(|q| !output.contains(&format!("{}=\"{}\"", config.id_attribute, a.id)))
-                .unwrap_or(true)
-        })
-        .map(|a| a.id.clone())
-        .collect();
+    let mut output = String::with_capacity(html.len() + pieces.len() * 128);
+    let mut copied = 0;
+    for (start, end, wrapped) in &pieces {
+        output.push_str(&html[copied..*start]);
+        output.push_str(wrapped);
+        copied = *end;
+    }
+    output.push_str(&html[copied..]);
 
     Ok(InjectionResult {
         html: output,
@@ -114,6 +134,207 @@ pub fn inject_highlights(
     })
 }
 
+/// Elements whose text is never highlighted
+const SKIPPED_ELEMENTS: &[&str] = &["head", "title", "script", "style"];
+
+/// A non-whitespace character of the document text
+struct TextChar {
+    c: char,
+    /// Text run the character is in
+    run: usize,
+    /// Byte range of the character (or its entity) in the HTML
+    start: usize,
+    end: usize,
+}
+
+/// Byte ranges of the character data between tags, outside skipped elements
+fn text_runs(html: &str) -> Vec<(usize, usize)> {
+    let bytes = html.as_bytes();
+    let mut runs = Vec::new();
+    // End tag that ends the skipped element we are in
+    let mut skip_until: Option<String> = None;
+    let mut text_start = 0;
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        if bytes[pos] != b'<' {
+            pos += 1;
+            continue;
+        }
+
+        let rest = &html[pos..];
+        let skip_past = |terminator: &str| {
+            rest.find(terminator)
+                .map_or(html.len(), |i| pos + i + terminator.len())
+        };
+        let (markup_end, tag) = if rest.starts_with("<!--") {
+            (skip_past("-->"), None)
+        } else if rest.starts_with("<![CDATA[") {
+            (skip_past("]]>"), None)
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            (skip_past(">"), None)
+        } else if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/') {
+            let end = tag_end(html, pos);
+            (end, Some(&html[pos..end]))
+        } else {
+            // A stray `<` is text
+            pos += 1;
+            continue;
+        };
+
+        if skip_until.is_none() && text_start < pos {
+            runs.push((text_start, pos));
+        }
+
+        if let Some(tag) = tag {
+            let (name, closing, empty) = parse_tag(tag);
+            match &skip_until {
+                Some(skipped) if closing && name == *skipped => skip_until = None,
+                // A body start ends an unclosed head
+                Some(_) if !closing && name == "body" => skip_until = None,
+                Some(_) => {}
+                None if !closing && !empty && SKIPPED_ELEMENTS.contains(&name.as_str()) => {
+                    skip_until = Some(name)
+                }
+                None => {}
+            }
+        }
+
+        pos = markup_end;
+        text_start = pos;
+    }
+
+    if skip_until.is_none() && text_start < html.len() {
+        runs.push((text_start, html.len()));
+    }
+    runs
+}
+
+/// End of the tag starting at `start`, just past its `>`
+fn tag_end(html: &str, start: usize) -> usize {
+    let mut quote = None;
+    for (i, b) in html.bytes().enumerate().skip(start + 1) {
+        match (quote, b) {
+            (None, b'"' | b'\'') => quote = Some(b),
+            (Some(q), b) if b == q => quote = None,
+            (None, b'>') => return i + 1,
+            _ => {}
+        }
+    }
+    html.len()
+}
+
+/// Local name (lowercase) of a tag, and whether it is an end or empty tag
+fn parse_tag(tag: &str) -> (String, bool, bool) {
+    let inner = tag.trim_start_matches('<').trim_end_matches('>');
+    let closing = inner.starts_with('/');
+    let empty = inner.trim_end().ends_with('/');
+    let name: String = inner
+        .trim_start_matches('/')
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || matches!(c, ':' | '-' | '_' | '.'))
+        .collect();
+    let name = name
+        .rsplit(':')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    (name, closing, empty)
+}
+
+/// The non-whitespace characters of the text runs, entities decoded
+fn document_text(html: &str, runs: &[(usize, usize)]) -> Vec<TextChar> {
+    let mut chars = Vec::new();
+    for (run, &(run_start, run_end)) in runs.iter().enumerate() {
+        let mut pos = run_start;
+        while pos < run_end {
+            let rest = &html[pos..run_end];
+            let c = rest.chars().next().unwrap_or_default();
+            let entity = (c == '&').then(|| decode_entity(rest)).flatten();
+            let len = entity.as_ref().map_or(c.len_utf8(), |(_, len)| *len);
+
+            let mut push = |c: char| {
+                if !c.is_whitespace() {
+                    chars.push(TextChar {
+                        c,
+                        run,
+                        start: pos,
+                        end: pos + len,
+                    });
+                }
+            };
+            match &entity {
+                Some((decoded, _)) => decoded.chars().for_each(&mut push),
+                None => push(c),
+            }
+            pos += len;
+        }
+    }
+    chars
+}
+
+/// Decoded text and length of the character reference `text` starts with
+fn decode_entity(text: &str) -> Option<(String, usize)> {
+    let semicolon = text.bytes().take(32).position(|b| b == b';')?;
+    let entity = &text[..=semicolon];
+    let decoded = html_escape::decode_html_entities(entity);
+    (decoded != entity).then(|| (decoded.into_owned(), entity.len()))
+}
+
+/// Characters of a string, without whitespace
+fn squeeze(text: &str) -> Vec<char> {
+    text.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// Range of document text characters an annotation's text quote anchors to
+///
+/// The occurrence whose surroundings best match the quote's prefix and
+/// suffix wins; ties go to the first.
+fn anchor(text: &[TextChar], annotation: &Annotation) -> Option<(usize, usize)> {
+    let (exact, prefix, suffix) = annotation.target.selectors.iter().find_map(|s| match s {
+        Selector::TextQuote {
+            exact,
+            prefix,
+            suffix,
+        } => Some((exact, prefix, suffix)),
+        _ => None,
+    })?;
+
+    let exact = squeeze(exact);
+    if exact.is_empty() || exact.len() > text.len() {
+        return None;
+    }
+    let prefix = prefix.as_deref().map(squeeze).unwrap_or_default();
+    let suffix = suffix.as_deref().map(squeeze).unwrap_or_default();
+
+    let mut best: Option<(usize, usize)> = None;
+    for start in 0..=text.len() - exact.len() {
+        let end = start + exact.len();
+        if !text[start..end].iter().zip(&exact).all(|(t, c)| t.c == *c) {
+            continue;
+        }
+
+        let before = text[..start]
+            .iter()
+            .rev()
+            .zip(prefix.iter().rev())
+            .take_while(|(t, c)| t.c == **c)
+            .count();
+        let after = text[end..]
+            .iter()
+            .zip(&suffix)
+            .take_while(|(t, c)| t.c == **c)
+            .count();
+        let score = before + after;
+
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((start, score));
+        }
+    }
+
+    best.map(|(start, _)| (start, start + exact.len()))
+}
+
 /// Format a highlight span element
 fn format_highlight_span(annotation: &Annotation, text: &str, config: &HighlightConfig) -> String {
     let class = format!(
@@ -130,7 +351,7 @@ fn format_highlight_span(annotation: &Annotation, text: &str, config: &Highlight
             .map(|s| {
                 format!(
                     " style=\"background-color: {}; opacity: {};\"",
-                    s.color,
+                    encode_double_quoted_attribute(&s.color),
                     s.opacity.unwrap_or(0.3)
                 )
             })
@@ -143,7 +364,7 @@ fn format_highlight_span(annotation: &Annotation, text: &str, config: &Highlight
         "<span class=\"{}\" {}=\"{}\" {}=\"{:?}\"{}>{}</span>",
         class,
         config.id_attribute,
-        annotation.id,
+        encode_double_quoted_attribute(&annotation.id),
         config.type_attribute,
         annotation.annotation_type,
         style,
@@ -176,21 +397,41 @@ pub fn sanitize_html(html: &str) -> Result<String, InjectError> {
                     el.remove();
                     Ok(())
                 }),
+                // Remove embedded documents and plugins, and <base>, which
+                // would redirect relative URLs
+                element!(EMBED_ELEMENTS, |el| {
+                    el.remove();
+                    Ok(())
+                }),
+                // Remove <meta http-equiv="refresh"> redirects
+                element!("meta[http-equiv]", |el| {
+                    if el
+                        .get_attribute("http-equiv")
+                        .is_some_and(|value| value.trim().eq_ignore_ascii_case("refresh"))
+                    {
+                        el.remove();
+                    }
+                    Ok(())
+                }),
                 // Strip dangerous attributes from all elements
                 element!("*", |el| {
                     // Remove event handlers
-                    for attr in ["onclick", "onload", "onerror", "onmouseover"] {
-                        el.remove_attribute(attr);
+                    let handlers: Vec<String> = el
+                        .attributes()
+                        .iter()
+                        .map(|attr| attr.name())
+                        .filter(|name| name.starts_with("on"))
+                        .collect();
+                    for name in handlers {
+                        el.remove_attribute(&name);
                     }
-                    // Remove javascript: URLs
-                    if let Some(href) = el.get_attribute("href") {
-                        if href.trim().to_lowercase().starts_with("javascript:") {
-                            el.remove_attribute("href");
-                        }
-                    }
-                    if let Some(src) = el.get_attribute("src") {
-                        if src.trim().to_lowercase().starts_with("javascript:") {
-                            el.remove_attribute("src");
+                    // Remove javascript:, vbscript: and non-image data: URLs
+                    for attr in URL_ATTRIBUTES {
+                        if el
+                            .get_attribute(attr)
+                            .is_some_and(|url| is_unsafe_url(&url))
+                        {
+                            el.remove_attribute(attr);
                         }
                     }
                     Ok(())
@@ -204,28 +445,75 @@ pub fn sanitize_html(html: &str) -> Result<String, InjectError> {
     Ok(result)
 }
 
+/// Embedded documents and plugins, and `<base>`
+const EMBED_ELEMENTS: &str = "iframe, frame, frameset, object, embed, applet, base";
+
+/// Attributes holding URLs a browser may load or navigate to
+const URL_ATTRIBUTES: &[&str] = &["href", "src", "xlink:href", "action", "formaction"];
+
+/// Raster image types allowed in `data:` URLs; SVG can carry script
+const DATA_IMAGE_TYPES: &[&str] = &["png", "jpeg", "jpg", "gif", "webp", "bmp"];
+
+/// Whether a URL may run script, ignoring the whitespace and control
+/// characters browsers strip from it
+///
+/// `data:` URLs are only kept for raster images, which EPUBs inline.
+fn is_unsafe_url(url: &str) -> bool {
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    if url.starts_with("javascript:") || url.starts_with("vbscript:") {
+        return true;
+    }
+    match url.strip_prefix("data:") {
+        Some(data) => !data
+            .strip_prefix("image/")
+            .and_then(|rest| rest.split([';', ',']).next())
+            .is_some_and(|subtype| DATA_IMAGE_TYPES.contains(&subtype)),
+        None => false,
+    }
+}
+
 /// Add base URL to relative paths in HTML
+///
+/// Rewrites stylesheet links and the sources of images (including SVG
+/// `<image>`) and media. `.` and `..` segments are resolved against the base,
+/// so it should be the URL of the directory the HTML was loaded from.
 pub fn rewrite_urls(html: &str, base_url: &str) -> Result<String, InjectError> {
+    let rewrite = |el: &mut lol_html::html_content::Element, attr: &str| {
+        if let Some(url) = el.get_attribute(attr) {
+            if is_relative_url(&url) {
+                el.set_attribute(attr, &join_url(base_url, &url))?;
+            }
+        }
+        Ok::<_, lol_html::errors::AttributeNameError>(())
+    };
+
     let result = rewrite_str(
         html,
         RewriteStrSettings {
             element_content_handlers: vec![
-                // Rewrite img src
-                element!("img[src]", |el| {
-                    if let Some(src) = el.get_attribute("src") {
-                        if !src.starts_with("http") && !src.starts_with("data:") {
-                            el.set_attribute("src", &format!("{}/{}", base_url, src))?;
-                        }
-                    }
+                // Rewrite img, audio, video and source src
+                element!("img[src], audio[src], video[src], source[src]", |el| {
+                    rewrite(el, "src")?;
+                    Ok(())
+                }),
+                element!("video[poster]", |el| {
+                    rewrite(el, "poster")?;
                     Ok(())
                 }),
                 // Rewrite link href
                 element!("link[href]", |el| {
-                    if let Some(href) = el.get_attribute("href") {
-                        if !href.starts_with("http") {
-                            el.set_attribute("href", &format!("{}/{}", base_url, href))?;
-                        }
-                    }
+                    rewrite(el, "href")?;
+                    Ok(())
+                }),
+                // Rewrite SVG image href
+                element!("image", |el| {
+                    rewrite(el, "href")?;
+                    rewrite(el, "xlink:href")?;
                     Ok(())
                 }),
             ],
@@ -237,11 +525,62 @@ pub fn rewrite_urls(html: &str, base_url: &str) -> Result<String, InjectError> {
     Ok(result)
 }
 
+/// Whether a URL is a path relative to the document
+fn is_relative_url(url: &str) -> bool {
+    let url = url.trim();
+    if url.is_empty() || url.starts_with(['#', '/', '?']) {
+        return false;
+    }
+    // A scheme is letters, digits, `+`, `-` and `.` before the first `:`
+    match url.split_once(':') {
+        Some((scheme, _)) => !scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')),
+        None => true,
+    }
+}
+
+/// Resolve a relative URL against a base directory URL
+fn join_url(base_url: &str, url: &str) -> String {
+    let url = url.trim();
+    let split = url.find(['?', '#']).unwrap_or(url.len());
+    let (path, rest) = url.split_at(split);
+
+    let mut segments: Vec<&str> = base_url.trim_end_matches('/').split('/').collect();
+    for segment in path.split('/') {
+        match segment {
+            "." => {}
+            // Never climb out of the base's root
+            ".." => {
+                if segments.len() > 1 {
+                    segments.pop();
+                }
+            }
+            segment => segments.push(segment),
+        }
+    }
+    format!("{}{}", segments.join("/"), rest)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::annotations::{AnnotationTarget, AnnotationType};
 
+    fn quote(exact: &str, prefix: Option<&str>, suffix: Option<&str>) -> Annotation {
+        let target = AnnotationTarget::with_selectors("test.xhtml", Vec::new());
+        let mut annotation = Annotation::new_highlight("book-1", target);
+        annotation.target.add_text_quote(exact, prefix, suffix);
+        annotation
+    }
+
+    fn config() -> HighlightConfig {
+        HighlightConfig {
+            include_inline_styles: false,
+            ..HighlightConfig::default()
+        }
+    }
+
     #[test]
     fn test_inject_single_highlight() {
         let html = "<p>Hello world, this is a test.</p>";
@@ -300,4 +639,143 @@ mod tests {
 
         assert!(result.contains("https://example.com/image.jpg"));
     }
+
+    #[test]
+    fn test_inject_across_elements() {
+        let html = "<html><head><title>Hello brave</title></head><body><p>Hello <em>brave</em>\n new world</p></body></html>";
+        let annotation = quote("brave new", None, None);
+        let id = annotation.id.clone();
+
+        let result = inject_highlights(html, &[annotation], &config()).unwrap();
+
+        assert_eq!(result.injected_count, 1);
+        let span = format!(
+            r#"<span class="ll-highlight ll-highlight-highlight" data-annotation-id="{}" data-annotation-type="Highlight">"#,
+            id
+        );
+        assert_eq!(
+            result.html,
+            format!(
+                "<html><head><title>Hello brave</title></head><body><p>Hello <em>{0}brave</span></em>\n {0}new</span> world</p></body></html>",
+                span
+            )
+        );
+    }
+
+    #[test]
+    fn test_inject_uses_prefix_and_suffix() {
+        let html = "<p>the cat sat. the cat ran.</p>";
+        let annotation = quote("cat", Some("the "), Some(" ran"));
+
+        let result = inject_highlights(html, &[annotation], &config()).unwrap();
+
+        assert!(result.html.starts_with("<p>the cat sat. the <span"));
+        assert!(result.html.ends_with(">cat</span> ran.</p>"));
+    }
+
+    #[test]
+    fn test_inject_decodes_entities() {
+        let html = "<p>Salt &amp; pepper</p>";
+        let result =
+            inject_highlights(html, &[quote("Salt & pepper", None, None)], &config()).unwrap();
+
+        assert_eq!(result.injected_count, 1);
+        assert!(result.html.contains(">Salt &amp; pepper</span>"));
+    }
+
+    #[test]
+    fn test_inject_overlapping_highlights_nest() {
+        let html = "<p>one two three</p>";
+        let first = quote("one two", None, None);
+        let second = quote("two three", None, None);
+        let (first_id, second_id) = (first.id.clone(), second.id.clone());
+
+        let result = inject_highlights(html, &[first, second], &config()).unwrap();
+
+        assert_eq!(result.injected_count, 2);
+        let stripped = result
+            .html
+            .replace(&format!(r#" data-annotation-id="{}""#, first_id), " 1")
+            .replace(&format!(r#" data-annotation-id="{}""#, second_id), " 2")
+            .replace(
+                r#"<span class="ll-highlight ll-highlight-highlight""#,
+                "<span",
+            )
+            .replace(r#" data-annotation-type="Highlight""#, "");
+        assert_eq!(
+            stripped,
+            "<p><span 1>one </span><span 1><span 2>two</span></span><span 2> three</span></p>"
+        );
+    }
+
+    #[test]
+    fn test_inject_reports_failed_annotations() {
+        let html = "<p>Hello world</p>";
+        let missing = quote("goodbye", None, None);
+        let target = AnnotationTarget::from_cfi("test.xhtml", "epubcfi(/6/4!/4/2)");
+        let cfi_only = Annotation::new_highlight("book-1", target);
+        let ids = vec![missing.id.clone(), cfi_only.id.clone()];
+
+        let result = inject_highlights(html, &[missing, cfi_only], &config()).unwrap();
+
+        assert_eq!(result.injected_count, 0);
+        assert_eq!(result.failed_annotations, ids);
+        assert_eq!(result.html, html);
+    }
+
+    #[test]
+    fn test_sanitize_all_handlers_and_javascript_urls() {
+        let html = "<svg><a xlink:href=\" java\tscript:x\" onfocusin=\"x()\">Link</a></svg>\
+                    <a href=\"JavaScript:x()\">A</a>";
+        let result = sanitize_html(html).unwrap();
+
+        assert!(!result.contains("onfocusin"));
+        assert!(!result.to_lowercase().contains("javascript"));
+        assert!(result.contains("Link"));
+    }
+
+    #[test]
+    fn test_sanitize_embeds_and_unsafe_urls() {
+        let html = r#"<head><meta http-equiv="Refresh" content="0;url=https://evil.test"><meta charset="utf-8"><base href="https://evil.test/"></head>
+            <body><iframe src="https://evil.test"></iframe><object data="x.swf"></object><embed src="x.swf">
+            <a href="vbscript:msgbox">V</a><a href="data:text/html,<script>x()</script>">D</a>
+            <img src="data:image/svg+xml,<svg/>"><img src="data:image/png;base64,AA"></body>"#;
+        let result = sanitize_html(html).unwrap();
+
+        for removed in [
+            "Refresh",
+            "<base",
+            "iframe",
+            "object",
+            "embed",
+            "vbscript",
+            "text/html",
+            "svg+xml",
+        ] {
+            assert!(!result.contains(removed), "{} kept", removed);
+        }
+        assert!(result.contains(r#"<meta charset="utf-8">"#));
+        assert!(result.contains(r#"src="data:image/png;base64,AA""#));
+        assert!(result.contains(">V</a>") && result.contains(">D</a>"));
+    }
+
+    #[test]
+    fn test_rewrite_resolves_relative_paths() {
+        let html = r##"<img src="../images/a.png#x"><svg><image xlink:href="./b.svg"/></svg><a href="../c.xhtml">c</a>"##;
+        let result = rewrite_urls(html, "/api/v1/documents/1/resources/OEBPS/text").unwrap();
+
+        assert!(result.contains(r#"src="/api/v1/documents/1/resources/OEBPS/images/a.png#x""#));
+        assert!(result.contains(r#"xlink:href="/api/v1/documents/1/resources/OEBPS/text/b.svg""#));
+        assert!(result.contains(r#"href="../c.xhtml""#));
+    }
+
+    #[test]
+    fn test_is_relative_url() {
+        assert!(is_relative_url("images/a.png"));
+        assert!(!is_relative_url("a:b/c.png"));
+        assert!(is_relative_url("x/a:b.png"));
+        assert!(!is_relative_url("data:image/png;base64,AA"));
+        assert!(!is_relative_url("/absolute.png"));
+        assert!(!is_relative_url("#anchor"));
+    }
 }
@@ -6,7 +6,7 @@
 //! # Modules
 //!
 //! - `document`: Unified document abstraction (format-agnostic)
-//! - `formats`: Format-specific implementations (PDF, EPUB)
+//! - `formats`: Format-specific implementations (PDF, EPUB, comics)
 //! - `pdf`: Low-level PDF parsing via MuPDF
 
 // Core modules needed for benchmarks
@@ -53,6 +53,14 @@ pub struct LibraryBook {
     /// Cover image S3 key
     pub cover_key: Option<String>,
 
+    /// Rating in stars (0-5)
+    #[serde(default)]
+    pub rating: Option<f32>,
+
+    /// Calibre custom columns by lookup name (e.g., "#genre")
+    #[serde(default)]
+    pub custom_columns: HashMap<String, serde_json::Value>,
+
     /// Path prefix in S3 (Author/Title)
     pub s3_prefix: String,
 
@@ -83,6 +91,8 @@ impl LibraryBook {
             identifiers: HashMap::new(),
             formats: Vec::new(),
             cover_key: None,
+            rating: None,
+            custom_columns: HashMap::new(),
             s3_prefix,
             added_at: now,
             updated_at: now,
@@ -104,6 +114,28 @@ impl LibraryBook {
         self.formats.iter().find(|f| f.format == FormatType::Epub)
     }
 
+    /// Whether the book has a cover, or a format one can be extracted from
+    pub fn has_cover(&self) -> bool {
+        self.cover_key.is_some()
+            || self
+                .formats
+                .iter()
+                .any(|f| {
+                    matches!(
+                        f.format,
+                        FormatType::Epub
+                            | FormatType::Pdf
+                            | FormatType::Xps
+                            | FormatType::Fb2
+                            | FormatType::Mobi
+                            | FormatType::Azw3
+                            | FormatType::Cbz
+                            | FormatType::Cbr
+                            | FormatType::Cb7
+                    )
+                })
+    }
+
     /// Get display author (first author or "Unknown")
     pub fn display_author(&self) -> &str {
         self.author.as_deref().unwrap_or("Unknown Author")
@@ -121,6 +153,10 @@ pub struct BookFormat {
 
     /// File size in bytes
     pub size: i64,
+
+    /// Page count of page-based formats (PDF, comics), once known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pages: Option<usize>,
 }
 
 /// Supported ebook formats
@@ -133,11 +169,26 @@ pub enum FormatType {
     Azw3,
     Cbz,
     Cbr,
+    Cb7,
     Fb2,
+    Xps,
     Other,
 }
 
 impl FormatType {
+    pub const ALL: [FormatType; 10] = [
+        FormatType::Epub,
+        FormatType::Pdf,
+        FormatType::Mobi,
+        FormatType::Azw3,
+        FormatType::Cbz,
+        FormatType::Cbr,
+        FormatType::Cb7,
+        FormatType::Fb2,
+        FormatType::Xps,
+        FormatType::Other,
+    ];
+
     /// Parse format from file extension
     pub fn from_extension(ext: &str) -> Self {
         match ext.to_lowercase().as_str() {
@@ -147,11 +198,29 @@ impl FormatType {
             "azw3" | "azw" => FormatType::Azw3,
             "cbz" => FormatType::Cbz,
             "cbr" => FormatType::Cbr,
+            "cb7" => FormatType::Cb7,
             "fb2" => FormatType::Fb2,
+            "xps" | "oxps" => FormatType::Xps,
             _ => FormatType::Other,
         }
     }
 
+    /// Lowercase name, as in serialized form
+    pub fn name(&self) -> &'static str {
+        match self {
+            FormatType::Epub => "epub",
+            FormatType::Pdf => "pdf",
+            FormatType::Mobi => "mobi",
+            FormatType::Azw3 => "azw3",
+            FormatType::Cbz => "cbz",
+            FormatType::Cbr => "cbr",
+            FormatType::Cb7 => "cb7",
+            FormatType::Fb2 => "fb2",
+            FormatType::Xps => "xps",
+            FormatType::Other => "other",
+        }
+    }
+
     /// Get MIME type for this format
     pub fn mime_type(&self) -> &'static str {
         match self {
@@ -161,7 +230,9 @@ impl FormatType {
             FormatType::Azw3 => "application/vnd.amazon.mobi8-ebook",
             FormatType::Cbz => "application/vnd.comicbook+zip",
             FormatType::Cbr => "application/vnd.comicbook-rar",
+            FormatType::Cb7 => "application/x-cb7",
             FormatType::Fb2 => "application/x-fictionbook+xml",
+            FormatType::Xps => "application/vnd.ms-xpsdocument",
             FormatType::Other => "application/octet-stream",
         }
     }
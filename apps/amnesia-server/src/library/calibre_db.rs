@@ -0,0 +1,610 @@
+//! Import of Calibre's `metadata.db`
+//!
+//! Calibre keeps its authoritative metadata in a SQLite database at the
+//! library root; per-book `metadata.opf` sidecars are optional and often
+//! stale. The importer reads books, authors, series, tags, identifiers,
+//! ratings, comments and custom columns from the database and maps them
+//! onto `LibraryBook`s, resolving each book's folder to storage keys
+//! relative to the library root.
+//!
+//! Files listed in the database but absent from storage are reported;
+//! books with no files left are not imported.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Connection, Row, SqliteConnection};
+use uuid::Uuid;
+
+use crate::error::Result;
+use crate::storage::{ObjectMetadata, Storage};
+
+use super::book::{BookFormat, FormatType, LibraryBook};
+
+/// Calibre's placeholder for an unknown publication date (year 101)
+const UNDEFINED_DATE_PREFIX: &str = "0101-";
+
+/// A file the database lists but storage does not have
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MissingBookFile {
+    /// Book ID in the Calibre database
+    pub calibre_id: i64,
+    pub title: String,
+    /// Storage key the file was expected at
+    pub key: String,
+}
+
+/// Result of reading a Calibre library
+#[derive(Debug, Default)]
+pub struct CalibreImport {
+    /// Books with at least one file in storage
+    pub books: Vec<LibraryBook>,
+    /// Files missing from storage
+    pub missing: Vec<MissingBookFile>,
+    /// Storage objects of each imported book folder
+    pub objects: HashMap<String, Vec<ObjectMetadata>>,
+}
+
+/// Importer for Calibre `metadata.db` files
+pub struct CalibreImporter {
+    storage: Storage,
+}
+
+impl CalibreImporter {
+    pub fn new(storage: Storage) -> Self {
+        Self { storage }
+    }
+
+    /// Import a `metadata.db` stored in the bucket
+    ///
+    /// Book folders are resolved relative to the folder holding the database.
+    pub async fn import_from_storage(&self, key: &str) -> Result<CalibreImport> {
+        let data = self.storage.get_object(key).await?.data;
+
+        // SQLite needs a file to open
+        let path = std::env::temp_dir().join(format!("calibre-{}.db", Uuid::new_v4()));
+        tokio::fs::write(&path, &data).await?;
+
+        let root = key
+            .rsplit_once('/')
+            .map(|(dir, _)| format!("{}/", dir))
+            .unwrap_or_default();
+        let result = self.import_from_path(&path, &root).await;
+
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            tracing::debug!("Failed to remove {}: {}", path.display(), e);
+        }
+        result
+    }
+
+    /// Import a local `metadata.db` whose book folders live under `root`
+    /// in storage
+    pub async fn import_from_path(&self, path: &Path, root: &str) -> Result<CalibreImport> {
+        let entries = read_metadata_db(path).await?;
+
+        let listing = self
+            .storage
+            .list_all_objects((!root.is_empty()).then_some(root))
+            .await?;
+        let stored: HashMap<&str, &ObjectMetadata> =
+            listing.iter().map(|obj| (obj.key.as_str(), obj)).collect();
+
+        let mut import = CalibreImport::default();
+
+        for entry in entries {
+            let prefix = format!("{}{}", root, entry.path.trim_end_matches('/'));
+            let mut book = entry.book;
+            book.s3_prefix = prefix.clone();
+
+            for file in &entry.files {
+                let key = format!("{}/{}.{}", prefix, file.name, file.format.to_lowercase());
+                match stored.get(key.as_str()) {
+                    Some(obj) => book.formats.push(BookFormat {
+                        format: FormatType::from_extension(&file.format.to_lowercase()),
+                        s3_key: key,
+                        size: obj.size,
+                        pages: None,
+                    }),
+                    None => import.missing.push(MissingBookFile {
+                        calibre_id: entry.calibre_id,
+                        title: book.title.clone(),
+                        key,
+                    }),
+                }
+            }
+
+            if book.formats.is_empty() {
+                tracing::debug!("Skipping Calibre book {} without files", entry.calibre_id);
+                continue;
+            }
+
+            let cover_key = format!("{}/cover.jpg", prefix);
+            if entry.has_cover && stored.contains_key(cover_key.as_str()) {
+                book.cover_key = Some(cover_key);
+            }
+
+            let folder = format!("{}/", prefix);
+            import.objects.insert(
+                prefix,
+                listing
+                    .iter()
+                    .filter(|obj| obj.key.starts_with(&folder))
+                    .cloned()
+                    .collect(),
+            );
+            import.books.push(book);
+        }
+
+        tracing::info!(
+            "Read {} books from Calibre database ({} files missing)",
+            import.books.len(),
+            import.missing.len()
+        );
+
+        Ok(import)
+    }
+}
+
+/// A book as recorded in `metadata.db`, before resolving its files
+struct CalibreEntry {
+    calibre_id: i64,
+    /// Folder relative to the library root (Author/Title (id))
+    path: String,
+    has_cover: bool,
+    files: Vec<CalibreFile>,
+    book: LibraryBook,
+}
+
+/// A row of the `data` table: `<path>/<name>.<format>`
+struct CalibreFile {
+    format: String,
+    name: String,
+}
+
+/// Read all books from a Calibre `metadata.db`
+async fn read_metadata_db(path: &Path) -> Result<Vec<CalibreEntry>> {
+    let mut conn = SqliteConnectOptions::new()
+        .filename(path)
+        .read_only(true)
+        .connect()
+        .await?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT id, title, author_sort, CAST(timestamp AS TEXT) AS timestamp,
+               CAST(pubdate AS TEXT) AS pubdate, series_index, path, uuid, has_cover,
+               CAST(last_modified AS TEXT) AS last_modified
+        FROM books
+        ORDER BY id
+        "#,
+    )
+    .fetch_all(&mut conn)
+    .await?;
+
+    let mut entries: Vec<CalibreEntry> = Vec::with_capacity(rows.len());
+    let mut index: HashMap<i64, usize> = HashMap::new();
+
+    for row in rows {
+        let calibre_id: i64 = row.try_get("id")?;
+        let title: String = row.try_get("title")?;
+        let uuid: Option<String> = row.try_get("uuid")?;
+
+        let mut book = LibraryBook::new(title, String::new());
+        if let Some(ref uuid) = uuid {
+            book.id = uuid.clone();
+            book.identifiers.insert("uuid".to_string(), uuid.clone());
+        }
+        book.identifiers
+            .insert("calibre".to_string(), calibre_id.to_string());
+        book.author_sort = row.try_get("author_sort")?;
+        book.series_index = row
+            .try_get::<Option<f64>, _>("series_index")?
+            .map(|i| i as f32);
+        book.pubdate = row
+            .try_get::<Option<String>, _>("pubdate")?
+            .filter(|date| !date.starts_with(UNDEFINED_DATE_PREFIX))
+            .map(|date| parse_calibre_date(&date).map_or(date, |dt| dt.to_rfc3339()));
+        if let Some(added) = row
+            .try_get::<Option<String>, _>("timestamp")?
+            .as_deref()
+            .and_then(parse_calibre_date)
+        {
+            book.added_at = added;
+        }
+        if let Some(modified) = row
+            .try_get::<Option<String>, _>("last_modified")?
+            .as_deref()
+            .and_then(parse_calibre_date)
+        {
+            book.updated_at = modified;
+        }
+
+        index.insert(calibre_id, entries.len());
+        entries.push(CalibreEntry {
+            calibre_id,
+            path: row.try_get("path")?,
+            has_cover: row.try_get::<Option<bool>, _>("has_cover")?.unwrap_or(false),
+            files: Vec::new(),
+            book,
+        });
+    }
+
+    let entry = |id: i64| index.get(&id).copied();
+
+    for (id, name) in pairs(
+        &mut conn,
+        "SELECT l.book, a.name FROM books_authors_link l
+         JOIN authors a ON a.id = l.author ORDER BY l.book, l.id",
+    )
+    .await?
+    {
+        if let Some(i) = entry(id) {
+            let book = &mut entries[i].book;
+            book.author.get_or_insert_with(|| name.clone());
+            book.authors.push(name);
+        }
+    }
+
+    for (id, name) in pairs(
+        &mut conn,
+        "SELECT l.book, s.name FROM books_series_link l JOIN series s ON s.id = l.series",
+    )
+    .await?
+    {
+        if let Some(i) = entry(id) {
+            entries[i].book.series = Some(name);
+        }
+    }
+
+    // Calibre gives every book a series index, in a series or not
+    for entry in &mut entries {
+        if entry.book.series.is_none() {
+            entry.book.series_index = None;
+        }
+    }
+
+    for (id, name) in pairs(
+        &mut conn,
+        "SELECT l.book, t.name FROM books_tags_link l
+         JOIN tags t ON t.id = l.tag ORDER BY l.book, t.name",
+    )
+    .await?
+    {
+        if let Some(i) = entry(id) {
+            entries[i].book.tags.push(name);
+        }
+    }
+
+    for (id, name) in pairs(
+        &mut conn,
+        "SELECT l.book, p.name FROM books_publishers_link l
+         JOIN publishers p ON p.id = l.publisher",
+    )
+    .await?
+    {
+        if let Some(i) = entry(id) {
+            entries[i].book.publisher = Some(name);
+        }
+    }
+
+    for (id, code) in pairs(
+        &mut conn,
+        "SELECT l.book, g.lang_code FROM books_languages_link l
+         JOIN languages g ON g.id = l.lang_code ORDER BY l.book, l.item_order DESC",
+    )
+    .await?
+    {
+        // Descending order leaves the first language set
+        if let Some(i) = entry(id) {
+            entries[i].book.language = Some(code);
+        }
+    }
+
+    for (id, text) in pairs(&mut conn, "SELECT book, text FROM comments").await? {
+        if let Some(i) = entry(id) {
+            entries[i].book.description = Some(text);
+        }
+    }
+
+    for (id, rating) in pairs(
+        &mut conn,
+        "SELECT l.book, CAST(r.rating AS TEXT) FROM books_ratings_link l
+         JOIN ratings r ON r.id = l.rating",
+    )
+    .await?
+    {
+        if let Some(i) = entry(id) {
+            entries[i].book.rating = half_stars(&rating).map(|r| r as f32);
+        }
+    }
+
+    let identifiers: Vec<(i64, String, String)> =
+        sqlx::query_as("SELECT book, type, val FROM identifiers")
+            .fetch_all(&mut conn)
+            .await?;
+    for (id, scheme, value) in identifiers {
+        if let Some(i) = entry(id) {
+            entries[i].book.identifiers.insert(scheme.to_lowercase(), value);
+        }
+    }
+
+    let files: Vec<(i64, String, String)> =
+        sqlx::query_as("SELECT book, format, name FROM data ORDER BY book, format")
+            .fetch_all(&mut conn)
+            .await?;
+    for (id, format, name) in files {
+        if let Some(i) = entry(id) {
+            entries[i].files.push(CalibreFile { format, name });
+        }
+    }
+
+    for (id, column, value) in read_custom_columns(&mut conn).await? {
+        if let Some(i) = entry(id) {
+            entries[i].book.custom_columns.insert(column, value);
+        }
+    }
+
+    conn.close().await?;
+    Ok(entries)
+}
+
+/// `(book, text)` pairs from a two-column query
+async fn pairs(conn: &mut SqliteConnection, sql: &str) -> Result<Vec<(i64, String)>> {
+    Ok(sqlx::query_as(sql).fetch_all(conn).await?)
+}
+
+/// A user-defined column from `custom_columns`
+#[derive(sqlx::FromRow)]
+struct CustomColumn {
+    id: i64,
+    label: String,
+    datatype: String,
+    is_multiple: bool,
+    normalized: bool,
+}
+
+/// `(book, "#label", value)` for every custom column value
+///
+/// Normalized columns (text, enumeration, series, rating) keep values in
+/// `custom_column_N` joined through `books_custom_column_N_link`; the rest
+/// store one value per book in `custom_column_N`. Composite columns are
+/// computed by Calibre from templates and have no stored values.
+async fn read_custom_columns(conn: &mut SqliteConnection) -> Result<Vec<(i64, String, Value)>> {
+    let columns: Vec<CustomColumn> = sqlx::query_as(
+        r#"
+        SELECT id, label, datatype, is_multiple, normalized
+        FROM custom_columns
+        WHERE mark_for_delete = 0 AND datatype != 'composite'
+        "#,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut values = Vec::new();
+
+    for column in columns {
+        let name = format!("#{}", column.label);
+
+        let rows: Vec<(i64, Option<String>, Option<f64>)> = if column.normalized {
+            let extra = if column.datatype == "series" { "l.extra" } else { "NULL" };
+            sqlx::query_as(&format!(
+                "SELECT l.book, CAST(v.value AS TEXT), {extra}
+                 FROM books_custom_column_{id}_link l
+                 JOIN custom_column_{id} v ON v.id = l.value
+                 ORDER BY l.book, l.id",
+                extra = extra,
+                id = column.id
+            ))
+            .fetch_all(&mut *conn)
+            .await?
+        } else {
+            sqlx::query_as(&format!(
+                "SELECT book, CAST(value AS TEXT), NULL FROM custom_column_{} ORDER BY book",
+                column.id
+            ))
+            .fetch_all(&mut *conn)
+            .await?
+        };
+
+        let mut by_book: Vec<(i64, Vec<Value>)> = Vec::new();
+        for (book, raw, extra) in rows {
+            let Some(value) = raw.and_then(|raw| custom_value(&column.datatype, &raw, extra)) else {
+                continue;
+            };
+            match by_book.last_mut() {
+                Some((last, list)) if *last == book => list.push(value),
+                _ => by_book.push((book, vec![value])),
+            }
+        }
+
+        for (book, mut list) in by_book {
+            let value = if column.is_multiple {
+                Value::Array(list)
+            } else {
+                list.swap_remove(0)
+            };
+            values.push((book, name.clone(), value));
+        }
+    }
+
+    Ok(values)
+}
+
+/// Convert a stored custom column value to JSON
+fn custom_value(datatype: &str, raw: &str, extra: Option<f64>) -> Option<Value> {
+    match datatype {
+        "int" => raw.parse::<i64>().ok().map(Value::from),
+        "float" => raw.parse::<f64>().ok().map(Value::from),
+        "bool" => Some(Value::Bool(raw != "0")),
+        "rating" => half_stars(raw).map(Value::from),
+        "datetime" => Some(Value::from(
+            parse_calibre_date(raw).map_or_else(|| raw.to_string(), |dt| dt.to_rfc3339()),
+        )),
+        "series" => Some(serde_json::json!({ "name": raw, "index": extra })),
+        _ => Some(Value::from(raw)),
+    }
+}
+
+/// Calibre stores ratings as half-stars (0-10); convert to stars
+fn half_stars(raw: &str) -> Option<f64> {
+    raw.parse::<f64>().ok().map(|r| r / 2.0)
+}
+
+/// Parse Calibre's `YYYY-MM-DD HH:MM:SS[.ffffff]+00:00` timestamps
+fn parse_calibre_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f%:z")
+        .or_else(|_| DateTime::parse_from_rfc3339(value))
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+                .ok()
+                .map(|dt| dt.and_utc())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalStorage;
+    use std::sync::Arc;
+
+    /// The parts of Calibre's schema the importer reads
+    const CALIBRE_SCHEMA: &str = r#"
+        CREATE TABLE books (id INTEGER PRIMARY KEY, title TEXT NOT NULL DEFAULT 'Unknown',
+            sort TEXT, timestamp TIMESTAMP, pubdate TIMESTAMP, series_index REAL NOT NULL DEFAULT 1.0,
+            author_sort TEXT, path TEXT NOT NULL DEFAULT '', uuid TEXT,
+            has_cover BOOL DEFAULT 0, last_modified TIMESTAMP);
+        CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT NOT NULL, sort TEXT);
+        CREATE TABLE books_authors_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, author INTEGER NOT NULL);
+        CREATE TABLE series (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+        CREATE TABLE books_series_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, series INTEGER NOT NULL);
+        CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+        CREATE TABLE books_tags_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, tag INTEGER NOT NULL);
+        CREATE TABLE publishers (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+        CREATE TABLE books_publishers_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, publisher INTEGER NOT NULL);
+        CREATE TABLE languages (id INTEGER PRIMARY KEY, lang_code TEXT NOT NULL);
+        CREATE TABLE books_languages_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL,
+            lang_code INTEGER NOT NULL, item_order INTEGER NOT NULL DEFAULT 0);
+        CREATE TABLE comments (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, text TEXT NOT NULL);
+        CREATE TABLE ratings (id INTEGER PRIMARY KEY, rating INTEGER);
+        CREATE TABLE books_ratings_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, rating INTEGER NOT NULL);
+        CREATE TABLE identifiers (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, type TEXT NOT NULL, val TEXT NOT NULL);
+        CREATE TABLE data (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, format TEXT NOT NULL,
+            uncompressed_size INTEGER NOT NULL, name TEXT NOT NULL);
+        CREATE TABLE custom_columns (id INTEGER PRIMARY KEY, label TEXT NOT NULL, name TEXT NOT NULL,
+            datatype TEXT NOT NULL, mark_for_delete BOOL DEFAULT 0, editable BOOL DEFAULT 1,
+            display TEXT DEFAULT '{}', is_multiple BOOL DEFAULT 0, normalized BOOL NOT NULL);
+        CREATE TABLE custom_column_1 (id INTEGER PRIMARY KEY, value TEXT NOT NULL);
+        CREATE TABLE books_custom_column_1_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, value INTEGER NOT NULL);
+        CREATE TABLE custom_column_2 (id INTEGER PRIMARY KEY, book INTEGER, value INTEGER);
+    "#;
+
+    const CALIBRE_DATA: &str = r#"
+        INSERT INTO books VALUES (1, 'Dune', 'Dune', '2020-01-02 03:04:05.123456+00:00',
+            '1965-08-01 00:00:00+00:00', 1.0, 'Herbert, Frank', 'Frank Herbert/Dune (1)',
+            'dune-uuid', 1, '2021-01-01 00:00:00+00:00');
+        INSERT INTO books VALUES (2, 'Lost Book', 'Lost Book', '2020-01-02 03:04:05+00:00',
+            '0101-01-01 00:00:00+00:00', 1.0, 'Nobody', 'Nobody/Lost Book (2)', 'lost-uuid', 0, NULL);
+        INSERT INTO authors VALUES (1, 'Frank Herbert', 'Herbert, Frank'), (2, 'Nobody', 'Nobody');
+        INSERT INTO books_authors_link VALUES (1, 1, 1), (2, 2, 2);
+        INSERT INTO series VALUES (1, 'Dune Chronicles');
+        INSERT INTO books_series_link VALUES (1, 1, 1);
+        INSERT INTO tags VALUES (1, 'Science Fiction'), (2, 'Classics');
+        INSERT INTO books_tags_link VALUES (1, 1, 1), (2, 1, 2);
+        INSERT INTO publishers VALUES (1, 'Chilton');
+        INSERT INTO books_publishers_link VALUES (1, 1, 1);
+        INSERT INTO languages VALUES (1, 'eng');
+        INSERT INTO books_languages_link VALUES (1, 1, 1, 0);
+        INSERT INTO comments VALUES (1, 1, '<p>Spice.</p>');
+        INSERT INTO ratings VALUES (1, 8);
+        INSERT INTO books_ratings_link VALUES (1, 1, 1);
+        INSERT INTO identifiers VALUES (1, 1, 'isbn', '9780441013593');
+        INSERT INTO data VALUES (1, 1, 'EPUB', 100, 'Dune - Frank Herbert'),
+                                (2, 1, 'MOBI', 100, 'Dune - Frank Herbert'),
+                                (3, 2, 'EPUB', 100, 'Lost Book - Nobody');
+        INSERT INTO custom_columns VALUES (1, 'moods', 'Moods', 'text', 0, 1, '{}', 1, 1),
+                                          (2, 'read', 'Read', 'bool', 0, 1, '{}', 0, 0);
+        INSERT INTO custom_column_1 VALUES (1, 'dark'), (2, 'epic');
+        INSERT INTO books_custom_column_1_link VALUES (1, 1, 2), (2, 1, 1);
+        INSERT INTO custom_column_2 VALUES (1, 1, 1);
+    "#;
+
+    #[test]
+    fn test_parse_calibre_date() {
+        let dt = parse_calibre_date("2020-01-02 03:04:05.123456+00:00").unwrap();
+        assert_eq!(dt.to_rfc3339(), "2020-01-02T03:04:05.123456+00:00");
+        assert!(parse_calibre_date("2020-01-02 03:04:05+00:00").is_some());
+        assert!(parse_calibre_date("2020-01-02T03:04:05Z").is_some());
+        assert!(parse_calibre_date("not a date").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_import_metadata_db() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage: Storage = Arc::new(LocalStorage::new(dir.path().join("bucket")).await.unwrap());
+
+        let db_path = dir.path().join("metadata.db");
+        let mut conn = SqliteConnectOptions::new()
+            .filename(&db_path)
+            .create_if_missing(true)
+            .connect()
+            .await
+            .unwrap();
+        sqlx::query(CALIBRE_SCHEMA).execute(&mut conn).await.unwrap();
+        sqlx::query(CALIBRE_DATA).execute(&mut conn).await.unwrap();
+        conn.close().await.unwrap();
+
+        for key in [
+            "calibre/Frank Herbert/Dune (1)/Dune - Frank Herbert.epub",
+            "calibre/Frank Herbert/Dune (1)/cover.jpg",
+        ] {
+            storage.put_object(key, b"data".to_vec(), "application/octet-stream").await.unwrap();
+        }
+
+        let import = CalibreImporter::new(storage)
+            .import_from_path(&db_path, "calibre/")
+            .await
+            .unwrap();
+
+        assert_eq!(import.books.len(), 1);
+        let book = &import.books[0];
+        assert_eq!(book.id, "dune-uuid");
+        assert_eq!(book.s3_prefix, "calibre/Frank Herbert/Dune (1)");
+        assert_eq!(book.author.as_deref(), Some("Frank Herbert"));
+        assert_eq!(book.author_sort.as_deref(), Some("Herbert, Frank"));
+        assert_eq!(book.series.as_deref(), Some("Dune Chronicles"));
+        assert_eq!(book.tags, vec!["Classics".to_string(), "Science Fiction".to_string()]);
+        assert_eq!(book.publisher.as_deref(), Some("Chilton"));
+        assert_eq!(book.language.as_deref(), Some("eng"));
+        assert_eq!(book.description.as_deref(), Some("<p>Spice.</p>"));
+        assert_eq!(book.rating, Some(4.0));
+        assert_eq!(book.identifiers["isbn"], "9780441013593");
+        assert_eq!(book.identifiers["calibre"], "1");
+        assert_eq!(book.pubdate.as_deref(), Some("1965-08-01T00:00:00+00:00"));
+        assert_eq!(book.custom_columns["#moods"], serde_json::json!(["epic", "dark"]));
+        assert_eq!(book.custom_columns["#read"], serde_json::json!(true));
+        assert_eq!(
+            book.cover_key.as_deref(),
+            Some("calibre/Frank Herbert/Dune (1)/cover.jpg")
+        );
+        assert_eq!(book.formats.len(), 1);
+        assert_eq!(book.formats[0].format, FormatType::Epub);
+        assert_eq!(import.objects["calibre/Frank Herbert/Dune (1)"].len(), 2);
+
+        let mut missing: Vec<_> = import.missing.iter().map(|m| m.key.as_str()).collect();
+        missing.sort();
+        assert_eq!(
+            missing,
+            vec![
+                "calibre/Frank Herbert/Dune (1)/Dune - Frank Herbert.mobi",
+                "calibre/Nobody/Lost Book (2)/Lost Book - Nobody.epub",
+            ]
+        );
+    }
+}
@@ -0,0 +1,455 @@
+//! Book covers
+//!
+//! A book's cover is the image in its folder (Calibre's `cover.jpg`) when
+//! there is one. Otherwise it is extracted on first request: the image an
+//! EPUB's manifest marks as its cover (`properties="cover-image"`, or the
+//! EPUB 2 `<meta name="cover">`), falling back to the first page of the
+//! EPUB, PDF or comic rendered through the `DocumentCache`. Extracted covers
+//! are stored beside the book so later scans and Calibre see them too.
+//!
+//! Resized WebP/JPEG variants are cached in memory.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, GenericImageView};
+use lru::LruCache;
+use parking_lot::Mutex;
+use sqlx::SqlitePool;
+
+use crate::db::CatalogRepository;
+use crate::document::{ImageFormat, RenderResult};
+use crate::error::{AppError, Result};
+use crate::formats::epub::EpubPackage;
+use crate::storage::Storage;
+
+use super::book::{BookFormat, LibraryBook};
+use super::documents::LibraryDocuments;
+
+/// Longest edge of a cover rendered from the first page, in pixels
+pub const RENDERED_COVER_SIZE: u32 = 1200;
+
+/// Bounds for a `size` given in pixels
+const MIN_VARIANT_SIZE: u32 = 16;
+const MAX_VARIANT_SIZE: u32 = 2400;
+
+/// JPEG quality of resized variants
+const JPEG_QUALITY: u8 = 85;
+
+/// Number of resized variants kept in memory
+const VARIANT_CACHE_SIZE: usize = 512;
+
+/// Requested cover size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CoverSize {
+    /// 160px, for OPDS thumbnails and lists
+    Thumbnail,
+    /// 320px
+    Small,
+    /// 600px
+    Medium,
+    /// 1200px
+    Large,
+    /// Longest edge in pixels
+    Pixels(u32),
+    /// The stored image's own dimensions
+    #[default]
+    Original,
+}
+
+impl CoverSize {
+    /// Longest edge of the variant, or `None` to keep the original size
+    pub fn max_edge(&self) -> Option<u32> {
+        match self {
+            CoverSize::Thumbnail => Some(160),
+            CoverSize::Small => Some(320),
+            CoverSize::Medium => Some(600),
+            CoverSize::Large => Some(1200),
+            CoverSize::Pixels(px) => Some(*px),
+            CoverSize::Original => None,
+        }
+    }
+}
+
+impl FromStr for CoverSize {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "thumbnail" | "thumb" => Ok(CoverSize::Thumbnail),
+            "small" => Ok(CoverSize::Small),
+            "medium" => Ok(CoverSize::Medium),
+            "large" => Ok(CoverSize::Large),
+            "original" | "full" => Ok(CoverSize::Original),
+            other => other
+                .parse::<u32>()
+                .map(|px| CoverSize::Pixels(px.clamp(MIN_VARIANT_SIZE, MAX_VARIANT_SIZE)))
+                .map_err(|_| AppError::BadRequest(format!("Invalid cover size: {}", s))),
+        }
+    }
+}
+
+/// A cover image and its format
+#[derive(Debug, Clone)]
+pub struct CoverImage {
+    pub data: Vec<u8>,
+    pub format: ImageFormat,
+}
+
+impl CoverImage {
+    /// Wrap an image of the given media type, converting formats other than
+    /// JPEG, PNG and WebP (GIF, BMP, ...) to JPEG
+    ///
+    /// Returns `None` for images that can't be decoded, such as SVG.
+    pub fn from_media_type(data: Vec<u8>, media_type: &str) -> Option<Self> {
+        let format = match media_type.to_lowercase().as_str() {
+            "image/jpeg" | "image/jpg" => ImageFormat::Jpeg,
+            "image/png" => ImageFormat::Png,
+            "image/webp" => ImageFormat::Webp,
+            _ => {
+                let image = image::load_from_memory(&data).ok()?;
+                return encode(&image, ImageFormat::Jpeg).ok().map(|data| Self {
+                    data,
+                    format: ImageFormat::Jpeg,
+                });
+            }
+        };
+        Some(Self { data, format })
+    }
+
+    /// File name of the cover in the book folder
+    pub fn file_name(&self) -> String {
+        format!("cover.{}", self.format.extension())
+    }
+}
+
+impl From<RenderResult> for CoverImage {
+    fn from(result: RenderResult) -> Self {
+        Self {
+            data: result.data,
+            format: result.format,
+        }
+    }
+}
+
+/// Extract the cover image an EPUB's package document points at
+///
+/// Uses the EPUB 3 manifest item with the `cover-image` property, or the
+/// item named by EPUB 2's `<meta name="cover" content="...">`.
+pub fn epub_cover(epub: Arc<Vec<u8>>) -> Option<CoverImage> {
+    let package = EpubPackage::from_bytes(epub).ok()?;
+    let (path, media_type) = package.cover()?;
+    let data = package.read(path).ok()?;
+
+    CoverImage::from_media_type(data, media_type)
+}
+
+/// Resize an image to fit within `max_edge` (never enlarging it) and
+/// encode it as `format`
+pub fn resize_cover(data: &[u8], max_edge: Option<u32>, format: ImageFormat) -> Result<Vec<u8>> {
+    let image = image::load_from_memory(data)
+        .map_err(|e| AppError::Internal(format!("Failed to decode cover: {}", e)))?;
+
+    let (width, height) = image.dimensions();
+    let image = match max_edge {
+        Some(edge) if width.max(height) > edge => image.resize(edge, edge, FilterType::Lanczos3),
+        _ => image,
+    };
+
+    encode(&image, format).map_err(|e| AppError::Internal(format!("Failed to encode cover: {}", e)))
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> image::ImageResult<Vec<u8>> {
+    let mut output = Vec::new();
+    match format {
+        // JPEG has no alpha channel
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut output, JPEG_QUALITY))?,
+        ImageFormat::Png => image.write_to(&mut Cursor::new(&mut output), image::ImageFormat::Png)?,
+        ImageFormat::Webp => DynamicImage::ImageRgba8(image.to_rgba8())
+            .write_to(&mut Cursor::new(&mut output), image::ImageFormat::WebP)?,
+    }
+    Ok(output)
+}
+
+/// Cache key of a resized variant
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct VariantKey {
+    cover_key: String,
+    /// Book's `updated_at`, so replaced covers aren't served stale
+    updated_at: DateTime<Utc>,
+    max_edge: Option<u32>,
+    format: ImageFormat,
+}
+
+/// Extracts, stores and resizes book covers
+#[derive(Clone)]
+pub struct CoverService {
+    inner: Arc<CoverInner>,
+}
+
+struct CoverInner {
+    storage: Storage,
+    pool: SqlitePool,
+    documents: LibraryDocuments,
+    variants: Mutex<LruCache<VariantKey, Vec<u8>>>,
+    /// Books without an extractable cover, with the `updated_at` they were
+    /// tried at
+    missing: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl CoverService {
+    /// Create a cover service rendering through `documents`
+    pub fn new(storage: Storage, pool: SqlitePool, documents: LibraryDocuments) -> Self {
+        let capacity = NonZeroUsize::new(VARIANT_CACHE_SIZE).unwrap();
+        Self {
+            inner: Arc::new(CoverInner {
+                storage,
+                pool,
+                documents,
+                variants: Mutex::new(LruCache::new(capacity)),
+                missing: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// A book's cover at `size` in `format` (JPEG or WebP)
+    ///
+    /// Extracts the cover first if the book has none yet. Returns `None`
+    /// if there is nothing to extract it from.
+    pub async fn cover(&self, id: &str, size: CoverSize, format: ImageFormat) -> Result<Option<CoverImage>> {
+        let catalog = CatalogRepository::new(&self.inner.pool);
+        let book = catalog
+            .get(id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Book not found: {}", id)))?;
+
+        let cover_key = match book.cover_key.clone() {
+            Some(key) => key,
+            None => match self.extract(&book).await? {
+                Some(key) => key,
+                None => return Ok(None),
+            },
+        };
+
+        let key = VariantKey {
+            cover_key,
+            updated_at: book.updated_at,
+            max_edge: size.max_edge(),
+            format,
+        };
+        if let Some(data) = self.inner.variants.lock().get(&key).cloned() {
+            return Ok(Some(CoverImage { data, format }));
+        }
+
+        let original = self.inner.storage.get_object(&key.cover_key).await?.data;
+        let max_edge = key.max_edge;
+        let data = tokio::task::spawn_blocking(move || resize_cover(&original, max_edge, format))
+            .await
+            .map_err(|e| AppError::Internal(format!("Cover resize task failed: {}", e)))??;
+
+        self.inner.variants.lock().put(key, data.clone());
+        Ok(Some(CoverImage { data, format }))
+    }
+
+    /// Extract a book's cover, store it in the book folder and record it in
+    /// the catalog
+    ///
+    /// Returns the stored key, or `None` if no cover could be extracted.
+    /// Failed books are not retried until their catalog entry changes.
+    pub async fn extract(&self, book: &LibraryBook) -> Result<Option<String>> {
+        if self.inner.missing.lock().get(&book.id) == Some(&book.updated_at) {
+            return Ok(None);
+        }
+
+        let cover = match self.find_cover(book).await {
+            Some(cover) => cover,
+            None => {
+                tracing::debug!(book_id = %book.id, "No cover found");
+                self.inner.missing.lock().insert(book.id.clone(), book.updated_at);
+                return Ok(None);
+            }
+        };
+
+        let key = format!("{}/{}", book.s3_prefix, cover.file_name());
+        self.inner
+            .storage
+            .put_object(&key, cover.data, cover.format.content_type())
+            .await?;
+
+        // Record the folder's objects too, so the next scan doesn't
+        // rebuild the entry just for the new file
+        let catalog = CatalogRepository::new(&self.inner.pool);
+        catalog.set_cover(&book.id, Some(&key)).await?;
+        let objects = self
+            .inner
+            .storage
+            .list_all_objects(Some(&format!("{}/", book.s3_prefix)))
+            .await?;
+        catalog.record_objects(&book.s3_prefix, &objects).await?;
+
+        tracing::info!(book_id = %book.id, key = %key, "Extracted cover");
+        Ok(Some(key))
+    }
+
+    /// The EPUB's own cover image, else the first page of the EPUB, a
+    /// page-based format, or any other format MuPDF opens (FB2, MOBI)
+    async fn find_cover(&self, book: &LibraryBook) -> Option<CoverImage> {
+        let epub = book.epub();
+        if let Some(format) = epub {
+            // A failed download or cover lookup still falls back to rendering
+            if let Some(data) = self.download(book, format).await {
+                let embedded = tokio::task::spawn_blocking(move || epub_cover(Arc::new(data)))
+                    .await
+                    .ok()
+                    .flatten();
+                if embedded.is_some() {
+                    return embedded;
+                }
+            }
+        }
+
+        let format = epub
+            .or_else(|| book.formats.iter().find(|f| LibraryDocuments::is_page_based(f)))
+            .or_else(|| {
+                book.formats
+                    .iter()
+                    .find(|f| LibraryDocuments::document_format(f).is_some())
+            })?;
+        self.render_first_page(book, format).await
+    }
+
+    /// Render the first page through the document cache, opening the
+    /// document if it isn't cached yet
+    async fn render_first_page(&self, book: &LibraryBook, format: &BookFormat) -> Option<CoverImage> {
+        let documents = &self.inner.documents;
+
+        let id = match documents.open(book, format).await {
+            Ok((id, _)) => id,
+            Err(e) => {
+                tracing::warn!(book_id = %book.id, "Failed to open {}: {}", format.s3_key, e);
+                return None;
+            }
+        };
+
+        match documents.cache().render_thumbnail(&id, 0, RENDERED_COVER_SIZE, None).await {
+            Ok(result) => Some(result.into()),
+            Err(e) => {
+                tracing::warn!(book_id = %book.id, "Failed to render cover: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn download(&self, book: &LibraryBook, format: &BookFormat) -> Option<Vec<u8>> {
+        match self.inner.storage.get_object(&format.s3_key).await {
+            Ok(object) => Some(object.data),
+            Err(e) => {
+                tracing::warn!(book_id = %book.id, "Failed to read {}: {}", format.s3_key, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    const CONTAINER: &str = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = DynamicImage::ImageRgb8(image::RgbImage::new(width, height));
+        encode(&image, ImageFormat::Png).unwrap()
+    }
+
+    fn epub(opf: &str, files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+        zip.start_file("mimetype", options).unwrap();
+        zip.write_all(b"application/epub+zip").unwrap();
+        zip.start_file("META-INF/container.xml", options).unwrap();
+        zip.write_all(CONTAINER.as_bytes()).unwrap();
+        zip.start_file("OEBPS/content.opf", options).unwrap();
+        zip.write_all(opf.as_bytes()).unwrap();
+        for (name, data) in files {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_epub_cover() {
+        let cover = png(40, 60);
+
+        // EPUB 3: manifest property, with a relative href
+        let opf3 = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+          <manifest>
+            <item id="title" href="Text/title.xhtml" media-type="application/xhtml+xml"/>
+            <item id="img" href="../Images/front%20cover.png" media-type="image/png" properties="cover-image"/>
+          </manifest>
+        </package>"#;
+        let data = epub(opf3, &[("Images/front cover.png", &cover)]);
+        let found = epub_cover(Arc::new(data)).unwrap();
+        assert_eq!(found.format, ImageFormat::Png);
+        assert_eq!(found.data, cover);
+        assert_eq!(found.file_name(), "cover.png");
+
+        // EPUB 2: <meta name="cover">
+        let opf2 = r#"<package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+          <metadata xmlns:opf="http://www.idpf.org/2007/opf">
+            <meta name="cover" content="cover-img"/>
+          </metadata>
+          <manifest>
+            <item id="cover-img" href="images/cover.jpeg" media-type="image/jpeg"/>
+          </manifest>
+        </package>"#;
+        let data = epub(opf2, &[("OEBPS/images/cover.jpeg", b"jpeg")]);
+        let found = epub_cover(Arc::new(data)).unwrap();
+        assert_eq!(found.format, ImageFormat::Jpeg);
+        assert_eq!(found.data, b"jpeg");
+
+        // No cover declared
+        let none = r#"<package><manifest>
+            <item id="c1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+        </manifest></package>"#;
+        assert!(epub_cover(Arc::new(epub(none, &[]))).is_none());
+        assert!(epub_cover(Arc::new(b"not a zip".to_vec())).is_none());
+    }
+
+    #[test]
+    fn test_resize_cover() {
+        let original = png(400, 200);
+
+        let thumb = resize_cover(&original, CoverSize::Thumbnail.max_edge(), ImageFormat::Jpeg).unwrap();
+        let decoded = image::load_from_memory(&thumb).unwrap();
+        assert_eq!(decoded.dimensions(), (160, 80));
+        assert_eq!(image::guess_format(&thumb).unwrap(), image::ImageFormat::Jpeg);
+
+        // Never enlarged
+        let large = resize_cover(&original, CoverSize::Large.max_edge(), ImageFormat::Webp).unwrap();
+        assert_eq!(image::load_from_memory(&large).unwrap().dimensions(), (400, 200));
+        assert_eq!(image::guess_format(&large).unwrap(), image::ImageFormat::WebP);
+    }
+
+    #[test]
+    fn test_cover_size_parse() {
+        assert_eq!("thumbnail".parse::<CoverSize>().unwrap(), CoverSize::Thumbnail);
+        assert_eq!("Large".parse::<CoverSize>().unwrap(), CoverSize::Large);
+        assert_eq!("250".parse::<CoverSize>().unwrap(), CoverSize::Pixels(250));
+        assert_eq!("99999".parse::<CoverSize>().unwrap(), CoverSize::Pixels(MAX_VARIANT_SIZE));
+        assert!("huge".parse::<CoverSize>().is_err());
+    }
+}
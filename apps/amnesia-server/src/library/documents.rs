@@ -0,0 +1,211 @@
+//! Catalog books opened through the `DocumentCache`
+//!
+//! Each format of a book is its own document, cached under
+//! `{book_id}.{format}`. Page counts of page-based formats (PDF, comics)
+//! are kept in the catalog once known, so feeds can advertise them without
+//! reopening every file. They are recorded at ingest, and counted in the
+//! background after library scans for everything else.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use sqlx::SqlitePool;
+
+use crate::db::CatalogRepository;
+use crate::document::{
+    DocumentCache, DocumentError, DocumentFormat, ImageFormat, ParsedDocument, RenderRequest,
+    RenderResult,
+};
+use crate::error::{AppError, Result};
+use crate::formats::open_document;
+use crate::storage::Storage;
+
+use super::book::{BookFormat, FormatType, LibraryBook};
+
+/// Smallest scale pages are rendered at
+const MIN_PAGE_SCALE: f32 = 0.1;
+
+/// Largest scale pages are rendered at, whatever width is asked for
+const MAX_PAGE_SCALE: f32 = 4.0;
+
+/// Opens catalog books for rendering
+#[derive(Clone)]
+pub struct LibraryDocuments {
+    storage: Storage,
+    pool: SqlitePool,
+    documents: DocumentCache,
+    /// Documents whose pages couldn't be counted, with the book's
+    /// `updated_at` they were tried at
+    uncountable: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
+    /// Held while page counts are being filled in
+    counting: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl LibraryDocuments {
+    pub fn new(storage: Storage, pool: SqlitePool, documents: DocumentCache) -> Self {
+        Self {
+            storage,
+            pool,
+            documents,
+            uncountable: Arc::new(Mutex::new(HashMap::new())),
+            counting: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /// The shared document cache
+    pub fn cache(&self) -> &DocumentCache {
+        &self.documents
+    }
+
+    /// Document handler format for a book format, if there is one
+    pub fn document_format(format: &BookFormat) -> Option<DocumentFormat> {
+        DocumentFormat::from_extension(format.format.name())
+    }
+
+    /// Whether a format is streamed page by page (OPDS-PSE)
+    pub fn is_page_based(format: &BookFormat) -> bool {
+        Self::document_format(format).is_some_and(|format| !format.is_reflowable())
+    }
+
+    /// Cache ID of a book format's document
+    pub fn document_id(book: &LibraryBook, format: &BookFormat) -> String {
+        format!("{}.{}", book.id, format.format.name())
+    }
+
+    /// Open a book format, loading it from storage unless already cached
+    ///
+    /// Returns the document's cache ID and its parsed structure.
+    pub async fn open(&self, book: &LibraryBook, format: &BookFormat) -> Result<(String, ParsedDocument)> {
+        let id = Self::document_id(book, format);
+        if let Some(parsed) = self.documents.get_document(&id).await {
+            if self.documents.get_renderer(&id).await.is_some() {
+                return Ok((id, parsed));
+            }
+        }
+
+        let document_format = Self::document_format(format).ok_or_else(|| {
+            AppError::BadRequest(format!("Unsupported format: {}", format.format.name()))
+        })?;
+
+        let data = self.storage.get_object(&format.s3_key).await?.data;
+        let (parser, renderer, parsed) = open_document(data, id.clone(), document_format)
+            .await
+            .map_err(|e| document_error(&format.s3_key, e))?;
+        self.documents
+            .store_document_with_renderer(id.clone(), parsed.clone(), parser, renderer)
+            .await;
+
+        Ok((id, parsed))
+    }
+
+    /// Render a page as JPEG, scaled to `max_width` pixels wide
+    ///
+    /// Pages are never scaled past `MAX_PAGE_SCALE`.
+    pub async fn render_page(
+        &self,
+        book: &LibraryBook,
+        format: &BookFormat,
+        page: usize,
+        max_width: u32,
+    ) -> Result<RenderResult> {
+        let (id, parsed) = self.open(book, format).await?;
+        if page >= parsed.item_count {
+            return Err(AppError::NotFound(format!(
+                "Page {} not found ({} pages)",
+                page, parsed.item_count
+            )));
+        }
+
+        let parser = self
+            .documents
+            .get_parser(&id)
+            .await
+            .ok_or_else(|| AppError::NotFound(format!("Document not loaded: {}", id)))?;
+        let (width, _) = parser
+            .get_item_dimensions(page)
+            .map_err(|e| document_error(&format.s3_key, e))?;
+        let scale = if width > 0.0 {
+            (max_width as f32 / width).clamp(MIN_PAGE_SCALE, MAX_PAGE_SCALE)
+        } else {
+            1.0
+        };
+
+        let request = RenderRequest {
+            item_index: page,
+            scale,
+            format: ImageFormat::Jpeg,
+            ..Default::default()
+        };
+        self.documents
+            .render(&id, &request)
+            .await
+            .map_err(|e| document_error(&format.s3_key, e))
+    }
+
+    /// Count the pages of page-based formats the catalog has no count for,
+    /// recording them in the catalog
+    ///
+    /// Skipped if a count is already running. Files that fail to open are
+    /// not retried until their catalog entry changes. Returns the number of
+    /// formats counted.
+    pub async fn fill_page_counts(&self) -> Result<usize> {
+        let Ok(_guard) = self.counting.try_lock() else {
+            return Ok(0);
+        };
+
+        let formats: Vec<&str> = FormatType::ALL
+            .iter()
+            .filter(|format| {
+                DocumentFormat::from_extension(format.name())
+                    .is_some_and(|format| !format.is_reflowable())
+            })
+            .map(FormatType::name)
+            .collect();
+        let catalog = CatalogRepository::new(&self.pool);
+        let books = catalog.missing_page_counts(&formats).await?;
+
+        let mut counted = 0;
+        for book in books {
+            for format in &book.formats {
+                if format.pages.is_some() || !Self::is_page_based(format) {
+                    continue;
+                }
+
+                let id = Self::document_id(&book, format);
+                if self.uncountable.lock().get(&id) == Some(&book.updated_at) {
+                    continue;
+                }
+
+                match self.open(&book, format).await {
+                    Ok((_, parsed)) => {
+                        // The entry may have been rescanned or renamed meanwhile
+                        if catalog
+                            .set_page_count(&book.id, &format.s3_key, parsed.item_count)
+                            .await?
+                        {
+                            counted += 1;
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!(book_id = %book.id, "Failed to count pages: {}", e);
+                        self.uncountable.lock().insert(id, book.updated_at);
+                    }
+                }
+            }
+        }
+
+        Ok(counted)
+    }
+}
+
+/// Map a document error for a stored file to an `AppError`
+fn document_error(key: &str, error: DocumentError) -> AppError {
+    match error {
+        DocumentError::NotFound(_) | DocumentError::ItemNotFound(_) => {
+            AppError::NotFound(format!("{}: {}", key, error))
+        }
+        _ => AppError::Internal(format!("Failed to open {}: {}", key, error)),
+    }
+}
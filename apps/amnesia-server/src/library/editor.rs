@@ -0,0 +1,547 @@
+//! Metadata editing for catalog books
+//!
+//! Edits update the catalog row and regenerate the book folder's
+//! `metadata.opf`, so Calibre and later scans see the same metadata. A
+//! folder can optionally be renamed to match the edited author and title.
+//! Every edit is recorded with before and after snapshots; reverting an
+//! edit restores the fields it changed and is itself recorded as an edit.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::SqlitePool;
+
+use crate::db::{BookRepository, CatalogRepository};
+use crate::error::{AppError, Result};
+use crate::storage::Storage;
+
+use super::book::LibraryBook;
+use super::metadata::CalibreMetadata;
+
+/// Characters Calibre does not allow in folder names
+const INVALID_FOLDER_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Maximum length of an Author or Title folder name
+const MAX_FOLDER_LEN: usize = 100;
+
+/// Editable metadata of a book, as stored in the edit history
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetadataSnapshot {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub author_sort: Option<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f32>,
+    pub tags: Vec<String>,
+    pub identifiers: HashMap<String, String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub publisher: Option<String>,
+    /// Book folder in storage
+    pub s3_prefix: String,
+}
+
+impl MetadataSnapshot {
+    pub fn from_book(book: &LibraryBook) -> Self {
+        let authors = if book.authors.is_empty() {
+            book.author.iter().cloned().collect()
+        } else {
+            book.authors.clone()
+        };
+
+        Self {
+            title: book.title.clone(),
+            authors,
+            author_sort: book.author_sort.clone(),
+            series: book.series.clone(),
+            series_index: book.series_index,
+            tags: book.tags.clone(),
+            identifiers: book.identifiers.clone(),
+            description: book.description.clone(),
+            language: book.language.clone(),
+            publisher: book.publisher.clone(),
+            s3_prefix: book.s3_prefix.clone(),
+        }
+    }
+
+    /// Copy the metadata onto a book (the folder is moved separately)
+    fn apply_to(&self, book: &mut LibraryBook) {
+        book.title = self.title.clone();
+        book.author = self.authors.first().cloned();
+        book.authors = self.authors.clone();
+        book.author_sort = self.author_sort.clone();
+        book.series = self.series.clone();
+        book.series_index = self.series_index;
+        book.tags = self.tags.clone();
+        book.identifiers = self.identifiers.clone();
+        book.description = self.description.clone();
+        book.language = self.language.clone();
+        book.publisher = self.publisher.clone();
+    }
+
+    /// Names of the fields that differ from `other`
+    fn changed_fields(&self, other: &Self) -> Vec<String> {
+        let mut fields = Vec::new();
+        let mut check = |name: &str, changed: bool| {
+            if changed {
+                fields.push(name.to_string());
+            }
+        };
+
+        check("title", self.title != other.title);
+        check("authors", self.authors != other.authors);
+        check("author_sort", self.author_sort != other.author_sort);
+        check("series", self.series != other.series);
+        check("series_index", self.series_index != other.series_index);
+        check("tags", self.tags != other.tags);
+        check("identifiers", self.identifiers != other.identifiers);
+        check("description", self.description != other.description);
+        check("language", self.language != other.language);
+        check("publisher", self.publisher != other.publisher);
+        check("s3_prefix", self.s3_prefix != other.s3_prefix);
+        fields
+    }
+
+    /// Copy the named fields from `from`
+    fn copy_fields(&mut self, from: &Self, fields: &[String]) {
+        for field in fields {
+            match field.as_str() {
+                "title" => self.title = from.title.clone(),
+                "authors" => self.authors = from.authors.clone(),
+                "author_sort" => self.author_sort = from.author_sort.clone(),
+                "series" => self.series = from.series.clone(),
+                "series_index" => self.series_index = from.series_index,
+                "tags" => self.tags = from.tags.clone(),
+                "identifiers" => self.identifiers = from.identifiers.clone(),
+                "description" => self.description = from.description.clone(),
+                "language" => self.language = from.language.clone(),
+                "publisher" => self.publisher = from.publisher.clone(),
+                "s3_prefix" => self.s3_prefix = from.s3_prefix.clone(),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// A recorded metadata edit
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookEdit {
+    pub id: i64,
+    pub book_id: String,
+    /// Fields the edit changed
+    pub fields: Vec<String>,
+    pub before: MetadataSnapshot,
+    pub after: MetadataSnapshot,
+    /// The edit this one reverted
+    pub reverts: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Partial metadata update
+///
+/// Absent fields are left alone; `null` clears optional fields.
+/// Identifiers are merged, with `null` values removing a scheme.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MetadataPatch {
+    pub title: Option<String>,
+    pub authors: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub author_sort: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub series: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub series_index: Option<Option<f32>>,
+    pub tags: Option<Vec<String>>,
+    pub identifiers: Option<HashMap<String, Option<String>>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub description: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub language: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    pub publisher: Option<Option<String>>,
+}
+
+/// Distinguish an explicit `null` (`Some(None)`) from an absent field (`None`)
+fn nullable<'de, D, T>(deserializer: D) -> std::result::Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+impl MetadataPatch {
+    /// Reject edits that would leave a book without a title or authors
+    pub fn validate(&self) -> Result<()> {
+        if self.title.as_deref().is_some_and(|t| t.trim().is_empty()) {
+            return Err(AppError::BadRequest("title must not be empty".to_string()));
+        }
+        if let Some(ref authors) = self.authors {
+            if authors.is_empty() || authors.iter().any(|a| a.trim().is_empty()) {
+                return Err(AppError::BadRequest(
+                    "authors must be a non-empty list of names".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&self, snapshot: &mut MetadataSnapshot) {
+        let trimmed = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+
+        if let Some(ref title) = self.title {
+            snapshot.title = title.trim().to_string();
+        }
+        if let Some(ref authors) = self.authors {
+            snapshot.authors = authors.iter().map(|a| a.trim().to_string()).collect();
+        }
+        if let Some(ref author_sort) = self.author_sort {
+            snapshot.author_sort = trimmed(author_sort);
+        }
+        if let Some(ref series) = self.series {
+            snapshot.series = trimmed(series);
+        }
+        if let Some(series_index) = self.series_index {
+            snapshot.series_index = series_index;
+        }
+        if let Some(ref tags) = self.tags {
+            snapshot.tags = tags.clone();
+        }
+        if let Some(ref identifiers) = self.identifiers {
+            for (scheme, value) in identifiers {
+                let scheme = scheme.to_lowercase();
+                match value {
+                    Some(value) => snapshot.identifiers.insert(scheme, value.clone()),
+                    None => snapshot.identifiers.remove(&scheme),
+                };
+            }
+        }
+        if let Some(ref description) = self.description {
+            snapshot.description = description.clone();
+        }
+        if let Some(ref language) = self.language {
+            snapshot.language = trimmed(language);
+        }
+        if let Some(ref publisher) = self.publisher {
+            snapshot.publisher = trimmed(publisher);
+        }
+    }
+}
+
+/// Applies metadata edits to catalog books and their storage folders
+pub struct MetadataEditor<'a> {
+    storage: &'a Storage,
+    pool: &'a SqlitePool,
+}
+
+impl<'a> MetadataEditor<'a> {
+    pub fn new(storage: &'a Storage, pool: &'a SqlitePool) -> Self {
+        Self { storage, pool }
+    }
+
+    /// Apply a patch, optionally renaming the folder to `Author/Title`
+    ///
+    /// Returns the updated book and the recorded edit (`None` if the patch
+    /// changed nothing).
+    pub async fn edit(
+        &self,
+        book_id: &str,
+        patch: &MetadataPatch,
+        rename_folder: bool,
+    ) -> Result<(LibraryBook, Option<BookEdit>)> {
+        patch.validate()?;
+        let book = self.load(book_id).await?;
+
+        let before = MetadataSnapshot::from_book(&book);
+        let mut after = before.clone();
+        patch.apply(&mut after);
+        if rename_folder {
+            after.s3_prefix = folder_for(&after, &before.s3_prefix);
+        }
+
+        self.commit(book, before, after, None).await
+    }
+
+    /// Revert the fields changed by an earlier edit
+    pub async fn revert(&self, book_id: &str, edit_id: i64) -> Result<(LibraryBook, Option<BookEdit>)> {
+        let catalog = CatalogRepository::new(self.pool);
+        let edit = catalog
+            .get_edit(edit_id)
+            .await?
+            .filter(|edit| edit.book_id == book_id)
+            .ok_or_else(|| AppError::NotFound(format!("Edit not found: {}", edit_id)))?;
+
+        let book = self.load(book_id).await?;
+        let before = MetadataSnapshot::from_book(&book);
+        let mut after = before.clone();
+        after.copy_fields(&edit.before, &edit.fields);
+
+        self.commit(book, before, after, Some(edit.id)).await
+    }
+
+    async fn load(&self, book_id: &str) -> Result<LibraryBook> {
+        CatalogRepository::new(self.pool)
+            .get(book_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Book not found: {}", book_id)))
+    }
+
+    async fn commit(
+        &self,
+        mut book: LibraryBook,
+        before: MetadataSnapshot,
+        after: MetadataSnapshot,
+        reverts: Option<i64>,
+    ) -> Result<(LibraryBook, Option<BookEdit>)> {
+        let fields = before.changed_fields(&after);
+        if fields.is_empty() {
+            return Ok((book, None));
+        }
+
+        let catalog = CatalogRepository::new(self.pool);
+        let old_prefix = book.s3_prefix.clone();
+
+        if after.s3_prefix != old_prefix {
+            // Uploaded books are referenced by their storage key elsewhere
+            if BookRepository::new(self.pool).exists(&book.id).await? {
+                return Err(AppError::BadRequest(
+                    "uploaded books cannot be moved to another folder".to_string(),
+                ));
+            }
+            if catalog.prefixes().await?.contains(&after.s3_prefix) {
+                return Err(AppError::BadRequest(format!(
+                    "folder already holds a book: {}",
+                    after.s3_prefix
+                )));
+            }
+            self.move_folder(&mut book, &after.s3_prefix).await?;
+        }
+
+        after.apply_to(&mut book);
+        book.updated_at = Utc::now();
+
+        let opf = CalibreMetadata::from(&book).to_opf()?;
+        self.storage
+            .put_object(
+                &format!("{}/metadata.opf", book.s3_prefix),
+                opf.into_bytes(),
+                "application/oebps-package+xml",
+            )
+            .await?;
+
+        catalog.update(&book).await?;
+
+        // Record the rewritten folder so scans don't re-read it
+        let objects = self
+            .storage
+            .list_all_objects(Some(&format!("{}/", book.s3_prefix)))
+            .await?;
+        catalog.record_objects(&book.s3_prefix, &objects).await?;
+        if book.s3_prefix != old_prefix {
+            catalog.record_objects(&old_prefix, &[]).await?;
+        }
+
+        let edit = catalog
+            .record_edit(&book.id, &fields, &before, &after, reverts)
+            .await?;
+
+        tracing::info!("Edited book {} ({})", book.id, fields.join(", "));
+        Ok((book, Some(edit)))
+    }
+
+    /// Move every object of a book's folder and update its keys
+    async fn move_folder(&self, book: &mut LibraryBook, new_prefix: &str) -> Result<()> {
+        let old_folder = format!("{}/", book.s3_prefix);
+        let new_folder = format!("{}/", new_prefix);
+        let rekey = |key: &str| match key.strip_prefix(&old_folder) {
+            Some(rest) => format!("{}{}", new_folder, rest),
+            None => key.to_string(),
+        };
+
+        let objects = self.storage.list_all_objects(Some(&old_folder)).await?;
+        for obj in &objects {
+            let object = self.storage.get_object(&obj.key).await?;
+            let content_type = object
+                .metadata
+                .content_type
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+            self.storage
+                .put_object(&rekey(&obj.key), object.data, &content_type)
+                .await?;
+        }
+        for obj in &objects {
+            self.storage.delete_object(&obj.key).await?;
+        }
+
+        for format in &mut book.formats {
+            format.s3_key = rekey(&format.s3_key);
+        }
+        book.cover_key = book.cover_key.as_deref().map(rekey);
+        book.s3_prefix = new_prefix.to_string();
+
+        tracing::info!("Moved {} to {}", old_folder, new_folder);
+        Ok(())
+    }
+}
+
+/// `Author/Title` folder for edited metadata, under the same library root
+///
+/// Calibre's ` (id)` suffix on the title folder is kept.
+fn folder_for(snapshot: &MetadataSnapshot, old_prefix: &str) -> String {
+    let mut parts = old_prefix.rsplitn(3, '/');
+    let old_title = parts.next().unwrap_or_default();
+    let root = parts.nth(1).map(|r| format!("{}/", r)).unwrap_or_default();
+
+    let suffix = old_title
+        .rsplit_once(" (")
+        .and_then(|(_, id)| id.strip_suffix(')'))
+        .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()))
+        .map(|id| format!(" ({})", id))
+        .unwrap_or_default();
+
+    let author = snapshot.authors.first().map(String::as_str).unwrap_or("Unknown");
+    format!(
+        "{}{}/{}{}",
+        root,
+        folder_name(author),
+        folder_name(&snapshot.title),
+        suffix
+    )
+}
+
+/// Folder-safe form of a name, as Calibre would write it
+fn folder_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| {
+            if INVALID_FOLDER_CHARS.contains(&c) || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .take(MAX_FOLDER_LEN)
+        .collect();
+
+    let cleaned = cleaned.trim().trim_end_matches('.').trim();
+    if cleaned.is_empty() {
+        "Unknown".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::{BookFormat, FormatType};
+    use crate::storage::LocalStorage;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_patch_nullable_fields() {
+        let patch: MetadataPatch = serde_json::from_str(
+            r#"{"series": null, "publisher": "Ace", "identifiers": {"ISBN": "123", "asin": null}}"#,
+        )
+        .unwrap();
+        assert_eq!(patch.series, Some(None));
+        assert_eq!(patch.publisher, Some(Some("Ace".to_string())));
+        assert!(patch.language.is_none());
+
+        let mut snapshot = MetadataSnapshot {
+            series: Some("Dune".to_string()),
+            language: Some("en".to_string()),
+            identifiers: HashMap::from([("asin".to_string(), "B000".to_string())]),
+            ..Default::default()
+        };
+        patch.apply(&mut snapshot);
+        assert_eq!(snapshot.series, None);
+        assert_eq!(snapshot.language.as_deref(), Some("en"));
+        assert_eq!(
+            snapshot.identifiers,
+            HashMap::from([("isbn".to_string(), "123".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_folder_for() {
+        let snapshot = MetadataSnapshot {
+            title: "What If?: Answers".to_string(),
+            authors: vec!["Randall Munroe".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            folder_for(&snapshot, "Unknown/What If (42)"),
+            "Randall Munroe/What If__ Answers (42)"
+        );
+        assert_eq!(
+            folder_for(&snapshot, "calibre/Unknown/What If"),
+            "calibre/Randall Munroe/What If__ Answers"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_edit_rename_and_revert() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage: Storage = Arc::new(LocalStorage::new(dir.path()).await.unwrap());
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        crate::db::initialize_schema(&pool).await.unwrap();
+
+        storage
+            .put_object("Unknown/Dune/Dune.epub", b"epub".to_vec(), "application/epub+zip")
+            .await
+            .unwrap();
+        let mut book = LibraryBook::new("Dune".to_string(), "Unknown/Dune".to_string());
+        book.author = Some("Unknown".to_string());
+        book.formats = vec![BookFormat {
+            format: FormatType::Epub,
+            s3_key: "Unknown/Dune/Dune.epub".to_string(),
+            size: 4,
+            pages: None,
+        }];
+        let catalog = CatalogRepository::new(&pool);
+        catalog.upsert(&book).await.unwrap();
+
+        let editor = MetadataEditor::new(&storage, &pool);
+        let patch = MetadataPatch {
+            authors: Some(vec!["Frank Herbert".to_string()]),
+            series: Some(Some("Dune".to_string())),
+            ..Default::default()
+        };
+        let (edited, edit) = editor.edit(&book.id, &patch, true).await.unwrap();
+        let edit = edit.unwrap();
+
+        assert_eq!(edited.s3_prefix, "Frank Herbert/Dune");
+        assert_eq!(edited.formats[0].s3_key, "Frank Herbert/Dune/Dune.epub");
+        assert_eq!(edit.fields, vec!["authors", "series", "s3_prefix"]);
+        assert!(!storage.object_exists("Unknown/Dune/Dune.epub").await.unwrap());
+
+        let opf = storage.get_object("Frank Herbert/Dune/metadata.opf").await.unwrap();
+        let metadata = CalibreMetadata::parse(&String::from_utf8(opf.data).unwrap()).unwrap();
+        assert_eq!(metadata.author.as_deref(), Some("Frank Herbert"));
+        assert_eq!(metadata.series.as_deref(), Some("Dune"));
+
+        // An unchanged patch records nothing
+        let (_, none) = editor.edit(&book.id, &patch, true).await.unwrap();
+        assert!(none.is_none());
+
+        let (reverted, revert) = editor.revert(&book.id, edit.id).await.unwrap();
+        assert_eq!(reverted.s3_prefix, "Unknown/Dune");
+        assert_eq!(reverted.author.as_deref(), Some("Unknown"));
+        assert_eq!(reverted.series, None);
+        assert_eq!(revert.unwrap().reverts, Some(edit.id));
+        assert!(storage.object_exists("Unknown/Dune/Dune.epub").await.unwrap());
+
+        let history = catalog.edits(&book.id).await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(catalog.get(&book.id).await.unwrap().unwrap().s3_prefix, "Unknown/Dune");
+    }
+}
@@ -2,12 +2,19 @@
 //!
 //! Parses Dublin Core metadata from Calibre's metadata.opf XML files.
 
-use quick_xml::de::from_str;
+use quick_xml::{
+    de::from_str,
+    events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event},
+    Writer,
+};
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::io::Cursor;
 
 use crate::error::Result;
 
+use super::book::LibraryBook;
+
 /// Parsed Calibre metadata
 #[derive(Debug, Clone, Default)]
 pub struct CalibreMetadata {
@@ -26,6 +33,30 @@ pub struct CalibreMetadata {
     pub cover_path: Option<String>,
 }
 
+impl From<&LibraryBook> for CalibreMetadata {
+    fn from(book: &LibraryBook) -> Self {
+        Self {
+            title: Some(book.title.clone()),
+            author: book.author.clone(),
+            author_sort: book.author_sort.clone(),
+            authors: book.authors.clone(),
+            publisher: book.publisher.clone(),
+            pubdate: book.pubdate.clone(),
+            language: book.language.clone(),
+            description: book.description.clone(),
+            series: book.series.clone(),
+            series_index: book.series_index,
+            tags: book.tags.clone(),
+            identifiers: book.identifiers.clone(),
+            cover_path: book
+                .cover_key
+                .as_deref()
+                .and_then(|key| key.rsplit('/').next())
+                .map(str::to_string),
+        }
+    }
+}
+
 impl CalibreMetadata {
     /// Parse metadata from an OPF XML string
     pub fn parse(xml: &str) -> Result<Self> {
@@ -33,6 +64,83 @@ impl CalibreMetadata {
         Ok(Self::from_opf(package))
     }
 
+    /// Serialize to a Calibre-compatible OPF 2.0 document
+    pub fn to_opf(&self) -> Result<String> {
+        let mut writer = Writer::new_with_indent(Cursor::new(Vec::new()), b' ', 4);
+
+        writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("utf-8"), None)))?;
+
+        let mut package = BytesStart::new("package");
+        package.push_attribute(("xmlns", "http://www.idpf.org/2007/opf"));
+        package.push_attribute(("unique-identifier", "uuid_id"));
+        package.push_attribute(("version", "2.0"));
+        writer.write_event(Event::Start(package))?;
+
+        let mut metadata = BytesStart::new("metadata");
+        metadata.push_attribute(("xmlns:dc", "http://purl.org/dc/elements/1.1/"));
+        metadata.push_attribute(("xmlns:opf", "http://www.idpf.org/2007/opf"));
+        writer.write_event(Event::Start(metadata))?;
+
+        if let Some(ref title) = self.title {
+            write_element(&mut writer, BytesStart::new("dc:title"), title)?;
+        }
+
+        for (i, author) in self.authors.iter().enumerate() {
+            let mut creator = BytesStart::new("dc:creator");
+            creator.push_attribute(("opf:role", "aut"));
+            if i == 0 {
+                if let Some(ref sort) = self.author_sort {
+                    creator.push_attribute(("opf:file-as", sort.as_str()));
+                }
+            }
+            write_element(&mut writer, creator, author)?;
+        }
+
+        if let Some(ref publisher) = self.publisher {
+            write_element(&mut writer, BytesStart::new("dc:publisher"), publisher)?;
+        }
+        if let Some(ref pubdate) = self.pubdate {
+            write_element(&mut writer, BytesStart::new("dc:date"), pubdate)?;
+        }
+        if let Some(ref language) = self.language {
+            write_element(&mut writer, BytesStart::new("dc:language"), language)?;
+        }
+        if let Some(ref description) = self.description {
+            write_element(&mut writer, BytesStart::new("dc:description"), description)?;
+        }
+        for tag in &self.tags {
+            write_element(&mut writer, BytesStart::new("dc:subject"), tag)?;
+        }
+
+        // Sorted for stable output
+        let mut identifiers: Vec<_> = self.identifiers.iter().collect();
+        identifiers.sort();
+        for (scheme, value) in identifiers {
+            let mut identifier = BytesStart::new("dc:identifier");
+            identifier.push_attribute(("opf:scheme", scheme.as_str()));
+            if scheme == "uuid" {
+                identifier.push_attribute(("id", "uuid_id"));
+            }
+            write_element(&mut writer, identifier, value)?;
+        }
+
+        if let Some(ref series) = self.series {
+            write_meta(&mut writer, "calibre:series", series)?;
+            if let Some(index) = self.series_index {
+                write_meta(&mut writer, "calibre:series_index", &index.to_string())?;
+            }
+        }
+        if let Some(ref cover) = self.cover_path {
+            write_meta(&mut writer, "cover", cover)?;
+        }
+
+        writer.write_event(Event::End(BytesEnd::new("metadata")))?;
+        writer.write_event(Event::End(BytesEnd::new("package")))?;
+
+        let result = writer.into_inner().into_inner();
+        Ok(String::from_utf8(result)?)
+    }
+
     fn from_opf(package: OPFPackage) -> Self {
         let metadata = package.metadata;
         let mut result = CalibreMetadata::default();
@@ -127,6 +235,26 @@ impl CalibreMetadata {
     }
 }
 
+fn write_element<W: std::io::Write>(
+    writer: &mut Writer<W>,
+    start: BytesStart<'_>,
+    value: &str,
+) -> Result<()> {
+    let end = start.to_end().into_owned();
+    writer.write_event(Event::Start(start))?;
+    writer.write_event(Event::Text(BytesText::new(value)))?;
+    writer.write_event(Event::End(end))?;
+    Ok(())
+}
+
+fn write_meta<W: std::io::Write>(writer: &mut Writer<W>, name: &str, content: &str) -> Result<()> {
+    let mut meta = BytesStart::new("meta");
+    meta.push_attribute(("name", name));
+    meta.push_attribute(("content", content));
+    writer.write_event(Event::Empty(meta))?;
+    Ok(())
+}
+
 // OPF XML structures for deserialization
 
 #[derive(Debug, Deserialize)]
@@ -265,4 +393,36 @@ mod tests {
         assert_eq!(metadata.series, Some("Test Series".to_string()));
         assert_eq!(metadata.series_index, Some(1.0));
     }
+
+    #[test]
+    fn test_to_opf_round_trip() {
+        let mut metadata = CalibreMetadata {
+            title: Some("Dune & Sons".to_string()),
+            author: Some("Frank Herbert".to_string()),
+            author_sort: Some("Herbert, Frank".to_string()),
+            authors: vec!["Frank Herbert".to_string(), "Brian Herbert".to_string()],
+            language: Some("en".to_string()),
+            series: Some("Dune".to_string()),
+            series_index: Some(2.0),
+            tags: vec!["Science Fiction".to_string()],
+            ..Default::default()
+        };
+        metadata
+            .identifiers
+            .insert("isbn".to_string(), "978-0441013593".to_string());
+
+        let xml = metadata.to_opf().unwrap();
+        let parsed = CalibreMetadata::parse(&xml).unwrap();
+
+        assert_eq!(parsed.title, metadata.title);
+        assert_eq!(parsed.authors, metadata.authors);
+        assert_eq!(parsed.author_sort, metadata.author_sort);
+        assert_eq!(parsed.series, metadata.series);
+        assert_eq!(parsed.series_index, Some(2.0));
+        assert_eq!(parsed.tags, metadata.tags);
+        assert_eq!(
+            parsed.identifiers.get("isbn").map(String::as_str),
+            Some("978-0441013593")
+        );
+    }
 }
@@ -1,11 +1,22 @@
 //! Library module for book management
 //!
-//! Handles Calibre library scanning, metadata parsing, and book indexing.
+//! Handles Calibre library scanning, metadata parsing, book indexing and
+//! covers, and keeps the catalog in step with storage in the background.
 
 mod book;
+mod calibre_db;
+mod covers;
+mod documents;
+mod editor;
 mod metadata;
 mod scanner;
+mod watcher;
 
 pub use book::*;
+pub use calibre_db::*;
+pub use covers::*;
+pub use documents::*;
+pub use editor::*;
 pub use metadata::*;
 pub use scanner::*;
+pub use watcher::*;
@@ -1,87 +1,49 @@
 //! Library scanner for Calibre folder structure
 //!
-//! Scans S3 bucket for books following Calibre's Author/Title structure.
+//! Scans storage (an S3 bucket or local directory) for books following
+//! Calibre's Author/Title structure.
 
 use chrono::Utc;
 use std::collections::{HashMap, HashSet};
 
 use crate::error::Result;
-use crate::storage::{ListOptions, S3Client};
+use crate::storage::{ObjectMetadata, Storage};
 
 use super::book::{BookFormat, FormatType, LibraryBook, LibraryStats};
 use super::metadata::CalibreMetadata;
 
-/// Scanner for Calibre-style libraries in S3
+/// Scanner for Calibre-style libraries in storage
 pub struct LibraryScanner {
-    s3_client: S3Client,
+    storage: Storage,
 }
 
 impl LibraryScanner {
     /// Create a new library scanner
-    pub fn new(s3_client: S3Client) -> Self {
-        Self { s3_client }
+    pub fn new(storage: Storage) -> Self {
+        Self { storage }
     }
 
-    /// Scan the entire library and return all books
-    pub async fn scan_library(&self) -> Result<Vec<LibraryBook>> {
-        tracing::info!("Starting library scan...");
-        let start = std::time::Instant::now();
-
-        // List all objects in the bucket
-        let objects = self.s3_client.list_all_objects(None).await?;
+    /// List all objects in storage grouped by book folder (Author/Title)
+    async fn list_folders(&self) -> Result<HashMap<String, Vec<ObjectMetadata>>> {
+        let objects = self.storage.list_all_objects(None).await?;
         tracing::info!("Found {} objects in bucket", objects.len());
 
-        // Group objects by book folder (Author/Title/)
-        let mut book_folders: HashMap<String, Vec<(String, i64)>> = HashMap::new();
-
+        let mut book_folders: HashMap<String, Vec<ObjectMetadata>> = HashMap::new();
         for obj in objects {
-            let key = &obj.key;
-
-            // Parse path: Author/Title/filename
-            let parts: Vec<&str> = key.split('/').collect();
-            if parts.len() >= 3 {
-                let folder = format!("{}/{}", parts[0], parts[1]);
-                book_folders
-                    .entry(folder)
-                    .or_default()
-                    .push((key.clone(), obj.size));
+            if let Some(folder) = book_folder(&obj.key) {
+                book_folders.entry(folder).or_default().push(obj);
             }
         }
 
         tracing::info!("Found {} book folders", book_folders.len());
-
-        // Process each book folder
-        let mut books = Vec::new();
-
-        for (folder, files) in book_folders {
-            match self.process_book_folder(&folder, &files).await {
-                Ok(Some(book)) => {
-                    books.push(book);
-                }
-                Ok(None) => {
-                    tracing::debug!("Skipping folder without valid book: {}", folder);
-                }
-                Err(e) => {
-                    tracing::warn!("Error processing folder {}: {}", folder, e);
-                }
-            }
-        }
-
-        let elapsed = start.elapsed();
-        tracing::info!(
-            "Library scan complete: {} books in {:?}",
-            books.len(),
-            elapsed
-        );
-
-        Ok(books)
+        Ok(book_folders)
     }
 
     /// Process a single book folder
     async fn process_book_folder(
         &self,
         folder: &str,
-        files: &[(String, i64)],
+        files: &[ObjectMetadata],
     ) -> Result<Option<LibraryBook>> {
         let parts: Vec<&str> = folder.split('/').collect();
         if parts.len() < 2 {
@@ -94,12 +56,12 @@ impl LibraryScanner {
         // Find metadata.opf file
         let metadata_key = files
             .iter()
-            .find(|(key, _)| key.ends_with("metadata.opf"))
-            .map(|(key, _)| key.clone());
+            .find(|obj| obj.key.ends_with("metadata.opf"))
+            .map(|obj| obj.key.clone());
 
         // Find book formats
         let mut formats = Vec::new();
-        for (key, size) in files {
+        for ObjectMetadata { key, size, .. } in files {
             if let Some(ext) = key.rsplit('.').next() {
                 let format_type = FormatType::from_extension(ext);
                 if format_type != FormatType::Other
@@ -110,6 +72,7 @@ impl LibraryScanner {
                         format: format_type,
                         s3_key: key.clone(),
                         size: *size,
+                        pages: None,
                     });
                 }
             }
@@ -123,16 +86,17 @@ impl LibraryScanner {
         // Find cover image
         let cover_key = files
             .iter()
-            .find(|(key, _)| {
-                key.ends_with("cover.jpg")
-                    || key.ends_with("cover.jpeg")
-                    || key.ends_with("cover.png")
+            .find(|obj| {
+                obj.key.ends_with("cover.jpg")
+                    || obj.key.ends_with("cover.jpeg")
+                    || obj.key.ends_with("cover.png")
+                    || obj.key.ends_with("cover.webp")
             })
-            .map(|(key, _)| key.clone());
+            .map(|obj| obj.key.clone());
 
         // Parse metadata if available
         let metadata = if let Some(ref key) = metadata_key {
-            match self.s3_client.get_object(key).await {
+            match self.storage.get_object(key).await {
                 Ok(obj) => {
                     let xml = String::from_utf8_lossy(&obj.data);
                     CalibreMetadata::parse(&xml).ok()
@@ -221,57 +185,181 @@ impl LibraryScanner {
         }
     }
 
-    /// Scan for changes since last scan (incremental update)
+    /// Scan for changes against the objects recorded by the previous scan
+    ///
+    /// Only folders whose objects were added, removed or modified (by ETag,
+    /// size or last-modified time) are re-processed, so a refresh of an
+    /// unchanged library costs a bucket listing and no object reads.
+    /// `known_books` holds the folders that currently have a catalog entry.
     pub async fn scan_changes(
         &self,
-        existing_books: &[LibraryBook],
-        since: chrono::DateTime<Utc>,
+        known_objects: &HashMap<String, ObjectMetadata>,
+        known_books: &HashSet<String>,
     ) -> Result<LibraryChanges> {
-        let all_books = self.scan_library().await?;
+        let start = std::time::Instant::now();
+        let folders = self.list_folders().await?;
 
-        let existing_ids: HashSet<String> =
-            existing_books.iter().map(|b| b.s3_prefix.clone()).collect();
+        // Previous scan's objects, grouped like the current listing
+        let mut previous: HashMap<String, Vec<&ObjectMetadata>> = HashMap::new();
+        for obj in known_objects.values() {
+            if let Some(folder) = book_folder(&obj.key) {
+                previous.entry(folder).or_default().push(obj);
+            }
+        }
 
-        let new_ids: HashSet<String> = all_books.iter().map(|b| b.s3_prefix.clone()).collect();
+        let changes = self
+            .diff_folders(folders, previous, known_objects, known_books)
+            .await;
+        changes.log("Library", start);
+        Ok(changes)
+    }
 
-        let added: Vec<LibraryBook> = all_books
-            .iter()
-            .filter(|b| !existing_ids.contains(&b.s3_prefix))
-            .cloned()
-            .collect();
+    /// Scan only the given book folders for changes
+    ///
+    /// Used for bucket events, which name the objects that changed: each
+    /// folder is listed on its own instead of listing the whole bucket.
+    pub async fn scan_folders(
+        &self,
+        folders: &HashSet<String>,
+        known_objects: &HashMap<String, ObjectMetadata>,
+        known_books: &HashSet<String>,
+    ) -> Result<LibraryChanges> {
+        let start = std::time::Instant::now();
 
-        let removed: Vec<String> = existing_ids
-            .difference(&new_ids)
-            .cloned()
-            .collect();
+        let mut listing: HashMap<String, Vec<ObjectMetadata>> = HashMap::new();
+        for folder in folders {
+            let prefix = format!("{}/", folder);
+            for obj in self.storage.list_all_objects(Some(&prefix)).await? {
+                if book_folder(&obj.key).as_deref() == Some(folder.as_str()) {
+                    listing.entry(folder.clone()).or_default().push(obj);
+                }
+            }
+        }
 
-        // Check for updated books (modified after since)
-        let updated: Vec<LibraryBook> = all_books
-            .iter()
-            .filter(|b| {
-                existing_ids.contains(&b.s3_prefix) && b.updated_at > since
-            })
-            .cloned()
-            .collect();
-
-        Ok(LibraryChanges {
-            added,
-            updated,
-            removed,
-        })
+        let mut previous: HashMap<String, Vec<&ObjectMetadata>> = HashMap::new();
+        for obj in known_objects.values() {
+            if let Some(folder) = book_folder(&obj.key).filter(|f| folders.contains(f)) {
+                previous.entry(folder).or_default().push(obj);
+            }
+        }
+
+        let changes = self
+            .diff_folders(listing, previous, known_objects, known_books)
+            .await;
+        changes.log("Folder", start);
+        Ok(changes)
+    }
+
+    /// Re-process folders whose objects differ from the previous scan
+    async fn diff_folders(
+        &self,
+        folders: HashMap<String, Vec<ObjectMetadata>>,
+        previous: HashMap<String, Vec<&ObjectMetadata>>,
+        known_objects: &HashMap<String, ObjectMetadata>,
+        known_books: &HashSet<String>,
+    ) -> LibraryChanges {
+        let current: HashSet<String> = folders.keys().cloned().collect();
+        let mut changes = LibraryChanges::default();
+
+        for (folder, files) in folders {
+            let unchanged = previous.get(&folder).is_some_and(|old| {
+                old.len() == files.len()
+                    && files.iter().all(|obj| {
+                        known_objects
+                            .get(&obj.key)
+                            .is_some_and(|known| !object_changed(known, obj))
+                    })
+            });
+            if unchanged {
+                continue;
+            }
+
+            match self.process_book_folder(&folder, &files).await {
+                Ok(Some(book)) if known_books.contains(&folder) => changes.updated.push(book),
+                Ok(Some(book)) => changes.added.push(book),
+                Ok(None) if known_books.contains(&folder) => changes.removed.push(folder.clone()),
+                Ok(None) => {}
+                Err(e) => {
+                    // Leave the folder unrecorded so the next refresh retries it
+                    tracing::warn!("Error processing folder {}: {}", folder, e);
+                    continue;
+                }
+            }
+
+            changes.objects.insert(folder, files);
+        }
+
+        // Folders that disappeared from storage (their objects are forgotten too)
+        changes
+            .removed
+            .extend(previous.into_keys().filter(|folder| !current.contains(folder)));
+
+        changes
+    }
+}
+
+/// Storage prefix for files the server manages itself (conversions, form
+/// revisions). The scanner never treats objects under it as books.
+pub const RESERVED_PREFIX: &str = ".amnesia/";
+
+/// Book folder (`Author/Title`) an object belongs to, if any
+pub(super) fn book_folder(key: &str) -> Option<String> {
+    if key.starts_with(RESERVED_PREFIX) {
+        return None;
     }
+    let parts: Vec<&str> = key.split('/').collect();
+    (parts.len() >= 3).then(|| format!("{}/{}", parts[0], parts[1]))
+}
+
+/// Whether an object differs from the state recorded by an earlier scan
+fn object_changed(known: &ObjectMetadata, current: &ObjectMetadata) -> bool {
+    known.size != current.size
+        || known.etag != current.etag
+        || known.last_modified != current.last_modified
 }
 
 /// Changes detected in the library
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct LibraryChanges {
     pub added: Vec<LibraryBook>,
     pub updated: Vec<LibraryBook>,
+    /// Folders that no longer hold a book
     pub removed: Vec<String>,
+    /// Current objects of every re-processed folder, to record for the next scan
+    pub objects: HashMap<String, Vec<ObjectMetadata>>,
 }
 
 impl LibraryChanges {
     pub fn is_empty(&self) -> bool {
         self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
     }
+
+    fn log(&self, scope: &str, start: std::time::Instant) {
+        tracing::info!(
+            "{} changes: {} added, {} updated, {} removed in {:?}",
+            scope,
+            self.added.len(),
+            self.updated.len(),
+            self.removed.len(),
+            start.elapsed()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_book_folder() {
+        assert_eq!(
+            book_folder("Jane Austen/Emma/Emma.epub").as_deref(),
+            Some("Jane Austen/Emma")
+        );
+        assert_eq!(book_folder("Jane Austen/cover.jpg"), None);
+        assert_eq!(
+            book_folder(".amnesia/conversions/abc123/kepub.kepub.epub"),
+            None
+        );
+    }
 }
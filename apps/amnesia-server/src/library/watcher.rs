@@ -0,0 +1,400 @@
+//! Background library watcher
+//!
+//! Keeps the catalog in step with storage without manual refreshes. A full
+//! `scan_changes` runs at startup and then on an interval; bucket events
+//! (MinIO webhook notifications) queue the folders they touch for a
+//! targeted rescan a moment later, so uploads through other tools show up
+//! within seconds. Scans never overlap, and the outcome of the last one is
+//! kept for the status endpoint. After each scan, page counts missing from
+//! the catalog are filled in in the background.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use tokio::sync::Notify;
+
+use crate::db::CatalogRepository;
+use crate::error::Result;
+use crate::storage::Storage;
+
+use super::documents::LibraryDocuments;
+use super::scanner::{book_folder, LibraryChanges, LibraryScanner};
+
+/// Delay between the first queued event and the rescan, so a burst of
+/// events for one book (formats, cover, metadata.opf) is handled together
+const EVENT_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// What triggered a scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanKind {
+    /// Whole library, on the interval or on request
+    Full,
+    /// Folders named by bucket events
+    Events,
+}
+
+/// Counts of catalog changes made by a scan
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ChangeSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+impl From<&LibraryChanges> for ChangeSummary {
+    fn from(changes: &LibraryChanges) -> Self {
+        Self {
+            added: changes.added.len(),
+            updated: changes.updated.len(),
+            removed: changes.removed.len(),
+        }
+    }
+}
+
+/// Watcher state reported by the status endpoint
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatcherStatus {
+    /// Whether a scan is running now
+    pub scanning: bool,
+    /// Seconds between full scans (0 when only events trigger scans)
+    pub scan_interval_secs: u64,
+    /// Folders queued by events and not yet scanned
+    pub pending_folders: usize,
+    /// When the last scan finished
+    pub last_scan_at: Option<DateTime<Utc>>,
+    pub last_scan_kind: Option<ScanKind>,
+    pub last_scan_duration_ms: Option<u64>,
+    /// Changes made by the last successful scan
+    pub last_changes: Option<ChangeSummary>,
+    /// Error from the most recent failed scan
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+    /// Scans run since startup
+    pub scans: u64,
+    /// Scans that failed since startup
+    pub errors: u64,
+}
+
+/// Background task keeping the library catalog up to date
+#[derive(Clone)]
+pub struct LibraryWatcher {
+    inner: Arc<WatcherInner>,
+}
+
+struct WatcherInner {
+    scanner: LibraryScanner,
+    pool: SqlitePool,
+    /// Counts pages of scanned books
+    documents: LibraryDocuments,
+    status: Mutex<WatcherStatus>,
+    /// Folders queued by bucket events
+    pending: Mutex<HashSet<String>>,
+    /// Woken when folders are queued
+    wake: Notify,
+    /// Held for the duration of a scan
+    scan_lock: tokio::sync::Mutex<()>,
+}
+
+impl LibraryWatcher {
+    /// Create a watcher; nothing runs until [`LibraryWatcher::start`]
+    pub fn new(storage: Storage, pool: SqlitePool, documents: LibraryDocuments) -> Self {
+        Self {
+            inner: Arc::new(WatcherInner {
+                scanner: LibraryScanner::new(storage),
+                pool,
+                documents,
+                status: Mutex::new(WatcherStatus::default()),
+                pending: Mutex::new(HashSet::new()),
+                wake: Notify::new(),
+                scan_lock: tokio::sync::Mutex::new(()),
+            }),
+        }
+    }
+
+    /// Spawn the background task
+    ///
+    /// Runs a full scan immediately, then every `interval` (never, if zero),
+    /// and handles queued events in between.
+    pub fn start(&self, interval: Duration) {
+        self.inner.status.lock().scan_interval_secs = interval.as_secs();
+
+        let watcher = self.clone();
+        tokio::spawn(async move {
+            let _ = watcher.scan_now().await;
+
+            let period = interval.max(Duration::from_secs(1));
+            let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                tokio::select! {
+                    _ = ticker.tick(), if !interval.is_zero() => {
+                        let _ = watcher.scan_now().await;
+                    }
+                    _ = watcher.inner.wake.notified() => {
+                        tokio::time::sleep(EVENT_DEBOUNCE).await;
+                        watcher.scan_pending().await;
+                    }
+                }
+            }
+        });
+
+        tracing::info!(
+            "Library watcher started (full scan every {}s)",
+            interval.as_secs()
+        );
+    }
+
+    /// Run a full scan now, waiting for any scan in progress first
+    pub async fn scan_now(&self) -> Result<LibraryChanges> {
+        let _guard = self.inner.scan_lock.lock().await;
+        let catalog = CatalogRepository::new(&self.inner.pool);
+
+        self.record(ScanKind::Full, catalog.refresh(&self.inner.scanner))
+            .await
+    }
+
+    /// Queue the book folders of changed object keys for a rescan
+    ///
+    /// Returns the number of folders queued; keys outside a book folder
+    /// are ignored.
+    pub fn queue_keys<'k>(&self, keys: impl IntoIterator<Item = &'k str>) -> usize {
+        let folders: HashSet<String> = keys.into_iter().filter_map(book_folder).collect();
+        if folders.is_empty() {
+            return 0;
+        }
+
+        let count = folders.len();
+        self.inner.pending.lock().extend(folders);
+        self.inner.wake.notify_one();
+        count
+    }
+
+    /// Current watcher state
+    pub fn status(&self) -> WatcherStatus {
+        let mut status = self.inner.status.lock().clone();
+        status.pending_folders = self.inner.pending.lock().len();
+        status
+    }
+
+    /// Rescan the folders queued by events
+    async fn scan_pending(&self) {
+        let _guard = self.inner.scan_lock.lock().await;
+
+        let folders = std::mem::take(&mut *self.inner.pending.lock());
+        if folders.is_empty() {
+            return;
+        }
+
+        let catalog = CatalogRepository::new(&self.inner.pool);
+        let result = self
+            .record(
+                ScanKind::Events,
+                catalog.refresh_folders(&self.inner.scanner, &folders),
+            )
+            .await;
+
+        // Retry on the next event or interval rather than dropping them
+        if result.is_err() {
+            self.inner.pending.lock().extend(folders);
+        }
+    }
+
+    /// Run a scan, recording its outcome in the status
+    async fn record(
+        &self,
+        kind: ScanKind,
+        scan: impl std::future::Future<Output = Result<LibraryChanges>>,
+    ) -> Result<LibraryChanges> {
+        self.inner.status.lock().scanning = true;
+        let start = Instant::now();
+
+        let result = scan.await;
+
+        let now = Utc::now();
+        let mut status = self.inner.status.lock();
+        status.scanning = false;
+        status.scans += 1;
+        status.last_scan_at = Some(now);
+        status.last_scan_kind = Some(kind);
+        status.last_scan_duration_ms = Some(start.elapsed().as_millis() as u64);
+
+        match &result {
+            Ok(changes) => {
+                status.last_changes = Some(changes.into());
+                self.count_pages();
+            }
+            Err(e) => {
+                tracing::warn!("Library scan failed: {}", e);
+                status.errors += 1;
+                status.last_error = Some(e.to_string());
+                status.last_error_at = Some(now);
+            }
+        }
+
+        result
+    }
+
+    /// Fill in missing page counts in the background
+    fn count_pages(&self) {
+        let documents = self.inner.documents.clone();
+        tokio::spawn(async move {
+            match documents.fill_page_counts().await {
+                Ok(0) => {}
+                Ok(counted) => tracing::info!("Counted pages of {} book files", counted),
+                Err(e) => tracing::warn!("Failed to fill in page counts: {}", e),
+            }
+        });
+    }
+}
+
+/// Bucket event notification (S3 event format, as sent by MinIO webhooks)
+#[derive(Debug, Deserialize)]
+pub struct BucketEvent {
+    #[serde(rename = "Records", default)]
+    pub records: Vec<EventRecord>,
+}
+
+/// A single object event
+#[derive(Debug, Deserialize)]
+pub struct EventRecord {
+    #[serde(rename = "eventName", default)]
+    pub event_name: String,
+    pub s3: EventEntity,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventEntity {
+    pub bucket: EventBucket,
+    pub object: EventObject,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventBucket {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventObject {
+    /// Object key, URL-encoded
+    pub key: String,
+}
+
+impl BucketEvent {
+    /// Decoded keys of objects created or removed in `bucket` (any bucket,
+    /// if empty); access events are ignored
+    pub fn keys(&self, bucket: &str) -> Vec<String> {
+        self.records
+            .iter()
+            .filter(|record| {
+                record.event_name.starts_with("s3:ObjectCreated:")
+                    || record.event_name.starts_with("s3:ObjectRemoved:")
+            })
+            .filter(|record| bucket.is_empty() || record.s3.bucket.name == bucket)
+            .filter_map(|record| {
+                // Keys are query-escaped: spaces arrive as '+'
+                let key = record.s3.object.key.replace('+', " ");
+                urlencoding::decode(&key).ok().map(|k| k.into_owned())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{CatalogFilter, CatalogOrder};
+    use crate::document::{CacheConfig, DocumentCache};
+
+    #[test]
+    fn test_bucket_event_keys() {
+        let payload = r#"{
+            "EventName": "s3:ObjectCreated:Put",
+            "Key": "library/Frank Herbert/Dune/Dune.epub",
+            "Records": [
+                {
+                    "eventName": "s3:ObjectCreated:Put",
+                    "s3": {
+                        "bucket": {"name": "library"},
+                        "object": {"key": "Frank+Herbert%2FDune%2FDune+%281965%29.epub", "size": 1024}
+                    }
+                },
+                {
+                    "eventName": "s3:ObjectAccessed:Get",
+                    "s3": {
+                        "bucket": {"name": "library"},
+                        "object": {"key": "Frank+Herbert%2FDune%2Fcover.jpg"}
+                    }
+                },
+                {
+                    "eventName": "s3:ObjectRemoved:Delete",
+                    "s3": {
+                        "bucket": {"name": "other"},
+                        "object": {"key": "Jane+Austen%2FEmma%2FEmma.epub"}
+                    }
+                }
+            ]
+        }"#;
+
+        let event: BucketEvent = serde_json::from_str(payload).unwrap();
+        assert_eq!(
+            event.keys("library"),
+            vec!["Frank Herbert/Dune/Dune (1965).epub".to_string()]
+        );
+        assert_eq!(event.keys("").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_event_scan_updates_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage: Storage = Arc::new(crate::storage::LocalStorage::new(dir.path()).await.unwrap());
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        crate::db::initialize_schema(&pool).await.unwrap();
+
+        let cache = DocumentCache::new(CacheConfig::default());
+        let documents = LibraryDocuments::new(storage.clone(), pool.clone(), cache);
+        let watcher = LibraryWatcher::new(storage.clone(), pool.clone(), documents);
+        let catalog = CatalogRepository::new(&pool);
+
+        storage
+            .put_object("Frank Herbert/Dune/Dune.epub", b"epub".to_vec(), "application/epub+zip")
+            .await
+            .unwrap();
+        storage
+            .put_object("Jane Austen/Emma/Emma.epub", b"epub".to_vec(), "application/epub+zip")
+            .await
+            .unwrap();
+
+        // Only the queued folder is scanned
+        assert_eq!(watcher.queue_keys(["Frank Herbert/Dune/Dune.epub", "stray.txt"]), 1);
+        watcher.scan_pending().await;
+        let books = catalog
+            .list(&CatalogFilter::default(), CatalogOrder::Title, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(books.len(), 1);
+        assert_eq!(books[0].title, "Dune");
+
+        let status = watcher.status();
+        assert_eq!(status.last_scan_kind, Some(ScanKind::Events));
+        assert_eq!(status.pending_folders, 0);
+
+        // A full scan picks up the rest, and a removal event drops the book
+        watcher.scan_now().await.unwrap();
+        assert_eq!(catalog.count().await.unwrap(), 2);
+
+        storage.delete_object("Jane Austen/Emma/Emma.epub").await.unwrap();
+        watcher.queue_keys(["Jane Austen/Emma/Emma.epub"]);
+        watcher.scan_pending().await;
+        assert_eq!(catalog.count().await.unwrap(), 1);
+        assert_eq!(watcher.status().last_changes.unwrap().removed, 1);
+        assert_eq!(watcher.status().scans, 3);
+    }
+}
@@ -11,6 +11,7 @@ use axum::{
 };
 use serde::Serialize;
 use std::net::SocketAddr;
+use std::time::Duration;
 use tokio::signal;
 use tower_http::cors::{CorsLayer, Any};
 use tower_http::trace::TraceLayer;
@@ -36,12 +37,9 @@ mod storage;
 mod sync;
 mod upload;
 
-use config::Config;
-use library::LibraryScanner;
-use routes::opds::LibraryCache;
+use config::{Config, StorageProvider};
 use routes::upload::create_upload_state;
 use state::AppState;
-use storage::S3Client;
 
 #[derive(Serialize)]
 struct HealthResponse {
@@ -74,13 +72,20 @@ async fn main() {
     });
 
     tracing::info!("Starting Los Libros Server v{}", env!("CARGO_PKG_VERSION"));
-    tracing::info!("S3 endpoint: {}", config.storage.endpoint);
-    tracing::info!("S3 bucket: {}", config.storage.bucket);
+    match config.storage.provider {
+        StorageProvider::Local => {
+            tracing::info!("Storage directory: {}", config.storage.local_path);
+        }
+        _ => {
+            tracing::info!("S3 endpoint: {}", config.storage.endpoint);
+            tracing::info!("S3 bucket: {}", config.storage.bucket);
+        }
+    }
 
-    // Initialize S3 client
-    let s3_client = S3Client::new(&config.storage)
+    // Initialize storage backend
+    let storage = storage::connect(&config.storage)
         .await
-        .expect("Failed to initialize S3 client");
+        .expect("Failed to initialize storage");
 
     // Initialize database
     let db_pool = db::create_pool(&config.database.url)
@@ -89,23 +94,20 @@ async fn main() {
     tracing::info!("Database initialized at {}", config.database.url);
 
     // Create application state
-    let app_state = AppState::new(config.clone(), s3_client.clone(), db_pool.clone()).await;
-
-    // Create library cache and initial scan
-    let library_cache = LibraryCache::new();
-    let scanner = LibraryScanner::new(s3_client);
-    if let Err(e) = library_cache.refresh(&scanner).await {
-        tracing::warn!("Initial library scan failed: {}. Will retry on /opds/refresh", e);
-    } else {
-        let count = library_cache.get_books().await.len();
-        tracing::info!("Library initialized with {} books", count);
-    }
+    let app_state = AppState::new(config.clone(), storage, db_pool.clone()).await;
+
+    // Keep the persisted catalog up to date in the background; it is
+    // served as-is until the first scan completes
+    app_state
+        .library_watcher()
+        .start(Duration::from_secs(config.library.scan_interval_secs));
 
     // Build CORS layer
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
-        .allow_headers(Any);
+        .allow_headers(Any)
+        .expose_headers(Any);
 
     // Create upload state with local chunk storage
     let chunk_base_path = std::env::var("CHUNK_STORAGE_PATH")
@@ -115,8 +117,14 @@ async fn main() {
         std::path::PathBuf::from(chunk_base_path),
     );
 
-    // Start upload session cleanup task
-    upload_state.session_manager.clone().start_cleanup_task();
+    // Reload in-progress uploads, then start the session cleanup task
+    if let Err(e) = upload_state.session_manager.restore().await {
+        tracing::warn!("Failed to restore upload sessions: {}", e);
+    }
+    upload_state
+        .session_manager
+        .clone()
+        .start_cleanup_task(upload_state.chunk_store.clone());
 
     // Build router
     let app = Router::new()
@@ -125,9 +133,12 @@ async fn main() {
         .nest("/api/v1/documents", routes::documents::router())
         // Legacy /api/v1/books endpoint removed - use /api/v1/documents instead
         .nest("/api/v1/pdf", routes::pdf::router())
+        .nest("/api/v1/tus", routes::tus::router(upload_state.clone()))
         .nest("/api/v1/upload", routes::upload::router(upload_state))
-        .nest("/opds", routes::opds::router(library_cache))
+        .nest("/opds", routes::opds::router())
+        .nest("/api/v1/library", routes::library::router())
         .nest("/files", routes::files::router())
+        .nest("/covers", routes::covers::router())
         .nest("/api/v1/progress", routes::progress::router(db_pool.clone()))
         .nest("/api/v1/highlights", routes::highlights::router(db_pool.clone()))
         .nest("/api/v1/annotations", routes::annotations::router())
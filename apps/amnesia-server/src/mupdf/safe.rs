@@ -243,11 +243,12 @@ impl SafeDocument {
         f(&mut doc)
     }
 
-    /// Get MIME type for format
+    /// Get the MIME type MuPDF opens a format by
     fn format_to_mime(format: DocumentFormat) -> &'static str {
         match format {
-            DocumentFormat::Pdf => "application/pdf",
-            DocumentFormat::Epub => "application/epub+zip",
+            // MuPDF's FB2 handler doesn't know the "+xml" type
+            DocumentFormat::Fb2 => "application/x-fictionbook",
+            _ => format.mime_type(),
         }
     }
 
@@ -270,10 +271,11 @@ impl SafeDocument {
 
     /// Check if the document has a text layer
     ///
-    /// For PDFs, this checks if the first page has extractable text.
-    /// For EPUBs, this always returns true (text is always available).
+    /// For PDFs and XPS, this checks if the first page has extractable text.
+    /// For reflowable formats (EPUB, FB2, MOBI), this always returns true
+    /// (text is always available).
     pub fn has_text_layer(&self) -> DocumentResult<bool> {
-        if self.format == DocumentFormat::Epub {
+        if self.format.is_reflowable() {
             return Ok(true);
         }
 
@@ -331,5 +333,17 @@ mod tests {
             SafeDocument::format_to_mime(DocumentFormat::Epub),
             "application/epub+zip"
         );
+        assert_eq!(
+            SafeDocument::format_to_mime(DocumentFormat::Fb2),
+            "application/x-fictionbook"
+        );
+        assert_eq!(
+            SafeDocument::format_to_mime(DocumentFormat::Mobi),
+            "application/x-mobipocket-ebook"
+        );
+        assert_eq!(
+            SafeDocument::format_to_mime(DocumentFormat::Xps),
+            "application/vnd.ms-xpsdocument"
+        );
     }
 }
@@ -0,0 +1,340 @@
+//! Acquisition feed paging and facets
+//!
+//! Listings take `?page=` plus `language`, `tag`, `format` and `publisher`
+//! filters and a `sort` order. Facet groups offer the values found in the
+//! filtered listing; facet links keep the other parameters and go back to
+//! the first page. Filtering, sorting, paging and facet counts all run in
+//! the catalog query, so a page never loads the whole catalog.
+
+use serde::Deserialize;
+
+use super::feed::{OPDSFacet, OPDSFacetGroup, OPDSFeed};
+use crate::db::{CatalogFacet, CatalogFilter, CatalogOrder, CatalogRepository};
+use crate::error::Result;
+use crate::library::LibraryBook;
+
+/// Books per page of a listing
+pub const PAGE_SIZE: usize = 50;
+
+/// Most values offered per facet group (the most common ones)
+const MAX_FACETS: usize = 20;
+
+/// Listing order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Title,
+    Author,
+    /// Newest first
+    Added,
+}
+
+impl SortOrder {
+    const ALL: [SortOrder; 3] = [SortOrder::Title, SortOrder::Author, SortOrder::Added];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortOrder::Title => "title",
+            SortOrder::Author => "author",
+            SortOrder::Added => "added",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            SortOrder::Title => "Title",
+            SortOrder::Author => "Author",
+            SortOrder::Added => "Recently Added",
+        }
+    }
+}
+
+impl From<SortOrder> for CatalogOrder {
+    fn from(order: SortOrder) -> Self {
+        match order {
+            SortOrder::Title => CatalogOrder::Title,
+            SortOrder::Author => CatalogOrder::Author,
+            SortOrder::Added => CatalogOrder::Added,
+        }
+    }
+}
+
+/// Query parameters of a listing
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FeedQuery {
+    /// Search query (search feeds only)
+    pub q: Option<String>,
+    /// 1-based page
+    pub page: Option<usize>,
+    pub sort: Option<SortOrder>,
+    pub language: Option<String>,
+    pub tag: Option<String>,
+    /// Format name, such as `epub`
+    pub format: Option<String>,
+    pub publisher: Option<String>,
+}
+
+impl FeedQuery {
+    /// `scope` (the feed's own selection) narrowed by these filters
+    fn filter(&self, scope: CatalogFilter) -> CatalogFilter {
+        CatalogFilter {
+            language: self.language.clone(),
+            tag: self.tag.clone(),
+            format: self.format.clone(),
+            publisher: self.publisher.clone(),
+            ..scope
+        }
+    }
+
+    /// `path` with these parameters as its query string
+    pub fn href(&self, path: &str) -> String {
+        let page = self.page.map(|page| page.to_string());
+        let params = [
+            ("q", self.q.as_deref()),
+            ("sort", self.sort.as_ref().map(SortOrder::name)),
+            ("language", self.language.as_deref()),
+            ("tag", self.tag.as_deref()),
+            ("format", self.format.as_deref()),
+            ("publisher", self.publisher.as_deref()),
+            ("page", page.as_deref()),
+        ];
+
+        let query: Vec<String> = params
+            .iter()
+            .filter_map(|(name, value)| {
+                value.map(|value| format!("{}={}", name, urlencoding::encode(value)))
+            })
+            .collect();
+        if query.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}?{}", path, query.join("&"))
+        }
+    }
+
+    /// Link to a facet: these parameters with one changed, on the first page
+    fn facet_href(&self, path: &str, change: impl FnOnce(&mut FeedQuery)) -> String {
+        let mut query = self.clone();
+        query.page = None;
+        change(&mut query);
+        query.href(path)
+    }
+}
+
+/// Load one page of the books in `scope` for a feed at `path`, filtered
+/// and sorted by `query`, adding facet groups and paging links
+///
+/// `natural` is the feed's order when no sort is requested (series order,
+/// say).
+pub async fn apply_facets(
+    feed: &mut OPDSFeed,
+    catalog: &CatalogRepository<'_>,
+    scope: CatalogFilter,
+    query: &FeedQuery,
+    path: &str,
+    natural: CatalogOrder,
+) -> Result<Vec<LibraryBook>> {
+    let filter = query.filter(scope);
+
+    let groups = [
+        ("Language", CatalogFacet::Language, query.language.as_ref()),
+        ("Tag", CatalogFacet::Tag, query.tag.as_ref()),
+        ("Format", CatalogFacet::Format, query.format.as_ref()),
+        (
+            "Publisher",
+            CatalogFacet::Publisher,
+            query.publisher.as_ref(),
+        ),
+    ];
+    for (title, facet, selected) in groups {
+        let counts = catalog
+            .facet_counts(&filter, facet, MAX_FACETS as i64)
+            .await?;
+        let field: fn(&mut FeedQuery) -> &mut Option<String> = match facet {
+            CatalogFacet::Language => |query| &mut query.language,
+            CatalogFacet::Tag => |query| &mut query.tag,
+            CatalogFacet::Format => |query| &mut query.format,
+            CatalogFacet::Publisher => |query| &mut query.publisher,
+        };
+        feed.facets
+            .extend(filter_group(title, counts, selected, query, path, field));
+    }
+
+    let active = query.sort.or_else(|| {
+        SortOrder::ALL
+            .into_iter()
+            .find(|order| CatalogOrder::from(*order) == natural)
+    });
+    feed.facets.push(OPDSFacetGroup {
+        title: "Sort by".to_string(),
+        facets: SortOrder::ALL
+            .iter()
+            .map(|order| OPDSFacet {
+                title: order.label().to_string(),
+                href: query.facet_href(path, |query| query.sort = Some(*order)),
+                count: None,
+                active: active == Some(*order),
+            })
+            .collect(),
+    });
+
+    let total = catalog.count_matching(&filter).await?;
+    let page = query.page.unwrap_or(1).max(1);
+    feed.add_pagination(total as usize, page, PAGE_SIZE, |page| {
+        let mut query = query.clone();
+        query.page = Some(page);
+        query.href(path)
+    });
+
+    let order = query.sort.map(CatalogOrder::from).unwrap_or(natural);
+    // A huge ?page= lists nothing rather than overflowing
+    let offset = (page - 1).saturating_mul(PAGE_SIZE).min(i64::MAX as usize) as i64;
+    catalog.list(&filter, order, PAGE_SIZE as i64, offset).await
+}
+
+/// Facet group for one filter: "All" plus the most common values
+///
+/// `values` come most common first. `None` when the listing has
+/// no values for the field and the filter is unset.
+fn filter_group(
+    title: &str,
+    mut values: Vec<(String, i64)>,
+    selected: Option<&String>,
+    query: &FeedQuery,
+    path: &str,
+    field: fn(&mut FeedQuery) -> &mut Option<String>,
+) -> Option<OPDSFacetGroup> {
+    if values.is_empty() && selected.is_none() {
+        return None;
+    }
+
+    if let Some(selected) = selected {
+        if !values.iter().any(|(value, _)| value == selected) {
+            values.push((selected.clone(), 0));
+        }
+    }
+
+    let mut facets = vec![OPDSFacet {
+        title: "All".to_string(),
+        href: query.facet_href(path, |query| *field(query) = None),
+        count: None,
+        active: selected.is_none(),
+    }];
+    facets.extend(values.into_iter().map(|(value, count)| OPDSFacet {
+        title: value.clone(),
+        active: selected == Some(&value),
+        href: query.facet_href(path, |query| *field(query) = Some(value)),
+        count: Some(count as usize),
+    }));
+
+    Some(OPDSFacetGroup {
+        title: title.to_string(),
+        facets,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::initialize_schema;
+    use crate::library::{BookFormat, FormatType};
+    use crate::opds::FeedFormat;
+    use sqlx::SqlitePool;
+
+    fn book(title: &str, language: &str, tags: &[&str]) -> LibraryBook {
+        let mut book = LibraryBook::new(title.to_string(), format!("Author/{}", title));
+        book.language = Some(language.to_string());
+        book.tags = tags.iter().map(|t| t.to_string()).collect();
+        book.formats = vec![BookFormat {
+            format: FormatType::Epub,
+            s3_key: format!("Author/{}/{}.epub", title, title),
+            size: 1,
+            pages: None,
+        }];
+        book
+    }
+
+    #[test]
+    fn test_feed_query_href() {
+        let query = FeedQuery {
+            q: Some("author:\"Le Guin\"".to_string()),
+            page: Some(2),
+            sort: Some(SortOrder::Added),
+            ..Default::default()
+        };
+        assert_eq!(
+            query.href("/opds/search"),
+            "/opds/search?q=author%3A%22Le%20Guin%22&sort=added&page=2"
+        );
+        assert_eq!(FeedQuery::default().href("/opds/all"), "/opds/all");
+    }
+
+    #[tokio::test]
+    async fn test_apply_facets() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        initialize_schema(&pool).await.unwrap();
+        let catalog = CatalogRepository::new(&pool);
+        for book in [
+            book("Dune", "en", &["SF"]),
+            book("Emma", "en", &["Classics"]),
+            book("Solaris", "pl", &["SF"]),
+        ] {
+            catalog.upsert(&book).await.unwrap();
+        }
+        let query = FeedQuery {
+            tag: Some("SF".to_string()),
+            sort: Some(SortOrder::Title),
+            ..Default::default()
+        };
+
+        let mut feed = OPDSFeed::acquisition(FeedFormat::Atom, "All", "/opds/all");
+        let scope = CatalogFilter::default();
+        let page = apply_facets(
+            &mut feed,
+            &catalog,
+            scope,
+            &query,
+            "/opds/all",
+            CatalogOrder::Title,
+        )
+        .await
+        .unwrap();
+        let titles: Vec<&str> = page.iter().map(|b| b.title.as_str()).collect();
+        assert_eq!(titles, vec!["Dune", "Solaris"]);
+        assert_eq!(feed.pagination.unwrap().number_of_items, 2);
+
+        let language = feed.facets.iter().find(|g| g.title == "Language").unwrap();
+        assert_eq!(language.facets.len(), 3);
+        assert!(language.facets[0].active);
+        assert_eq!(
+            language.facets[1].href,
+            "/opds/all?sort=title&language=en&tag=SF"
+        );
+
+        let tags = feed.facets.iter().find(|g| g.title == "Tag").unwrap();
+        assert!(tags
+            .facets
+            .iter()
+            .any(|f| f.title == "SF" && f.active && f.count == Some(2)));
+        assert!(feed.facets.iter().all(|g| g.title != "Publisher"));
+        assert!(feed.facets.last().unwrap().facets[0].active);
+
+        let query = FeedQuery {
+            page: Some(usize::MAX),
+            ..Default::default()
+        };
+        let mut feed = OPDSFeed::acquisition(FeedFormat::Atom, "All", "/opds/all");
+        let scope = CatalogFilter::default();
+        let page = apply_facets(
+            &mut feed,
+            &catalog,
+            scope,
+            &query,
+            "/opds/all",
+            CatalogOrder::Title,
+        )
+        .await
+        .unwrap();
+        assert!(page.is_empty());
+    }
+}
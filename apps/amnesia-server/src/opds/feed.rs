@@ -1,9 +1,14 @@
 //! OPDS feed generation
+//!
+//! Feeds are built once and serialized as OPDS 1.2 Atom (`xml`) or OPDS 2.0
+//! JSON (`json`), depending on their `FeedFormat`.
+
+use std::collections::HashMap;
 
 use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
-use crate::library::{BookFormat, FormatType, LibraryBook};
+use crate::library::{BookFormat, FormatType, LibraryBook, LibraryDocuments};
 
 /// OPDS feed types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -12,16 +17,55 @@ pub enum FeedType {
     Acquisition,
 }
 
+/// Serialization of a feed, which also decides its links' paths and types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    /// OPDS 1.2 Atom, under `/opds`
+    Atom,
+    /// OPDS 2.0 JSON, under `/opds/v2`
+    Json,
+}
+
+impl FeedFormat {
+    /// Path of the catalog root
+    pub fn root(&self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "/opds",
+            FeedFormat::Json => "/opds/v2",
+        }
+    }
+
+    /// Media type of navigation feeds
+    pub fn navigation_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Atom => mime::ATOM_CATALOG,
+            FeedFormat::Json => mime::OPDS_JSON,
+        }
+    }
+
+    /// Media type of acquisition feeds
+    pub fn acquisition_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Atom => mime::ATOM_ACQUISITION,
+            FeedFormat::Json => mime::OPDS_JSON,
+        }
+    }
+}
+
 /// OPDS link relations
 pub mod rel {
     pub const SELF: &str = "self";
     pub const START: &str = "start";
     pub const UP: &str = "up";
+    pub const FIRST: &str = "first";
+    pub const LAST: &str = "last";
     pub const SUBSECTION: &str = "subsection";
     pub const ACQUISITION: &str = "http://opds-spec.org/acquisition";
     pub const ACQUISITION_OPEN: &str = "http://opds-spec.org/acquisition/open-access";
     pub const IMAGE: &str = "http://opds-spec.org/image";
     pub const THUMBNAIL: &str = "http://opds-spec.org/image/thumbnail";
+    pub const FACET: &str = "http://opds-spec.org/facet";
+    pub const PSE_STREAM: &str = "http://vaemendis.net/opds-pse/stream";
     pub const SEARCH: &str = "search";
     pub const NEXT: &str = "next";
     pub const PREVIOUS: &str = "previous";
@@ -33,6 +77,7 @@ pub mod mime {
     pub const ATOM_CATALOG: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
     pub const ATOM_ACQUISITION: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
     pub const OPENSEARCH: &str = "application/opensearchdescription+xml";
+    pub const OPDS_JSON: &str = "application/opds+json";
 }
 
 /// An OPDS feed
@@ -46,11 +91,17 @@ pub struct OPDSFeed {
     pub entries: Vec<OPDSEntry>,
     pub icon: Option<String>,
     pub subtitle: Option<String>,
+    pub format: FeedFormat,
+    /// Groups of entries shown together (OPDS 2.0 only)
+    pub groups: Vec<OPDSGroup>,
+    /// Facet groups for filtering or sorting the feed
+    pub facets: Vec<OPDSFacetGroup>,
+    pub pagination: Option<OPDSPagination>,
 }
 
 impl OPDSFeed {
     /// Create a navigation feed (for browsing)
-    pub fn navigation(title: &str, self_href: &str) -> Self {
+    pub fn navigation(format: FeedFormat, title: &str, self_href: &str) -> Self {
         Self {
             id: format!("urn:uuid:{}", Uuid::new_v4()),
             title: title.to_string(),
@@ -63,29 +114,89 @@ impl OPDSFeed {
                 OPDSLink {
                     href: self_href.to_string(),
                     rel: Some(rel::SELF.to_string()),
-                    link_type: Some(mime::ATOM_CATALOG.to_string()),
+                    link_type: Some(format.navigation_type().to_string()),
                     title: None,
+                    count: None,
                 },
                 OPDSLink {
-                    href: "/opds".to_string(),
+                    href: format.root().to_string(),
                     rel: Some(rel::START.to_string()),
-                    link_type: Some(mime::ATOM_CATALOG.to_string()),
+                    link_type: Some(format.navigation_type().to_string()),
                     title: None,
+                    count: None,
                 },
             ],
             entries: Vec::new(),
             icon: None,
             subtitle: None,
+            format,
+            groups: Vec::new(),
+            facets: Vec::new(),
+            pagination: None,
         }
     }
 
     /// Create an acquisition feed (for book listings)
-    pub fn acquisition(title: &str, self_href: &str) -> Self {
-        let mut feed = Self::navigation(title, self_href);
-        feed.links[0].link_type = Some(mime::ATOM_ACQUISITION.to_string());
+    pub fn acquisition(format: FeedFormat, title: &str, self_href: &str) -> Self {
+        let mut feed = Self::navigation(format, title, self_href);
+        feed.links[0].link_type = Some(format.acquisition_type().to_string());
         feed
     }
 
+    /// Add a link to the parent feed
+    pub fn add_up_link(&mut self, href: &str) {
+        self.links.push(OPDSLink {
+            href: href.to_string(),
+            rel: Some(rel::UP.to_string()),
+            link_type: Some(self.format.navigation_type().to_string()),
+            title: None,
+            count: None,
+        });
+    }
+
+    /// Record the pagination of a listing of `total` items and add
+    /// `first`/`previous`/`next`/`last` links built by `page_href`
+    ///
+    /// Pages are numbered from 1; the items themselves are paged by the
+    /// caller (in the catalog query).
+    pub fn add_pagination(
+        &mut self,
+        total: usize,
+        page: usize,
+        per_page: usize,
+        page_href: impl Fn(usize) -> String,
+    ) {
+        let page = page.max(1);
+        let per_page = per_page.max(1);
+        let last = total.div_ceil(per_page).max(1);
+
+        let link_type = self.links[0].link_type.clone();
+        let mut add_link = |rel: &str, page: usize| {
+            self.links.push(OPDSLink {
+                href: page_href(page),
+                rel: Some(rel.to_string()),
+                link_type: link_type.clone(),
+                title: None,
+                count: None,
+            });
+        };
+
+        add_link(rel::FIRST, 1);
+        if page > 1 {
+            add_link(rel::PREVIOUS, (page - 1).min(last));
+        }
+        if page < last {
+            add_link(rel::NEXT, page + 1);
+        }
+        add_link(rel::LAST, last);
+
+        self.pagination = Some(OPDSPagination {
+            number_of_items: total,
+            items_per_page: per_page,
+            current_page: page,
+        });
+    }
+
     /// Add a navigation entry
     pub fn add_navigation_entry(&mut self, entry: OPDSEntry) {
         self.entries.push(entry);
@@ -98,34 +209,79 @@ impl OPDSFeed {
         }
     }
 
+    /// Point acquisition links at direct (presigned) storage URLs
+    ///
+    /// `urls` maps storage keys to URLs; links without an entry keep
+    /// pointing at `{base_url}/files/`.
+    pub fn use_direct_acquisition_links(&mut self, base_url: &str, urls: &HashMap<String, String>) {
+        let files_prefix = format!("{}/files/", base_url);
+
+        for link in self.entries.iter_mut().flat_map(|entry| entry.links.iter_mut()) {
+            if link.rel.as_deref() != Some(rel::ACQUISITION_OPEN) {
+                continue;
+            }
+            let direct = link
+                .href
+                .strip_prefix(&files_prefix)
+                .and_then(|key| urls.get(key));
+            if let Some(url) = direct {
+                link.href = url.clone();
+            }
+        }
+    }
+
     /// Create the root catalog
-    pub fn root_catalog(base_url: &str) -> Self {
-        let mut feed = Self::navigation("Los Libros Catalog", &format!("{}/opds", base_url));
+    pub fn root_catalog(format: FeedFormat, base_url: &str) -> Self {
+        let root = format!("{}{}", base_url, format.root());
+        let mut feed = Self::navigation(format, "Los Libros Catalog", &root);
         feed.subtitle = Some("Your personal ebook library".to_string());
 
+        // Search: OpenSearch description for Atom clients, a URI template
+        // for OPDS 2.0 ones
+        let search_links = match format {
+            FeedFormat::Atom => vec![
+                (format!("{}/opensearch.xml", root), mime::OPENSEARCH),
+                (format!("{}/search?q={{searchTerms}}", root), mime::ATOM_ACQUISITION),
+            ],
+            FeedFormat::Json => vec![(format!("{}/search{{?q}}", root), mime::OPDS_JSON)],
+        };
+        for (href, link_type) in search_links {
+            feed.links.push(OPDSLink {
+                href,
+                rel: Some(rel::SEARCH.to_string()),
+                link_type: Some(link_type.to_string()),
+                title: Some("Search".to_string()),
+                count: None,
+            });
+        }
+
         // Add navigation entries
         feed.add_navigation_entry(OPDSEntry::navigation(
+            format,
             "All Books",
             "Browse all books in the library",
-            &format!("{}/opds/all", base_url),
+            &format!("{}/all", root),
         ));
 
         feed.add_navigation_entry(OPDSEntry::navigation(
+            format,
             "By Author",
             "Browse books by author",
-            &format!("{}/opds/authors", base_url),
+            &format!("{}/authors", root),
         ));
 
         feed.add_navigation_entry(OPDSEntry::navigation(
+            format,
             "By Series",
             "Browse books by series",
-            &format!("{}/opds/series", base_url),
+            &format!("{}/series", root),
         ));
 
         feed.add_navigation_entry(OPDSEntry::navigation(
+            format,
             "Recent",
             "Recently added books",
-            &format!("{}/opds/recent", base_url),
+            &format!("{}/recent", root),
         ));
 
         feed
@@ -145,11 +301,13 @@ pub struct OPDSEntry {
     pub categories: Vec<OPDSCategory>,
     pub published: Option<String>,
     pub language: Option<String>,
+    pub publisher: Option<String>,
+    pub series: Option<OPDSSeries>,
 }
 
 impl OPDSEntry {
     /// Create a navigation entry
-    pub fn navigation(title: &str, summary: &str, href: &str) -> Self {
+    pub fn navigation(format: FeedFormat, title: &str, summary: &str, href: &str) -> Self {
         Self {
             id: format!("urn:uuid:{}", Uuid::new_v4()),
             title: title.to_string(),
@@ -157,8 +315,9 @@ impl OPDSEntry {
             links: vec![OPDSLink {
                 href: href.to_string(),
                 rel: Some(rel::SUBSECTION.to_string()),
-                link_type: Some(mime::ATOM_ACQUISITION.to_string()),
+                link_type: Some(format.acquisition_type().to_string()),
                 title: None,
+                count: None,
             }],
             content: None,
             summary: Some(summary.to_string()),
@@ -166,6 +325,8 @@ impl OPDSEntry {
             categories: Vec::new(),
             published: None,
             language: None,
+            publisher: None,
+            series: None,
         }
     }
 
@@ -178,19 +339,28 @@ impl OPDSEntry {
             links.push(OPDSLink::acquisition(format, base_url));
         }
 
-        // Add cover image links
-        if let Some(ref cover_key) = book.cover_key {
+        // Page streaming for page-based formats whose page count is known
+        for format in &book.formats {
+            if let Some(pages) = format.pages.filter(|_| LibraryDocuments::is_page_based(format)) {
+                links.push(OPDSLink::page_stream(book, format, pages, base_url));
+            }
+        }
+
+        // Add cover image links (covers are extracted on first request)
+        if book.has_cover() {
             links.push(OPDSLink {
-                href: format!("{}/files/{}", base_url, cover_key),
+                href: format!("{}/covers/{}", base_url, book.id),
                 rel: Some(rel::IMAGE.to_string()),
                 link_type: Some("image/jpeg".to_string()),
                 title: None,
+                count: None,
             });
             links.push(OPDSLink {
-                href: format!("{}/files/{}", base_url, cover_key),
+                href: format!("{}/covers/{}?size=thumbnail", base_url, book.id),
                 rel: Some(rel::THUMBNAIL.to_string()),
                 link_type: Some("image/jpeg".to_string()),
                 title: None,
+                count: None,
             });
         }
 
@@ -244,6 +414,11 @@ impl OPDSEntry {
             categories,
             published: book.pubdate.clone(),
             language: book.language.clone(),
+            publisher: book.publisher.clone(),
+            series: book.series.clone().map(|name| OPDSSeries {
+                name,
+                position: book.series_index,
+            }),
         }
     }
 }
@@ -255,6 +430,8 @@ pub struct OPDSLink {
     pub rel: Option<String>,
     pub link_type: Option<String>,
     pub title: Option<String>,
+    /// Number of pages of a page streaming link
+    pub count: Option<usize>,
 }
 
 impl OPDSLink {
@@ -265,6 +442,25 @@ impl OPDSLink {
             rel: Some(rel::ACQUISITION_OPEN.to_string()),
             link_type: Some(format.format.mime_type().to_string()),
             title: Some(format!("{:?}", format.format)),
+            count: None,
+        }
+    }
+
+    /// Create an OPDS-PSE page streaming link for a page-based format
+    ///
+    /// Readers fill in `{pageNumber}` (0-based) and `{maxWidth}`.
+    pub fn page_stream(book: &LibraryBook, format: &BookFormat, pages: usize, base_url: &str) -> Self {
+        Self {
+            href: format!(
+                "{}/opds/pse/{}/{}/{{pageNumber}}?maxWidth={{maxWidth}}",
+                base_url,
+                book.id,
+                format.format.name()
+            ),
+            rel: Some(rel::PSE_STREAM.to_string()),
+            link_type: Some("image/jpeg".to_string()),
+            title: None,
+            count: Some(pages),
         }
     }
 }
@@ -284,6 +480,49 @@ pub struct OPDSCategory {
     pub scheme: Option<String>,
 }
 
+/// Series an entry belongs to
+#[derive(Debug, Clone)]
+pub struct OPDSSeries {
+    pub name: String,
+    pub position: Option<f32>,
+}
+
+/// A titled group of entries
+#[derive(Debug, Clone)]
+pub struct OPDSGroup {
+    pub title: String,
+    /// Feed with all of the group's entries
+    pub href: Option<String>,
+    pub entries: Vec<OPDSEntry>,
+}
+
+/// A set of alternative views of a feed, such as sort orders
+#[derive(Debug, Clone)]
+pub struct OPDSFacetGroup {
+    pub title: String,
+    pub facets: Vec<OPDSFacet>,
+}
+
+/// One view within a facet group
+#[derive(Debug, Clone)]
+pub struct OPDSFacet {
+    pub title: String,
+    pub href: String,
+    /// Number of entries in the view, when known
+    pub count: Option<usize>,
+    /// Whether the feed is showing this view
+    pub active: bool,
+}
+
+/// Paging state of a feed
+#[derive(Debug, Clone, Copy)]
+pub struct OPDSPagination {
+    pub number_of_items: usize,
+    pub items_per_page: usize,
+    /// 1-based
+    pub current_page: usize,
+}
+
 /// OPDS content (HTML or text)
 #[derive(Debug, Clone)]
 pub struct OPDSContent {
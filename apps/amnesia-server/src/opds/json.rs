@@ -0,0 +1,344 @@
+//! OPDS 2.0 JSON serialization
+//!
+//! Maps the feed model onto an OPDS 2.0 document: navigation entries go to
+//! `navigation`, book entries (those with acquisition links) to
+//! `publications`, with image links moved to each publication's `images`.
+
+use serde_json::{json, Map, Value};
+
+use super::feed::{
+    rel, OPDSEntry, OPDSFacetGroup, OPDSFeed, OPDSGroup, OPDSLink, OPDSPagination,
+};
+use crate::error::{AppError, Result};
+
+/// Serialize an OPDS feed to OPDS 2.0 JSON
+pub fn serialize_feed_json(feed: &OPDSFeed) -> Result<String> {
+    serde_json::to_string(&feed_value(feed))
+        .map_err(|e| AppError::Internal(format!("Failed to serialize feed: {}", e)))
+}
+
+fn feed_value(feed: &OPDSFeed) -> Value {
+    let mut metadata = Map::new();
+    metadata.insert("title".into(), json!(feed.title));
+    if let Some(ref subtitle) = feed.subtitle {
+        metadata.insert("subtitle".into(), json!(subtitle));
+    }
+    metadata.insert("modified".into(), json!(feed.updated.to_rfc3339()));
+    if let Some(ref pagination) = feed.pagination {
+        insert_pagination(&mut metadata, pagination);
+    }
+
+    let (publications, navigation): (Vec<&OPDSEntry>, Vec<&OPDSEntry>) =
+        feed.entries.iter().partition(|entry| is_publication(entry));
+
+    let mut value = Map::new();
+    value.insert("metadata".into(), Value::Object(metadata));
+    value.insert("links".into(), links_value(&feed.links));
+
+    if !navigation.is_empty() {
+        let items = navigation.iter().filter_map(|entry| navigation_value(entry)).collect();
+        value.insert("navigation".into(), Value::Array(items));
+    }
+    // A feed must have at least one collection, so empty listings still
+    // get an empty `publications`
+    if !publications.is_empty() || (navigation.is_empty() && feed.groups.is_empty()) {
+        let items = publications.into_iter().map(publication_value).collect();
+        value.insert("publications".into(), Value::Array(items));
+    }
+    if !feed.groups.is_empty() {
+        let groups = feed.groups.iter().map(|group| group_value(group, feed)).collect();
+        value.insert("groups".into(), Value::Array(groups));
+    }
+    if !feed.facets.is_empty() {
+        let facets = feed.facets.iter().map(|group| facet_group_value(group, feed)).collect();
+        value.insert("facets".into(), Value::Array(facets));
+    }
+
+    Value::Object(value)
+}
+
+/// Whether an entry is a book rather than a link to another feed
+fn is_publication(entry: &OPDSEntry) -> bool {
+    entry.links.iter().any(|link| {
+        link.rel
+            .as_deref()
+            .is_some_and(|rel| rel.starts_with(rel::ACQUISITION))
+    })
+}
+
+fn is_image(link: &OPDSLink) -> bool {
+    matches!(link.rel.as_deref(), Some(rel::IMAGE) | Some(rel::THUMBNAIL))
+}
+
+fn insert_pagination(metadata: &mut Map<String, Value>, pagination: &OPDSPagination) {
+    metadata.insert("numberOfItems".into(), json!(pagination.number_of_items));
+    metadata.insert("itemsPerPage".into(), json!(pagination.items_per_page));
+    metadata.insert("currentPage".into(), json!(pagination.current_page));
+}
+
+fn link_value(link: &OPDSLink) -> Value {
+    let mut value = Map::new();
+    value.insert("href".into(), json!(link.href));
+    if let Some(ref rel) = link.rel {
+        value.insert("rel".into(), json!(rel));
+    }
+    if let Some(ref link_type) = link.link_type {
+        value.insert("type".into(), json!(link_type));
+    }
+    if let Some(ref title) = link.title {
+        value.insert("title".into(), json!(title));
+    }
+    if link.href.contains('{') {
+        value.insert("templated".into(), json!(true));
+    }
+    if let Some(count) = link.count {
+        value.insert("properties".into(), json!({ "numberOfItems": count }));
+    }
+    Value::Object(value)
+}
+
+fn links_value<'a>(links: impl IntoIterator<Item = &'a OPDSLink>) -> Value {
+    Value::Array(links.into_iter().map(link_value).collect())
+}
+
+/// A navigation entry as a titled link
+fn navigation_value(entry: &OPDSEntry) -> Option<Value> {
+    let link = entry.links.first()?;
+    let mut value = link_value(link);
+    value["title"] = json!(entry.title);
+    Some(value)
+}
+
+fn publication_value(entry: &OPDSEntry) -> Value {
+    let mut metadata = Map::new();
+    metadata.insert("@type".into(), json!("http://schema.org/Book"));
+    metadata.insert("identifier".into(), json!(entry.id));
+    metadata.insert("title".into(), json!(entry.title));
+    if !entry.authors.is_empty() {
+        let authors: Vec<Value> = entry
+            .authors
+            .iter()
+            .map(|author| json!({ "name": author.name }))
+            .collect();
+        metadata.insert("author".into(), Value::Array(authors));
+    }
+    if let Some(ref language) = entry.language {
+        metadata.insert("language".into(), json!(language));
+    }
+    if let Some(ref publisher) = entry.publisher {
+        metadata.insert("publisher".into(), json!(publisher));
+    }
+    if let Some(ref published) = entry.published {
+        metadata.insert("published".into(), json!(published));
+    }
+    metadata.insert("modified".into(), json!(entry.updated.to_rfc3339()));
+    let description = entry
+        .content
+        .as_ref()
+        .map(|content| &content.value)
+        .or(entry.summary.as_ref());
+    if let Some(description) = description {
+        metadata.insert("description".into(), json!(description));
+    }
+    if !entry.categories.is_empty() {
+        let subjects: Vec<Value> = entry
+            .categories
+            .iter()
+            .map(|category| {
+                json!({ "name": category.label.as_ref().unwrap_or(&category.term) })
+            })
+            .collect();
+        metadata.insert("subject".into(), Value::Array(subjects));
+    }
+    if let Some(ref series) = entry.series {
+        let mut value = Map::new();
+        value.insert("name".into(), json!(series.name));
+        if let Some(position) = series.position {
+            value.insert("position".into(), json!(position));
+        }
+        metadata.insert("belongsTo".into(), json!({ "series": [value] }));
+    }
+
+    // Full-size image first, so readers that take the first one get it
+    let mut images: Vec<&OPDSLink> = entry.links.iter().filter(|link| is_image(link)).collect();
+    images.sort_by_key(|link| link.rel.as_deref() != Some(rel::IMAGE));
+    let images: Vec<Value> = images
+        .into_iter()
+        .map(|link| {
+            let mut value = Map::new();
+            value.insert("href".into(), json!(link.href));
+            if let Some(ref link_type) = link.link_type {
+                value.insert("type".into(), json!(link_type));
+            }
+            Value::Object(value)
+        })
+        .collect();
+
+    let mut value = Map::new();
+    value.insert("metadata".into(), Value::Object(metadata));
+    value.insert(
+        "links".into(),
+        links_value(entry.links.iter().filter(|link| !is_image(link))),
+    );
+    if !images.is_empty() {
+        value.insert("images".into(), Value::Array(images));
+    }
+    Value::Object(value)
+}
+
+fn group_value(group: &OPDSGroup, feed: &OPDSFeed) -> Value {
+    let mut value = Map::new();
+    value.insert("metadata".into(), json!({ "title": group.title }));
+    if let Some(ref href) = group.href {
+        value.insert(
+            "links".into(),
+            json!([{
+                "href": href,
+                "rel": rel::SELF,
+                "type": feed.format.acquisition_type(),
+            }]),
+        );
+    }
+
+    let (publications, navigation): (Vec<&OPDSEntry>, Vec<&OPDSEntry>) =
+        group.entries.iter().partition(|entry| is_publication(entry));
+    if !publications.is_empty() {
+        let items = publications.into_iter().map(publication_value).collect();
+        value.insert("publications".into(), Value::Array(items));
+    }
+    if !navigation.is_empty() {
+        let items = navigation.iter().filter_map(|entry| navigation_value(entry)).collect();
+        value.insert("navigation".into(), Value::Array(items));
+    }
+    Value::Object(value)
+}
+
+fn facet_group_value(group: &OPDSFacetGroup, feed: &OPDSFeed) -> Value {
+    let links: Vec<Value> = group
+        .facets
+        .iter()
+        .map(|facet| {
+            let mut value = Map::new();
+            value.insert("href".into(), json!(facet.href));
+            value.insert("type".into(), json!(feed.format.acquisition_type()));
+            value.insert("title".into(), json!(facet.title));
+            if facet.active {
+                value.insert("rel".into(), json!(rel::SELF));
+            }
+            if let Some(count) = facet.count {
+                value.insert("properties".into(), json!({ "numberOfItems": count }));
+            }
+            Value::Object(value)
+        })
+        .collect();
+
+    json!({
+        "metadata": { "title": group.title },
+        "links": links,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::{BookFormat, FormatType, LibraryBook};
+    use crate::opds::{mime, FeedFormat, OPDSFacet};
+
+    fn book(title: &str) -> LibraryBook {
+        let mut book = LibraryBook::new(title.to_string(), format!("Author/{}", title));
+        book.id = title.to_lowercase();
+        book.authors = vec!["Author".to_string()];
+        book.series = Some("Saga".to_string());
+        book.series_index = Some(2.0);
+        book.formats = vec![BookFormat {
+            format: FormatType::Epub,
+            s3_key: format!("Author/{}/{}.epub", title, title),
+            size: 10,
+            pages: None,
+        }];
+        book
+    }
+
+    #[test]
+    fn test_acquisition_feed_json() {
+        let base = "http://localhost";
+        let mut feed = OPDSFeed::acquisition(FeedFormat::Json, "All Books", "http://localhost/opds/v2/all");
+        feed.add_pagination(3, 2, 2, |page| format!("/opds/v2/all?page={}", page));
+        feed.add_books(&[book("Ulysses")], base);
+        feed.facets.push(OPDSFacetGroup {
+            title: "Sort".to_string(),
+            facets: vec![OPDSFacet {
+                title: "Title".to_string(),
+                href: "/opds/v2/all?sort=title".to_string(),
+                count: Some(3),
+                active: true,
+            }],
+        });
+
+        let value: Value = serde_json::from_str(&serialize_feed_json(&feed).unwrap()).unwrap();
+        assert_eq!(value["metadata"]["numberOfItems"], 3);
+        assert_eq!(value["metadata"]["currentPage"], 2);
+        assert_eq!(value["links"][0]["type"], mime::OPDS_JSON);
+        let rels: Vec<&str> = value["links"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|link| link["rel"].as_str())
+            .collect();
+        assert!(rels.contains(&"previous") && !rels.contains(&"next"));
+
+        let publications = value["publications"].as_array().unwrap();
+        assert_eq!(publications.len(), 1);
+        let publication = &publications[0];
+        assert_eq!(publication["metadata"]["title"], "Ulysses");
+        assert_eq!(publication["metadata"]["belongsTo"]["series"][0]["position"], 2.0);
+        assert_eq!(publication["links"][0]["type"], "application/epub+zip");
+        assert_eq!(publication["images"][0]["href"], "http://localhost/covers/ulysses");
+        assert!(value.get("navigation").is_none());
+        assert_eq!(value["facets"][0]["links"][0]["rel"], "self");
+    }
+
+    #[test]
+    fn test_navigation_feed_json() {
+        let mut feed = OPDSFeed::root_catalog(FeedFormat::Json, "http://localhost");
+        feed.groups.push(OPDSGroup {
+            title: "Recent".to_string(),
+            href: Some("http://localhost/opds/v2/recent".to_string()),
+            entries: vec![OPDSEntry::from_book(&book("Dune"), "http://localhost")],
+        });
+
+        let value: Value = serde_json::from_str(&serialize_feed_json(&feed).unwrap()).unwrap();
+        let navigation = value["navigation"].as_array().unwrap();
+        assert_eq!(navigation[0]["title"], "All Books");
+        assert_eq!(navigation[0]["href"], "http://localhost/opds/v2/all");
+        assert_eq!(navigation[0]["type"], mime::OPDS_JSON);
+        assert!(value.get("publications").is_none());
+        assert_eq!(value["groups"][0]["publications"][0]["metadata"]["title"], "Dune");
+    }
+
+    #[test]
+    fn test_page_stream_link_json() {
+        let mut book = book("Dune");
+        book.formats.push(BookFormat {
+            format: FormatType::Pdf,
+            s3_key: "Author/Dune/Dune.pdf".to_string(),
+            size: 10,
+            pages: Some(12),
+        });
+
+        let mut feed = OPDSFeed::acquisition(FeedFormat::Json, "All Books", "http://localhost/opds/v2/all");
+        feed.add_books(&[book], "http://localhost");
+
+        let value: Value = serde_json::from_str(&serialize_feed_json(&feed).unwrap()).unwrap();
+        let links = value["publications"][0]["links"].as_array().unwrap();
+        let stream = links.iter().find(|link| link["rel"] == rel::PSE_STREAM).unwrap();
+        assert_eq!(
+            stream["href"],
+            "http://localhost/opds/pse/dune/pdf/{pageNumber}?maxWidth={maxWidth}"
+        );
+        assert_eq!(stream["templated"], true);
+        assert_eq!(stream["properties"]["numberOfItems"], 12);
+        // EPUBs aren't streamed page by page
+        assert_eq!(links.iter().filter(|link| link["rel"] == rel::PSE_STREAM).count(), 1);
+    }
+}
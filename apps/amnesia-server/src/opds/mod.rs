@@ -1,9 +1,14 @@
 //! OPDS (Open Publication Distribution System) module
 //!
-//! Generates OPDS 1.2 Atom feeds for browsing and downloading books.
+//! Generates OPDS 1.2 Atom and OPDS 2.0 JSON feeds for browsing and
+//! downloading books.
 
+mod facets;
 mod feed;
+mod json;
 mod xml;
 
+pub use facets::*;
 pub use feed::*;
+pub use json::*;
 pub use xml::*;
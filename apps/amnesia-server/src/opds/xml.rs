@@ -1,6 +1,7 @@
 //! OPDS XML serialization
 //!
-//! Generates Atom XML for OPDS feeds.
+//! Generates Atom XML for OPDS feeds, and the OpenSearch description of
+//! the catalog search.
 
 use quick_xml::{
     events::{BytesCData, BytesDecl, BytesEnd, BytesStart, BytesText, Event},
@@ -8,7 +9,7 @@ use quick_xml::{
 };
 use std::io::Cursor;
 
-use super::feed::{OPDSCategory, OPDSContent, OPDSEntry, OPDSFeed, OPDSLink};
+use super::feed::{mime, rel, OPDSCategory, OPDSContent, OPDSEntry, OPDSFeed, OPDSLink};
 use crate::error::Result;
 
 /// Serialize an OPDS feed to XML
@@ -23,6 +24,9 @@ pub fn serialize_feed(feed: &OPDSFeed) -> Result<String> {
     feed_elem.push_attribute(("xmlns", "http://www.w3.org/2005/Atom"));
     feed_elem.push_attribute(("xmlns:dc", "http://purl.org/dc/terms/"));
     feed_elem.push_attribute(("xmlns:opds", "http://opds-spec.org/2010/catalog"));
+    feed_elem.push_attribute(("xmlns:opensearch", "http://a9.com/-/spec/opensearch/1.1/"));
+    feed_elem.push_attribute(("xmlns:thr", "http://purl.org/syndication/thread/1.0"));
+    feed_elem.push_attribute(("xmlns:pse", "http://vaemendis.net/opds-pse/ns"));
     writer.write_event(Event::Start(feed_elem))?;
 
     // ID
@@ -54,11 +58,38 @@ pub fn serialize_feed(feed: &OPDSFeed) -> Result<String> {
         writer.write_event(Event::End(BytesEnd::new("author")))?;
     }
 
+    // Paging
+    if let Some(ref pagination) = feed.pagination {
+        let start_index = (pagination.current_page - 1) * pagination.items_per_page + 1;
+        write_simple_element(&mut writer, "opensearch:totalResults", &pagination.number_of_items.to_string())?;
+        write_simple_element(&mut writer, "opensearch:itemsPerPage", &pagination.items_per_page.to_string())?;
+        write_simple_element(&mut writer, "opensearch:startIndex", &start_index.to_string())?;
+    }
+
     // Links
     for link in &feed.links {
         write_link(&mut writer, link)?;
     }
 
+    // Facets
+    for group in &feed.facets {
+        for facet in &group.facets {
+            let mut elem = BytesStart::new("link");
+            elem.push_attribute(("rel", rel::FACET));
+            elem.push_attribute(("href", facet.href.as_str()));
+            elem.push_attribute(("type", feed.format.acquisition_type()));
+            elem.push_attribute(("title", facet.title.as_str()));
+            elem.push_attribute(("opds:facetGroup", group.title.as_str()));
+            if facet.active {
+                elem.push_attribute(("opds:activeFacet", "true"));
+            }
+            if let Some(count) = facet.count {
+                elem.push_attribute(("thr:count", count.to_string().as_str()));
+            }
+            writer.write_event(Event::Empty(elem))?;
+        }
+    }
+
     // Entries
     for entry in &feed.entries {
         write_entry(&mut writer, entry)?;
@@ -70,6 +101,48 @@ pub fn serialize_feed(feed: &OPDSFeed) -> Result<String> {
     Ok(String::from_utf8(result)?)
 }
 
+/// Serialize the OpenSearch description of the catalog search
+pub fn serialize_opensearch(base_url: &str) -> Result<String> {
+    let mut writer = Writer::new_with_indent(Cursor::new(Vec::new()), b' ', 2);
+
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("utf-8"), None)))?;
+
+    let mut root = BytesStart::new("OpenSearchDescription");
+    root.push_attribute(("xmlns", "http://a9.com/-/spec/opensearch/1.1/"));
+    writer.write_event(Event::Start(root))?;
+
+    write_simple_element(&mut writer, "ShortName", "Los Libros")?;
+    write_simple_element(
+        &mut writer,
+        "Description",
+        "Search the Los Libros catalog by title, author, series or tag. \
+         Use author: and title: to search one field.",
+    )?;
+    write_simple_element(&mut writer, "InputEncoding", "UTF-8")?;
+    write_simple_element(&mut writer, "OutputEncoding", "UTF-8")?;
+
+    let templates = [
+        (format!("{}/opds/search?q={{searchTerms}}", base_url), mime::ATOM_ACQUISITION),
+        (format!("{}/opds/v2/search?q={{searchTerms}}", base_url), mime::OPDS_JSON),
+    ];
+    for (template, url_type) in &templates {
+        let mut url = BytesStart::new("Url");
+        url.push_attribute(("type", *url_type));
+        url.push_attribute(("template", template.as_str()));
+        writer.write_event(Event::Empty(url))?;
+    }
+
+    let mut example = BytesStart::new("Query");
+    example.push_attribute(("role", "example"));
+    example.push_attribute(("searchTerms", "author:herbert dune"));
+    writer.write_event(Event::Empty(example))?;
+
+    writer.write_event(Event::End(BytesEnd::new("OpenSearchDescription")))?;
+
+    let result = writer.into_inner().into_inner();
+    Ok(String::from_utf8(result)?)
+}
+
 fn write_simple_element<W: std::io::Write>(
     writer: &mut Writer<W>,
     name: &str,
@@ -93,6 +166,9 @@ fn write_link<W: std::io::Write>(writer: &mut Writer<W>, link: &OPDSLink) -> Res
     if let Some(ref title) = link.title {
         elem.push_attribute(("title", title.as_str()));
     }
+    if let Some(count) = link.count {
+        elem.push_attribute(("pse:count", count.to_string().as_str()));
+    }
     writer.write_event(Event::Empty(elem))?;
     Ok(())
 }
@@ -1,18 +1,25 @@
 //! PDF Annotation Extractor
 //!
 //! Extracts native PDF annotations (highlights, underlines, comments, squiggly)
-//! made in Adobe Reader, Foxit, Preview, and other PDF readers.
+//! made in Adobe Reader, Foxit, Preview, Zotero and other PDF readers.
 //!
-//! # Note
+//! # Approach
 //!
-//! The current MuPDF Rust binding (0.5.x) has limited annotation API support.
-//! This module provides a foundation that can be enhanced as the binding improves.
-//! Currently, it extracts text content which can be correlated with annotations
-//! from the raw PDF structure.
+//! The MuPDF Rust binding (0.5.x) only exposes an annotation's type and
+//! author, so annotations are read by walking each page's `/Annots` array
+//! and reading the annotation dictionaries directly (`/Subtype`, `/Rect`,
+//! `/QuadPoints`, `/InkList`, `/Contents`, `/T`, `/C`, `/CreationDate`,
+//! `/M`). Coordinates are mapped from PDF user space onto the page with the
+//! page's transform, so rotated and cropped pages come out right, then
+//! normalized to the page box. The text under text markup annotations is
+//! the page text whose characters' centers fall inside the QuadPoints.
 
+use chrono::NaiveDateTime;
+use mupdf::pdf::{PdfDocument, PdfObject};
+use mupdf::{Matrix, Rect, TextPageOptions};
 use serde::{Deserialize, Serialize};
 
-use crate::document::DocumentResult;
+use crate::document::{DocumentError, DocumentFormat, DocumentResult};
 use crate::mupdf::SafeDocument;
 
 /// Types of PDF annotations we can extract
@@ -25,9 +32,37 @@ pub enum ExtractedAnnotationType {
     Squiggly,
     Text, // Sticky note / comment
     FreeText,
+    Ink,
     Unknown,
 }
 
+impl ExtractedAnnotationType {
+    /// Type of an annotation with the given `/Subtype`
+    fn from_subtype(subtype: &[u8]) -> Self {
+        match subtype {
+            b"Highlight" => Self::Highlight,
+            b"Underline" => Self::Underline,
+            b"StrikeOut" => Self::StrikeOut,
+            b"Squiggly" => Self::Squiggly,
+            b"Text" => Self::Text,
+            b"FreeText" => Self::FreeText,
+            b"Ink" => Self::Ink,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Whether the annotation marks up the text under its QuadPoints
+    fn is_text_markup(self) -> bool {
+        matches!(
+            self,
+            Self::Highlight | Self::Underline | Self::StrikeOut | Self::Squiggly
+        )
+    }
+}
+
+/// Annotation subtypes that aren't annotations a reader made
+const IGNORED_SUBTYPES: &[&[u8]] = &[b"Popup", b"Widget", b"Link"];
+
 /// Normalized rectangle (0-1 coordinates relative to page size)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NormalizedRect {
@@ -37,6 +72,13 @@ pub struct NormalizedRect {
     pub height: f64,
 }
 
+/// Normalized point (0-1 coordinates relative to page size)
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NormalizedPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
 /// An extracted PDF annotation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -62,6 +104,9 @@ pub struct ExtractedAnnotation {
     pub modified_date: Option<String>,
     /// Author/creator name
     pub author: Option<String>,
+    /// Strokes of an ink annotation, as paths of points
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ink_list: Vec<Vec<NormalizedPoint>>,
 }
 
 /// Options for annotation extraction
@@ -102,6 +147,7 @@ pub struct ExtractionStats {
     pub squiggly: usize,
     pub text_notes: usize,
     pub free_text: usize,
+    pub ink: usize,
     pub other: usize,
 }
 
@@ -115,6 +161,7 @@ impl Default for ExtractionStats {
             squiggly: 0,
             text_notes: 0,
             free_text: 0,
+            ink: 0,
             other: 0,
         }
     }
@@ -122,30 +169,25 @@ impl Default for ExtractionStats {
 
 /// Extract annotations from a PDF document
 ///
-/// # Note
-///
-/// The current MuPDF Rust binding has limited annotation API support.
-/// This function provides document metadata and text content. Full annotation
-/// extraction will be available when the binding is updated to expose
-/// the annotation enumeration APIs.
+/// Annotations are returned in page order, and in `/Annots` order within
+/// a page.
 pub fn extract_annotations(
     doc: &SafeDocument,
     book_id: &str,
-    _options: &ExtractionOptions,
+    options: &ExtractionOptions,
 ) -> DocumentResult<ExtractionResult> {
+    if doc.format() != DocumentFormat::Pdf {
+        return Err(DocumentError::UnsupportedFormat(format!(
+            "{:?} documents have no PDF annotations",
+            doc.format()
+        )));
+    }
+
     let total_pages = doc.item_count() as u32;
+    let bytes = doc.get_bytes()?;
+    let pdf_doc = PdfDocument::from_bytes(&bytes)?;
 
-    // Current MuPDF binding limitation: annotation enumeration not fully exposed
-    // We can extract text and document structure, but not native PDF annotations
-    //
-    // Future implementation would iterate:
-    // for page in doc.pages() {
-    //     for annot in page.annotations() {
-    //         // Extract annotation details
-    //     }
-    // }
-
-    let annotations: Vec<ExtractedAnnotation> = Vec::new();
+    let annotations = extract_pdf_annotations(&pdf_doc, total_pages, options)?;
     let stats = calculate_stats(&annotations);
 
     Ok(ExtractionResult {
@@ -153,14 +195,392 @@ pub fn extract_annotations(
         total_pages,
         annotations,
         stats,
-        note: Some(
-            "PDF annotation extraction requires MuPDF binding updates. \
-            Text content is available via /api/v1/pdf/{book_id}/text endpoint."
-                .to_string(),
-        ),
+        note: None,
     })
 }
 
+/// Extract the annotations on the first `total_pages` pages of an open PDF
+pub(super) fn extract_pdf_annotations(
+    pdf_doc: &PdfDocument,
+    total_pages: u32,
+    options: &ExtractionOptions,
+) -> DocumentResult<Vec<ExtractedAnnotation>> {
+    let mut annotations = Vec::new();
+    for page in 1..=total_pages {
+        if options.pages.is_empty() || options.pages.contains(&page) {
+            annotations.extend(extract_page_annotations(pdf_doc, page, options)?);
+        }
+    }
+    Ok(annotations)
+}
+
+/// Extract the annotations on one page (1-indexed)
+fn extract_page_annotations(
+    pdf_doc: &PdfDocument,
+    page: u32,
+    options: &ExtractionOptions,
+) -> DocumentResult<Vec<ExtractedAnnotation>> {
+    let page_index = page as i32 - 1;
+    let page_obj = pdf_doc.find_page(page_index)?;
+    let Some(annots) = page_obj.get_dict("Annots")? else {
+        return Ok(Vec::new());
+    };
+
+    let mut raw_annotations = Vec::new();
+    for i in 0..annots.len()? {
+        let Ok(Some(annot)) = annots.get_array(i as i32) else {
+            continue;
+        };
+        // Malformed annotations are skipped rather than failing the page
+        let Ok(Some(raw)) = read_annotation(&annot) else {
+            continue;
+        };
+        if options.types.is_empty() || options.types.contains(&raw.annotation_type) {
+            raw_annotations.push(raw);
+        }
+    }
+    if raw_annotations.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let fz_page = pdf_doc.load_page(page_index)?;
+    let geometry = PageGeometry {
+        ctm: page_obj.page_ctm()?,
+        bounds: fz_page.bounds()?,
+    };
+    let needs_text = options.include_text
+        && raw_annotations
+            .iter()
+            .any(|raw| raw.annotation_type.is_text_markup());
+    let chars = if needs_text {
+        page_chars(&fz_page.to_text_page(TextPageOptions::empty())?)
+    } else {
+        Vec::new()
+    };
+
+    Ok(raw_annotations
+        .iter()
+        .map(|raw| raw.to_extracted(page, &geometry, needs_text.then_some(chars.as_slice())))
+        .collect())
+}
+
+/// An annotation's entries, as read from its dictionary
+#[derive(Debug, Clone)]
+struct RawAnnotation {
+    annotation_type: ExtractedAnnotationType,
+    /// `/Rect`, in PDF user space
+    rect: Vec<f32>,
+    /// `/QuadPoints`: eight numbers per quad, in PDF user space
+    quad_points: Vec<f32>,
+    /// `/InkList`: one path of x, y pairs per stroke, in PDF user space
+    ink_list: Vec<Vec<f32>>,
+    contents: Option<String>,
+    author: Option<String>,
+    /// `/C`: gray, RGB or CMYK components
+    color: Vec<f32>,
+    created: Option<String>,
+    modified: Option<String>,
+}
+
+/// Read an annotation dictionary, or `None` if it isn't a reader's annotation
+fn read_annotation(annot: &PdfObject) -> Result<Option<RawAnnotation>, mupdf::Error> {
+    let Some(subtype) = annot.get_dict("Subtype")? else {
+        return Ok(None);
+    };
+    let subtype = subtype.as_name()?;
+    if IGNORED_SUBTYPES.contains(&subtype) {
+        return Ok(None);
+    }
+
+    let mut ink_list = Vec::new();
+    if let Some(strokes) = annot.get_dict("InkList")? {
+        for i in 0..strokes.len()? {
+            if let Some(stroke) = strokes.get_array(i as i32)? {
+                ink_list.push(numbers(&stroke)?);
+            }
+        }
+    }
+
+    Ok(Some(RawAnnotation {
+        annotation_type: ExtractedAnnotationType::from_subtype(subtype),
+        rect: number_entry(annot, "Rect")?,
+        quad_points: number_entry(annot, "QuadPoints")?,
+        ink_list,
+        contents: text_entry(annot, "Contents")?,
+        author: text_entry(annot, "T")?,
+        color: number_entry(annot, "C")?,
+        created: text_entry(annot, "CreationDate")?,
+        modified: text_entry(annot, "M")?,
+    }))
+}
+
+/// A non-empty text string entry of a dictionary
+fn text_entry(dict: &PdfObject, key: &str) -> Result<Option<String>, mupdf::Error> {
+    Ok(dict
+        .get_dict(key)?
+        .and_then(|value| value.as_string().ok().map(|s| s.trim().to_string()))
+        .filter(|s| !s.is_empty()))
+}
+
+/// The numbers in an array entry of a dictionary
+fn number_entry(dict: &PdfObject, key: &str) -> Result<Vec<f32>, mupdf::Error> {
+    match dict.get_dict(key)? {
+        Some(array) => numbers(&array),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The numbers in an array
+fn numbers(array: &PdfObject) -> Result<Vec<f32>, mupdf::Error> {
+    if !array.is_array()? {
+        return Ok(Vec::new());
+    }
+    let mut values = Vec::new();
+    for i in 0..array.len()? {
+        if let Some(value) = array.get_array(i as i32)? {
+            values.push(value.as_float()?);
+        }
+    }
+    Ok(values)
+}
+
+/// Where a page sits: its box, and the transform from PDF user space onto it
+struct PageGeometry {
+    ctm: Matrix,
+    bounds: Rect,
+}
+
+impl PageGeometry {
+    /// Map a point from PDF user space onto the page
+    fn transform(&self, x: f32, y: f32) -> (f32, f32) {
+        let m = &self.ctm;
+        (x * m.a + y * m.c + m.e, x * m.b + y * m.d + m.f)
+    }
+
+    /// Page-space bounding box of x, y pairs in PDF user space
+    fn bbox(&self, coords: &[f32]) -> Option<Rect> {
+        coords
+            .chunks_exact(2)
+            .map(|point| self.transform(point[0], point[1]))
+            .fold(None, |bbox: Option<Rect>, (x, y)| {
+                Some(match bbox {
+                    Some(r) => Rect {
+                        x0: r.x0.min(x),
+                        y0: r.y0.min(y),
+                        x1: r.x1.max(x),
+                        y1: r.y1.max(y),
+                    },
+                    None => Rect {
+                        x0: x,
+                        y0: y,
+                        x1: x,
+                        y1: y,
+                    },
+                })
+            })
+    }
+
+    /// Page size, never zero
+    fn size(&self) -> (f64, f64) {
+        let width = (self.bounds.x1 - self.bounds.x0) as f64;
+        let height = (self.bounds.y1 - self.bounds.y0) as f64;
+        (width.max(f64::EPSILON), height.max(f64::EPSILON))
+    }
+
+    fn normalize_rect(&self, rect: &Rect) -> NormalizedRect {
+        let (width, height) = self.size();
+        NormalizedRect {
+            x: (rect.x0 - self.bounds.x0) as f64 / width,
+            y: (rect.y0 - self.bounds.y0) as f64 / height,
+            width: (rect.x1 - rect.x0) as f64 / width,
+            height: (rect.y1 - rect.y0) as f64 / height,
+        }
+    }
+
+    fn normalize_point(&self, x: f32, y: f32) -> NormalizedPoint {
+        let (width, height) = self.size();
+        let (x, y) = self.transform(x, y);
+        NormalizedPoint {
+            x: (x - self.bounds.x0) as f64 / width,
+            y: (y - self.bounds.y0) as f64 / height,
+        }
+    }
+}
+
+/// A character of a page's text, with its page-space box
+struct PageChar {
+    c: char,
+    /// Index of the text line the character is on
+    line: usize,
+    rect: Rect,
+}
+
+/// The characters of a page's text, in reading order
+fn page_chars(text_page: &mupdf::TextPage) -> Vec<PageChar> {
+    let mut chars = Vec::new();
+    let mut line_index = 0;
+    for block in text_page.blocks() {
+        for line in block.lines() {
+            for ch in line.chars() {
+                if let Some(c) = ch.char() {
+                    let quad = ch.quad();
+                    let xs = [quad.ul.x, quad.ur.x, quad.ll.x, quad.lr.x];
+                    let ys = [quad.ul.y, quad.ur.y, quad.ll.y, quad.lr.y];
+                    chars.push(PageChar {
+                        c,
+                        line: line_index,
+                        rect: Rect {
+                            x0: xs.iter().copied().fold(f32::MAX, f32::min),
+                            y0: ys.iter().copied().fold(f32::MAX, f32::min),
+                            x1: xs.iter().copied().fold(f32::MIN, f32::max),
+                            y1: ys.iter().copied().fold(f32::MIN, f32::max),
+                        },
+                    });
+                }
+            }
+            line_index += 1;
+        }
+    }
+    chars
+}
+
+/// Text of the characters whose centers are inside any of the quads
+///
+/// Lines are joined with a space and runs of whitespace collapsed.
+fn text_under(chars: &[PageChar], quads: &[Rect]) -> Option<String> {
+    let mut text = String::new();
+    let mut last_line = None;
+    for ch in chars {
+        let x = (ch.rect.x0 + ch.rect.x1) / 2.0;
+        let y = (ch.rect.y0 + ch.rect.y1) / 2.0;
+        let inside = quads
+            .iter()
+            .any(|q| x >= q.x0 && x <= q.x1 && y >= q.y0 && y <= q.y1);
+        if !inside {
+            continue;
+        }
+        if last_line.is_some_and(|line| line != ch.line) {
+            text.push(' ');
+        }
+        last_line = Some(ch.line);
+        text.push(ch.c);
+    }
+
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+impl RawAnnotation {
+    /// The annotation on page `page`, with text from `chars` if given
+    fn to_extracted(
+        &self,
+        page: u32,
+        geometry: &PageGeometry,
+        chars: Option<&[PageChar]>,
+    ) -> ExtractedAnnotation {
+        let quads: Vec<Rect> = self
+            .quad_points
+            .chunks_exact(8)
+            .filter_map(|quad| geometry.bbox(quad))
+            .collect();
+        let rect = geometry
+            .bbox(&self.rect)
+            .or_else(|| geometry.bbox(&self.quad_points))
+            .unwrap_or(Rect {
+                x0: geometry.bounds.x0,
+                y0: geometry.bounds.y0,
+                x1: geometry.bounds.x0,
+                y1: geometry.bounds.y0,
+            });
+
+        let text = match chars {
+            Some(chars) if self.annotation_type.is_text_markup() => text_under(chars, &quads),
+            _ => None,
+        };
+
+        ExtractedAnnotation {
+            annotation_type: self.annotation_type,
+            page,
+            text,
+            comment: self.contents.clone(),
+            color: hex_color(&self.color),
+            rect: geometry.normalize_rect(&rect),
+            quads: quads.iter().map(|q| geometry.normalize_rect(q)).collect(),
+            created_date: self.created.as_deref().and_then(pdf_date_to_iso),
+            modified_date: self.modified.as_deref().and_then(pdf_date_to_iso),
+            author: self.author.clone(),
+            ink_list: self
+                .ink_list
+                .iter()
+                .map(|stroke| {
+                    stroke
+                        .chunks_exact(2)
+                        .map(|point| geometry.normalize_point(point[0], point[1]))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Hex color (e.g. "#FFFF00") of gray, RGB or CMYK components
+fn hex_color(components: &[f32]) -> Option<String> {
+    let (r, g, b) = match *components {
+        [gray] => (gray, gray, gray),
+        [r, g, b] => (r, g, b),
+        [c, m, y, k] => (
+            (1.0 - c) * (1.0 - k),
+            (1.0 - m) * (1.0 - k),
+            (1.0 - y) * (1.0 - k),
+        ),
+        _ => return None,
+    };
+    let byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Some(format!("#{:02X}{:02X}{:02X}", byte(r), byte(g), byte(b)))
+}
+
+/// Convert a PDF date (`D:YYYYMMDDHHmmSSOHH'mm'`) to ISO 8601
+///
+/// Every part after the year is optional. Dates without a time zone are
+/// returned without an offset.
+pub(super) fn pdf_date_to_iso(date: &str) -> Option<String> {
+    let date = date.trim();
+    let date = date.strip_prefix("D:").unwrap_or(date);
+    let digits_len = date.bytes().take_while(u8::is_ascii_digit).count();
+    let (digits, zone) = date.split_at(digits_len);
+    if digits.len() < 4 {
+        return None;
+    }
+
+    let part =
+        |start: usize, default: &'static str| digits.get(start..start + 2).unwrap_or(default);
+    let local = format!(
+        "{}-{}-{}T{}:{}:{}",
+        &digits[..4],
+        part(4, "01"),
+        part(6, "01"),
+        part(8, "00"),
+        part(10, "00"),
+        part(12, "00")
+    );
+    NaiveDateTime::parse_from_str(&local, "%Y-%m-%dT%H:%M:%S").ok()?;
+
+    let offset = match zone.chars().next() {
+        Some('Z') => "Z".to_string(),
+        Some(sign @ ('+' | '-')) => {
+            let zone: String = zone.chars().filter(char::is_ascii_digit).collect();
+            format!(
+                "{}{}:{}",
+                sign,
+                zone.get(0..2).unwrap_or("00"),
+                zone.get(2..4).unwrap_or("00")
+            )
+        }
+        _ => String::new(),
+    };
+    Some(local + &offset)
+}
+
 /// Calculate extraction statistics
 fn calculate_stats(annotations: &[ExtractedAnnotation]) -> ExtractionStats {
     let mut stats = ExtractionStats::default();
@@ -174,6 +594,7 @@ fn calculate_stats(annotations: &[ExtractedAnnotation]) -> ExtractionStats {
             ExtractedAnnotationType::Squiggly => stats.squiggly += 1,
             ExtractedAnnotationType::Text => stats.text_notes += 1,
             ExtractedAnnotationType::FreeText => stats.free_text += 1,
+            ExtractedAnnotationType::Ink => stats.ink += 1,
             ExtractedAnnotationType::Unknown => stats.other += 1,
         }
     }
@@ -200,6 +621,147 @@ mod tests {
         assert_eq!(stats.underlines, 0);
     }
 
+    /// A US Letter page with MuPDF's flip from PDF user space
+    fn letter_page() -> PageGeometry {
+        PageGeometry {
+            ctm: Matrix {
+                a: 1.0,
+                b: 0.0,
+                c: 0.0,
+                d: -1.0,
+                e: 0.0,
+                f: 792.0,
+            },
+            bounds: Rect {
+                x0: 0.0,
+                y0: 0.0,
+                x1: 612.0,
+                y1: 792.0,
+            },
+        }
+    }
+
+    fn page_char(c: char, line: usize, x: f32, y: f32) -> PageChar {
+        PageChar {
+            c,
+            line,
+            rect: Rect {
+                x0: x,
+                y0: y,
+                x1: x + 5.0,
+                y1: y + 10.0,
+            },
+        }
+    }
+
+    #[test]
+    fn test_highlight_to_extracted() {
+        let raw = RawAnnotation {
+            annotation_type: ExtractedAnnotationType::Highlight,
+            rect: vec![61.2, 633.6, 306.0, 712.8],
+            // Two lines, as UL, UR, LL, LR
+            quad_points: vec![
+                61.2, 712.8, 306.0, 712.8, 61.2, 700.0, 306.0, 700.0, //
+                61.2, 690.0, 120.0, 690.0, 61.2, 677.0, 120.0, 677.0,
+            ],
+            ink_list: Vec::new(),
+            contents: Some("Key point".to_string()),
+            author: Some("Ada".to_string()),
+            color: vec![1.0, 1.0, 0.0],
+            created: Some("D:20230115103000+01'00'".to_string()),
+            modified: None,
+        };
+        let chars = vec![
+            page_char('H', 0, 70.0, 82.0),
+            page_char('i', 0, 75.0, 82.0),
+            page_char('x', 0, 400.0, 82.0),
+            page_char('y', 1, 70.0, 104.0),
+            page_char('o', 1, 75.0, 104.0),
+            page_char('z', 2, 70.0, 300.0),
+        ];
+
+        let annotation = raw.to_extracted(3, &letter_page(), Some(&chars));
+
+        assert_eq!(annotation.page, 3);
+        assert_eq!(annotation.text.as_deref(), Some("Hi yo"));
+        assert_eq!(annotation.comment.as_deref(), Some("Key point"));
+        assert_eq!(annotation.author.as_deref(), Some("Ada"));
+        assert_eq!(annotation.color.as_deref(), Some("#FFFF00"));
+        assert_eq!(
+            annotation.created_date.as_deref(),
+            Some("2023-01-15T10:30:00+01:00")
+        );
+        assert_eq!(annotation.quads.len(), 2);
+        let quad = &annotation.quads[0];
+        assert!((quad.x - 0.1).abs() < 1e-6);
+        assert!((quad.y - 0.1).abs() < 1e-6);
+        assert!((quad.width - 0.4).abs() < 1e-6);
+        assert!((annotation.rect.height - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_ink_to_extracted() {
+        let raw = RawAnnotation {
+            annotation_type: ExtractedAnnotationType::Ink,
+            rect: vec![0.0, 0.0, 612.0, 792.0],
+            quad_points: Vec::new(),
+            ink_list: vec![vec![0.0, 792.0, 306.0, 396.0]],
+            contents: None,
+            author: None,
+            color: vec![0.0],
+            created: None,
+            modified: Some("D:2021".to_string()),
+        };
+
+        let annotation = raw.to_extracted(1, &letter_page(), Some(&[]));
+
+        assert_eq!(annotation.text, None);
+        assert_eq!(annotation.color.as_deref(), Some("#000000"));
+        assert_eq!(
+            annotation.modified_date.as_deref(),
+            Some("2021-01-01T00:00:00")
+        );
+        let stroke = &annotation.ink_list[0];
+        assert_eq!((stroke[0].x, stroke[0].y), (0.0, 0.0));
+        assert_eq!((stroke[1].x, stroke[1].y), (0.5, 0.5));
+    }
+
+    #[test]
+    fn test_hex_color() {
+        assert_eq!(hex_color(&[1.0, 0.5, 0.0]).as_deref(), Some("#FF8000"));
+        assert_eq!(hex_color(&[0.0, 0.0, 1.0, 0.0]).as_deref(), Some("#FFFF00"));
+        assert_eq!(hex_color(&[0.5]).as_deref(), Some("#808080"));
+        assert_eq!(hex_color(&[]), None);
+    }
+
+    #[test]
+    fn test_pdf_date_to_iso() {
+        assert_eq!(
+            pdf_date_to_iso("D:20240229235959Z").as_deref(),
+            Some("2024-02-29T23:59:59Z")
+        );
+        assert_eq!(
+            pdf_date_to_iso("D:199812231952-08'00").as_deref(),
+            Some("1998-12-23T19:52:00-08:00")
+        );
+        assert_eq!(pdf_date_to_iso("D:20231301"), None);
+        assert_eq!(pdf_date_to_iso("yesterday"), None);
+    }
+
+    #[test]
+    fn test_subtypes() {
+        assert_eq!(
+            ExtractedAnnotationType::from_subtype(b"StrikeOut"),
+            ExtractedAnnotationType::StrikeOut
+        );
+        assert_eq!(
+            ExtractedAnnotationType::from_subtype(b"Square"),
+            ExtractedAnnotationType::Unknown
+        );
+        assert!(ExtractedAnnotationType::Squiggly.is_text_markup());
+        assert!(!ExtractedAnnotationType::FreeText.is_text_markup());
+    }
+
     #[test]
     fn test_normalized_rect() {
         let rect = NormalizedRect {
@@ -0,0 +1,454 @@
+//! PDF Annotation Writer
+//!
+//! Writes highlights, underlines and notes into a PDF as native annotations,
+//! so they show up in Adobe Reader, Preview and other PDF readers.
+//!
+//! # Approach
+//!
+//! Like [`super::annotation_extractor`], this works on the annotation
+//! dictionaries directly, since the MuPDF binding can't set an annotation's
+//! rect, quads or color. Normalized page rects are mapped back into PDF user
+//! space with the inverse of the page transform and written as `/QuadPoints`
+//! (text markup) or an icon `/Rect` (notes). The annotations get no
+//! appearance streams here; save with the `appearance` write option so
+//! MuPDF generates them for readers that don't draw annotations themselves.
+
+use chrono::{DateTime, Utc};
+use mupdf::pdf::{PdfDocument, PdfObject};
+use mupdf::{Matrix, Rect};
+use serde::{Deserialize, Serialize};
+
+use super::types::NormalizedRect;
+
+/// Size of a note's icon, in points
+const NOTE_ICON_SIZE: f32 = 20.0;
+
+/// Types of PDF annotations we can write
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WrittenAnnotationType {
+    Highlight,
+    Underline,
+    /// Sticky note
+    Text,
+}
+
+impl WrittenAnnotationType {
+    fn subtype(&self) -> &'static str {
+        match self {
+            Self::Highlight => "Highlight",
+            Self::Underline => "Underline",
+            Self::Text => "Text",
+        }
+    }
+}
+
+/// An annotation to write into a PDF
+#[derive(Debug, Clone)]
+pub struct WrittenAnnotation {
+    /// Annotation type
+    pub annotation_type: WrittenAnnotationType,
+    /// Page number (1-indexed)
+    pub page: u32,
+    /// Normalized rects (0-1, origin top-left), one per line of text
+    ///
+    /// Notes are placed at the top-left corner of the first rect.
+    pub rects: Vec<NormalizedRect>,
+    /// CSS color (hex, `rgb()`/`rgba()` or a highlight color name)
+    pub color: Option<String>,
+    /// Note or comment text
+    pub contents: Option<String>,
+    /// Author name
+    pub author: Option<String>,
+    /// Unique name (`/NM`), e.g. the stored annotation's ID
+    pub name: Option<String>,
+    /// Last modification time
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// Write annotations into a PDF document
+///
+/// Annotations on pages the document doesn't have, or without rects, are
+/// skipped. Returns the number of annotations written.
+pub fn write_annotations(
+    pdf_doc: &mut PdfDocument,
+    annotations: &[WrittenAnnotation],
+) -> Result<usize, mupdf::Error> {
+    let page_count = pdf_doc.page_count()?.max(0) as u32;
+
+    let mut pages: Vec<u32> = annotations
+        .iter()
+        .map(|annotation| annotation.page)
+        .filter(|page| (1..=page_count).contains(page))
+        .collect();
+    pages.sort_unstable();
+    pages.dedup();
+
+    let mut written = 0;
+    for page in pages {
+        let page_index = page as i32 - 1;
+        let mut page_obj = pdf_doc.find_page(page_index)?;
+        let geometry = PageGeometry {
+            ctm: page_obj.page_ctm()?,
+            bounds: pdf_doc.load_page(page_index)?.bounds()?,
+        };
+
+        let mut annots = match page_obj.get_dict("Annots")? {
+            Some(annots) => annots,
+            None => {
+                page_obj.dict_put("Annots", pdf_doc.new_array()?)?;
+                page_obj
+                    .get_dict("Annots")?
+                    .ok_or(mupdf::Error::InvalidPdfDocument)?
+            }
+        };
+
+        for annotation in annotations.iter().filter(|a| a.page == page) {
+            let Some(dict) = annotation_dict(pdf_doc, &geometry, annotation)? else {
+                continue;
+            };
+            let annot = pdf_doc.add_object(&dict)?;
+            annots.array_push(annot)?;
+            written += 1;
+        }
+    }
+
+    Ok(written)
+}
+
+/// Build the dictionary of an annotation, or `None` if it has no rects
+fn annotation_dict(
+    pdf_doc: &PdfDocument,
+    geometry: &PageGeometry,
+    annotation: &WrittenAnnotation,
+) -> Result<Option<PdfObject>, mupdf::Error> {
+    let Some(first) = annotation.rects.first() else {
+        return Ok(None);
+    };
+
+    let mut dict = pdf_doc.new_dict()?;
+    dict.dict_put("Type", pdf_doc.new_name("Annot")?)?;
+    dict.dict_put(
+        "Subtype",
+        pdf_doc.new_name(annotation.annotation_type.subtype())?,
+    )?;
+    // Print flag, so the annotation shows up on paper too
+    dict.dict_put("F", pdf_doc.new_int(4)?)?;
+
+    let rect = match annotation.annotation_type {
+        WrittenAnnotationType::Highlight | WrittenAnnotationType::Underline => {
+            let quad_points: Vec<f32> = annotation
+                .rects
+                .iter()
+                .flat_map(|rect| geometry.quad_points(rect))
+                .collect();
+            dict.dict_put("QuadPoints", number_array(pdf_doc, &quad_points)?)?;
+            geometry.user_bbox(&annotation.rects)
+        }
+        WrittenAnnotationType::Text => {
+            dict.dict_put("Name", pdf_doc.new_name("Comment")?)?;
+            dict.dict_put("Open", pdf_doc.new_bool(false))?;
+            geometry.icon_rect(first)
+        }
+    };
+    dict.dict_put(
+        "Rect",
+        number_array(pdf_doc, &[rect.x0, rect.y0, rect.x1, rect.y1])?,
+    )?;
+
+    let color = annotation
+        .color
+        .as_deref()
+        .and_then(parse_color)
+        .unwrap_or(DEFAULT_COLOR);
+    dict.dict_put("C", number_array(pdf_doc, &color)?)?;
+
+    if let Some(contents) = annotation.contents.as_deref().filter(|c| !c.is_empty()) {
+        dict.dict_put("Contents", text_string(pdf_doc, contents)?)?;
+    }
+    if let Some(author) = annotation.author.as_deref() {
+        dict.dict_put("T", text_string(pdf_doc, author)?)?;
+    }
+    if let Some(name) = annotation.name.as_deref() {
+        dict.dict_put("NM", text_string(pdf_doc, name)?)?;
+    }
+    if let Some(modified) = annotation.modified {
+        dict.dict_put("M", pdf_doc.new_string(&pdf_date(&modified))?)?;
+    }
+
+    Ok(Some(dict))
+}
+
+/// A PDF text string: literal for ASCII, UTF-16BE otherwise
+pub(super) fn text_string(pdf_doc: &PdfDocument, text: &str) -> Result<PdfObject, mupdf::Error> {
+    let text = text.replace('\0', "");
+    if text.is_ascii() {
+        return pdf_doc.new_string(&text);
+    }
+    let hex: String = text
+        .encode_utf16()
+        .map(|unit| format!("{:04X}", unit))
+        .collect();
+    pdf_doc.new_object_from_str(&format!("<FEFF{}>", hex))
+}
+
+fn number_array(pdf_doc: &PdfDocument, numbers: &[f32]) -> Result<PdfObject, mupdf::Error> {
+    let mut array = pdf_doc.new_array()?;
+    for &number in numbers {
+        array.array_push(pdf_doc.new_real(number)?)?;
+    }
+    Ok(array)
+}
+
+/// The page box and its transform from PDF user space
+struct PageGeometry {
+    ctm: Matrix,
+    bounds: Rect,
+}
+
+impl PageGeometry {
+    /// Map a point on the page back into PDF user space
+    fn to_user(&self, x: f32, y: f32) -> (f32, f32) {
+        let m = &self.ctm;
+        let det = m.a * m.d - m.b * m.c;
+        if det.abs() < f32::EPSILON {
+            return (x, y);
+        }
+        let (x, y) = (x - m.e, y - m.f);
+        ((x * m.d - y * m.c) / det, (y * m.a - x * m.b) / det)
+    }
+
+    /// Page-space corners of a normalized rect: x0, y0, x1, y1
+    fn page_rect(&self, rect: &NormalizedRect) -> [f32; 4] {
+        let width = self.bounds.x1 - self.bounds.x0;
+        let height = self.bounds.y1 - self.bounds.y0;
+        let x0 = self.bounds.x0 + rect.x as f32 * width;
+        let y0 = self.bounds.y0 + rect.y as f32 * height;
+        [
+            x0,
+            y0,
+            x0 + rect.width as f32 * width,
+            y0 + rect.height as f32 * height,
+        ]
+    }
+
+    /// QuadPoints of a normalized rect, in the order readers expect:
+    /// upper-left, upper-right, lower-left, lower-right
+    fn quad_points(&self, rect: &NormalizedRect) -> [f32; 8] {
+        let [x0, y0, x1, y1] = self.page_rect(rect);
+        let (ulx, uly) = self.to_user(x0, y0);
+        let (urx, ury) = self.to_user(x1, y0);
+        let (llx, lly) = self.to_user(x0, y1);
+        let (lrx, lry) = self.to_user(x1, y1);
+        [ulx, uly, urx, ury, llx, lly, lrx, lry]
+    }
+
+    /// User-space bounding box of normalized rects
+    fn user_bbox(&self, rects: &[NormalizedRect]) -> Rect {
+        let points: Vec<f32> = rects.iter().flat_map(|r| self.quad_points(r)).collect();
+        bbox(&points)
+    }
+
+    /// User-space rect of a note icon at the top-left corner of a rect
+    fn icon_rect(&self, rect: &NormalizedRect) -> Rect {
+        let [x0, y0, ..] = self.page_rect(rect);
+        let (x1, y1) = (x0 + NOTE_ICON_SIZE, y0 + NOTE_ICON_SIZE);
+        let corners = [
+            self.to_user(x0, y0),
+            self.to_user(x1, y0),
+            self.to_user(x0, y1),
+            self.to_user(x1, y1),
+        ];
+        let points: Vec<f32> = corners.iter().flat_map(|&(x, y)| [x, y]).collect();
+        bbox(&points)
+    }
+}
+
+/// Bounding box of x, y pairs
+fn bbox(points: &[f32]) -> Rect {
+    points.chunks_exact(2).fold(
+        Rect {
+            x0: f32::MAX,
+            y0: f32::MAX,
+            x1: f32::MIN,
+            y1: f32::MIN,
+        },
+        |r, point| Rect {
+            x0: r.x0.min(point[0]),
+            y0: r.y0.min(point[1]),
+            x1: r.x1.max(point[0]),
+            y1: r.y1.max(point[1]),
+        },
+    )
+}
+
+/// Color used when an annotation has none, or one we can't parse
+const DEFAULT_COLOR: [f32; 3] = [1.0, 235.0 / 255.0, 59.0 / 255.0];
+
+/// Parse a CSS color into RGB components (0-1)
+///
+/// Accepts `#rgb`, `#rrggbb` (alpha is ignored), `rgb()`/`rgba()` and the
+/// reader's highlight color names.
+fn parse_color(color: &str) -> Option<[f32; 3]> {
+    let color = color.trim().to_ascii_lowercase();
+    let rgb = |r: u8, g: u8, b: u8| [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0];
+
+    if let Some(hex) = color.strip_prefix('#') {
+        let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+        return match hex.len() {
+            3 | 4 => {
+                let digit = |i: usize| channel(&hex[i..i + 1]).map(|v| v * 17);
+                Some(rgb(digit(0)?, digit(1)?, digit(2)?))
+            }
+            6 | 8 => Some(rgb(
+                channel(&hex[0..2])?,
+                channel(&hex[2..4])?,
+                channel(&hex[4..6])?,
+            )),
+            _ => None,
+        };
+    }
+
+    if let Some(args) = color
+        .strip_prefix("rgba(")
+        .or_else(|| color.strip_prefix("rgb("))
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let mut channels = args.split(',').map(|v| v.trim().parse::<f32>().ok());
+        let mut next = || {
+            channels
+                .next()
+                .flatten()
+                .map(|v| v.clamp(0.0, 255.0) / 255.0)
+        };
+        return Some([next()?, next()?, next()?]);
+    }
+
+    // Same palette as the reader's PDF highlight layer
+    match color.as_str() {
+        "yellow" => Some(rgb(255, 235, 59)),
+        "green" => Some(rgb(76, 175, 80)),
+        "blue" => Some(rgb(33, 150, 243)),
+        "pink" => Some(rgb(233, 30, 99)),
+        "purple" => Some(rgb(156, 39, 176)),
+        "orange" => Some(rgb(255, 152, 0)),
+        "red" => Some(rgb(255, 0, 0)),
+        _ => None,
+    }
+}
+
+/// Format a time as a PDF date (`D:YYYYMMDDHHmmSSZ`)
+fn pdf_date(date: &DateTime<Utc>) -> String {
+    date.format("D:%Y%m%d%H%M%SZ").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A US Letter page with MuPDF's flip from PDF user space
+    fn letter_page() -> PageGeometry {
+        PageGeometry {
+            ctm: Matrix {
+                a: 1.0,
+                b: 0.0,
+                c: 0.0,
+                d: -1.0,
+                e: 0.0,
+                f: 792.0,
+            },
+            bounds: Rect {
+                x0: 0.0,
+                y0: 0.0,
+                x1: 612.0,
+                y1: 792.0,
+            },
+        }
+    }
+
+    fn rect(x: f64, y: f64, width: f64, height: f64) -> NormalizedRect {
+        NormalizedRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_quad_points() {
+        let geometry = letter_page();
+        let quad = geometry.quad_points(&rect(0.5, 0.25, 0.25, 0.125));
+
+        // Upper-left, upper-right, lower-left, lower-right in user space
+        assert_eq!(
+            quad,
+            [306.0, 594.0, 459.0, 594.0, 306.0, 495.0, 459.0, 495.0]
+        );
+    }
+
+    #[test]
+    fn test_user_bbox() {
+        let geometry = letter_page();
+        let bbox =
+            geometry.user_bbox(&[rect(0.5, 0.25, 0.25, 0.125), rect(0.25, 0.375, 0.25, 0.125)]);
+
+        assert_eq!(
+            (bbox.x0, bbox.y0, bbox.x1, bbox.y1),
+            (153.0, 396.0, 459.0, 594.0)
+        );
+    }
+
+    #[test]
+    fn test_icon_rect() {
+        let geometry = letter_page();
+        let icon = geometry.icon_rect(&rect(0.5, 0.25, 0.25, 0.125));
+
+        assert_eq!(
+            (icon.x0, icon.y0, icon.x1, icon.y1),
+            (306.0, 574.0, 326.0, 594.0)
+        );
+    }
+
+    #[test]
+    fn test_rotated_page_round_trip() {
+        // A letter page rotated 90 degrees: user (x, y) -> page (y, x)
+        let geometry = PageGeometry {
+            ctm: Matrix {
+                a: 0.0,
+                b: 1.0,
+                c: 1.0,
+                d: 0.0,
+                e: 0.0,
+                f: 0.0,
+            },
+            bounds: Rect {
+                x0: 0.0,
+                y0: 0.0,
+                x1: 792.0,
+                y1: 612.0,
+            },
+        };
+
+        assert_eq!(geometry.to_user(100.0, 50.0), (50.0, 100.0));
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#FFFF00"), Some([1.0, 1.0, 0.0]));
+        assert_eq!(parse_color("#ff000080"), Some([1.0, 0.0, 0.0]));
+        assert_eq!(parse_color("#0f0"), Some([0.0, 1.0, 0.0]));
+        assert_eq!(parse_color("rgba(0, 0, 255, 0.4)"), Some([0.0, 0.0, 1.0]));
+        assert_eq!(parse_color(" Yellow "), Some(DEFAULT_COLOR));
+        assert_eq!(parse_color("#12345"), None);
+        assert_eq!(parse_color("chartreuse"), None);
+    }
+
+    #[test]
+    fn test_pdf_date() {
+        let date = DateTime::parse_from_rfc3339("2024-01-15T10:30:05Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(pdf_date(&date), "D:20240115103005Z");
+    }
+}
@@ -24,8 +24,14 @@ const TEXT_TIMEOUT_SECS: u64 = 15; // 15 seconds per page
 /// Timeout for search operations (prevent DoS on large PDFs)
 const SEARCH_TIMEOUT_SECS: u64 = 30; // 30 seconds max for search
 
+use super::annotation_extractor::{ExtractedAnnotation, ExtractionOptions};
+use super::annotation_writer::WrittenAnnotation;
 use super::mupdf_parser::{PdfParseError, PdfParser};
-use super::types::{FormInfo, ImageFormat, PageRenderRequest, ParsedPdf, SignatureInfo, TextLayer};
+use super::signature_validator::TrustAnchors;
+use super::types::{
+    FillFormRequest, FillFormResult, FormInfo, ImageFormat, PageRenderRequest, ParsedPdf,
+    SignatureInfo, TextLayer,
+};
 
 /// Thread-safe wrapper for PdfParser that serializes all operations
 /// MuPDF is NOT thread-safe, so we use parking_lot::Mutex for efficient serialization
@@ -83,9 +89,12 @@ impl SafePdfParser {
     }
 
     /// Get signatures with exclusive access
-    pub fn get_signatures(&self) -> Result<Vec<SignatureInfo>, PdfParseError> {
+    pub fn get_signatures(
+        &self,
+        trust_anchors: &TrustAnchors,
+    ) -> Result<Vec<SignatureInfo>, PdfParseError> {
         let parser = self.inner.lock();
-        parser.get_signatures()
+        parser.get_signatures(trust_anchors)
     }
 
     /// Check if PDF has forms with exclusive access
@@ -93,6 +102,34 @@ impl SafePdfParser {
         let parser = self.inner.lock();
         parser.has_forms()
     }
+
+    /// Read native annotations with exclusive access
+    pub fn extract_annotations(
+        &self,
+        options: &ExtractionOptions,
+    ) -> Result<Vec<ExtractedAnnotation>, PdfParseError> {
+        let parser = self.inner.lock();
+        parser.extract_annotations(options)
+    }
+
+    /// Export with annotations with exclusive access
+    pub fn export_with_annotations(
+        &self,
+        annotations: &[WrittenAnnotation],
+    ) -> Result<(Vec<u8>, usize), PdfParseError> {
+        let parser = self.inner.lock();
+        parser.export_with_annotations(annotations)
+    }
+
+    /// Fill form fields with exclusive access
+    pub fn fill_form(
+        &self,
+        requests: &[FillFormRequest],
+        flatten: bool,
+    ) -> Result<(FillFormResult, Option<Vec<u8>>), PdfParseError> {
+        let parser = self.inner.lock();
+        parser.fill_form(requests, flatten)
+    }
 }
 
 /// Cache key for rendered pages
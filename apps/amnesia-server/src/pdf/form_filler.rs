@@ -0,0 +1,957 @@
+//! PDF Form Filler
+//!
+//! Writes values into AcroForm fields, recalculates calculated fields and
+//! optionally flattens the form into page content.
+//!
+//! # Approach
+//!
+//! Values are validated against the fields reported by
+//! [`super::PdfParser::get_form_info`] and written into the field
+//! dictionaries directly. Widgets of changed text and choice fields lose
+//! their appearance streams, so saving with the `appearance` write option
+//! makes MuPDF generate new ones; checkboxes and radio buttons switch
+//! between their existing appearance states.
+//!
+//! MuPDF only runs calculation scripts with JavaScript enabled, and only
+//! after edits made through its own form API. Calculated fields using
+//! Acrobat's built-in `AFSimple_Calculate` (sum, product, average, minimum,
+//! maximum) are therefore recalculated here; custom scripts are reported
+//! as errors.
+//!
+//! Flattening draws each visible widget's appearance stream into its page
+//! as a form XObject, then removes the widgets and the AcroForm. It needs
+//! appearance streams, so it runs on the saved document.
+
+use mupdf::pdf::{PdfDocument, PdfObject};
+
+use super::annotation_writer::text_string;
+use super::types::{FillFormRequest, FillFormResult, FormField, FormFieldType};
+
+/// Annotation flags that hide a widget (Hidden, NoView)
+const HIDDEN_FLAGS: i32 = 2 | 32;
+
+/// Appearance state of a button that is switched off
+const OFF_STATE: &str = "Off";
+
+/// A validated field value, ready to be written
+#[derive(Debug, Clone, PartialEq)]
+enum FieldValue {
+    /// Text string value (text and choice fields)
+    Text(String),
+    /// Appearance state name (checkboxes and radio buttons)
+    State(String),
+}
+
+/// Calculations supported by `AFSimple_Calculate`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Calculation {
+    Sum,
+    Product,
+    Average,
+    Minimum,
+    Maximum,
+}
+
+impl Calculation {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "SUM" => Some(Self::Sum),
+            "PRD" => Some(Self::Product),
+            "AVG" => Some(Self::Average),
+            "MIN" => Some(Self::Minimum),
+            "MAX" => Some(Self::Maximum),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, values: &[f64]) -> f64 {
+        if values.is_empty() {
+            return 0.0;
+        }
+        match self {
+            Self::Sum => values.iter().sum(),
+            Self::Product => values.iter().product(),
+            Self::Average => values.iter().sum::<f64>() / values.len() as f64,
+            Self::Minimum => values.iter().copied().fold(f64::INFINITY, f64::min),
+            Self::Maximum => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+/// Fill form fields in a PDF document
+///
+/// Each request is matched against `fields` by full name, then by name.
+/// Requests that don't match a field or don't validate are reported in the
+/// result's errors and leave the field unchanged. When `recalculate` is
+/// set and any field was updated, calculated fields are recalculated.
+pub fn fill_form(
+    pdf_doc: &mut PdfDocument,
+    fields: &[FormField],
+    requests: &[FillFormRequest],
+    recalculate: bool,
+) -> Result<FillFormResult, mupdf::Error> {
+    let mut result = FillFormResult {
+        fields_updated: 0,
+        errors: Vec::new(),
+        recalculated: false,
+        flattened: false,
+        revision_key: None,
+    };
+
+    let mut field_objects = Vec::new();
+    if let Some(acro_form) = acro_form(pdf_doc)? {
+        if let Some(roots) = acro_form.get_dict("Fields")? {
+            for i in 0..roots.len()? {
+                if let Some(root) = roots.get_array(i as i32)? {
+                    collect_fields(root, None, &mut field_objects)?;
+                }
+            }
+        }
+    }
+
+    for request in requests {
+        let Some(field) = find_field(fields, &request.field_name) else {
+            result
+                .errors
+                .push(format!("Field '{}' not found", request.field_name));
+            continue;
+        };
+        let key = field_key(field);
+        let Some((_, field_obj)) = field_objects
+            .iter_mut()
+            .find(|(name, _)| name.as_str() == key)
+        else {
+            result.errors.push(format!("Field '{}' not found", key));
+            continue;
+        };
+
+        let on_states = button_states(field_obj)?;
+        match validate_value(field, &request.value, &on_states) {
+            Ok(value) => {
+                write_value(pdf_doc, field_obj, &value)?;
+                result.fields_updated += 1;
+            }
+            Err(error) => result.errors.push(error),
+        }
+    }
+
+    if recalculate && result.fields_updated > 0 {
+        result.recalculated = recalculate_fields(pdf_doc, &mut field_objects, &mut result.errors)?;
+    }
+
+    Ok(result)
+}
+
+/// Flatten form fields into page content
+///
+/// Returns the number of widgets removed.
+pub fn flatten_form(pdf_doc: &mut PdfDocument) -> Result<usize, mupdf::Error> {
+    let mut removed = 0;
+
+    for page_index in 0..pdf_doc.page_count()?.max(0) {
+        let mut page_obj = pdf_doc.find_page(page_index)?;
+        let Some(annots) = page_obj.get_dict("Annots")? else {
+            continue;
+        };
+
+        let mut kept = pdf_doc.new_array()?;
+        let mut draws = Vec::new();
+        let mut page_removed = 0;
+        for i in 0..annots.len()? {
+            let Some(annot) = annots.get_array(i as i32)? else {
+                continue;
+            };
+            if !is_widget(&annot)? {
+                kept.array_push(annot)?;
+                continue;
+            }
+            page_removed += 1;
+
+            let hidden = match annot.get_dict("F")? {
+                Some(flags) => flags.as_int()? & HIDDEN_FLAGS != 0,
+                None => false,
+            };
+            if hidden {
+                continue;
+            }
+            let Some(appearance) = appearance_stream(&annot)? else {
+                continue;
+            };
+            let (Some(bbox), Some(rect)) = (
+                read_numbers::<4>(&appearance, "BBox")?,
+                read_numbers::<4>(&annot, "Rect")?,
+            ) else {
+                continue;
+            };
+            let matrix =
+                read_numbers::<6>(&appearance, "Matrix")?.unwrap_or([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+            if let Some(placement) = placement(bbox, matrix, rect) {
+                draws.push((appearance, placement));
+            }
+        }
+
+        if page_removed == 0 {
+            continue;
+        }
+        page_obj.dict_put("Annots", kept)?;
+        removed += page_removed;
+
+        if !draws.is_empty() {
+            draw_appearances(pdf_doc, &mut page_obj, draws)?;
+        }
+    }
+
+    if let Some(mut root) = pdf_doc.trailer()?.get_dict("Root")? {
+        root.dict_delete("AcroForm")?;
+    }
+
+    Ok(removed)
+}
+
+/// Check a requested value against a field's type and options
+///
+/// `on_states` are the appearance states a checkbox or radio button can be
+/// switched to, besides `Off`.
+fn validate_value(
+    field: &FormField,
+    value: &str,
+    on_states: &[String],
+) -> Result<FieldValue, String> {
+    let key = field_key(field);
+
+    if field.read_only {
+        return Err(format!("Field '{}' is read-only", key));
+    }
+    if field.required && value.trim().is_empty() {
+        return Err(format!("Field '{}' is required", key));
+    }
+
+    match field.field_type {
+        FormFieldType::Text => {
+            if let Some(max_length) = field.max_length.filter(|max| *max > 0) {
+                if value.chars().count() > max_length {
+                    return Err(format!(
+                        "Value for '{}' is longer than {} characters",
+                        key, max_length
+                    ));
+                }
+            }
+            if !field.multiline.unwrap_or(false) && value.contains(['\n', '\r']) {
+                return Err(format!("Field '{}' is a single-line field", key));
+            }
+            Ok(FieldValue::Text(value.to_string()))
+        }
+        FormFieldType::Checkbox => {
+            let value = value.trim();
+            if on_states.iter().any(|state| state == value) || value == OFF_STATE {
+                return Ok(FieldValue::State(value.to_string()));
+            }
+            match value.to_ascii_lowercase().as_str() {
+                "" | "off" | "false" | "no" | "0" => Ok(FieldValue::State(OFF_STATE.to_string())),
+                "on" | "true" | "yes" | "1" => Ok(FieldValue::State(
+                    on_states
+                        .first()
+                        .cloned()
+                        .unwrap_or_else(|| "Yes".to_string()),
+                )),
+                _ => Err(format!("Invalid value for checkbox '{}': {}", key, value)),
+            }
+        }
+        FormFieldType::Radio => {
+            let value = value.trim();
+            if on_states.iter().any(|state| state == value) {
+                Ok(FieldValue::State(value.to_string()))
+            } else if value.is_empty() || value == OFF_STATE {
+                Ok(FieldValue::State(OFF_STATE.to_string()))
+            } else {
+                Err(format!("'{}' is not an option of '{}'", value, key))
+            }
+        }
+        FormFieldType::Dropdown | FormFieldType::ListBox => {
+            let options = field.options.as_deref().unwrap_or_default();
+            if options.is_empty() || value.is_empty() {
+                return Ok(FieldValue::Text(value.to_string()));
+            }
+            options
+                .iter()
+                .find_map(|option| {
+                    let export = option.value.as_deref().unwrap_or(&option.label);
+                    (export == value || option.label == value).then(|| export.to_string())
+                })
+                .map(FieldValue::Text)
+                .ok_or_else(|| format!("'{}' is not an option of '{}'", value, key))
+        }
+        FormFieldType::Signature | FormFieldType::Button | FormFieldType::Unknown => {
+            Err(format!("Field '{}' can't be filled", key))
+        }
+    }
+}
+
+/// Write a validated value into a field and its widgets
+fn write_value(
+    pdf_doc: &PdfDocument,
+    field_obj: &mut PdfObject,
+    value: &FieldValue,
+) -> Result<(), mupdf::Error> {
+    match value {
+        FieldValue::Text(text) => {
+            field_obj.dict_put("V", text_string(pdf_doc, text)?)?;
+            for mut widget in widgets(field_obj)? {
+                // Dropped so MuPDF generates an appearance for the new value
+                widget.dict_delete("AP")?;
+            }
+        }
+        FieldValue::State(state) => {
+            field_obj.dict_put("V", pdf_doc.new_name(state)?)?;
+            for mut widget in widgets(field_obj)? {
+                let shown = if widget_states(&widget)?.contains(state) {
+                    state.as_str()
+                } else {
+                    OFF_STATE
+                };
+                widget.dict_put("AS", pdf_doc.new_name(shown)?)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Recalculate the fields in the AcroForm's calculation order
+///
+/// Returns whether any field was recalculated.
+fn recalculate_fields(
+    pdf_doc: &PdfDocument,
+    field_objects: &mut [(String, PdfObject)],
+    errors: &mut Vec<String>,
+) -> Result<bool, mupdf::Error> {
+    let Some(order) = acro_form(pdf_doc)?.and_then(|form| form.get_dict("CO").ok().flatten())
+    else {
+        return Ok(false);
+    };
+
+    let mut recalculated = false;
+    for i in 0..order.len()? {
+        let Some(calculated) = order.get_array(i as i32)? else {
+            continue;
+        };
+        let name = full_name(&calculated)?;
+        let Some(script) = calculation_script(&calculated)? else {
+            continue;
+        };
+        let Some((calculation, operands)) = parse_simple_calculation(&script) else {
+            errors.push(format!(
+                "Field '{}' has a calculation script that can't be run",
+                name
+            ));
+            continue;
+        };
+
+        let mut values = Vec::with_capacity(operands.len());
+        for operand in &operands {
+            match field_objects.iter().find(|(key, _)| key == operand) {
+                Some((_, obj)) => values.push(parse_number(&field_text(obj)?)),
+                None => errors.push(format!(
+                    "Field '{}' is calculated from unknown field '{}'",
+                    name, operand
+                )),
+            }
+        }
+        if values.len() != operands.len() {
+            continue;
+        }
+
+        let value = FieldValue::Text(format_number(calculation.apply(&values)));
+        match field_objects.iter_mut().find(|(key, _)| *key == name) {
+            Some((_, obj)) => write_value(pdf_doc, obj, &value)?,
+            None => write_value(pdf_doc, &mut calculated.clone(), &value)?,
+        }
+        recalculated = true;
+    }
+
+    Ok(recalculated)
+}
+
+/// Parse an `AFSimple_Calculate("SUM", new Array("a", "b"))` script
+///
+/// Returns `None` for any other script.
+fn parse_simple_calculation(script: &str) -> Option<(Calculation, Vec<String>)> {
+    let call = script.trim().trim_end_matches(';').trim_end();
+    let arguments = call
+        .strip_prefix("AFSimple_Calculate")?
+        .trim_start()
+        .strip_prefix('(')?
+        .strip_suffix(')')?;
+    if arguments.contains(';') {
+        return None;
+    }
+
+    let mut literals = string_literals(arguments)?.into_iter();
+    let calculation = Calculation::from_name(&literals.next()?)?;
+    // Field names may also be given as a single comma-separated string
+    let operands: Vec<String> = literals
+        .flat_map(|names| {
+            names
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .collect::<Vec<_>>()
+        })
+        .filter(|name| !name.is_empty())
+        .collect();
+    if operands.is_empty() {
+        return None;
+    }
+    Some((calculation, operands))
+}
+
+/// Collect the JavaScript string literals in `source`, in order
+///
+/// Returns `None` if a literal isn't terminated.
+fn string_literals(source: &str) -> Option<Vec<String>> {
+    let mut literals = Vec::new();
+    let mut chars = source.chars();
+    while let Some(c) = chars.next() {
+        if c != '"' && c != '\'' {
+            continue;
+        }
+        let mut literal = String::new();
+        loop {
+            match chars.next()? {
+                '\\' => literal.push(chars.next()?),
+                end if end == c => break,
+                other => literal.push(other),
+            }
+        }
+        literals.push(literal);
+    }
+    Some(literals)
+}
+
+/// Parse a field value as a number, treating anything else as zero
+fn parse_number(value: &str) -> f64 {
+    value.trim().replace(',', "").parse().unwrap_or(0.0)
+}
+
+/// Format a calculated value without float noise
+fn format_number(value: f64) -> String {
+    if !value.is_finite() {
+        return String::new();
+    }
+    let rounded = (value * 1e6).round() / 1e6;
+    // Avoids "-0"
+    format!("{}", rounded + 0.0)
+}
+
+/// Scale and offset mapping an appearance onto a widget's rect
+///
+/// Follows the PDF spec's algorithm for drawing annotation appearances:
+/// the form bbox, transformed by the form matrix, is fitted to the rect.
+/// Returns `[sx, sy, tx, ty]`, or `None` for an empty bbox.
+fn placement(bbox: [f32; 4], matrix: [f32; 6], rect: [f32; 4]) -> Option<[f32; 4]> {
+    let [a, b, c, d, e, f] = matrix;
+    let corners = [
+        (bbox[0], bbox[1]),
+        (bbox[2], bbox[1]),
+        (bbox[0], bbox[3]),
+        (bbox[2], bbox[3]),
+    ]
+    .map(|(x, y)| (a * x + c * y + e, b * x + d * y + f));
+
+    let x0 = corners.iter().map(|p| p.0).fold(f32::INFINITY, f32::min);
+    let y0 = corners.iter().map(|p| p.1).fold(f32::INFINITY, f32::min);
+    let x1 = corners
+        .iter()
+        .map(|p| p.0)
+        .fold(f32::NEG_INFINITY, f32::max);
+    let y1 = corners
+        .iter()
+        .map(|p| p.1)
+        .fold(f32::NEG_INFINITY, f32::max);
+    if x1 - x0 <= 0.0 || y1 - y0 <= 0.0 {
+        return None;
+    }
+
+    let (rx0, rx1) = (rect[0].min(rect[2]), rect[0].max(rect[2]));
+    let (ry0, ry1) = (rect[1].min(rect[3]), rect[1].max(rect[3]));
+    let sx = (rx1 - rx0) / (x1 - x0);
+    let sy = (ry1 - ry0) / (y1 - y0);
+    Some([sx, sy, rx0 - x0 * sx, ry0 - y0 * sy])
+}
+
+/// Draw appearance streams into a page as form XObjects
+fn draw_appearances(
+    pdf_doc: &mut PdfDocument,
+    page_obj: &mut PdfObject,
+    draws: Vec<(PdfObject, [f32; 4])>,
+) -> Result<(), mupdf::Error> {
+    let mut resources = match page_obj.get_dict_inheritable("Resources")? {
+        Some(resources) => resources,
+        None => {
+            page_obj.dict_put("Resources", pdf_doc.new_dict()?)?;
+            page_obj
+                .get_dict("Resources")?
+                .ok_or(mupdf::Error::InvalidPdfDocument)?
+        }
+    };
+    let mut xobjects = match resources.get_dict("XObject")? {
+        Some(xobjects) => xobjects,
+        None => {
+            resources.dict_put("XObject", pdf_doc.new_dict()?)?;
+            resources
+                .get_dict("XObject")?
+                .ok_or(mupdf::Error::InvalidPdfDocument)?
+        }
+    };
+
+    let mut operators = String::from("Q\n");
+    let mut next = 0;
+    for (mut appearance, [sx, sy, tx, ty]) in draws {
+        let name = loop {
+            next += 1;
+            let name = format!("FlatField{}", next);
+            if xobjects.get_dict(name.as_str())?.is_none() {
+                break name;
+            }
+        };
+        if appearance.get_dict("Subtype")?.is_none() {
+            appearance.dict_put("Subtype", pdf_doc.new_name("Form")?)?;
+        }
+        xobjects.dict_put(name.as_str(), appearance)?;
+        operators.push_str(&format!(
+            "q {} 0 0 {} {} {} cm /{} Do Q\n",
+            sx, sy, tx, ty, name
+        ));
+    }
+
+    // Wrap the existing content in q/Q so its graphics state can't leak
+    let mut contents = pdf_doc.new_array()?;
+    let mut save = pdf_doc.add_object(&pdf_doc.new_dict()?)?;
+    save.write_stream_string("q\n")?;
+    contents.array_push(save)?;
+    if let Some(existing) = page_obj.get_dict("Contents")? {
+        if existing.is_array()? {
+            for i in 0..existing.len()? {
+                if let Some(stream) = existing.get_array(i as i32)? {
+                    contents.array_push(stream)?;
+                }
+            }
+        } else {
+            contents.array_push(existing)?;
+        }
+    }
+    let mut restore = pdf_doc.add_object(&pdf_doc.new_dict()?)?;
+    restore.write_stream_string(&operators)?;
+    contents.array_push(restore)?;
+    page_obj.dict_put("Contents", contents)
+}
+
+/// The AcroForm dictionary of a document
+pub(super) fn acro_form(pdf_doc: &PdfDocument) -> Result<Option<PdfObject>, mupdf::Error> {
+    match pdf_doc.trailer()?.get_dict("Root")? {
+        Some(root) => root.get_dict("AcroForm"),
+        None => Ok(None),
+    }
+}
+
+/// Collect named fields below `obj`, keyed by full name
+pub(super) fn collect_fields(
+    obj: PdfObject,
+    parent: Option<&str>,
+    fields: &mut Vec<(String, PdfObject)>,
+) -> Result<(), mupdf::Error> {
+    let name = match obj.get_dict("T")? {
+        Some(t) => {
+            let t = t.as_string().unwrap_or("");
+            Some(match parent {
+                Some(parent) => format!("{}.{}", parent, t),
+                None => t.to_string(),
+            })
+        }
+        None => parent.map(str::to_string),
+    };
+
+    if let Some(kids) = obj.get_dict("Kids")? {
+        for i in 0..kids.len()? {
+            if let Some(kid) = kids.get_array(i as i32)? {
+                collect_fields(kid, name.as_deref(), fields)?;
+            }
+        }
+    }
+
+    if obj.get_dict("T")?.is_some() {
+        if let Some(name) = name {
+            fields.push((name, obj));
+        }
+    }
+    Ok(())
+}
+
+/// Find the field a request refers to, by full name first
+fn find_field<'a>(fields: &'a [FormField], name: &str) -> Option<&'a FormField> {
+    fields
+        .iter()
+        .find(|field| field.full_name.as_deref() == Some(name))
+        .or_else(|| fields.iter().find(|field| field.name == name))
+}
+
+/// The name a field is reported and looked up by
+pub(super) fn field_key(field: &FormField) -> &str {
+    field.full_name.as_deref().unwrap_or(&field.name)
+}
+
+/// A field's full name, from its `/T` and its parents'
+fn full_name(obj: &PdfObject) -> Result<String, mupdf::Error> {
+    let mut names = Vec::new();
+    let mut current = Some(obj.clone());
+    // Bounded, in case of a cyclic /Parent chain
+    for _ in 0..32 {
+        let Some(node) = current else {
+            break;
+        };
+        if let Some(t) = node.get_dict("T")? {
+            names.push(t.as_string().unwrap_or("").to_string());
+        }
+        current = node.get_dict("Parent")?;
+    }
+    names.reverse();
+    Ok(names.join("."))
+}
+
+/// A field's value as text
+fn field_text(obj: &PdfObject) -> Result<String, mupdf::Error> {
+    Ok(match obj.get_dict_inheritable("V")? {
+        Some(v) if v.is_string()? => v.as_string().unwrap_or("").to_string(),
+        Some(v) if v.is_name()? => String::from_utf8_lossy(v.as_name()?).to_string(),
+        Some(v) if v.is_number()? => v.as_float()?.to_string(),
+        _ => String::new(),
+    })
+}
+
+/// The calculation script of a field (`/AA /C /JS`)
+fn calculation_script(obj: &PdfObject) -> Result<Option<String>, mupdf::Error> {
+    let Some(js) = obj
+        .get_dict("AA")?
+        .and_then(|aa| aa.get_dict("C").ok().flatten())
+        .and_then(|c| c.get_dict("JS").ok().flatten())
+    else {
+        return Ok(None);
+    };
+    if js.is_stream()? {
+        Ok(Some(
+            String::from_utf8_lossy(&js.read_stream()?).to_string(),
+        ))
+    } else {
+        Ok(js.as_string().ok().map(str::to_string))
+    }
+}
+
+fn is_widget(obj: &PdfObject) -> Result<bool, mupdf::Error> {
+    Ok(match obj.get_dict("Subtype")? {
+        Some(subtype) => subtype.as_name()? == b"Widget",
+        None => false,
+    })
+}
+
+/// The widgets of a field: the field itself or its unnamed kids
+fn widgets(field_obj: &PdfObject) -> Result<Vec<PdfObject>, mupdf::Error> {
+    let mut widgets = Vec::new();
+    if is_widget(field_obj)? {
+        widgets.push(field_obj.clone());
+    }
+    if let Some(kids) = field_obj.get_dict("Kids")? {
+        for i in 0..kids.len()? {
+            if let Some(kid) = kids.get_array(i as i32)? {
+                if kid.get_dict("T")?.is_none() && is_widget(&kid)? {
+                    widgets.push(kid);
+                }
+            }
+        }
+    }
+    Ok(widgets)
+}
+
+/// The on-states of a widget, from its normal appearance dictionary
+fn widget_states(widget: &PdfObject) -> Result<Vec<String>, mupdf::Error> {
+    let mut states = Vec::new();
+    let Some(normal) = widget
+        .get_dict("AP")?
+        .and_then(|ap| ap.get_dict("N").ok().flatten())
+    else {
+        return Ok(states);
+    };
+    if !normal.is_dict()? {
+        return Ok(states);
+    }
+    for i in 0..normal.dict_len()? {
+        if let Some(key) = normal.get_dict_key(i as i32)? {
+            let state = String::from_utf8_lossy(key.as_name()?).to_string();
+            if state != OFF_STATE && !states.contains(&state) {
+                states.push(state);
+            }
+        }
+    }
+    Ok(states)
+}
+
+/// The on-states of all widgets of a button field
+fn button_states(field_obj: &PdfObject) -> Result<Vec<String>, mupdf::Error> {
+    let mut states = Vec::new();
+    for widget in widgets(field_obj)? {
+        for state in widget_states(&widget)? {
+            if !states.contains(&state) {
+                states.push(state);
+            }
+        }
+    }
+    Ok(states)
+}
+
+/// The normal appearance stream a widget currently shows
+fn appearance_stream(widget: &PdfObject) -> Result<Option<PdfObject>, mupdf::Error> {
+    let Some(normal) = widget
+        .get_dict("AP")?
+        .and_then(|ap| ap.get_dict("N").ok().flatten())
+    else {
+        return Ok(None);
+    };
+    if normal.is_stream()? {
+        return Ok(Some(normal));
+    }
+    match widget.get_dict("AS")? {
+        Some(state) if normal.is_dict()? => {
+            let state = String::from_utf8_lossy(state.as_name()?).to_string();
+            normal.get_dict(state.as_str())
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Read a number array of exactly `N` entries
+fn read_numbers<const N: usize>(
+    obj: &PdfObject,
+    key: &str,
+) -> Result<Option<[f32; N]>, mupdf::Error> {
+    let Some(array) = obj.get_dict(key)? else {
+        return Ok(None);
+    };
+    if !array.is_array()? || array.len()? != N {
+        return Ok(None);
+    }
+    let mut numbers = [0.0; N];
+    for (i, number) in numbers.iter_mut().enumerate() {
+        match array.get_array(i as i32)? {
+            Some(value) if value.is_number()? => *number = value.as_float()?,
+            _ => return Ok(None),
+        }
+    }
+    Ok(Some(numbers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::FormOption;
+
+    fn field(field_type: FormFieldType) -> FormField {
+        FormField {
+            name: "field".to_string(),
+            full_name: None,
+            field_type,
+            value: None,
+            default_value: None,
+            page: 1,
+            bounds: None,
+            read_only: false,
+            required: false,
+            options: None,
+            max_length: None,
+            multiline: None,
+            password: None,
+        }
+    }
+
+    fn states(states: &[&str]) -> Vec<String> {
+        states.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_validate_text() {
+        let mut text = field(FormFieldType::Text);
+        text.max_length = Some(5);
+        assert_eq!(
+            validate_value(&text, "hello", &[]),
+            Ok(FieldValue::Text("hello".to_string()))
+        );
+        assert!(validate_value(&text, "hello!", &[]).is_err());
+        assert!(validate_value(&text, "a\nb", &[]).is_err());
+
+        text.multiline = Some(true);
+        assert!(validate_value(&text, "a\nb", &[]).is_ok());
+
+        text.read_only = true;
+        assert!(validate_value(&text, "a", &[]).is_err());
+    }
+
+    #[test]
+    fn test_validate_required() {
+        let mut text = field(FormFieldType::Text);
+        text.required = true;
+        assert!(validate_value(&text, "  ", &[]).is_err());
+        assert!(validate_value(&text, "x", &[]).is_ok());
+    }
+
+    #[test]
+    fn test_validate_checkbox() {
+        let checkbox = field(FormFieldType::Checkbox);
+        let on = states(&["Agreed"]);
+        assert_eq!(
+            validate_value(&checkbox, "true", &on),
+            Ok(FieldValue::State("Agreed".to_string()))
+        );
+        assert_eq!(
+            validate_value(&checkbox, "Agreed", &on),
+            Ok(FieldValue::State("Agreed".to_string()))
+        );
+        assert_eq!(
+            validate_value(&checkbox, "no", &on),
+            Ok(FieldValue::State("Off".to_string()))
+        );
+        assert_eq!(
+            validate_value(&checkbox, "yes", &[]),
+            Ok(FieldValue::State("Yes".to_string()))
+        );
+        assert!(validate_value(&checkbox, "maybe", &on).is_err());
+    }
+
+    #[test]
+    fn test_validate_radio() {
+        let radio = field(FormFieldType::Radio);
+        let on = states(&["Small", "Large"]);
+        assert_eq!(
+            validate_value(&radio, "Large", &on),
+            Ok(FieldValue::State("Large".to_string()))
+        );
+        assert_eq!(
+            validate_value(&radio, "", &on),
+            Ok(FieldValue::State("Off".to_string()))
+        );
+        assert!(validate_value(&radio, "Medium", &on).is_err());
+    }
+
+    #[test]
+    fn test_validate_choice() {
+        let mut dropdown = field(FormFieldType::Dropdown);
+        assert!(validate_value(&dropdown, "anything", &[]).is_ok());
+
+        dropdown.options = Some(vec![
+            FormOption {
+                label: "Germany".to_string(),
+                value: Some("DE".to_string()),
+                selected: false,
+            },
+            FormOption {
+                label: "France".to_string(),
+                value: None,
+                selected: false,
+            },
+        ]);
+        assert_eq!(
+            validate_value(&dropdown, "Germany", &[]),
+            Ok(FieldValue::Text("DE".to_string()))
+        );
+        assert_eq!(
+            validate_value(&dropdown, "DE", &[]),
+            Ok(FieldValue::Text("DE".to_string()))
+        );
+        assert_eq!(
+            validate_value(&dropdown, "France", &[]),
+            Ok(FieldValue::Text("France".to_string()))
+        );
+        assert!(validate_value(&dropdown, "Spain", &[]).is_err());
+    }
+
+    #[test]
+    fn test_validate_unfillable() {
+        assert!(validate_value(&field(FormFieldType::Signature), "x", &[]).is_err());
+        assert!(validate_value(&field(FormFieldType::Button), "x", &[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_simple_calculation() {
+        assert_eq!(
+            parse_simple_calculation(r#"AFSimple_Calculate("SUM", new Array ("Qty1", "Qty2"));"#),
+            Some((
+                Calculation::Sum,
+                vec!["Qty1".to_string(), "Qty2".to_string()]
+            ))
+        );
+        assert_eq!(
+            parse_simple_calculation("AFSimple_Calculate('AVG', 'a, b.c')"),
+            Some((
+                Calculation::Average,
+                vec!["a".to_string(), "b.c".to_string()]
+            ))
+        );
+        assert_eq!(
+            parse_simple_calculation(r#"event.value = this.getField("a").value * 2;"#),
+            None
+        );
+        assert_eq!(
+            parse_simple_calculation(r#"AFSimple_Calculate("SUM", ["a"]); app.alert("x");"#),
+            None
+        );
+        assert_eq!(
+            parse_simple_calculation(r#"AFSimple_Calculate("MED", ["a"])"#),
+            None
+        );
+    }
+
+    #[test]
+    fn test_calculation_apply() {
+        let values = [2.0, 3.0, 7.0];
+        assert_eq!(Calculation::Sum.apply(&values), 12.0);
+        assert_eq!(Calculation::Product.apply(&values), 42.0);
+        assert_eq!(Calculation::Average.apply(&values), 4.0);
+        assert_eq!(Calculation::Minimum.apply(&values), 2.0);
+        assert_eq!(Calculation::Maximum.apply(&values), 7.0);
+        assert_eq!(Calculation::Maximum.apply(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(parse_number(" 1,250.5 "), 1250.5);
+        assert_eq!(parse_number("n/a"), 0.0);
+        assert_eq!(format_number(0.1 + 0.2), "0.3");
+        assert_eq!(format_number(12.0), "12");
+        assert_eq!(format_number(-0.0), "0");
+    }
+
+    #[test]
+    fn test_placement() {
+        // Identity matrix, bbox at the origin
+        assert_eq!(
+            placement(
+                [0.0, 0.0, 100.0, 20.0],
+                [1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+                [50.0, 700.0, 150.0, 720.0]
+            ),
+            Some([1.0, 1.0, 50.0, 700.0])
+        );
+        // Scaled down into a smaller rect
+        assert_eq!(
+            placement(
+                [0.0, 0.0, 100.0, 20.0],
+                [1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 50.0, 10.0]
+            ),
+            Some([0.5, 0.5, 0.0, 0.0])
+        );
+        assert_eq!(
+            placement(
+                [0.0, 0.0, 0.0, 20.0],
+                [1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 50.0, 10.0]
+            ),
+            None
+        );
+    }
+}
@@ -10,21 +10,29 @@
 //! - Actual font metadata extraction
 //! - Native page labels support
 //! - PDF annotation extraction (highlights, underlines, comments)
+//! - Writing stored annotations back into PDFs
+//! - Filling, recalculating and flattening AcroForm fields
+//! - Cryptographic validation of digital signatures
 
 pub mod annotation_extractor;
+pub mod annotation_writer;
 mod cache;
+mod form_filler;
 mod mupdf_parser;
+mod signature_validator;
 mod types;
 
 pub use annotation_extractor::{
     extract_annotations, ExtractedAnnotation, ExtractedAnnotationType, ExtractionOptions,
     ExtractionResult, ExtractionStats,
 };
+pub use annotation_writer::{WrittenAnnotation, WrittenAnnotationType};
 pub use cache::PdfCache;
 pub use mupdf_parser::{PdfParseError, PdfParser};
+pub use signature_validator::TrustAnchors;
 pub use types::{
     BoundingBox, CharPosition, FillFormRequest, FillFormResult, FormField, FormFieldType,
     FormInfo, FormOption, ImageFormat, NormalizedPosition, NormalizedRect, PageDimensions,
     PageOrientation, PageRenderRequest, ParsedPdf, PdfMetadata, PdfSearchResult, SignatureInfo,
-    SignatureValidationStatus, TextItem, TextLayer,
+    SignatureValidationStatus, SignerCertificate, TextItem, TextLayer,
 };
@@ -10,19 +10,27 @@
 //! - Actual font metadata extraction
 //! - Native page labels support
 
+use std::borrow::Cow;
 use std::path::Path;
 
-use mupdf::pdf::PdfDocument;
+use mupdf::pdf::{PdfDocument, PdfWriteOptions};
 use mupdf::{Colorspace, Document, Matrix, MetadataName, TextPageOptions};
 use thiserror::Error;
 
 use crate::document::TocEntry;
 
+use super::annotation_extractor::{
+    extract_pdf_annotations, ExtractedAnnotation, ExtractionOptions,
+};
+use super::annotation_writer::{write_annotations, WrittenAnnotation};
+use super::form_filler::{fill_form, flatten_form};
+use super::signature_validator::{validate_signatures, TrustAnchors};
+
 use super::types::{
-    BoundingBox, CharPosition, FormField, FormFieldType, FormInfo, FormOption, ImageFormat,
-    NormalizedPosition, NormalizedRect, PageDimensions, PageOrientation, PageRenderRequest,
-    ParsedPdf, PdfMetadata, PdfSearchResult, SignatureInfo, SignatureValidationStatus, TextItem,
-    TextLayer,
+    BoundingBox, CharPosition, FillFormRequest, FillFormResult, FormField, FormFieldType, FormInfo,
+    FormOption, ImageFormat, NormalizedPosition, NormalizedRect, PageDimensions, PageOrientation,
+    PageRenderRequest, ParsedPdf, PdfMetadata, PdfSearchResult, SignatureInfo,
+    SignatureValidationStatus, TextItem, TextLayer,
 };
 
 /// PDF parsing errors
@@ -1204,28 +1212,29 @@ impl PdfParser {
         false
     }
 
-    /// Get signature fields with basic information
+    /// Get signature fields, with each signature validated
     ///
-    /// Note: Full signature verification requires cryptographic validation
-    /// which is beyond the scope of basic PDF parsing.
-    pub fn get_signatures(&self) -> Result<Vec<SignatureInfo>, PdfParseError> {
+    /// Signatures are checked against the signed byte range and their
+    /// signer certificates against `trust_anchors`.
+    pub fn get_signatures(
+        &self,
+        trust_anchors: &TrustAnchors,
+    ) -> Result<Vec<SignatureInfo>, PdfParseError> {
         let form_info = self.get_form_info()?;
-        let mut signatures = Vec::new();
-
-        for field in form_info.fields {
-            if matches!(field.field_type, FormFieldType::Signature) {
-                signatures.push(SignatureInfo {
-                    signer_name: field.value.clone(),
-                    signing_time: None, // Would need to parse /M from signature dictionary
-                    location: None,     // Would need to parse /Location
-                    reason: None,       // Would need to parse /Reason
-                    covers_whole_document: false, // Would need byte range analysis
-                    validation_status: SignatureValidationStatus::NotVerified,
-                    page: field.page,
-                });
-            }
+        if !form_info
+            .fields
+            .iter()
+            .any(|field| matches!(field.field_type, FormFieldType::Signature))
+        {
+            return Ok(Vec::new());
         }
 
+        let pdf_doc = self.open_pdf_document()?;
+        let file = match &self.data {
+            PdfData::Bytes(data) => Cow::Borrowed(data.as_slice()),
+            PdfData::Path(path) => Cow::Owned(std::fs::read(path)?),
+        };
+        let signatures = validate_signatures(&pdf_doc, &file, &form_info.fields, trust_anchors)?;
         Ok(signatures)
     }
 
@@ -1235,6 +1244,72 @@ impl PdfParser {
             .map(|info| info.has_acro_form || info.has_xfa_form)
             .unwrap_or(false)
     }
+
+    /// Read the native annotations already in the PDF
+    pub fn extract_annotations(
+        &self,
+        options: &ExtractionOptions,
+    ) -> Result<Vec<ExtractedAnnotation>, PdfParseError> {
+        let pdf_doc = self.open_pdf_document()?;
+        let total_pages = pdf_doc.page_count()?.max(0) as u32;
+        extract_pdf_annotations(&pdf_doc, total_pages, options)
+            .map_err(|e| PdfParseError::MuPdfError(e.to_string()))
+    }
+
+    /// Export a copy of the PDF with annotations written into it
+    ///
+    /// Returns the new PDF and the number of annotations written. The
+    /// document this parser reads from is left unchanged.
+    pub fn export_with_annotations(
+        &self,
+        annotations: &[WrittenAnnotation],
+    ) -> Result<(Vec<u8>, usize), PdfParseError> {
+        let mut pdf_doc = self.open_pdf_document()?;
+        let written = write_annotations(&mut pdf_doc, annotations)?;
+
+        // Generate appearance streams for the new annotations
+        let mut options = PdfWriteOptions::default();
+        options.set_appearance(true);
+        let mut output = Vec::new();
+        pdf_doc.write_to_with_options(&mut output, options)?;
+        Ok((output, written))
+    }
+
+    /// Fill form fields and save the result as a new PDF
+    ///
+    /// Calculated fields are recalculated if the form needs it. With
+    /// `flatten`, the fields are drawn into the page content and the form is
+    /// removed. Returns no PDF if no field was updated. The document this
+    /// parser reads from is left unchanged.
+    pub fn fill_form(
+        &self,
+        requests: &[FillFormRequest],
+        flatten: bool,
+    ) -> Result<(FillFormResult, Option<Vec<u8>>), PdfParseError> {
+        let info = self.get_form_info()?;
+        let mut pdf_doc = self.open_pdf_document()?;
+        let mut result = fill_form(&mut pdf_doc, &info.fields, requests, info.needs_calculation)?;
+        if result.fields_updated == 0 {
+            return Ok((result, None));
+        }
+
+        // Generate appearance streams for the changed fields
+        let mut options = PdfWriteOptions::default();
+        options.set_appearance(true);
+        let mut output = Vec::new();
+        pdf_doc.write_to_with_options(&mut output, options)?;
+
+        if flatten {
+            // Flattening draws the appearance streams generated above
+            let mut flat_doc = PdfDocument::from_bytes(&output)?;
+            flatten_form(&mut flat_doc)?;
+            output.clear();
+            flat_doc.write_to(&mut output)?;
+            result.flattened = true;
+        }
+
+        Ok((result, Some(output)))
+    }
 }
 
 #[cfg(test)]
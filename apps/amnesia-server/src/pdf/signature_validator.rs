@@ -0,0 +1,860 @@
+//! PDF Signature Validator
+//!
+//! Cryptographically validates the digital signatures of a PDF.
+//!
+//! # Approach
+//!
+//! A signature field's value is a signature dictionary: `/Contents` holds a
+//! PKCS#7/CMS SignedData blob, and `/ByteRange` lists the parts of the file
+//! it signs (everything but `/Contents` itself). Each signature is checked in
+//! three steps:
+//!
+//! 1. The byte range must start at the beginning of the file and leave out
+//!    nothing but `/Contents`. If it ends before the end of the file,
+//!    revisions were appended after signing.
+//! 2. The signed digest and the signature over the byte range are verified
+//!    with OpenSSL. `adbe.pkcs7.detached` and `ETSI.CAdES.detached` sign the
+//!    byte range itself; `adbe.pkcs7.sha1` signs its SHA-1 digest.
+//! 3. The signer's certificate is chained to a trust anchor, using the
+//!    certificates embedded in the signature, at the `signingTime` from the
+//!    signature's signed attributes (or the current time without one). The
+//!    dictionary's `/M` date isn't signed, so a backdated `/M` could make an
+//!    expired certificate validate; it is only reported.
+//!
+//! Trust anchors are certificates loaded from a local directory; system
+//! roots, revocation (OCSP, CRL) and timestamp tokens are not consulted.
+
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use mupdf::pdf::{PdfDocument, PdfObject};
+use openssl::asn1::{Asn1Time, Asn1TimeRef};
+use openssl::error::ErrorStack;
+use openssl::hash::{hash, MessageDigest};
+use openssl::nid::Nid;
+use openssl::pkcs7::{Pkcs7, Pkcs7Flags};
+use openssl::stack::Stack;
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::verify::{X509VerifyFlags, X509VerifyParam};
+use openssl::x509::{X509NameRef, X509Ref, X509StoreContext, X509};
+
+use super::annotation_extractor::pdf_date_to_iso;
+use super::form_filler::{acro_form, collect_fields, field_key};
+use super::types::{
+    FormField, FormFieldType, SignatureInfo, SignatureValidationStatus, SignerCertificate,
+};
+
+/// OID of the CMS `signingTime` attribute (1.2.840.113549.1.9.5)
+const SIGNING_TIME_OID: [u8; 9] = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x09, 0x05];
+
+/// File extensions read from a trust anchor directory
+const CERTIFICATE_EXTENSIONS: [&str; 4] = ["pem", "crt", "cer", "der"];
+
+/// Trusted certificates that signer certificates are validated against
+#[derive(Clone, Default)]
+pub struct TrustAnchors {
+    certificates: Vec<X509>,
+}
+
+impl TrustAnchors {
+    /// Load every certificate in a directory
+    ///
+    /// Reads `.pem`, `.crt`, `.cer` and `.der` files, PEM files may hold
+    /// several certificates. Anchors don't have to be self-signed roots.
+    /// Files that can't be read or parsed are logged and skipped.
+    pub fn load_dir(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut certificates = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let is_certificate = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| CERTIFICATE_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+            if !is_certificate || !path.is_file() {
+                continue;
+            }
+
+            let data = match std::fs::read(&path) {
+                Ok(data) => data,
+                Err(e) => {
+                    tracing::warn!("Skipping trust anchor {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let parsed = if data.starts_with(b"-----BEGIN") {
+                X509::stack_from_pem(&data)
+            } else {
+                X509::from_der(&data).map(|cert| vec![cert])
+            };
+            match parsed {
+                Ok(parsed) => certificates.extend(parsed),
+                Err(e) => tracing::warn!("Skipping trust anchor {}: {}", path.display(), e),
+            }
+        }
+        Ok(Self { certificates })
+    }
+
+    /// Number of trusted certificates
+    pub fn len(&self) -> usize {
+        self.certificates.len()
+    }
+
+    /// Whether no certificates are trusted
+    pub fn is_empty(&self) -> bool {
+        self.certificates.is_empty()
+    }
+}
+
+/// Outcome of validating one signature
+#[derive(Debug, Default)]
+struct Validation {
+    covers_whole_document: bool,
+    modified_after_signing: bool,
+    digest_valid: Option<bool>,
+    signature_valid: Option<bool>,
+    certificate_trusted: Option<bool>,
+    certificate: Option<SignerCertificate>,
+    errors: Vec<String>,
+}
+
+impl Validation {
+    fn status(&self) -> SignatureValidationStatus {
+        if self.digest_valid == Some(false) || self.signature_valid == Some(false) {
+            return SignatureValidationStatus::Invalid;
+        }
+        match (self.signature_valid, self.certificate_trusted) {
+            (Some(true), Some(true)) => SignatureValidationStatus::Valid,
+            _ => SignatureValidationStatus::Unknown,
+        }
+    }
+}
+
+/// Validate the signatures of a PDF
+///
+/// `file` is the PDF `pdf_doc` was opened from, `fields` its form fields.
+/// Every signature field is reported; unsigned ones as `NotVerified`.
+pub fn validate_signatures(
+    pdf_doc: &PdfDocument,
+    file: &[u8],
+    fields: &[FormField],
+    trust_anchors: &TrustAnchors,
+) -> Result<Vec<SignatureInfo>, mupdf::Error> {
+    let mut field_objects = Vec::new();
+    if let Some(roots) = acro_form(pdf_doc)?.and_then(|form| form.get_dict("Fields").ok().flatten())
+    {
+        for i in 0..roots.len()? {
+            if let Some(root) = roots.get_array(i as i32)? {
+                collect_fields(root, None, &mut field_objects)?;
+            }
+        }
+    }
+
+    let mut signatures = Vec::new();
+    for field in fields {
+        if !matches!(field.field_type, FormFieldType::Signature) {
+            continue;
+        }
+        let key = field_key(field);
+        let signature = field_objects
+            .iter()
+            .find(|(name, _)| name.as_str() == key)
+            .map(|(_, obj)| obj.get_dict("V"))
+            .transpose()?
+            .flatten()
+            .filter(|v| v.is_dict().unwrap_or(false));
+
+        let info = match signature {
+            Some(signature) => signature_info(&signature, file, field.page, trust_anchors)?,
+            None => SignatureInfo {
+                signer_name: None,
+                signing_time: None,
+                location: None,
+                reason: None,
+                sub_filter: None,
+                covers_whole_document: false,
+                modified_after_signing: false,
+                validation_status: SignatureValidationStatus::NotVerified,
+                digest_valid: None,
+                signature_valid: None,
+                certificate_trusted: None,
+                certificate: None,
+                validation_errors: Vec::new(),
+                page: field.page,
+            },
+        };
+        signatures.push(info);
+    }
+
+    Ok(signatures)
+}
+
+/// Read and validate a signature dictionary
+fn signature_info(
+    signature: &PdfObject,
+    file: &[u8],
+    page: usize,
+    trust_anchors: &TrustAnchors,
+) -> Result<SignatureInfo, mupdf::Error> {
+    let text = |key: &str| -> Result<Option<String>, mupdf::Error> {
+        Ok(signature
+            .get_dict(key)?
+            .and_then(|v| v.as_string().ok().map(str::to_string))
+            .filter(|s| !s.is_empty()))
+    };
+    let signing_time = text("M")?.as_deref().and_then(pdf_date_to_iso);
+    let sub_filter = match signature.get_dict("SubFilter")? {
+        Some(name) => Some(String::from_utf8_lossy(name.as_name()?).to_string()),
+        None => None,
+    };
+
+    let mut byte_range = Vec::new();
+    if let Some(array) = signature.get_dict("ByteRange")? {
+        for i in 0..array.len()? {
+            if let Some(value) = array.get_array(i as i32)? {
+                byte_range.push(value.as_int()? as i64);
+            }
+        }
+    }
+    let contents = match signature.get_dict("Contents")? {
+        Some(contents) => contents.as_bytes()?.to_vec(),
+        None => Vec::new(),
+    };
+
+    let validation = validate(
+        file,
+        &byte_range,
+        &contents,
+        sub_filter.as_deref().unwrap_or(""),
+        trust_anchors,
+    );
+
+    Ok(SignatureInfo {
+        signer_name: text("Name")?.or_else(|| {
+            validation
+                .certificate
+                .as_ref()
+                .and_then(|cert| cert.common_name.clone())
+        }),
+        signing_time,
+        location: text("Location")?,
+        reason: text("Reason")?,
+        sub_filter,
+        covers_whole_document: validation.covers_whole_document,
+        modified_after_signing: validation.modified_after_signing,
+        validation_status: validation.status(),
+        digest_valid: validation.digest_valid,
+        signature_valid: validation.signature_valid,
+        certificate_trusted: validation.certificate_trusted,
+        certificate: validation.certificate,
+        validation_errors: validation.errors,
+        page,
+    })
+}
+
+/// Validate a signature over `file`
+fn validate(
+    file: &[u8],
+    byte_range: &[i64],
+    contents: &[u8],
+    sub_filter: &str,
+    trust_anchors: &TrustAnchors,
+) -> Validation {
+    let mut validation = Validation::default();
+
+    let ranges = match signed_ranges(byte_range, file.len()) {
+        Ok(ranges) => ranges,
+        Err(error) => {
+            validation.errors.push(error);
+            validation.digest_valid = Some(false);
+            return validation;
+        }
+    };
+    let end = ranges.last().map(|range| range.1).unwrap_or(0);
+    validation.modified_after_signing = !file[end..].iter().all(u8::is_ascii_whitespace);
+    let gaps_are_contents = ranges
+        .windows(2)
+        .all(|pair| is_hex_string(&file[pair[0].1..pair[1].0]));
+    validation.covers_whole_document = gaps_are_contents && !validation.modified_after_signing;
+    if !gaps_are_contents {
+        validation
+            .errors
+            .push("Byte range leaves out more than the signature".to_string());
+        validation.digest_valid = Some(false);
+        return validation;
+    }
+    if validation.modified_after_signing {
+        validation
+            .errors
+            .push("Document was modified after signing".to_string());
+    }
+
+    let signed: Vec<u8> = ranges
+        .iter()
+        .flat_map(|&(start, end)| file[start..end].iter().copied())
+        .collect();
+
+    if let Err(error) = verify(
+        &mut validation,
+        &signed,
+        contents,
+        sub_filter,
+        trust_anchors,
+    ) {
+        validation
+            .errors
+            .push(format!("Failed to verify signature: {}", error));
+    }
+    validation
+}
+
+/// Verify the signature blob and the signer's certificate
+fn verify(
+    validation: &mut Validation,
+    signed: &[u8],
+    contents: &[u8],
+    sub_filter: &str,
+    trust_anchors: &TrustAnchors,
+) -> Result<(), ErrorStack> {
+    let detached = match sub_filter {
+        "adbe.pkcs7.detached" | "ETSI.CAdES.detached" => true,
+        "adbe.pkcs7.sha1" => false,
+        _ => {
+            validation
+                .errors
+                .push(format!("Unsupported signature format '{}'", sub_filter));
+            return Ok(());
+        }
+    };
+
+    let blob = &contents[..der_length(contents).unwrap_or(contents.len())];
+    let pkcs7 = match Pkcs7::from_der(blob) {
+        Ok(pkcs7) => pkcs7,
+        Err(_) => {
+            validation
+                .errors
+                .push("Signature is not a PKCS#7 SignedData blob".to_string());
+            validation.signature_valid = Some(false);
+            return Ok(());
+        }
+    };
+
+    let no_certs = Stack::new()?;
+    let signer = pkcs7.signers(&no_certs, Pkcs7Flags::empty())?.pop();
+    let Some(signer) = signer else {
+        validation
+            .errors
+            .push("Signer certificate is missing".to_string());
+        return Ok(());
+    };
+    validation.certificate = Some(certificate_details(&signer)?);
+
+    // Digest and signature only; the chain is checked against the anchors below
+    let empty_store = X509StoreBuilder::new()?.build();
+    let flags = Pkcs7Flags::NOVERIFY | Pkcs7Flags::BINARY;
+    let mut content = Vec::new();
+    let verified = if detached {
+        pkcs7.verify(&no_certs, &empty_store, Some(signed), None, flags)
+    } else {
+        pkcs7.verify(&no_certs, &empty_store, None, Some(&mut content), flags)
+    };
+    match verified {
+        Ok(()) if detached => {
+            validation.digest_valid = Some(true);
+            validation.signature_valid = Some(true);
+        }
+        Ok(()) => {
+            // The signed content is the SHA-1 digest of the byte range
+            let digest = hash(MessageDigest::sha1(), signed)?;
+            validation.digest_valid = Some(content == *digest);
+            validation.signature_valid = Some(true);
+        }
+        Err(error) => {
+            let digest_failed = error
+                .errors()
+                .iter()
+                .any(|e| e.reason() == Some("digest failure"));
+            // The digest is checked before the signature
+            validation.digest_valid = Some(!digest_failed);
+            validation.signature_valid = Some(false);
+        }
+    }
+    if validation.digest_valid == Some(false) {
+        validation
+            .errors
+            .push("Signed content doesn't match the signature's digest".to_string());
+    } else if validation.signature_valid == Some(false) {
+        validation
+            .errors
+            .push("Signature doesn't match the signer's certificate".to_string());
+    }
+
+    if trust_anchors.is_empty() {
+        validation
+            .errors
+            .push("No trust anchors configured".to_string());
+        return Ok(());
+    }
+
+    let mut chain = Stack::new()?;
+    if let Some(certificates) = pkcs7.signed().and_then(|signed| signed.certificates()) {
+        for certificate in certificates {
+            chain.push(certificate.to_owned())?;
+        }
+    }
+    let mut store = X509StoreBuilder::new()?;
+    for anchor in &trust_anchors.certificates {
+        store.add_cert(anchor.clone())?;
+    }
+    let mut param = X509VerifyParam::new()?;
+    param.set_flags(X509VerifyFlags::PARTIAL_CHAIN)?;
+    // The signed attributes are only authentic if the signature is
+    if validation.signature_valid == Some(true) {
+        if let Some(signing_time) = signed_signing_time(blob) {
+            param.set_time(signing_time.timestamp());
+        }
+    }
+    store.set_param(&param)?;
+    let store = store.build();
+
+    let (trusted, result) = X509StoreContext::new()?.init(&store, &signer, &chain, |ctx| {
+        Ok((ctx.verify_cert()?, ctx.error()))
+    })?;
+    validation.certificate_trusted = Some(trusted);
+    if !trusted {
+        validation.errors.push(format!(
+            "Signer certificate is not trusted: {}",
+            result.error_string()
+        ));
+    }
+    Ok(())
+}
+
+/// Check a `/ByteRange` against the file length
+///
+/// Returns the signed ranges as `(start, end)` offsets.
+fn signed_ranges(byte_range: &[i64], file_len: usize) -> Result<Vec<(usize, usize)>, String> {
+    if byte_range.is_empty() || byte_range.len() % 2 != 0 {
+        return Err("Signature has no valid byte range".to_string());
+    }
+
+    let mut ranges = Vec::with_capacity(byte_range.len() / 2);
+    let mut previous_end = 0;
+    for pair in byte_range.chunks(2) {
+        let (Ok(start), Ok(len)) = (usize::try_from(pair[0]), usize::try_from(pair[1])) else {
+            return Err("Byte range has negative offsets".to_string());
+        };
+        let end = start
+            .checked_add(len)
+            .filter(|end| *end <= file_len)
+            .ok_or_else(|| "Byte range extends past the end of the file".to_string())?;
+        if start < previous_end {
+            return Err("Byte range overlaps itself".to_string());
+        }
+        ranges.push((start, end));
+        previous_end = end;
+    }
+
+    if ranges[0].0 != 0 {
+        return Err("Byte range doesn't start at the beginning of the file".to_string());
+    }
+    Ok(ranges)
+}
+
+/// Whether `bytes` is a PDF hex string, e.g. the excluded `/Contents`
+fn is_hex_string(bytes: &[u8]) -> bool {
+    match bytes {
+        [b'<', inner @ .., b'>'] => inner
+            .iter()
+            .all(|b| b.is_ascii_hexdigit() || b.is_ascii_whitespace()),
+        _ => false,
+    }
+}
+
+/// Length of the DER object at the start of `bytes`
+///
+/// `/Contents` is padded with zeros to a fixed size; this finds where the
+/// signature actually ends. `None` for indefinite or malformed lengths.
+fn der_length(bytes: &[u8]) -> Option<usize> {
+    let first = *bytes.get(1)?;
+    let (header, len) = if first < 0x80 {
+        (2, first as usize)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let len = bytes
+            .get(2..2 + count)?
+            .iter()
+            .fold(0usize, |len, b| (len << 8) | *b as usize);
+        (2 + count, len)
+    };
+    let total = header + len;
+    (total <= bytes.len()).then_some(total)
+}
+
+/// Split the DER element at the start of `bytes` into its tag, its contents
+/// and the bytes after it
+fn der_element(bytes: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let total = der_length(bytes)?;
+    let header = match bytes[1] {
+        first if first < 0x80 => 2,
+        first => 2 + (first & 0x7f) as usize,
+    };
+    Some((bytes[0], &bytes[header..total], &bytes[total..]))
+}
+
+/// `signingTime` from the first signer's signed attributes, if it has one
+///
+/// Walks ContentInfo → SignedData → SignerInfo → signedAttrs. Unlike `/M`,
+/// these attributes are covered by the signature.
+fn signed_signing_time(blob: &[u8]) -> Option<DateTime<Utc>> {
+    // ContentInfo: contentType, [0] SignedData
+    let (_, content_info, _) = der_element(blob)?;
+    let (_, _, content) = der_element(content_info)?;
+    let (_, explicit, _) = der_element(content)?;
+    let (_, mut signed_data, _) = der_element(explicit)?;
+
+    // SignedData ends with the signerInfos set
+    let signer_infos = loop {
+        let (_, contents, rest) = der_element(signed_data)?;
+        if rest.is_empty() {
+            break contents;
+        }
+        signed_data = rest;
+    };
+    let (_, mut signer_info, _) = der_element(signer_infos)?;
+
+    // SignerInfo: version, sid, digestAlgorithm, [0] signedAttrs, ...
+    let mut attributes = loop {
+        let (tag, contents, rest) = der_element(signer_info)?;
+        if tag == 0xa0 {
+            break contents;
+        }
+        signer_info = rest;
+    };
+    while !attributes.is_empty() {
+        let (_, attribute, rest) = der_element(attributes)?;
+        let (_, oid, values) = der_element(attribute)?;
+        if oid == SIGNING_TIME_OID {
+            let (_, values, _) = der_element(values)?;
+            let (tag, time, _) = der_element(values)?;
+            return der_time(tag, time);
+        }
+        attributes = rest;
+    }
+    None
+}
+
+/// Parse a DER UTCTime or GeneralizedTime
+fn der_time(tag: u8, time: &[u8]) -> Option<DateTime<Utc>> {
+    let time = std::str::from_utf8(time).ok()?;
+    let time = match tag {
+        // UTCTime years 50-99 are 19xx
+        0x17 if time.get(..2)? >= "50" => format!("19{}", time),
+        0x17 => format!("20{}", time),
+        0x18 => time.to_string(),
+        _ => return None,
+    };
+    NaiveDateTime::parse_from_str(&time, "%Y%m%d%H%M%SZ")
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+/// Describe the signer's certificate
+fn certificate_details(certificate: &X509Ref) -> Result<SignerCertificate, ErrorStack> {
+    let common_name = certificate
+        .subject_name()
+        .entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .and_then(|entry| entry.data().to_string().ok());
+
+    Ok(SignerCertificate {
+        subject: distinguished_name(certificate.subject_name()),
+        common_name,
+        issuer: distinguished_name(certificate.issuer_name()),
+        serial_number: certificate
+            .serial_number()
+            .to_bn()?
+            .to_hex_str()?
+            .to_string(),
+        not_before: asn1_time_to_iso(certificate.not_before())?,
+        not_after: asn1_time_to_iso(certificate.not_after())?,
+        fingerprint_sha256: hex::encode(certificate.digest(MessageDigest::sha256())?),
+    })
+}
+
+/// Format a name as `CN=..., O=...`
+fn distinguished_name(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry.data().to_string().unwrap_or_default();
+            format!("{}={}", key, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Format an ASN.1 time as ISO 8601
+fn asn1_time_to_iso(time: &Asn1TimeRef) -> Result<String, ErrorStack> {
+    let diff = Asn1Time::from_unix(0)?.diff(time)?;
+    let seconds = diff.days as i64 * 86_400 + diff.secs as i64;
+    Ok(DateTime::from_timestamp(seconds, 0)
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::asn1::Asn1Integer;
+    use openssl::bn::BigNum;
+    use openssl::pkey::{PKey, Private};
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509Builder, X509NameBuilder};
+
+    fn certificate(name: &str) -> (X509, PKey<Private>) {
+        certificate_valid(name, 1_600_000_000, 2_000_000_000)
+    }
+
+    /// Self-signed certificate valid between two Unix times
+    fn certificate_valid(name: &str, not_before: i64, not_after: i64) -> (X509, PKey<Private>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_text("CN", name).unwrap();
+        let subject = subject.build();
+
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = Asn1Integer::from_bn(&BigNum::from_u32(42).unwrap()).unwrap();
+        builder.set_serial_number(&serial).unwrap();
+        builder.set_subject_name(&subject).unwrap();
+        builder.set_issuer_name(&subject).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::from_unix(not_before).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::from_unix(not_after).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        (builder.build(), key)
+    }
+
+    /// A fake signed file: `head <hex signature> tail`, and its byte range
+    fn signed_file(cert: &X509, key: &PKey<Private>) -> (Vec<u8>, Vec<i64>, Vec<u8>) {
+        let head = b"%PDF-1.7 signed head ".to_vec();
+        let tail = b" signed tail %%EOF\n".to_vec();
+        let signed = [head.clone(), tail.clone()].concat();
+
+        let flags = Pkcs7Flags::DETACHED | Pkcs7Flags::BINARY;
+        let no_certs = Stack::new().unwrap();
+        let der = Pkcs7::sign(cert, key, &no_certs, &signed, flags)
+            .unwrap()
+            .to_der()
+            .unwrap();
+        // Padded like a real /Contents placeholder
+        let mut contents = der.clone();
+        contents.resize(der.len() + 64, 0);
+
+        let placeholder = format!("<{}>", hex::encode(&contents));
+        let file = [head.clone(), placeholder.clone().into_bytes(), tail.clone()].concat();
+        let byte_range = vec![
+            0,
+            head.len() as i64,
+            (head.len() + placeholder.len()) as i64,
+            tail.len() as i64,
+        ];
+        (file, byte_range, contents)
+    }
+
+    #[test]
+    fn test_valid_trusted_signature() {
+        let (cert, key) = certificate("Jane Signer");
+        let (file, byte_range, contents) = signed_file(&cert, &key);
+        let anchors = TrustAnchors {
+            certificates: vec![cert],
+        };
+
+        let validation = validate(
+            &file,
+            &byte_range,
+            &contents,
+            "adbe.pkcs7.detached",
+            &anchors,
+        );
+        assert_eq!(validation.status(), SignatureValidationStatus::Valid);
+        assert!(validation.covers_whole_document);
+        assert!(!validation.modified_after_signing);
+        assert!(validation.errors.is_empty(), "{:?}", validation.errors);
+
+        let certificate = validation.certificate.unwrap();
+        assert_eq!(certificate.common_name.as_deref(), Some("Jane Signer"));
+        assert_eq!(certificate.subject, "CN=Jane Signer");
+        assert_eq!(certificate.serial_number, "2A");
+        assert_eq!(certificate.not_before, "2020-09-13T12:26:40+00:00");
+    }
+
+    #[test]
+    fn test_tampered_content() {
+        let (cert, key) = certificate("Jane Signer");
+        let (mut file, byte_range, contents) = signed_file(&cert, &key);
+        file[10] = b'X';
+        let anchors = TrustAnchors {
+            certificates: vec![cert],
+        };
+
+        let validation = validate(
+            &file,
+            &byte_range,
+            &contents,
+            "ETSI.CAdES.detached",
+            &anchors,
+        );
+        assert_eq!(validation.status(), SignatureValidationStatus::Invalid);
+        assert_eq!(validation.digest_valid, Some(false));
+    }
+
+    #[test]
+    fn test_untrusted_signer() {
+        let (cert, key) = certificate("Jane Signer");
+        let (other, _) = certificate("Someone Else");
+        let (file, byte_range, contents) = signed_file(&cert, &key);
+
+        let validation = validate(
+            &file,
+            &byte_range,
+            &contents,
+            "adbe.pkcs7.detached",
+            &TrustAnchors::default(),
+        );
+        assert_eq!(validation.status(), SignatureValidationStatus::Unknown);
+        assert_eq!(validation.signature_valid, Some(true));
+        assert_eq!(validation.certificate_trusted, None);
+
+        let anchors = TrustAnchors {
+            certificates: vec![other],
+        };
+        let validation = validate(
+            &file,
+            &byte_range,
+            &contents,
+            "adbe.pkcs7.detached",
+            &anchors,
+        );
+        assert_eq!(validation.status(), SignatureValidationStatus::Unknown);
+        assert_eq!(validation.certificate_trusted, Some(false));
+    }
+
+    #[test]
+    fn test_modified_after_signing() {
+        let (cert, key) = certificate("Jane Signer");
+        let (mut file, byte_range, contents) = signed_file(&cert, &key);
+        file.extend_from_slice(b"1 0 obj << >> endobj\n%%EOF\n");
+        let anchors = TrustAnchors {
+            certificates: vec![cert],
+        };
+
+        let validation = validate(
+            &file,
+            &byte_range,
+            &contents,
+            "adbe.pkcs7.detached",
+            &anchors,
+        );
+        // The signed revision itself is intact
+        assert_eq!(validation.status(), SignatureValidationStatus::Valid);
+        assert!(validation.modified_after_signing);
+        assert!(!validation.covers_whole_document);
+    }
+
+    #[test]
+    fn test_expired_signer() {
+        let (cert, key) = certificate_valid("Jane Signer", 1_400_000_000, 1_500_000_000);
+        let (file, byte_range, contents) = signed_file(&cert, &key);
+        let anchors = TrustAnchors {
+            certificates: vec![cert],
+        };
+
+        // Checked at the signed signingTime (now), not at a claimed /M
+        let validation = validate(
+            &file,
+            &byte_range,
+            &contents,
+            "adbe.pkcs7.detached",
+            &anchors,
+        );
+        assert_eq!(validation.signature_valid, Some(true));
+        assert_eq!(validation.certificate_trusted, Some(false));
+    }
+
+    #[test]
+    fn test_signed_signing_time() {
+        let (cert, key) = certificate("Jane Signer");
+        let (_, _, contents) = signed_file(&cert, &key);
+
+        let signing_time = signed_signing_time(&contents).unwrap();
+        assert!((Utc::now() - signing_time).num_seconds().abs() < 300);
+        assert_eq!(signed_signing_time(&[0x30, 0x00]), None);
+    }
+
+    #[test]
+    fn test_der_time() {
+        let time = |s: &str| der_time(0x17, s.as_bytes()).map(|t| t.to_rfc3339());
+        assert_eq!(
+            time("260101120000Z").as_deref(),
+            Some("2026-01-01T12:00:00+00:00")
+        );
+        assert_eq!(
+            time("991231235959Z").as_deref(),
+            Some("1999-12-31T23:59:59+00:00")
+        );
+        assert_eq!(
+            der_time(0x18, b"20260101120000Z").map(|t| t.timestamp()),
+            Some(1_767_268_800)
+        );
+        assert_eq!(der_time(0x04, b"20260101120000Z"), None);
+    }
+
+    #[test]
+    fn test_load_dir_skips_bad_files() {
+        let (cert, _) = certificate("Jane Signer");
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("good.pem"), cert.to_pem().unwrap()).unwrap();
+        std::fs::write(dir.path().join("bad.crt"), b"not a certificate").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"ignored").unwrap();
+
+        let anchors = TrustAnchors::load_dir(dir.path()).unwrap();
+        assert_eq!(anchors.len(), 1);
+    }
+
+    #[test]
+    fn test_signed_ranges() {
+        assert_eq!(
+            signed_ranges(&[0, 10, 20, 5], 25),
+            Ok(vec![(0, 10), (20, 25)])
+        );
+        assert!(signed_ranges(&[], 25).is_err());
+        assert!(signed_ranges(&[0, 10, 20], 25).is_err());
+        assert!(signed_ranges(&[0, 10, 20, 10], 25).is_err());
+        assert!(signed_ranges(&[5, 10, 20, 5], 25).is_err());
+        assert!(signed_ranges(&[0, 10, 5, 5], 25).is_err());
+        assert!(signed_ranges(&[0, -1, 20, 5], 25).is_err());
+    }
+
+    #[test]
+    fn test_der_length() {
+        assert_eq!(der_length(&[0x30, 0x03, 1, 2, 3, 0, 0]), Some(5));
+        assert_eq!(der_length(&[0x30, 0x82, 0x00, 0x02, 1, 2, 0]), Some(6));
+        assert_eq!(der_length(&[0x30, 0x80, 1, 2]), None);
+        assert_eq!(der_length(&[0x30, 0x05, 1]), None);
+    }
+
+    #[test]
+    fn test_is_hex_string() {
+        assert!(is_hex_string(b"<3082ab00>"));
+        assert!(!is_hex_string(b"<3082> extra"));
+        assert!(!is_hex_string(b"3082"));
+    }
+}
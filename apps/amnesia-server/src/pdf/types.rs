@@ -368,6 +368,11 @@ pub struct FillFormResult {
     pub errors: Vec<String>,
     /// Whether the form was recalculated
     pub recalculated: bool,
+    /// Whether the fields were flattened into page content
+    pub flattened: bool,
+    /// Storage key of the saved revision
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision_key: Option<String>,
 }
 
 /// Digital signature information
@@ -377,7 +382,7 @@ pub struct SignatureInfo {
     /// Signer name (from /Name key)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub signer_name: Option<String>,
-    /// Signing time (from /M key)
+    /// Signing time claimed by the /M key (unsigned; not used for validation)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub signing_time: Option<String>,
     /// Location (from /Location key)
@@ -386,14 +391,56 @@ pub struct SignatureInfo {
     /// Reason for signing (from /Reason key)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reason: Option<String>,
+    /// Signature format (from /SubFilter key)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub_filter: Option<String>,
     /// Whether the signature covers the whole document
     pub covers_whole_document: bool,
+    /// Whether revisions were appended to the document after signing
+    #[serde(default)]
+    pub modified_after_signing: bool,
     /// Signature validation status
     pub validation_status: SignatureValidationStatus,
+    /// Whether the signed content matches the signed digest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest_valid: Option<bool>,
+    /// Whether the signature matches the signer's certificate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature_valid: Option<bool>,
+    /// Whether the signer's certificate chains to a trust anchor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate_trusted: Option<bool>,
+    /// Signer's certificate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<SignerCertificate>,
+    /// Why the signature isn't valid, or what couldn't be checked
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub validation_errors: Vec<String>,
     /// Page containing the signature (1-indexed)
     pub page: usize,
 }
 
+/// Certificate of a signature's signer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignerCertificate {
+    /// Subject distinguished name
+    pub subject: String,
+    /// Subject common name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub common_name: Option<String>,
+    /// Issuer distinguished name
+    pub issuer: String,
+    /// Serial number (hex)
+    pub serial_number: String,
+    /// Start of validity (ISO 8601)
+    pub not_before: String,
+    /// End of validity (ISO 8601)
+    pub not_after: String,
+    /// SHA-256 fingerprint (hex)
+    pub fingerprint_sha256: String,
+}
+
 /// Signature validation status
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
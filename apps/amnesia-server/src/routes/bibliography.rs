@@ -14,7 +14,9 @@ use serde::{Deserialize, Serialize};
 use crate::bibliography::{
     generate_bibtex, generate_citation, generate_citation_list, BookMetadata, CitationFormat,
 };
+use crate::db::CatalogRepository;
 use crate::error::{AppError, Result};
+use crate::library::LibraryBook;
 use crate::state::AppState;
 
 /// Create the bibliography router
@@ -204,8 +206,12 @@ async fn list_formats() -> Json<FormatsResponse> {
     })
 }
 
-/// Fetch book metadata from database
+/// Fetch book metadata from the library catalog, then the books table
 async fn get_book_metadata(state: &AppState, book_id: &str) -> Result<BookMetadata> {
+    if let Some(book) = CatalogRepository::new(state.db()).get(book_id).await? {
+        return Ok(catalog_metadata(book));
+    }
+
     // Query book from database
     let book: Option<BookRow> = sqlx::query_as(
         r#"
@@ -256,6 +262,40 @@ async fn get_book_metadata(state: &AppState, book_id: &str) -> Result<BookMetada
     })
 }
 
+/// Citation metadata for a catalog book
+fn catalog_metadata(book: LibraryBook) -> BookMetadata {
+    let identifier = |scheme: &str| {
+        book.identifiers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(scheme))
+            .map(|(_, value)| value.clone())
+    };
+
+    let authors = if book.authors.is_empty() {
+        book.author.iter().cloned().collect()
+    } else {
+        book.authors.clone()
+    };
+
+    BookMetadata {
+        year: book
+            .pubdate
+            .as_deref()
+            .and_then(|date| date.get(..4))
+            .and_then(|year| year.parse().ok()),
+        isbn: identifier("isbn"),
+        doi: identifier("doi"),
+        url: identifier("url"),
+        publisher: book.publisher,
+        series: book.series,
+        volume: book.series_index.map(|index| index.to_string()),
+        language: book.language,
+        abstract_text: book.description,
+        keywords: book.tags,
+        ..BookMetadata::new(book.id, book.title, authors)
+    }
+}
+
 /// Database row for book query
 #[derive(sqlx::FromRow)]
 struct BookRow {
@@ -0,0 +1,81 @@
+//! Cover routes
+//!
+//! `GET /:id?size=thumbnail|small|medium|large|original|<px>&format=webp|jpeg`
+//! serves a book's cover, extracting it from the book on first request.
+//! Without `format`, WebP is served to clients that accept it and JPEG to
+//! everything else.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+
+use crate::document::ImageFormat;
+use crate::error::{AppError, Result};
+use crate::library::CoverSize;
+use crate::state::AppState;
+
+/// Create the covers router
+pub fn router() -> Router<AppState> {
+    Router::new().route("/:id", get(get_cover))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CoverQuery {
+    /// Named size or longest edge in pixels (default: original)
+    pub size: Option<String>,
+    /// `webp` or `jpeg`
+    pub format: Option<String>,
+}
+
+/// Serve a book's cover
+///
+/// GET /covers/:id?size=thumbnail
+async fn get_cover(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<CoverQuery>,
+    headers: HeaderMap,
+) -> Result<Response> {
+    let size = match query.size {
+        Some(ref size) => size.parse::<CoverSize>()?,
+        None => CoverSize::default(),
+    };
+
+    let format = match query.format.as_deref().map(str::to_lowercase).as_deref() {
+        Some("webp") => ImageFormat::Webp,
+        Some("jpeg" | "jpg") => ImageFormat::Jpeg,
+        Some(other) => return Err(AppError::BadRequest(format!("Invalid cover format: {}", other))),
+        None => {
+            let accepts_webp = headers
+                .get(header::ACCEPT)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|accept| accept.contains("image/webp"));
+            if accepts_webp {
+                ImageFormat::Webp
+            } else {
+                ImageFormat::Jpeg
+            }
+        }
+    };
+
+    let cover = state
+        .covers()
+        .cover(&id, size, format)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No cover for book: {}", id)))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, cover.format.content_type()),
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+            (header::VARY, "Accept"),
+        ],
+        cover.data,
+    )
+        .into_response())
+}
@@ -1,18 +1,49 @@
 //! Unified Document API endpoints
 //!
 //! Provides format-agnostic REST API for document management:
-//! - Upload documents (PDF, EPUB)
+//! - Upload documents (PDF, EPUB, FB2, MOBI/AZW3, XPS, CBZ/CB7 comics)
 //! - List documents
 //! - Get document metadata and TOC
 //! - Render items (pages/chapters)
 //! - Get structured text with positions
 //! - Search content with bounding boxes
 //! - Get embedded resources (CSS, images, fonts, XHTML chapters)
+//! - Convert to other formats (PDF, EPUB, KEPUB, plain text)
+//! - Map EPUB CFIs to laid-out pages
+//! - Get EPUB chapters as sanitized HTML with highlights
 //!
 //! This is the unified API that replaces separate `/books` and `/pdf` endpoints.
 //! It uses the `DocumentParser` and `DocumentRenderer` traits for format-agnostic
 //! operations.
 //!
+//! ## Persistence
+//!
+//! Uploaded files are written to object storage under `books/{id}/` and recorded
+//! in the `books` table. Parsers and renderers live in `DocumentCache` and are
+//! re-hydrated from storage on first access, so documents survive restarts and
+//! are shared between replicas.
+//!
+//! ## Layout
+//!
+//! Reflowable documents (EPUB, FB2, MOBI) are laid out into pages. The
+//! detail, render, text, thumbnail and search endpoints take `width`,
+//! `height` (points) and `em` (base font size, points) query parameters;
+//! item indices and counts are for that layout, and each layout is cached
+//! separately. Without them the default layout is used. Fixed-layout formats
+//! ignore the parameters.
+//!
+//! Page numbers change with the layout, so reading positions should be kept
+//! as CFIs. `GET /api/v1/documents/:id/pages` lists the CFI each laid-out page
+//! of an EPUB starts at, and `GET /api/v1/documents/:id/locate?cfi=...` finds
+//! the page containing a CFI.
+//!
+//! ## Conversion
+//!
+//! `POST /api/v1/documents/:id/convert?to=kepub` converts the stored file and
+//! returns the result. Converted files are kept in object storage under
+//! `.amnesia/conversions/{sha256}/` (outside the library scan), keyed by the source file's hash and the target
+//! (plus the layout, for PDF), so each conversion runs once per file.
+//!
 //! ## EPUB Content Access
 //!
 //! For EPUBs, the resources endpoint supports accessing raw XHTML chapter content:
@@ -30,25 +61,40 @@
 //! - Exact match first (e.g., "OEBPS/Styles/style.css")
 //! - Path suffix match (e.g., "Styles/style.css" → "OEBPS/Styles/style.css")
 //! - Filename match (e.g., "style.css" → any file named style.css)
+//!
+//! `GET /api/v1/documents/:id/items/:index/html?user_id=...` returns spine
+//! item `index` ready to display: scripts and event handlers stripped,
+//! resource URLs pointing at the resources endpoint, and the user's stored
+//! annotations injected as highlight spans. Annotations that can't be
+//! anchored in the chapter are listed in the response.
 
 use axum::{
     body::Body,
     extract::{DefaultBodyLimit, Multipart, Path, Query, State},
     http::{header, StatusCode},
     response::Response,
-    routing::{delete, get, post},
+    routing::{get, post},
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
+use crate::annotations::{Annotation, AnnotationQuery, AnnotationRepository, AnnotationType};
+use crate::cfi::{self, Cfi, CfiBuilder};
+use crate::db::{BookRecord, BookRepository, CatalogRepository, CreateBook};
 use crate::document::{
-    DocumentFormat, DocumentParser, DocumentRenderer, ImageFormat, ParsedDocument, RenderRequest,
-    SearchOptions, StructuredText, TocEntry,
+    ContentPosition, DocumentError, DocumentFormat, DocumentRenderer, ImageFormat,
+    LayoutConfig, ParsedDocument, RenderRequest, SearchOptions, StructuredText, TocEntry,
 };
-use crate::formats::epub::EpubDocumentHandler;
-use crate::formats::pdf::PdfDocumentHandler;
+use crate::error::{AppError, StorageError};
+use crate::formats::convert::{convert, ConversionTarget};
+use crate::formats::{open_document, open_document_with_layout};
+use crate::html::{inject_highlights, rewrite_urls, sanitize_html, HighlightConfig};
+use crate::library::{LibraryChanges, RESERVED_PREFIX};
+use crate::routes::files::content_disposition;
 use crate::state::AppState;
+use crate::upload::compute_hash;
+use crate::upload::ingest::catalog_document;
 
 // ============================================================================
 // Input Validation Constants
@@ -66,6 +112,12 @@ const MAX_SEARCH_LIMIT: usize = 1000;
 const MAX_CONTEXT_LENGTH: usize = 500;
 /// Maximum thumbnail dimension
 const MAX_THUMBNAIL_SIZE: u32 = 2048;
+/// Page size limits for laying out reflowable documents, in points
+const MIN_LAYOUT_SIZE: f32 = 100.0;
+const MAX_LAYOUT_SIZE: f32 = 5000.0;
+/// Em size limits for laying out reflowable documents, in points
+const MIN_LAYOUT_EM: f32 = 4.0;
+const MAX_LAYOUT_EM: f32 = 72.0;
 
 /// Response for document list
 #[derive(Serialize)]
@@ -99,7 +151,12 @@ pub struct DocumentDetailResponse {
     pub date: Option<String>,
     pub toc: Vec<TocEntry>,
     pub item_count: usize,
+    /// Layout `toc` and `item_count` are for; null for fixed-layout formats
+    pub layout: Option<LayoutConfig>,
     pub has_text_layer: bool,
+    /// Where to download the original file: a presigned storage URL when
+    /// `presigned_urls` is enabled, otherwise the `/files` route
+    pub download_url: String,
 }
 
 /// Creator info response
@@ -208,6 +265,74 @@ fn default_thumbnail_size() -> u32 {
     200
 }
 
+/// Query parameters for format conversion
+#[derive(Debug, Deserialize)]
+pub struct ConvertQuery {
+    /// Target format (pdf, epub, kepub, txt)
+    pub to: String,
+    /// Page width in points, for PDF output
+    pub width: Option<f32>,
+    /// Page height in points, for PDF output
+    pub height: Option<f32>,
+    /// Em (base font) size in points, for PDF output
+    pub em: Option<f32>,
+}
+
+impl ConvertQuery {
+    /// Layout for reflowable sources, defaults filled in and clamped
+    fn layout(&self) -> LayoutConfig {
+        clamped_layout(self.width, self.height, self.em)
+    }
+}
+
+/// Layout query parameters for reflowable documents
+///
+/// Extracted alongside an endpoint's own query; all are optional.
+#[derive(Debug, Deserialize)]
+pub struct LayoutQuery {
+    /// Page width in points
+    pub width: Option<f32>,
+    /// Page height in points
+    pub height: Option<f32>,
+    /// Em (base font) size in points
+    pub em: Option<f32>,
+}
+
+impl LayoutQuery {
+    /// Requested layout, if any parameter was given
+    fn layout(&self) -> Option<LayoutConfig> {
+        (self.width.is_some() || self.height.is_some() || self.em.is_some())
+            .then(|| clamped_layout(self.width, self.height, self.em))
+    }
+}
+
+/// Layout with defaults filled in and sizes clamped to sane limits
+///
+/// Non-finite values (`NaN`, infinities) fall back to the defaults, since
+/// `clamp` would let `NaN` through.
+fn clamped_layout(width: Option<f32>, height: Option<f32>, em: Option<f32>) -> LayoutConfig {
+    let default = LayoutConfig::default();
+    let finite = |v: Option<f32>| v.filter(|v| v.is_finite());
+    LayoutConfig {
+        width: finite(width)
+            .unwrap_or(default.width)
+            .clamp(MIN_LAYOUT_SIZE, MAX_LAYOUT_SIZE),
+        height: finite(height)
+            .unwrap_or(default.height)
+            .clamp(MIN_LAYOUT_SIZE, MAX_LAYOUT_SIZE),
+        em: finite(em)
+            .unwrap_or(default.em)
+            .clamp(MIN_LAYOUT_EM, MAX_LAYOUT_EM),
+    }
+}
+
+/// Query parameters for locating a CFI
+#[derive(Debug, Deserialize)]
+pub struct LocateQuery {
+    /// EPUB CFI; a range is located by its start
+    pub cfi: String,
+}
+
 /// Search result response
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -215,6 +340,8 @@ pub struct SearchResultResponse {
     pub results: Vec<SearchHit>,
     pub total: usize,
     pub query: String,
+    /// Item count of the layout the hits' item indices are for
+    pub item_count: usize,
 }
 
 /// Individual search hit
@@ -237,60 +364,324 @@ pub struct BoundingBoxResponse {
     pub height: f32,
 }
 
-/// Cached document entry containing all related data
-/// Using a single struct prevents race conditions between separate maps
-struct CachedDocument {
-    parser: Arc<dyn DocumentParser>,
-    renderer: Arc<dyn DocumentRenderer>,
-    metadata: ParsedDocument,
+/// Query parameters for a spine item's HTML
+#[derive(Debug, Deserialize)]
+pub struct ItemHtmlQuery {
+    /// User whose annotations are injected; required, so one user's
+    /// highlights are never shown to another
+    user_id: String,
 }
 
-/// In-memory document store (temporary until we integrate with the unified cache)
-/// This is a placeholder - in production this would use DocumentCache
-struct DocumentStore {
-    /// Single map for all document data - ensures atomic inserts/lookups
-    entries: tokio::sync::RwLock<std::collections::HashMap<String, CachedDocument>>,
+/// A spine item's XHTML with highlights injected
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemHtmlResponse {
+    pub spine_index: usize,
+    /// Path of the content document inside the EPUB
+    pub href: String,
+    pub html: String,
+    pub injected_count: usize,
+    /// IDs of annotations on the item that couldn't be anchored
+    pub failed_annotations: Vec<String>,
 }
 
-impl DocumentStore {
-    fn new() -> Self {
-        Self {
-            entries: tokio::sync::RwLock::new(std::collections::HashMap::new()),
-        }
+/// Where each laid-out page of an EPUB starts
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageMapResponse {
+    pub item_count: usize,
+    pub layout: Option<LayoutConfig>,
+    pub pages: Vec<PagePosition>,
+}
+
+/// Start of a laid-out page
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PagePosition {
+    pub item_index: usize,
+    pub spine_index: usize,
+    pub cfi: String,
+    /// How far through the spine item the page starts (0.0 to 1.0)
+    pub progression: f32,
+}
+
+/// Page containing a CFI
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocateResponse {
+    pub item_index: usize,
+    pub item_count: usize,
+    pub layout: Option<LayoutConfig>,
+    /// Where the page starts
+    pub page_cfi: String,
+}
+
+/// A document resolved through the persistent store and loaded into `DocumentCache`
+struct LoadedDocument {
+    storage_key: String,
+    parsed: ParsedDocument,
+    renderer: Arc<dyn DocumentRenderer>,
+    /// Layout variant the document was loaded with; `None` for its own layout
+    layout: Option<LayoutConfig>,
+}
+
+impl LoadedDocument {
+    /// Layout the document's pages are laid out with, if reflowable
+    fn laid_out_with(&self) -> Option<LayoutConfig> {
+        self.layout
+            .or_else(|| self.parsed.format.is_reflowable().then(LayoutConfig::default))
     }
+}
+
+/// Build an error tuple for a failed document operation
+fn document_error(
+    context: impl Into<String>,
+    error: DocumentError,
+) -> (StatusCode, Json<ErrorResponse>) {
+    let status = match error {
+        DocumentError::NotFound(_)
+        | DocumentError::ItemNotFound(_)
+        | DocumentError::ResourceNotFound(_) => StatusCode::NOT_FOUND,
+        DocumentError::UnsupportedFormat(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        DocumentError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (
+        status,
+        Json(ErrorResponse::with_details(context, error.to_string())),
+    )
+}
+
+/// Build an error tuple for a failed database or storage operation
+fn app_error(context: impl Into<String>, error: AppError) -> (StatusCode, Json<ErrorResponse>) {
+    let status = match error {
+        AppError::Storage(StorageError::ObjectNotFound(_)) => StatusCode::NOT_FOUND,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    tracing::error!("{}: {}", context.into(), error);
+    (
+        status,
+        Json(ErrorResponse::with_details(
+            "Document storage error",
+            error.to_string(),
+        )),
+    )
+}
+
+fn not_found(id: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse::new(format!("Document '{}' not found", id))),
+    )
+}
+
+/// Format recorded for a stored book, from its MIME type or file extension
+fn record_format(record: &BookRecord) -> Option<DocumentFormat> {
+    DocumentFormat::from_mime(&record.mime_type).or_else(|| {
+        record
+            .file_name
+            .rsplit_once('.')
+            .and_then(|(_, ext)| DocumentFormat::from_extension(ext))
+    })
+}
+
+/// Parsed metadata captured when the book was stored, if any
+fn stored_metadata(record: &BookRecord) -> Option<ParsedDocument> {
+    record
+        .metadata
+        .as_deref()
+        .and_then(|json| serde_json::from_str(json).ok())
+}
+
+/// Resolve a document by ID, laid out with `layout` if it is reflowable
+///
+/// Layout variants are opened from storage on first use and cached
+/// alongside the document. The default layout is the document itself.
+async fn load_document(
+    state: &AppState,
+    id: &str,
+    layout: Option<LayoutConfig>,
+) -> Result<LoadedDocument, (StatusCode, Json<ErrorResponse>)> {
+    let doc = load_base_document(state, id).await?;
+
+    let Some(layout) = layout
+        .filter(|layout| doc.parsed.format.is_reflowable() && *layout != LayoutConfig::default())
+    else {
+        return Ok(doc);
+    };
 
-    /// Check if a document ID already exists
-    async fn contains(&self, id: &str) -> bool {
-        self.entries.read().await.contains_key(id)
+    let cache = state.document_cache();
+    if let Some((parsed, _, renderer)) = cache.get_layout(id, &layout).await {
+        return Ok(LoadedDocument {
+            storage_key: doc.storage_key,
+            parsed,
+            renderer,
+            layout: Some(layout),
+        });
     }
 
-    /// Insert a document atomically (all data at once)
-    async fn insert(
-        &self,
-        id: String,
-        parser: Arc<dyn DocumentParser>,
-        renderer: Arc<dyn DocumentRenderer>,
-        metadata: ParsedDocument,
+    tracing::debug!(
+        "Laying out document '{}' at {}x{} ({}em)",
+        id,
+        layout.width,
+        layout.height,
+        layout.em
+    );
+
+    let object = state
+        .storage()
+        .get_object(&doc.storage_key)
+        .await
+        .map_err(|e| app_error(format!("Failed to fetch document '{}'", id), e))?;
+
+    let (parser, renderer, parsed) =
+        open_document_with_layout(object.data, id.to_string(), doc.parsed.format, layout)
+            .await
+            .map_err(|e| document_error(format!("Failed to lay out document '{}'", id), e))?;
+
+    cache
+        .store_layout(
+            id.to_string(),
+            layout,
+            parsed.clone(),
+            parser,
+            renderer.clone(),
+        )
+        .await;
+
+    Ok(LoadedDocument {
+        storage_key: doc.storage_key,
+        parsed,
+        renderer,
+        layout: Some(layout),
+    })
+}
+
+/// Resolve a document by ID, re-hydrating it from storage if it is not cached
+///
+/// The `books` table is the source of truth: a document that has been deleted
+/// (possibly by another replica) is evicted from the local cache.
+async fn load_base_document(
+    state: &AppState,
+    id: &str,
+) -> Result<LoadedDocument, (StatusCode, Json<ErrorResponse>)> {
+    let cache = state.document_cache();
+
+    let record = BookRepository::new(state.db())
+        .get(id)
+        .await
+        .map_err(|e| app_error(format!("Failed to look up document '{}'", id), e))?;
+
+    let Some(record) = record else {
+        if cache.contains(id).await {
+            cache.remove(id).await;
+        }
+        return Err(not_found(id));
+    };
+
+    if let (Some(parsed), Some(_), Some(renderer)) = (
+        cache.get_document(id).await,
+        cache.get_parser(id).await,
+        cache.get_renderer(id).await,
     ) {
-        let mut entries = self.entries.write().await;
-        entries.insert(
-            id,
-            CachedDocument {
-                parser,
-                renderer,
-                metadata,
-            },
-        );
+        return Ok(LoadedDocument {
+            storage_key: record.storage_key,
+            parsed,
+            renderer,
+            layout: None,
+        });
     }
 
-    /// Remove a document atomically
-    async fn remove(&self, id: &str) -> bool {
-        self.entries.write().await.remove(id).is_some()
+    let format = record_format(&record).ok_or_else(|| {
+        (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Json(ErrorResponse::new(format!(
+                "Document '{}' has unsupported type '{}'",
+                id, record.mime_type
+            ))),
+        )
+    })?;
+
+    tracing::debug!("Loading document '{}' from {}", id, record.storage_key);
+
+    let object = state
+        .storage()
+        .get_object(&record.storage_key)
+        .await
+        .map_err(|e| app_error(format!("Failed to fetch document '{}'", id), e))?;
+
+    let (parser, renderer, parsed) = open_document(object.data, id.to_string(), format)
+        .await
+        .map_err(|e| document_error(format!("Failed to open document '{}'", id), e))?;
+
+    cache
+        .store_document_with_renderer(
+            id.to_string(),
+            parsed.clone(),
+            parser,
+            renderer.clone(),
+        )
+        .await;
+
+    Ok(LoadedDocument {
+        storage_key: record.storage_key,
+        parsed,
+        renderer,
+        layout: None,
+    })
+}
+
+/// Storage key of a converted file
+///
+/// PDF output depends on the layout, so it is part of the key.
+fn conversion_key(source_hash: &str, target: ConversionTarget, layout: &LayoutConfig) -> String {
+    match target {
+        ConversionTarget::Pdf => format!(
+            "{}conversions/{}/{}-{}x{}-{}.{}",
+            RESERVED_PREFIX,
+            source_hash,
+            target.name(),
+            layout.width,
+            layout.height,
+            layout.em,
+            target.extension()
+        ),
+        _ => format!(
+            "{}conversions/{}/{}.{}",
+            RESERVED_PREFIX,
+            source_hash,
+            target.name(),
+            target.extension()
+        ),
     }
 }
 
-static DOCUMENT_STORE: std::sync::LazyLock<DocumentStore> =
-    std::sync::LazyLock::new(DocumentStore::new);
+/// Percent-encode each segment of a storage key for use in a URL path
+fn encode_key(key: &str) -> String {
+    key.split('/')
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Validate an item index against the document's item count
+fn validate_item_index(
+    parsed: &ParsedDocument,
+    index: usize,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if index >= parsed.item_count {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(format!(
+                "Item {} not found. Document has {} items (0-{})",
+                index,
+                parsed.item_count,
+                parsed.item_count.saturating_sub(1)
+            ))),
+        ));
+    }
+    Ok(())
+}
 
 /// Create the documents router
 pub fn router() -> Router<AppState> {
@@ -300,38 +691,58 @@ pub fn router() -> Router<AppState> {
         .route("/:id/items/:index/render", get(render_item))
         .route("/:id/items/:index/text", get(get_structured_text))
         .route("/:id/items/:index/thumbnail", get(render_thumbnail))
+        .route("/:id/items/:index/html", get(get_item_html))
         .route("/:id/search", get(search_document))
+        .route("/:id/pages", get(get_page_positions))
+        .route("/:id/locate", get(locate_cfi))
         .route("/:id/resources/*href", get(get_resource))
+        .route("/:id/convert", post(convert_document))
         // Allow up to 200MB uploads for large documents
         .layer(DefaultBodyLimit::max(200 * 1024 * 1024))
 }
 
-/// List all cached documents
-async fn list_documents(State(_state): State<AppState>) -> Json<DocumentListResponse> {
-    let entries = DOCUMENT_STORE.entries.read().await;
-
-    let summaries: Vec<DocumentSummary> = entries
-        .values()
-        .map(|entry| DocumentSummary {
-            id: entry.metadata.id.clone(),
-            format: format!("{:?}", entry.metadata.format).to_lowercase(),
-            title: entry.metadata.metadata.title.clone(),
-            author: entry.metadata.metadata.creators.first().map(|c| c.name.clone()),
-            item_count: entry.metadata.item_count,
+/// List all stored documents
+async fn list_documents(
+    State(state): State<AppState>,
+) -> Result<Json<DocumentListResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let records = BookRepository::new(state.db())
+        .list()
+        .await
+        .map_err(|e| app_error("Failed to list documents", e))?;
+
+    let summaries: Vec<DocumentSummary> = records
+        .iter()
+        .filter_map(|record| {
+            let format = record_format(record)?;
+            let parsed = stored_metadata(record);
+            Some(DocumentSummary {
+                id: record.id.clone(),
+                format: format!("{:?}", format).to_lowercase(),
+                title: record.title.clone(),
+                author: parsed
+                    .as_ref()
+                    .and_then(|p| p.metadata.creators.first().map(|c| c.name.clone()))
+                    .or_else(|| record.authors.clone()),
+                item_count: parsed.map(|p| p.item_count).unwrap_or(0),
+            })
         })
         .collect();
 
     let total = summaries.len();
 
-    Json(DocumentListResponse {
+    Ok(Json(DocumentListResponse {
         documents: summaries,
         total,
-    })
+    }))
 }
 
 /// Upload a new document (PDF or EPUB)
+///
+/// The original file is written to object storage under `books/{id}/` and
+/// recorded in the `books` table, so it survives restarts and is visible to
+/// every replica.
 async fn upload_document(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     mut multipart: Multipart,
 ) -> Result<Json<UploadResponse>, (StatusCode, Json<ErrorResponse>)> {
     tracing::debug!("Starting document upload processing");
@@ -378,28 +789,38 @@ async fn upload_document(
 
             tracing::debug!("Read {} bytes of file data", data.len());
 
-            // Detect format from magic bytes
-            let format = DocumentFormat::from_magic_bytes(&data).ok_or_else(|| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    Json(ErrorResponse::new(
-                        "Unsupported document format. Only PDF and EPUB are supported.",
-                    )),
-                )
-            })?;
+            // Detect format from magic bytes, then the extension (CBZs are
+            // plain ZIPs)
+            let format = DocumentFormat::from_magic_bytes(&data)
+                .or_else(|| {
+                    filename
+                        .rsplit_once('.')
+                        .and_then(|(_, ext)| DocumentFormat::from_extension(ext))
+                })
+                .ok_or_else(|| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(ErrorResponse::new(
+                            "Unsupported document format. Only PDF, EPUB, FB2, MOBI/AZW3, XPS and comic archives are supported.",
+                        )),
+                    )
+                })?;
 
             // Generate document ID from filename
-            let extension = match format {
-                DocumentFormat::Pdf => ".pdf",
-                DocumentFormat::Epub => ".epub",
-            };
+            let extension = format!(".{}", format.extension());
+            let mime_type = format.mime_type();
             let doc_id = filename
-                .strip_suffix(extension)
+                .strip_suffix(extension.as_str())
                 .unwrap_or(&filename)
                 .to_string();
 
-            // Check if document ID already exists (prevent silent overwrites)
-            if DOCUMENT_STORE.contains(&doc_id).await {
+            // Fail fast before parsing; the insert below is what claims the ID
+            let books = BookRepository::new(state.db());
+            let exists = books
+                .exists(&doc_id)
+                .await
+                .map_err(|e| app_error("Failed to check for existing document", e))?;
+            if exists {
                 return Err((
                     StatusCode::CONFLICT,
                     Json(ErrorResponse::new(format!(
@@ -409,70 +830,98 @@ async fn upload_document(
                 ));
             }
 
-            // Parse the document based on format
-            let (parser, renderer, parsed): (
-                Arc<dyn DocumentParser>,
-                Arc<dyn DocumentRenderer>,
-                ParsedDocument,
-            ) = match format {
-                DocumentFormat::Pdf => {
-                    let handler = PdfDocumentHandler::from_bytes(data.to_vec(), doc_id.clone())
-                        .map_err(|e| {
-                            tracing::error!("Failed to parse PDF: {}", e);
-                            (
-                                StatusCode::BAD_REQUEST,
-                                Json(ErrorResponse::with_details(
-                                    "Failed to parse PDF",
-                                    e.to_string(),
-                                )),
-                            )
-                        })?;
-                    let handler = Arc::new(handler);
-                    let parsed = handler.parse().await.map_err(|e| {
-                        (
-                            StatusCode::BAD_REQUEST,
-                            Json(ErrorResponse::with_details(
-                                "Failed to parse PDF metadata",
-                                e.to_string(),
-                            )),
-                        )
-                    })?;
-                    (handler.clone(), handler, parsed)
-                }
-                DocumentFormat::Epub => {
-                    let handler = EpubDocumentHandler::from_bytes(data.to_vec(), doc_id.clone())
-                        .map_err(|e| {
-                            tracing::error!("Failed to parse EPUB: {}", e);
-                            (
-                                StatusCode::BAD_REQUEST,
-                                Json(ErrorResponse::with_details(
-                                    "Failed to parse EPUB",
-                                    e.to_string(),
-                                )),
-                            )
-                        })?;
-                    let handler = Arc::new(handler);
-                    let parsed = handler.parse().await.map_err(|e| {
+            // Parse the document before storing anything
+            let (parser, renderer, parsed) =
+                open_document(data.to_vec(), doc_id.clone(), format)
+                    .await
+                    .map_err(|e| {
+                        tracing::error!("Failed to parse {:?}: {}", format, e);
                         (
                             StatusCode::BAD_REQUEST,
                             Json(ErrorResponse::with_details(
-                                "Failed to parse EPUB metadata",
+                                format!("Failed to parse {}", format!("{:?}", format).to_uppercase()),
                                 e.to_string(),
                             )),
                         )
                     })?;
-                    (handler.clone(), handler, parsed)
-                }
-            };
 
-            // Store atomically in our temporary store
+            // Record the document
+            let storage_key = format!("books/{}/{}", doc_id, filename);
             let id = parsed.id.clone();
             let title = parsed.metadata.title.clone();
             let item_count = parsed.item_count;
             let format_str = format!("{:?}", format).to_lowercase();
+            let authors = parsed
+                .metadata
+                .creators
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let record = CreateBook {
+                id: id.clone(),
+                title: title.clone(),
+                authors: (!authors.is_empty()).then_some(authors),
+                file_name: filename.clone(),
+                file_size: data.len() as i64,
+                file_hash: Some(compute_hash(&data)),
+                mime_type: mime_type.to_string(),
+                storage_key: storage_key.clone(),
+                cover_key: None,
+                metadata: serde_json::to_string(&parsed).ok(),
+            };
+
+            // Claim the ID before writing storage, so a concurrent upload of
+            // the same file can't overwrite (or clean up) this one's object
+            let claimed = books
+                .try_create(&record)
+                .await
+                .map_err(|e| app_error(format!("Failed to record document '{}'", id), e))?;
+            if claimed.is_none() {
+                return Err((
+                    StatusCode::CONFLICT,
+                    Json(ErrorResponse::new(format!(
+                        "Document with ID '{}' already exists. Use DELETE first to replace.",
+                        id
+                    ))),
+                ));
+            }
+
+            // Persist the original file
+            if let Err(e) = state
+                .storage()
+                .put_object(&storage_key, data.to_vec(), mime_type)
+                .await
+            {
+                // Don't leave a record without a file behind
+                if let Err(cleanup) = books.delete(&id).await {
+                    tracing::warn!(
+                        "Failed to remove record of failed upload {}: {}",
+                        id,
+                        cleanup
+                    );
+                }
+                return Err(app_error(
+                    format!("Failed to store document '{}'", doc_id),
+                    e,
+                ));
+            }
+
+            // Searchable and listed in OPDS like ingested uploads
+            catalog_document(
+                &state,
+                &id,
+                &filename,
+                &storage_key,
+                data.len() as i64,
+                &parsed,
+            )
+            .await;
 
-            DOCUMENT_STORE
-                .insert(id.clone(), parser, renderer, parsed)
+            state
+                .document_cache()
+                .store_document_with_renderer(id.clone(), parsed, parser, renderer)
                 .await;
 
             tracing::info!(
@@ -503,20 +952,19 @@ async fn upload_document(
 
 /// Get document details by ID
 async fn get_document(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path(id): Path<String>,
+    Query(layout): Query<LayoutQuery>,
 ) -> Result<Json<DocumentDetailResponse>, (StatusCode, Json<ErrorResponse>)> {
     tracing::debug!("Looking up document with ID: '{}'", id);
 
-    let entries = DOCUMENT_STORE.entries.read().await;
-    let entry = entries.get(&id).ok_or_else(|| {
-        tracing::warn!("Document '{}' not found", id);
-        (
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse::new(format!("Document '{}' not found", id))),
-        )
-    })?;
-    let doc = &entry.metadata;
+    let loaded = load_document(&state, &id, layout.layout()).await?;
+    let download_url = match state.presigned_url(&loaded.storage_key).await {
+        Some(url) => url,
+        None => format!("/files/{}", encode_key(&loaded.storage_key)),
+    };
+    let layout = loaded.laid_out_with();
+    let doc = loaded.parsed;
 
     Ok(Json(DocumentDetailResponse {
         id: doc.id.clone(),
@@ -536,32 +984,59 @@ async fn get_document(
         date: doc.metadata.date.clone(),
         toc: doc.toc.clone(),
         item_count: doc.item_count,
+        layout,
         has_text_layer: doc.has_text_layer,
+        download_url,
     }))
 }
 
 /// Delete a document
 async fn delete_document(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<StatusCode, (StatusCode, Json<ErrorResponse>)> {
-    // Remove atomically - returns false if document didn't exist
-    if !DOCUMENT_STORE.remove(&id).await {
-        return Err((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse::new(format!("Document '{}' not found", id))),
-        ));
+    let books = BookRepository::new(state.db());
+    let record = books
+        .get(&id)
+        .await
+        .map_err(|e| app_error(format!("Failed to look up document '{}'", id), e))?
+        .ok_or_else(|| not_found(&id))?;
+
+    // Remove the record first so no replica re-hydrates a half-deleted document
+    if !books
+        .delete(&id)
+        .await
+        .map_err(|e| app_error(format!("Failed to delete document '{}'", id), e))?
+    {
+        return Err(not_found(&id));
+    }
+
+    if let Err(e) = state.storage().delete_object(&record.storage_key).await {
+        tracing::warn!("Failed to delete stored file {}: {}", record.storage_key, e);
     }
 
+    let removed = LibraryChanges {
+        removed: vec![format!("books/{}", id)],
+        ..Default::default()
+    };
+    if let Err(e) = CatalogRepository::new(state.db())
+        .apply_changes(&removed)
+        .await
+    {
+        tracing::warn!("Failed to remove document '{}' from catalog: {}", id, e);
+    }
+    state.document_cache().remove(&id).await;
+
     tracing::info!("Document '{}' deleted", id);
     Ok(StatusCode::NO_CONTENT)
 }
 
 /// Render an item (page for PDF, chapter for EPUB) as an image
 async fn render_item(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path((id, index)): Path<(String, usize)>,
     Query(query): Query<RenderQuery>,
+    Query(layout): Query<LayoutQuery>,
 ) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
     // Validate rotation parameter
     if !VALID_ROTATIONS.contains(&query.rotation) {
@@ -576,27 +1051,10 @@ async fn render_item(
     // Clamp scale to valid range
     let scale = query.scale.clamp(MIN_SCALE, MAX_SCALE);
 
-    // Get entry (contains renderer, parser, and metadata)
-    let entries = DOCUMENT_STORE.entries.read().await;
-    let entry = entries.get(&id).ok_or_else(|| {
-        (
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse::new(format!("Document '{}' not found", id))),
-        )
-    })?;
+    let doc = load_document(&state, &id, layout.layout()).await?;
 
     // Validate item index before expensive rendering
-    if index >= entry.metadata.item_count {
-        return Err((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse::new(format!(
-                "Item {} not found. Document has {} items (0-{})",
-                index,
-                entry.metadata.item_count,
-                entry.metadata.item_count.saturating_sub(1)
-            ))),
-        ));
-    }
+    validate_item_index(&doc.parsed, index)?;
 
     // Parse format
     let format = match query.format.to_lowercase().as_str() {
@@ -610,18 +1068,20 @@ async fn render_item(
         scale,
         format,
         rotation: query.rotation,
+        layout: doc.layout,
         ..Default::default()
     };
 
-    let result = entry.renderer.render_item(&request).await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse::with_details(
+    let result = state
+        .document_cache()
+        .render(&id, &request)
+        .await
+        .map_err(|e| {
+            document_error(
                 format!("Failed to render item {} of document '{}'", index, id),
-                e.to_string(),
-            )),
-        )
-    })?;
+                e,
+            )
+        })?;
 
     // Build response with proper content type
     let content_type = match result.format {
@@ -642,92 +1102,58 @@ async fn render_item(
 
 /// Get structured text with character positions for an item
 async fn get_structured_text(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path((id, index)): Path<(String, usize)>,
+    Query(layout): Query<LayoutQuery>,
 ) -> Result<Json<StructuredText>, (StatusCode, Json<ErrorResponse>)> {
-    // Get entry
-    let entries = DOCUMENT_STORE.entries.read().await;
-    let entry = entries.get(&id).ok_or_else(|| {
-        (
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse::new(format!("Document '{}' not found", id))),
-        )
-    })?;
+    let doc = load_document(&state, &id, layout.layout()).await?;
 
     // Validate item index before expensive operation
-    if index >= entry.metadata.item_count {
-        return Err((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse::new(format!(
-                "Item {} not found. Document has {} items (0-{})",
-                index,
-                entry.metadata.item_count,
-                entry.metadata.item_count.saturating_sub(1)
-            ))),
-        ));
-    }
+    validate_item_index(&doc.parsed, index)?;
 
-    let stext = entry.parser.get_structured_text(index).await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse::with_details(
+    let stext = state
+        .document_cache()
+        .get_structured_text(&id, index, doc.layout.as_ref())
+        .await
+        .map_err(|e| {
+            document_error(
                 format!(
                     "Failed to get structured text for item {} of document '{}'",
                     index, id
                 ),
-                e.to_string(),
-            )),
-        )
-    })?;
+                e,
+            )
+        })?;
 
     Ok(Json(stext))
 }
 
 /// Render a thumbnail for an item
 async fn render_thumbnail(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path((id, index)): Path<(String, usize)>,
     Query(query): Query<ThumbnailQuery>,
+    Query(layout): Query<LayoutQuery>,
 ) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
     // Clamp size to valid range
     let size = query.size.min(MAX_THUMBNAIL_SIZE);
 
-    // Get entry
-    let entries = DOCUMENT_STORE.entries.read().await;
-    let entry = entries.get(&id).ok_or_else(|| {
-        (
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse::new(format!("Document '{}' not found", id))),
-        )
-    })?;
+    let doc = load_document(&state, &id, layout.layout()).await?;
 
     // Validate item index before expensive operation
-    if index >= entry.metadata.item_count {
-        return Err((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse::new(format!(
-                "Item {} not found. Document has {} items (0-{})",
-                index,
-                entry.metadata.item_count,
-                entry.metadata.item_count.saturating_sub(1)
-            ))),
-        ));
-    }
+    validate_item_index(&doc.parsed, index)?;
 
-    let result = entry
-        .renderer
-        .render_thumbnail(index, size)
+    let result = state
+        .document_cache()
+        .render_thumbnail(&id, index, size, doc.layout.as_ref())
         .await
         .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse::with_details(
-                    format!(
-                        "Failed to render thumbnail for item {} of document '{}'",
-                        index, id
-                    ),
-                    e.to_string(),
-                )),
+            document_error(
+                format!(
+                    "Failed to render thumbnail for item {} of document '{}'",
+                    index, id
+                ),
+                e,
             )
         })?;
 
@@ -750,22 +1176,16 @@ async fn render_thumbnail(
 
 /// Search document content
 async fn search_document(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path(id): Path<String>,
     Query(query): Query<SearchQuery>,
+    Query(layout): Query<LayoutQuery>,
 ) -> Result<Json<SearchResultResponse>, (StatusCode, Json<ErrorResponse>)> {
     // Clamp search parameters to prevent resource exhaustion
     let limit = query.limit.min(MAX_SEARCH_LIMIT);
     let context_length = query.context_length.min(MAX_CONTEXT_LENGTH);
 
-    // Get entry
-    let entries = DOCUMENT_STORE.entries.read().await;
-    let entry = entries.get(&id).ok_or_else(|| {
-        (
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse::new(format!("Document '{}' not found", id))),
-        )
-    })?;
+    let doc = load_document(&state, &id, layout.layout()).await?;
 
     let options = SearchOptions {
         limit,
@@ -776,15 +1196,11 @@ async fn search_document(
         ..Default::default()
     };
 
-    let results = entry.parser.search(&query.q, options).await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse::with_details(
-                format!("Failed to search document '{}'", id),
-                e.to_string(),
-            )),
-        )
-    })?;
+    let results = state
+        .document_cache()
+        .search(&id, &query.q, options, doc.layout.as_ref())
+        .await
+        .map_err(|e| document_error(format!("Failed to search document '{}'", id), e))?;
 
     let total = results.len();
     let hits: Vec<SearchHit> = results
@@ -811,24 +1227,216 @@ async fn search_document(
         results: hits,
         total,
         query: query.q,
+        item_count: doc.parsed.item_count,
+    }))
+}
+
+/// List where each laid-out page of an EPUB starts, as CFIs
+///
+/// Other formats have no CFIs and get an empty list.
+async fn get_page_positions(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(layout): Query<LayoutQuery>,
+) -> Result<Json<PageMapResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let doc = load_document(&state, &id, layout.layout()).await?;
+    let positions = page_positions(&state, &doc, &id).await?;
+
+    Ok(Json(PageMapResponse {
+        item_count: doc.parsed.item_count,
+        layout: doc.laid_out_with(),
+        pages: positions
+            .iter()
+            .map(|position| PagePosition {
+                item_index: position.item_index,
+                spine_index: position.spine_index,
+                cfi: position_cfi(position).to_string(),
+                progression: position.progression,
+            })
+            .collect(),
+    }))
+}
+
+/// Find the laid-out page of an EPUB that contains a CFI
+async fn locate_cfi(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<LocateQuery>,
+    Query(layout): Query<LayoutQuery>,
+) -> Result<Json<LocateResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let target = cfi::parse(&query.cfi)
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::with_details("Invalid CFI", e.to_string())),
+            )
+        })?
+        .start();
+
+    let doc = load_document(&state, &id, layout.layout()).await?;
+    let positions = page_positions(&state, &doc, &id).await?;
+    if positions.is_empty() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ErrorResponse::new(format!(
+                "Document '{}' has no CFI page positions",
+                id
+            ))),
+        ));
+    }
+
+    // Page starts are in reading order: the page is the last one starting
+    // at or before the target
+    let starts: Vec<Cfi> = positions.iter().map(position_cfi).collect();
+    let page = starts.partition_point(|start| *start <= target).saturating_sub(1);
+
+    Ok(Json(LocateResponse {
+        item_index: positions[page].item_index,
+        item_count: doc.parsed.item_count,
+        layout: doc.laid_out_with(),
+        page_cfi: starts[page].to_string(),
+    }))
+}
+
+/// Page start positions of a loaded document's layout
+async fn page_positions(
+    state: &AppState,
+    doc: &LoadedDocument,
+    id: &str,
+) -> Result<Vec<ContentPosition>, (StatusCode, Json<ErrorResponse>)> {
+    state
+        .document_cache()
+        .content_positions(id, doc.layout.as_ref())
+        .await
+        .map_err(|e| {
+            document_error(
+                format!("Failed to map pages of document '{}'", id),
+                e,
+            )
+        })
+}
+
+/// CFI of a page start position
+fn position_cfi(position: &ContentPosition) -> Cfi {
+    position
+        .path
+        .iter()
+        .fold(
+            CfiBuilder::new()
+                .package_step()
+                .spine_item(position.spine_index)
+                .indirection(),
+            |builder, step| builder.element_raw(*step),
+        )
+        .character_offset(position.offset)
+        .build()
+}
+
+/// Get a spine item's XHTML with the user's highlights
+///
+/// `index` is the spine index, not a laid-out page. Scripts and event
+/// handlers are stripped, resource URLs point at the resources endpoint,
+/// and the stored annotations on the item are injected as highlight spans,
+/// anchored by their text quotes.
+async fn get_item_html(
+    State(state): State<AppState>,
+    Path((id, index)): Path<(String, usize)>,
+    Query(query): Query<ItemHtmlQuery>,
+) -> Result<Json<ItemHtmlResponse>, (StatusCode, Json<ErrorResponse>)> {
+    load_document(&state, &id, None).await?;
+
+    let content = state
+        .document_cache()
+        .content_document(&id, index)
+        .await
+        .map_err(|e| {
+            document_error(
+                format!("Failed to read item {} of document '{}'", index, id),
+                e,
+            )
+        })?;
+
+    let mut annotations: Vec<Annotation> = AnnotationRepository::new(state.db())
+        .list(&AnnotationQuery {
+            book_id: Some(id.clone()),
+            user_id: Some(query.user_id),
+            ..AnnotationQuery::default()
+        })
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to load annotations for document '{}': {}", id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::with_details(
+                    "Failed to load annotations",
+                    e.to_string(),
+                )),
+            )
+        })?
+        .into_iter()
+        .filter(|annotation| {
+            annotation.annotation_type != AnnotationType::Bookmark
+                && !annotation.is_pdf_annotation()
+                && source_matches(&annotation.target.source, &content.href)
+        })
+        .collect();
+    // Oldest first, so later highlights nest inside earlier ones
+    annotations.reverse();
+
+    let directory = content.href.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let base_url = format!(
+        "/api/v1/documents/{}/resources/{}",
+        urlencoding::encode(&id),
+        encode_key(directory)
+    );
+
+    let html_error = |e: crate::html::InjectError| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse::with_details(
+                format!("Failed to process item {} of document '{}'", index, id),
+                e.to_string(),
+            )),
+        )
+    };
+    let html = sanitize_html(&content.content).map_err(html_error)?;
+    let html = rewrite_urls(&html, &base_url).map_err(html_error)?;
+    let result =
+        inject_highlights(&html, &annotations, &HighlightConfig::default()).map_err(html_error)?;
+
+    Ok(Json(ItemHtmlResponse {
+        spine_index: content.spine_index,
+        href: content.href,
+        html: result.html,
+        injected_count: result.injected_count,
+        failed_annotations: result.failed_annotations,
     }))
 }
 
+/// Whether an annotation's source names a content document
+///
+/// Sources are spine item hrefs as the client saw them, which may be
+/// relative to the package document or percent-encoded.
+fn source_matches(source: &str, href: &str) -> bool {
+    let source = source.split('#').next().unwrap_or(source);
+    let source = urlencoding::decode(source).map_or_else(|_| source.into(), |s| s);
+    let source = source.trim_start_matches('/');
+    if source.is_empty() {
+        return false;
+    }
+    source == href
+        || href.ends_with(&format!("/{}", source))
+        || source.ends_with(&format!("/{}", href))
+}
+
 /// Get an embedded resource (image, CSS, font)
 async fn get_resource(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path((id, href)): Path<(String, String)>,
 ) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
-    // Get entry
-    let entries = DOCUMENT_STORE.entries.read().await;
-    let entry = entries.get(&id).ok_or_else(|| {
-        (
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse::new(format!("Document '{}' not found", id))),
-        )
-    })?;
+    let doc = load_document(&state, &id, None).await?;
 
-    let resource = entry.renderer.get_resource(&href).await.map_err(|e| {
+    let resource = doc.renderer.get_resource(&href).await.map_err(|e| {
         (
             StatusCode::NOT_FOUND,
             Json(ErrorResponse::with_details(
@@ -847,3 +1455,139 @@ async fn get_resource(
 
     Ok(response)
 }
+
+/// Convert a document to another format
+///
+/// Returns the converted file as a download. Results are cached in storage
+/// by source hash and target; a cached conversion is served without
+/// opening the source.
+async fn convert_document(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ConvertQuery>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let target = ConversionTarget::from_name(&query.to).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new(format!(
+                "Unknown conversion target '{}'. Use pdf, epub, kepub or txt.",
+                query.to
+            ))),
+        )
+    })?;
+    let layout = query.layout();
+
+    let record = BookRepository::new(state.db())
+        .get(&id)
+        .await
+        .map_err(|e| app_error(format!("Failed to look up document '{}'", id), e))?
+        .ok_or_else(|| not_found(&id))?;
+
+    let format = record_format(&record)
+        .filter(|format| target.accepts(*format))
+        .ok_or_else(|| {
+            (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                Json(ErrorResponse::new(format!(
+                    "Document '{}' can't be converted to {}",
+                    id,
+                    target.name()
+                ))),
+            )
+        })?;
+
+    let stem = record
+        .file_name
+        .rsplit_once('.')
+        .map_or(record.file_name.as_str(), |(stem, _)| stem);
+    let filename = format!("{}.{}", stem, target.extension());
+    let respond = |data: Vec<u8>| {
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, target.mime_type())
+            .header(header::CONTENT_DISPOSITION, content_disposition(&filename, true))
+            .header(header::CACHE_CONTROL, "max-age=3600")
+            .body(Body::from(data))
+            .expect("hardcoded headers cannot fail")
+    };
+
+    let storage = state.storage();
+    let mut source = None;
+    let source_hash = match record.file_hash.clone() {
+        Some(hash) => hash,
+        None => {
+            let object = storage
+                .get_object(&record.storage_key)
+                .await
+                .map_err(|e| app_error(format!("Failed to fetch document '{}'", id), e))?;
+            let hash = compute_hash(&object.data);
+            source = Some(object.data);
+            hash
+        }
+    };
+
+    let key = conversion_key(&source_hash, target, &layout);
+    match storage.get_object(&key).await {
+        Ok(object) => {
+            tracing::debug!("Serving cached conversion {}", key);
+            return Ok(respond(object.data));
+        }
+        Err(AppError::Storage(StorageError::ObjectNotFound(_))) => {}
+        Err(e) => tracing::warn!("Failed to read cached conversion {}: {}", key, e),
+    }
+
+    let data = match source {
+        Some(data) => data,
+        None => {
+            storage
+                .get_object(&record.storage_key)
+                .await
+                .map_err(|e| app_error(format!("Failed to fetch document '{}'", id), e))?
+                .data
+        }
+    };
+
+    let converted = convert(data, id.clone(), format, target, layout)
+        .await
+        .map_err(|e| {
+            document_error(
+                format!("Failed to convert document '{}' to {}", id, target.name()),
+                e,
+            )
+        })?;
+
+    if let Err(e) = storage
+        .put_object(&key, converted.clone(), target.mime_type())
+        .await
+    {
+        tracing::warn!("Failed to cache conversion {}: {}", key, e);
+    }
+
+    tracing::info!(
+        "Converted document '{}' from {:?} to {}",
+        id,
+        format,
+        target.name()
+    );
+    Ok(respond(converted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamped_layout() {
+        let default = LayoutConfig::default();
+
+        let layout = clamped_layout(Some(f32::NAN), Some(f32::INFINITY), Some(f32::NAN));
+        assert_eq!(layout.width, default.width);
+        assert_eq!(layout.height, default.height);
+        assert_eq!(layout.em, default.em);
+
+        let layout = clamped_layout(Some(1.0), Some(1e9), Some(100.0));
+        assert_eq!(layout.width, MIN_LAYOUT_SIZE);
+        assert_eq!(layout.height, MAX_LAYOUT_SIZE);
+        assert_eq!(layout.em, MAX_LAYOUT_EM);
+    }
+}
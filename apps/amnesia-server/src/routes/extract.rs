@@ -3,11 +3,10 @@
 //! Endpoints for extracting native PDF annotations (highlights, underlines, etc.)
 //! from documents. These are annotations made in Adobe Reader, Foxit, Preview, etc.
 //!
-//! # Note
-//!
-//! This API is currently a stub. Full annotation extraction requires MuPDF binding
-//! updates to expose the annotation enumeration APIs. The endpoints are defined
-//! here to establish the API contract for future implementation.
+//! Documents are read from object storage by their `books` ID; see
+//! [`crate::pdf::annotation_extractor`] for how annotations are read.
+//! Extracted annotations can be imported into the annotation or highlight
+//! store; see [`crate::annotations::import_annotations`].
 
 use axum::{
     extract::{Path, Query, State},
@@ -16,9 +15,14 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::error::Result;
+use crate::annotations::{import_annotations, ImportSummary, ImportTarget};
+use crate::db::BookRepository;
+use crate::document::DocumentFormat;
+use crate::error::{AppError, Result};
+use crate::mupdf::SafeDocument;
 use crate::pdf::annotation_extractor::{
-    ExtractedAnnotation, ExtractedAnnotationType, ExtractionResult, ExtractionStats,
+    extract_annotations, ExtractedAnnotation, ExtractedAnnotationType, ExtractionOptions,
+    ExtractionResult,
 };
 use crate::state::AppState;
 
@@ -33,6 +37,10 @@ pub fn router() -> Router<AppState> {
             "/documents/:book_id/annotations/search",
             post(search_annotations),
         )
+        .route(
+            "/documents/:book_id/annotations/import",
+            post(import_document_annotations),
+        )
         .route("/batch/annotations", post(batch_extract_annotations))
 }
 
@@ -55,25 +63,63 @@ fn default_true() -> bool {
 }
 
 /// Extract annotations from a document
-///
-/// Note: Currently returns a stub response. Full implementation pending MuPDF binding updates.
 async fn extract_document_annotations(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path(book_id): Path<String>,
-    Query(_query): Query<ExtractQuery>,
+    Query(query): Query<ExtractQuery>,
 ) -> Result<Json<ExtractionResult>> {
-    // Return stub result - full implementation requires MuPDF annotation API
-    Ok(Json(ExtractionResult {
-        book_id,
-        total_pages: 0,
-        annotations: Vec::new(),
-        stats: ExtractionStats::default(),
-        note: Some(
-            "PDF annotation extraction requires MuPDF binding updates. \
-            This endpoint is a placeholder for the planned feature."
-                .to_string(),
-        ),
-    }))
+    let types = match &query.types {
+        Some(types) => parse_annotation_types(types.split(','))?,
+        None => Vec::new(),
+    };
+    let options = ExtractionOptions {
+        types,
+        pages: query
+            .pages
+            .as_deref()
+            .map(parse_page_ranges)
+            .unwrap_or_default(),
+        include_text: query.include_text,
+    };
+
+    Ok(Json(
+        extract_book_annotations(&state, &book_id, options).await?,
+    ))
+}
+
+/// Extract annotations from a stored PDF
+async fn extract_book_annotations(
+    state: &AppState,
+    book_id: &str,
+    options: ExtractionOptions,
+) -> Result<ExtractionResult> {
+    let record = BookRepository::new(state.db())
+        .get(book_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Document '{}' not found", book_id)))?;
+
+    let format = DocumentFormat::from_mime(&record.mime_type).or_else(|| {
+        record
+            .file_name
+            .rsplit_once('.')
+            .and_then(|(_, ext)| DocumentFormat::from_extension(ext))
+    });
+    if format != Some(DocumentFormat::Pdf) {
+        return Err(AppError::BadRequest(format!(
+            "Document '{}' is not a PDF",
+            book_id
+        )));
+    }
+
+    let object = state.storage().get_object(&record.storage_key).await?;
+    let book_id = book_id.to_string();
+    tokio::task::spawn_blocking(move || {
+        let doc = SafeDocument::from_bytes(object.data, book_id.clone())?;
+        extract_annotations(&doc, &book_id, &options)
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Task join error: {}", e)))?
+    .map_err(|e| AppError::Internal(format!("Failed to extract annotations: {}", e)))
 }
 
 /// Search request for filtered annotation extraction
@@ -109,23 +155,114 @@ pub struct SearchAnnotationsResponse {
 
 /// Search annotations with filtering
 ///
-/// Note: Currently returns a stub response. Full implementation pending MuPDF binding updates.
+/// `query` matches the text under an annotation or its comment, ignoring
+/// case. Colors match hex values with or without the `#`.
 async fn search_annotations(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path(book_id): Path<String>,
-    Json(_request): Json<SearchAnnotationsRequest>,
+    Json(request): Json<SearchAnnotationsRequest>,
 ) -> Result<Json<SearchAnnotationsResponse>> {
-    // Return stub result
+    let options = ExtractionOptions {
+        types: parse_annotation_types(request.types.iter().map(String::as_str))?,
+        pages: request.pages.clone(),
+        include_text: request.include_text,
+    };
+    let result = extract_book_annotations(&state, &book_id, options).await?;
+
+    let query = request
+        .query
+        .as_deref()
+        .map(|q| q.trim().to_lowercase())
+        .filter(|q| !q.is_empty());
+    let normalize_color = |color: &str| color.trim().trim_start_matches('#').to_ascii_uppercase();
+    let colors: Vec<String> = request.colors.iter().map(|c| normalize_color(c)).collect();
+
+    let annotations: Vec<ExtractedAnnotation> = result
+        .annotations
+        .into_iter()
+        .filter(|annotation| {
+            let matches_query = query.as_ref().is_none_or(|query| {
+                [&annotation.text, &annotation.comment]
+                    .iter()
+                    .filter_map(|field| field.as_deref())
+                    .any(|field| field.to_lowercase().contains(query.as_str()))
+            });
+            let matches_color = colors.is_empty()
+                || annotation
+                    .color
+                    .as_deref()
+                    .is_some_and(|color| colors.contains(&normalize_color(color)));
+            matches_query && matches_color
+        })
+        .collect();
+
     Ok(Json(SearchAnnotationsResponse {
         book_id,
-        total_pages: 0,
-        matched: 0,
-        annotations: Vec::new(),
-        note: Some(
-            "PDF annotation extraction requires MuPDF binding updates. \
-            This endpoint is a placeholder for the planned feature."
-                .to_string(),
-        ),
+        total_pages: result.total_pages,
+        matched: annotations.len(),
+        annotations,
+        note: None,
+    }))
+}
+
+/// Import request for extracted annotations
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportAnnotationsRequest {
+    /// Store to import into (default: annotations)
+    #[serde(default)]
+    pub target: ImportTarget,
+    /// User the imported annotations belong to
+    pub user_id: Option<String>,
+    /// Only import these annotation types
+    #[serde(default)]
+    pub types: Vec<String>,
+    /// Only import from these pages
+    #[serde(default)]
+    pub pages: Vec<u32>,
+}
+
+/// Import response
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportAnnotationsResponse {
+    pub book_id: String,
+    pub target: ImportTarget,
+    pub extracted: usize,
+    #[serde(flatten)]
+    pub summary: ImportSummary,
+}
+
+/// Import native annotations into the store
+///
+/// Re-importing the same document is safe: annotations imported before are
+/// reported as skipped instead of being duplicated.
+async fn import_document_annotations(
+    State(state): State<AppState>,
+    Path(book_id): Path<String>,
+    Json(request): Json<ImportAnnotationsRequest>,
+) -> Result<Json<ImportAnnotationsResponse>> {
+    let options = ExtractionOptions {
+        types: parse_annotation_types(request.types.iter().map(String::as_str))?,
+        pages: request.pages.clone(),
+        include_text: true,
+    };
+    let result = extract_book_annotations(&state, &book_id, options).await?;
+
+    let summary = import_annotations(
+        state.db(),
+        &book_id,
+        request.user_id.as_deref(),
+        request.target,
+        &result.annotations,
+    )
+    .await?;
+
+    Ok(Json(ImportAnnotationsResponse {
+        book_id,
+        target: request.target,
+        extracted: result.annotations.len(),
+        summary,
     }))
 }
 
@@ -165,38 +302,63 @@ pub struct BatchExtractItem {
 
 /// Batch extract annotations from multiple documents
 ///
-/// Note: Currently returns a stub response. Full implementation pending MuPDF binding updates.
+/// Documents are extracted one at a time; a failure is reported for that
+/// document and doesn't stop the batch.
 async fn batch_extract_annotations(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Json(request): Json<BatchExtractRequest>,
 ) -> Result<Json<BatchExtractResponse>> {
-    // Return stub results for each requested document
-    let results: Vec<BatchExtractItem> = request
-        .book_ids
-        .iter()
-        .map(|book_id| BatchExtractItem {
-            book_id: book_id.clone(),
-            success: true,
-            annotation_count: 0,
-            error: None,
-        })
-        .collect();
+    let types = parse_annotation_types(request.types.iter().map(String::as_str))?;
+
+    let mut results = Vec::with_capacity(request.book_ids.len());
+    for book_id in &request.book_ids {
+        let options = ExtractionOptions {
+            types: types.clone(),
+            pages: Vec::new(),
+            include_text: request.include_text,
+        };
+        let item = match extract_book_annotations(&state, book_id, options).await {
+            Ok(result) => BatchExtractItem {
+                book_id: book_id.clone(),
+                success: true,
+                annotation_count: result.annotations.len(),
+                error: None,
+            },
+            Err(e) => BatchExtractItem {
+                book_id: book_id.clone(),
+                success: false,
+                annotation_count: 0,
+                error: Some(e.to_string()),
+            },
+        };
+        results.push(item);
+    }
 
+    let successful = results.iter().filter(|item| item.success).count();
     Ok(Json(BatchExtractResponse {
+        total_annotations: results.iter().map(|item| item.annotation_count).sum(),
+        successful,
+        failed: results.len() - successful,
         results,
-        total_annotations: 0,
-        successful: request.book_ids.len(),
-        failed: 0,
-        note: Some(
-            "PDF annotation extraction requires MuPDF binding updates. \
-            This endpoint is a placeholder for the planned feature."
-                .to_string(),
-        ),
+        note: None,
     }))
 }
 
+/// Parse a list of annotation type names, rejecting unknown ones
+fn parse_annotation_types<'a>(
+    names: impl Iterator<Item = &'a str>,
+) -> Result<Vec<ExtractedAnnotationType>> {
+    names
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            parse_annotation_type(name)
+                .ok_or_else(|| AppError::BadRequest(format!("Unknown annotation type '{}'", name)))
+        })
+        .collect()
+}
+
 /// Parse annotation type from string
-#[allow(dead_code)]
 fn parse_annotation_type(s: &str) -> Option<ExtractedAnnotationType> {
     match s.to_lowercase().as_str() {
         "highlight" => Some(ExtractedAnnotationType::Highlight),
@@ -205,13 +367,13 @@ fn parse_annotation_type(s: &str) -> Option<ExtractedAnnotationType> {
         "squiggly" => Some(ExtractedAnnotationType::Squiggly),
         "text" | "note" | "comment" => Some(ExtractedAnnotationType::Text),
         "freetext" | "free-text" => Some(ExtractedAnnotationType::FreeText),
+        "ink" | "drawing" => Some(ExtractedAnnotationType::Ink),
         _ => None,
     }
 }
 
 /// Parse page ranges like "1,2,5-10,15" into a list of page numbers
 /// Includes safety limit to prevent DoS via huge ranges
-#[allow(dead_code)]
 fn parse_page_ranges(s: &str) -> Vec<u32> {
     const MAX_PAGES: usize = 10000; // Safety limit
     let mut pages = Vec::new();
@@ -256,8 +418,8 @@ mod tests {
         assert_eq!(parse_page_ranges("1,2,3"), vec![1, 2, 3]);
         assert_eq!(parse_page_ranges("1-5"), vec![1, 2, 3, 4, 5]);
         assert_eq!(parse_page_ranges("1,3-5,10"), vec![1, 3, 4, 5, 10]);
-        assert_eq!(parse_page_ranges("5-3"), vec![]); // Invalid range
-        assert_eq!(parse_page_ranges(""), vec![]);
+        assert_eq!(parse_page_ranges("5-3"), Vec::<u32>::new()); // Invalid range
+        assert_eq!(parse_page_ranges(""), Vec::<u32>::new());
     }
 
     #[test]
@@ -281,6 +443,23 @@ mod tests {
             parse_annotation_type("strike-out"),
             Some(ExtractedAnnotationType::StrikeOut)
         );
+        assert_eq!(
+            parse_annotation_type("ink"),
+            Some(ExtractedAnnotationType::Ink)
+        );
         assert_eq!(parse_annotation_type("unknown"), None);
     }
+
+    #[test]
+    fn test_parse_annotation_types() {
+        let types = parse_annotation_types("highlight, text,".split(',')).unwrap();
+        assert_eq!(
+            types,
+            vec![
+                ExtractedAnnotationType::Highlight,
+                ExtractedAnnotationType::Text
+            ]
+        );
+        assert!(parse_annotation_types(["circle"].into_iter()).is_err());
+    }
 }
@@ -1,65 +1,361 @@
 //! File serving routes
 //!
-//! Serves book files and covers from S3 storage.
+//! Serves book files and covers from storage.
+//!
+//! Responses are streamed and support:
+//! - `Range` requests (single and multi-range) with `206 Partial Content`,
+//!   fetched with ranged reads from storage
+//! - Conditional requests via `ETag`/`If-None-Match` and
+//!   `Last-Modified`/`If-Modified-Since`, plus `If-Range`
+//! - `?download=true` for `Content-Disposition: attachment` (default `inline`)
 
 use axum::{
-    body::Body,
-    extract::{Path, State},
-    http::{header, StatusCode},
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
     response::Response,
     routing::get,
     Router,
 };
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::Deserialize;
 
 use crate::error::{AppError, Result};
 use crate::state::AppState;
+use crate::storage::{ObjectMetadata, Storage};
+
+/// Ranges beyond this count are answered with the full file
+const MAX_RANGES: usize = 16;
+
+/// Boundary for `multipart/byteranges` responses
+const MULTIPART_BOUNDARY: &str = "AMNESIA_BYTERANGES";
 
 /// Create the files router
 pub fn router() -> Router<AppState> {
     Router::new().route("/*path", get(serve_file))
 }
 
-/// Serve a file from S3
+#[derive(Debug, Default, Deserialize)]
+struct FileQuery {
+    /// Serve as an attachment instead of inline
+    #[serde(default)]
+    download: bool,
+}
+
+/// Serve a file from storage
 async fn serve_file(
     State(state): State<AppState>,
     Path(path): Path<String>,
+    Query(query): Query<FileQuery>,
+    headers: HeaderMap,
 ) -> Result<Response> {
-    let s3_client = state.s3_client();
+    let storage = state.storage();
 
     // Get object metadata first
-    let metadata = s3_client.head_object(&path).await?;
-
-    // Get the object stream
-    let stream = s3_client.get_object_stream(&path).await?;
+    let metadata = storage.head_object(&path).await?;
+    let size = metadata.size.max(0) as u64;
 
     // Determine content type
     let content_type = metadata
         .content_type
+        .clone()
         .unwrap_or_else(|| guess_content_type(&path));
 
     // Get filename for Content-Disposition
     let filename = path.rsplit('/').next().unwrap_or(&path);
 
-    // Collect the stream data into bytes
-    let bytes = stream
-        .collect()
-        .await
-        .map_err(|e| AppError::Internal(format!("Failed to read file stream: {}", e)))?
-        .into_bytes();
+    let mut response = Response::builder()
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CACHE_CONTROL, "public, max-age=86400");
+
+    if let Some(ref etag) = metadata.etag {
+        response = response.header(header::ETAG, etag);
+    }
+    if let Some(last_modified) = metadata.last_modified {
+        response = response.header(header::LAST_MODIFIED, http_date(last_modified));
+    }
+
+    if is_not_modified(&headers, &metadata) {
+        return response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .map_err(|e| AppError::Internal(e.to_string()));
+    }
+
+    response = response.header(
+        header::CONTENT_DISPOSITION,
+        content_disposition(filename, query.download),
+    );
+
+    let ranges = match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(range) if range_applies(&headers, &metadata) => parse_range(range, size),
+        _ => RangeRequest::Full,
+    };
+
+    let response = match ranges {
+        RangeRequest::Full => {
+            let stream = storage.get_object_stream(&path).await?;
+            response
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, content_type)
+                .header(header::CONTENT_LENGTH, size)
+                .body(Body::from_stream(stream))
+        }
+        RangeRequest::Unsatisfiable => response
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+            .body(Body::empty()),
+        RangeRequest::Ranges(ranges) if ranges.len() == 1 => {
+            let range = ranges[0];
+            let stream = storage.get_object_range(&path, range.start, range.end).await?;
+            response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_TYPE, content_type)
+                .header(header::CONTENT_LENGTH, range.len())
+                .header(header::CONTENT_RANGE, range.content_range(size))
+                .body(Body::from_stream(stream))
+        }
+        RangeRequest::Ranges(ranges) => {
+            let (length, body) =
+                multipart_body(storage.clone(), path.clone(), ranges, &content_type, size);
+            response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(
+                    header::CONTENT_TYPE,
+                    format!("multipart/byteranges; boundary={}", MULTIPART_BOUNDARY),
+                )
+                .header(header::CONTENT_LENGTH, length)
+                .body(body)
+        }
+    };
+
+    response.map_err(|e| AppError::Internal(e.to_string()))
+}
+
+// ============================================================================
+// Ranges
+// ============================================================================
+
+/// An inclusive byte range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ByteRange {
+    start: u64,
+    end: u64,
+}
+
+impl ByteRange {
+    fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    fn content_range(&self, size: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, size)
+    }
+}
+
+/// Outcome of parsing a `Range` header
+#[derive(Debug, PartialEq, Eq)]
+enum RangeRequest {
+    /// No usable range: send the whole file
+    Full,
+    /// No range overlaps the file (416)
+    Unsatisfiable,
+    /// Satisfiable ranges, in request order
+    Ranges(Vec<ByteRange>),
+}
+
+/// Parse a `Range` header (RFC 9110 §14.2) against a file size
+///
+/// Invalid headers and non-byte units are ignored (full response), as are
+/// requests with more than [`MAX_RANGES`] ranges.
+fn parse_range(header: &str, size: u64) -> RangeRequest {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    let mut ranges = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let Some((first, last)) = part.split_once('-') else {
+            return RangeRequest::Full;
+        };
+        let (first, last) = (first.trim(), last.trim());
+
+        let range = if first.is_empty() {
+            // Suffix range: last N bytes
+            let Ok(suffix) = last.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            if suffix == 0 || size == 0 {
+                continue;
+            }
+            ByteRange {
+                start: size.saturating_sub(suffix),
+                end: size - 1,
+            }
+        } else {
+            let Ok(start) = first.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let end = if last.is_empty() {
+                u64::MAX
+            } else {
+                match last.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => return RangeRequest::Full,
+                }
+            };
+            if start >= size {
+                continue;
+            }
+            ByteRange {
+                start,
+                end: end.min(size - 1),
+            }
+        };
+
+        ranges.push(range);
+    }
+
+    if ranges.len() > MAX_RANGES {
+        return RangeRequest::Full;
+    }
+
+    if ranges.is_empty() {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Ranges(ranges)
+    }
+}
+
+/// Build a streamed `multipart/byteranges` body and its exact length
+fn multipart_body(
+    storage: Storage,
+    path: String,
+    ranges: Vec<ByteRange>,
+    content_type: &str,
+    size: u64,
+) -> (u64, Body) {
+    let part_headers: Vec<String> = ranges
+        .iter()
+        .map(|range| {
+            format!(
+                "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                MULTIPART_BOUNDARY,
+                content_type,
+                range.content_range(size)
+            )
+        })
+        .collect();
+    let closing = format!("\r\n--{}--\r\n", MULTIPART_BOUNDARY);
+
+    let length = part_headers.iter().map(|h| h.len() as u64).sum::<u64>()
+        + ranges.iter().map(ByteRange::len).sum::<u64>()
+        + closing.len() as u64;
+
+    let parts = futures::stream::iter(ranges.into_iter().zip(part_headers)).then(
+        move |(range, part_header)| {
+            let storage = storage.clone();
+            let path = path.clone();
+            async move {
+                let head = futures::stream::once(async move { Ok(Bytes::from(part_header)) });
+                match storage.get_object_range(&path, range.start, range.end).await {
+                    Ok(data) => head.chain(data).boxed(),
+                    Err(e) => futures::stream::once(async move { Err(e) }).boxed(),
+                }
+            }
+        },
+    );
 
-    let body = Body::from(bytes);
+    let stream = parts
+        .flatten()
+        .chain(futures::stream::once(async move { Ok(Bytes::from(closing)) }));
 
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, content_type)
-        .header(header::CONTENT_LENGTH, metadata.size)
-        .header(
-            header::CONTENT_DISPOSITION,
-            format!("inline; filename=\"{}\"", filename),
-        )
-        .header(header::CACHE_CONTROL, "public, max-age=86400")
-        .body(body)
-        .map_err(|e| AppError::Internal(e.to_string()))?)
+    (length, Body::from_stream(stream))
+}
+
+// ============================================================================
+// Conditional Requests
+// ============================================================================
+
+/// Evaluate `If-None-Match` (preferred) or `If-Modified-Since`
+fn is_not_modified(headers: &HeaderMap, metadata: &ObjectMetadata) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        return match metadata.etag {
+            Some(ref etag) => etag_matches(if_none_match, etag),
+            None => if_none_match.trim() == "*",
+        };
+    }
+
+    let since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_http_date);
+
+    match (since, metadata.last_modified) {
+        // HTTP dates have one-second resolution
+        (Some(since), Some(modified)) => modified.timestamp() <= since.timestamp(),
+        _ => false,
+    }
+}
+
+/// Whether a `Range` should be honored given `If-Range`
+fn range_applies(headers: &HeaderMap, metadata: &ObjectMetadata) -> bool {
+    let Some(if_range) = headers.get(header::IF_RANGE).and_then(|v| v.to_str().ok()) else {
+        return true;
+    };
+
+    if let Some(date) = parse_http_date(if_range) {
+        return metadata
+            .last_modified
+            .is_some_and(|modified| modified.timestamp() == date.timestamp());
+    }
+
+    // If-Range requires a strong comparison
+    metadata
+        .etag
+        .as_deref()
+        .is_some_and(|etag| !etag.starts_with("W/") && etag == if_range.trim())
+}
+
+/// Weak comparison of an `If-None-Match` list against an ETag
+fn etag_matches(header: &str, etag: &str) -> bool {
+    let strip_weak = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = strip_weak(etag);
+
+    header
+        .split(',')
+        .any(|candidate| candidate.trim() == "*" || strip_weak(candidate) == etag)
+}
+
+fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value.trim())
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// `Content-Disposition` with an ASCII fallback and RFC 5987 UTF-8 name
+pub(crate) fn content_disposition(filename: &str, download: bool) -> String {
+    let disposition = if download { "attachment" } else { "inline" };
+    let fallback: String = filename
+        .chars()
+        .map(|c| if c.is_ascii() && c != '"' && c != '\\' && !c.is_ascii_control() { c } else { '_' })
+        .collect();
+
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        disposition,
+        fallback,
+        urlencoding::encode(filename)
+    )
 }
 
 /// Guess content type from file extension
@@ -72,7 +368,9 @@ fn guess_content_type(path: &str) -> String {
         "azw3" | "azw" => "application/vnd.amazon.mobi8-ebook",
         "cbz" => "application/vnd.comicbook+zip",
         "cbr" => "application/vnd.comicbook-rar",
+        "cb7" => "application/x-cb7",
         "fb2" => "application/x-fictionbook+xml",
+        "xps" | "oxps" => "application/vnd.ms-xpsdocument",
         "jpg" | "jpeg" => "image/jpeg",
         "png" => "image/png",
         "gif" => "image/gif",
@@ -83,3 +381,71 @@ fn guess_content_type(path: &str) -> String {
     }
     .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), RangeRequest::Ranges(vec![range(0, 99)]));
+        assert_eq!(parse_range("bytes=900-", 1000), RangeRequest::Ranges(vec![range(900, 999)]));
+        assert_eq!(parse_range("bytes=-100", 1000), RangeRequest::Ranges(vec![range(900, 999)]));
+        assert_eq!(parse_range("bytes=990-2000", 1000), RangeRequest::Ranges(vec![range(990, 999)]));
+        assert_eq!(
+            parse_range("bytes=0-0, -1", 1000),
+            RangeRequest::Ranges(vec![range(0, 0), range(999, 999)])
+        );
+
+        assert_eq!(parse_range("bytes=1000-", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+
+        assert_eq!(parse_range("items=0-1", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=5-1", 1000), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=abc", 1000), RangeRequest::Full);
+    }
+
+    #[test]
+    fn test_conditional_headers() {
+        let modified = DateTime::parse_from_rfc3339("2024-01-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        let metadata = ObjectMetadata {
+            key: "a.epub".to_string(),
+            size: 10,
+            last_modified: Some(modified),
+            content_type: None,
+            etag: Some("\"abc\"".to_string()),
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, "W/\"abc\", \"def\"".parse().unwrap());
+        assert!(is_not_modified(&headers, &metadata));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MODIFIED_SINCE, http_date(modified).parse().unwrap());
+        assert!(is_not_modified(&headers, &metadata));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MODIFIED_SINCE, "Sun, 31 Dec 2023 12:00:00 GMT".parse().unwrap());
+        assert!(!is_not_modified(&headers, &metadata));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_RANGE, "\"abc\"".parse().unwrap());
+        assert!(range_applies(&headers, &metadata));
+        headers.insert(header::IF_RANGE, "\"old\"".parse().unwrap());
+        assert!(!range_applies(&headers, &metadata));
+    }
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition("Dune.epub", false),
+            "inline; filename=\"Dune.epub\"; filename*=UTF-8''Dune.epub"
+        );
+        assert!(content_disposition("Ça va.pdf", true)
+            .starts_with("attachment; filename=\"_a va.pdf\"; filename*=UTF-8''%C3%87a%20va.pdf"));
+    }
+}
@@ -0,0 +1,264 @@
+//! Library watcher routes
+//!
+//! `POST /events` accepts bucket event notifications (point a MinIO webhook
+//! target at it) and queues the touched book folders for a rescan.
+//! `GET /status` reports the last catalog scan.
+//! `POST /import/calibre` imports a Calibre `metadata.db` into the catalog.
+//! `PATCH /books/:id` edits a book's metadata and rewrites its `metadata.opf`;
+//! `GET /books/:id/history` lists edits and `POST .../:edit_id/revert`
+//! undoes one.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::db::CatalogRepository;
+use crate::error::{AppError, Result};
+use crate::library::{
+    BookEdit, BucketEvent, CalibreImporter, LibraryBook, LibraryChanges, MetadataEditor,
+    MetadataPatch, MissingBookFile, WatcherStatus,
+};
+use crate::state::AppState;
+
+/// Create the library router
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/status", get(watcher_status))
+        .route("/events", post(bucket_events))
+        .route("/import/calibre", post(import_calibre))
+        .route("/books/:id", get(get_book).patch(edit_book))
+        .route("/books/:id/history", get(book_history))
+        .route("/books/:id/history/:edit_id/revert", post(revert_edit))
+}
+
+/// Watcher status with the current catalog size
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatusResponse {
+    books: i64,
+    #[serde(flatten)]
+    watcher: WatcherStatus,
+}
+
+/// Report the last scan time, duration and errors
+///
+/// GET /api/v1/library/status
+async fn watcher_status(State(state): State<AppState>) -> Result<Json<StatusResponse>> {
+    let books = CatalogRepository::new(state.db()).count().await?;
+
+    Ok(Json(StatusResponse {
+        books,
+        watcher: state.library_watcher().status(),
+    }))
+}
+
+#[derive(Serialize)]
+struct EventsResponse {
+    queued: usize,
+}
+
+/// Queue folders named by a bucket event notification
+///
+/// POST /api/v1/library/events
+async fn bucket_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(event): Json<BucketEvent>,
+) -> Response {
+    if let Some(ref token) = state.config().library.webhook_token {
+        let authorized = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .is_some_and(|provided| provided == token);
+        if !authorized {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+
+    let keys = event.keys(&state.config().storage.bucket);
+    let queued = state
+        .library_watcher()
+        .queue_keys(keys.iter().map(String::as_str));
+
+    (StatusCode::ACCEPTED, Json(EventsResponse { queued })).into_response()
+}
+
+/// Calibre import source: a bucket key, or a server-local file
+#[derive(Debug, Deserialize)]
+pub struct CalibreImportRequest {
+    /// Key of `metadata.db` in storage (default: `metadata.db`)
+    pub key: Option<String>,
+    /// Local path of `metadata.db`, instead of `key`; must be inside the
+    /// configured `LIBRARY_IMPORT_DIR` (relative paths are resolved against it)
+    pub path: Option<String>,
+    /// Storage prefix of the library folders when importing from `path`
+    #[serde(default)]
+    pub root: String,
+}
+
+#[derive(Serialize)]
+struct CalibreImportResponse {
+    imported: usize,
+    missing: Vec<MissingBookFile>,
+}
+
+/// Import books from a Calibre `metadata.db`
+///
+/// POST /api/v1/library/import/calibre
+async fn import_calibre(
+    State(state): State<AppState>,
+    Json(request): Json<CalibreImportRequest>,
+) -> Result<Json<CalibreImportResponse>> {
+    let importer = CalibreImporter::new(state.storage().clone());
+    let import = match request.path {
+        Some(path) => {
+            let import_dir = state.config().library.import_dir.as_deref();
+            let path = resolve_import_path(import_dir, &path).await?;
+            importer.import_from_path(&path, &request.root).await?
+        }
+        None => {
+            let key = request.key.as_deref().unwrap_or("metadata.db");
+            importer.import_from_storage(key).await?
+        }
+    };
+
+    // Record the folders' objects too, so scans keep the imported metadata
+    // until the files change
+    let imported = import.books.len();
+    let changes = LibraryChanges {
+        added: import.books,
+        objects: import.objects,
+        ..Default::default()
+    };
+    CatalogRepository::new(state.db()).apply_changes(&changes).await?;
+
+    Ok(Json(CalibreImportResponse {
+        imported,
+        missing: import.missing,
+    }))
+}
+
+/// Resolve a requested `metadata.db` path inside the import directory
+///
+/// Both paths are canonicalized first, so `..` and symlinks can't escape it.
+async fn resolve_import_path(import_dir: Option<&str>, path: &str) -> Result<PathBuf> {
+    let import_dir = import_dir.ok_or_else(|| {
+        AppError::BadRequest("Importing from a path requires LIBRARY_IMPORT_DIR".to_string())
+    })?;
+    let import_dir = tokio::fs::canonicalize(import_dir).await?;
+
+    let not_found =
+        || AppError::BadRequest(format!("No such file in the import directory: {}", path));
+    let resolved = tokio::fs::canonicalize(import_dir.join(path))
+        .await
+        .map_err(|_| not_found())?;
+    if !resolved.starts_with(&import_dir) {
+        return Err(not_found());
+    }
+
+    Ok(resolved)
+}
+
+/// Get a catalog book
+///
+/// GET /api/v1/library/books/:id
+async fn get_book(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<LibraryBook>> {
+    let book = CatalogRepository::new(state.db())
+        .get(&id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Book not found: {}", id)))?;
+
+    Ok(Json(book))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EditQuery {
+    /// Move the book folder to match the edited author and title
+    #[serde(default)]
+    pub rename_folder: bool,
+}
+
+#[derive(Serialize)]
+struct EditResponse {
+    book: LibraryBook,
+    /// `None` when the patch changed nothing
+    edit: Option<BookEdit>,
+}
+
+/// Edit a book's metadata
+///
+/// PATCH /api/v1/library/books/:id?rename_folder=true
+async fn edit_book(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<EditQuery>,
+    Json(patch): Json<MetadataPatch>,
+) -> Result<Json<EditResponse>> {
+    let (book, edit) = MetadataEditor::new(state.storage(), state.db())
+        .edit(&id, &patch, query.rename_folder)
+        .await?;
+
+    Ok(Json(EditResponse { book, edit }))
+}
+
+/// List a book's metadata edits, newest first
+///
+/// GET /api/v1/library/books/:id/history
+async fn book_history(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<BookEdit>>> {
+    Ok(Json(CatalogRepository::new(state.db()).edits(&id).await?))
+}
+
+/// Revert the fields changed by an edit
+///
+/// POST /api/v1/library/books/:id/history/:edit_id/revert
+async fn revert_edit(
+    State(state): State<AppState>,
+    Path((id, edit_id)): Path<(String, i64)>,
+) -> Result<Json<EditResponse>> {
+    let (book, edit) = MetadataEditor::new(state.storage(), state.db())
+        .revert(&id, edit_id)
+        .await?;
+
+    Ok(Json(EditResponse { book, edit }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolve_import_path_stays_in_import_dir() {
+        let root = tempfile::tempdir().unwrap();
+        let import_dir = root.path().join("import");
+        std::fs::create_dir(&import_dir).unwrap();
+        std::fs::write(import_dir.join("metadata.db"), b"").unwrap();
+        std::fs::write(root.path().join("secret.db"), b"").unwrap();
+        let dir = import_dir.to_str();
+
+        let resolved = resolve_import_path(dir, "metadata.db").await.unwrap();
+        assert!(resolved.ends_with("import/metadata.db"));
+
+        let absolute = import_dir.join("metadata.db");
+        let absolute = absolute.to_str().unwrap();
+        assert!(resolve_import_path(dir, absolute).await.is_ok());
+
+        assert!(resolve_import_path(dir, "../secret.db").await.is_err());
+        let outside = root.path().join("secret.db");
+        let outside = outside.to_str().unwrap();
+        assert!(resolve_import_path(dir, outside).await.is_err());
+        assert!(resolve_import_path(None, "metadata.db").await.is_err());
+    }
+}
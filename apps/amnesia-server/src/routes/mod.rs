@@ -2,15 +2,18 @@
 
 pub mod annotations;
 pub mod bibliography;
+pub mod covers;
 // pub mod books;  // Deprecated - use documents API instead
 pub mod documents;
 pub mod extract;
 pub mod files;
 pub mod health;
 pub mod highlights;
+pub mod library;
 pub mod opds;
 pub mod pdf;
 pub mod progress;
 pub mod search;
 pub mod sync;
+pub mod tus;
 pub mod upload;
@@ -1,6 +1,13 @@
 //! OPDS catalog routes
 //!
-//! Serves OPDS 1.2 Atom feeds for book browsing and acquisition.
+//! Serves OPDS 1.2 Atom feeds under `/opds` and the same sections as OPDS
+//! 2.0 JSON under `/opds/v2`, plus an OpenSearch description at
+//! `/opds/opensearch.xml`. Book listings are paged and faceted (see
+//! `opds::FeedQuery`). PDFs and comics also get OPDS-PSE page streaming
+//! links to `/opds/pse/:id/:format/:page`.
+//!
+//! With `presigned_urls` enabled, acquisition links point straight at the
+//! bucket so downloads bypass the server.
 
 use axum::{
     extract::{Path, Query, State},
@@ -11,63 +18,81 @@ use axum::{
 };
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
 
-use crate::error::Result;
-use crate::library::{LibraryBook, LibraryScanner};
-use crate::opds::{serialize_feed, mime, OPDSEntry, OPDSFeed};
+use crate::db::{CatalogFilter, CatalogOrder, CatalogRepository};
+use crate::error::{AppError, Result};
+use crate::library::{LibraryBook, LibraryDocuments};
+use crate::opds::{
+    apply_facets, mime, serialize_feed, serialize_feed_json, serialize_opensearch, FeedFormat,
+    FeedQuery, OPDSEntry, OPDSFeed, OPDSGroup,
+};
 use crate::state::AppState;
 
-/// Cached library state
-#[derive(Clone)]
-pub struct LibraryCache {
-    books: Arc<RwLock<Vec<LibraryBook>>>,
-}
+/// Books in the root catalog's "Recently Added" group
+const ROOT_GROUP_SIZE: i64 = 10;
 
-impl LibraryCache {
-    pub fn new() -> Self {
-        Self {
-            books: Arc::new(RwLock::new(Vec::new())),
-        }
-    }
+/// Page width streamed when the reader doesn't give one
+const DEFAULT_PAGE_WIDTH: u32 = 1200;
 
-    pub async fn refresh(&self, scanner: &LibraryScanner) -> Result<()> {
-        let books = scanner.scan_library().await?;
-        *self.books.write().await = books;
-        Ok(())
-    }
+/// Widest page streamed
+const MAX_PAGE_WIDTH: u32 = 4000;
 
-    pub async fn get_books(&self) -> Vec<LibraryBook> {
-        self.books.read().await.clone()
-    }
+/// Create the OPDS router
+pub fn router() -> Router<AppState> {
+    catalog_routes(FeedFormat::Atom)
+        .route("/opensearch.xml", get(opensearch_description))
+        .route("/refresh", get(refresh_library))
+        .route("/pse/:id/:format/:page", get(stream_page))
+        .nest("/v2", catalog_routes(FeedFormat::Json))
 }
 
-/// Create the OPDS router
-pub fn router(cache: LibraryCache) -> Router<AppState> {
+/// Catalog sections, serialized as `format`
+fn catalog_routes(format: FeedFormat) -> Router<AppState> {
     Router::new()
-        .route("/", get(root_catalog))
-        .route("/all", get(all_books))
-        .route("/authors", get(authors_list))
-        .route("/author/:name", get(author_books))
-        .route("/series", get(series_list))
-        .route("/series/:name", get(series_books))
-        .route("/recent", get(recent_books))
-        .route("/search", get(search_books))
-        .route("/refresh", get(refresh_library))
-        .layer(axum::Extension(cache))
+        .route("/", get(move |state: State<AppState>| root_catalog(state, format)))
+        .route(
+            "/all",
+            get(move |state: State<AppState>, query: Query<FeedQuery>| {
+                all_books(state, query, format)
+            }),
+        )
+        .route("/authors", get(move |state: State<AppState>| authors_list(state, format)))
+        .route(
+            "/author/:name",
+            get(move |state: State<AppState>, name: Path<String>, query: Query<FeedQuery>| {
+                author_books(state, name, query, format)
+            }),
+        )
+        .route("/series", get(move |state: State<AppState>| series_list(state, format)))
+        .route(
+            "/series/:name",
+            get(move |state: State<AppState>, name: Path<String>, query: Query<FeedQuery>| {
+                series_books(state, name, query, format)
+            }),
+        )
+        .route("/recent", get(move |state: State<AppState>| recent_books(state, format)))
+        .route(
+            "/search",
+            get(move |state: State<AppState>, query: Query<FeedQuery>| {
+                search_books(state, query, format)
+            }),
+        )
 }
 
-/// Response with OPDS XML content type
-struct OPDSResponse(String);
+/// Feed response, serialized according to the feed's format
+struct OPDSResponse(OPDSFeed);
 
 impl IntoResponse for OPDSResponse {
     fn into_response(self) -> Response {
-        (
-            [(header::CONTENT_TYPE, mime::ATOM_XML)],
-            self.0,
-        )
-            .into_response()
+        let (body, content_type) = match self.0.format {
+            FeedFormat::Atom => (serialize_feed(&self.0), mime::ATOM_XML),
+            FeedFormat::Json => (serialize_feed_json(&self.0), mime::OPDS_JSON),
+        };
+
+        match body {
+            Ok(body) => ([(header::CONTENT_TYPE, content_type)], body).into_response(),
+            Err(e) => e.into_response(),
+        }
     }
 }
 
@@ -80,255 +105,297 @@ fn base_url(state: &AppState) -> String {
     )
 }
 
+/// Add book entries to a feed, presigning acquisition links when enabled
+///
+/// Page streaming links need a page count recorded in the catalog (see
+/// `LibraryDocuments::fill_page_counts`); files aren't opened here.
+async fn add_books(state: &AppState, feed: &mut OPDSFeed, books: Vec<LibraryBook>, base: &str) {
+    feed.add_books(&books, base);
+
+    if !state.config().storage.presigned_urls {
+        return;
+    }
+
+    let mut urls = HashMap::new();
+    for format in books.iter().flat_map(|book| &book.formats) {
+        if let Some(url) = state.presigned_url(&format.s3_key).await {
+            urls.insert(format.s3_key.clone(), url);
+        }
+    }
+    feed.use_direct_acquisition_links(base, &urls);
+}
+
 /// Root catalog
-async fn root_catalog(State(state): State<AppState>) -> Result<OPDSResponse> {
-    let feed = OPDSFeed::root_catalog(&base_url(&state));
-    let xml = serialize_feed(&feed)?;
-    Ok(OPDSResponse(xml))
+async fn root_catalog(State(state): State<AppState>, format: FeedFormat) -> Result<OPDSResponse> {
+    let base = base_url(&state);
+    let mut feed = OPDSFeed::root_catalog(format, &base);
+
+    // OPDS 2.0 clients show groups on the start page
+    if format == FeedFormat::Json {
+        let recent = CatalogRepository::new(state.db()).recent(ROOT_GROUP_SIZE).await?;
+        let href = format!("{}{}/recent", base, format.root());
+        let mut recent_feed = OPDSFeed::acquisition(format, "Recently Added", &href);
+        add_books(&state, &mut recent_feed, recent, &base).await;
+        feed.groups.push(OPDSGroup {
+            title: recent_feed.title,
+            href: Some(href),
+            entries: recent_feed.entries,
+        });
+    }
+
+    Ok(OPDSResponse(feed))
 }
 
 /// All books
 async fn all_books(
     State(state): State<AppState>,
-    axum::Extension(cache): axum::Extension<LibraryCache>,
+    Query(query): Query<FeedQuery>,
+    format: FeedFormat,
 ) -> Result<OPDSResponse> {
-    let books = cache.get_books().await;
+    let catalog = CatalogRepository::new(state.db());
     let base = base_url(&state);
+    let path = format!("{}{}/all", base, format.root());
+
+    let mut feed = OPDSFeed::acquisition(format, "All Books", &query.href(&path));
+    feed.add_up_link(format.root());
+    let scope = CatalogFilter::default();
+    let books = apply_facets(
+        &mut feed,
+        &catalog,
+        scope,
+        &query,
+        &path,
+        CatalogOrder::Title,
+    )
+    .await?;
+    add_books(&state, &mut feed, books, &base).await;
 
-    let mut feed = OPDSFeed::acquisition("All Books", &format!("{}/opds/all", base));
-    feed.links.push(crate::opds::OPDSLink {
-        href: "/opds".to_string(),
-        rel: Some(crate::opds::rel::UP.to_string()),
-        link_type: Some(mime::ATOM_CATALOG.to_string()),
-        title: None,
-    });
-    feed.add_books(&books, &base);
-
-    let xml = serialize_feed(&feed)?;
-    Ok(OPDSResponse(xml))
+    Ok(OPDSResponse(feed))
 }
 
 /// Authors list
-async fn authors_list(
-    State(state): State<AppState>,
-    axum::Extension(cache): axum::Extension<LibraryCache>,
-) -> Result<OPDSResponse> {
-    let books = cache.get_books().await;
+async fn authors_list(State(state): State<AppState>, format: FeedFormat) -> Result<OPDSResponse> {
+    let authors = CatalogRepository::new(state.db()).author_counts().await?;
     let base = base_url(&state);
+    let root = format!("{}{}", base, format.root());
 
-    // Group by author
-    let mut author_counts: HashMap<String, usize> = HashMap::new();
-    for book in &books {
-        if let Some(ref author) = book.author {
-            *author_counts.entry(author.clone()).or_insert(0) += 1;
-        }
-    }
-
-    let mut authors: Vec<_> = author_counts.into_iter().collect();
-    authors.sort_by(|a, b| a.0.cmp(&b.0));
-
-    let mut feed = OPDSFeed::navigation("Authors", &format!("{}/opds/authors", base));
-    feed.links.push(crate::opds::OPDSLink {
-        href: "/opds".to_string(),
-        rel: Some(crate::opds::rel::UP.to_string()),
-        link_type: Some(mime::ATOM_CATALOG.to_string()),
-        title: None,
-    });
+    let mut feed = OPDSFeed::navigation(format, "Authors", &format!("{}/authors", root));
+    feed.add_up_link(format.root());
 
     for (author, count) in authors {
         let encoded = urlencoding::encode(&author);
         feed.add_navigation_entry(OPDSEntry::navigation(
+            format,
             &author,
             &format!("{} books", count),
-            &format!("{}/opds/author/{}", base, encoded),
+            &format!("{}/author/{}", root, encoded),
         ));
     }
 
-    let xml = serialize_feed(&feed)?;
-    Ok(OPDSResponse(xml))
+    Ok(OPDSResponse(feed))
 }
 
 /// Books by a specific author
 async fn author_books(
     State(state): State<AppState>,
-    axum::Extension(cache): axum::Extension<LibraryCache>,
     Path(name): Path<String>,
+    Query(query): Query<FeedQuery>,
+    format: FeedFormat,
 ) -> Result<OPDSResponse> {
-    let books = cache.get_books().await;
+    let catalog = CatalogRepository::new(state.db());
     let base = base_url(&state);
+    let path = format!("{}{}/author/{}", base, format.root(), urlencoding::encode(&name));
+
+    let mut feed = OPDSFeed::acquisition(format, &name, &query.href(&path));
+    feed.add_up_link(&format!("{}/authors", format.root()));
+    let scope = CatalogFilter {
+        author: Some(name),
+        ..Default::default()
+    };
+    let author_books = apply_facets(
+        &mut feed,
+        &catalog,
+        scope,
+        &query,
+        &path,
+        CatalogOrder::Title,
+    )
+    .await?;
+    add_books(&state, &mut feed, author_books, &base).await;
 
-    let author_books: Vec<_> = books
-        .iter()
-        .filter(|b| b.author.as_deref() == Some(&name))
-        .cloned()
-        .collect();
-
-    let mut feed = OPDSFeed::acquisition(&name, &format!("{}/opds/author/{}", base, urlencoding::encode(&name)));
-    feed.links.push(crate::opds::OPDSLink {
-        href: "/opds/authors".to_string(),
-        rel: Some(crate::opds::rel::UP.to_string()),
-        link_type: Some(mime::ATOM_CATALOG.to_string()),
-        title: None,
-    });
-    feed.add_books(&author_books, &base);
-
-    let xml = serialize_feed(&feed)?;
-    Ok(OPDSResponse(xml))
+    Ok(OPDSResponse(feed))
 }
 
 /// Series list
-async fn series_list(
-    State(state): State<AppState>,
-    axum::Extension(cache): axum::Extension<LibraryCache>,
-) -> Result<OPDSResponse> {
-    let books = cache.get_books().await;
+async fn series_list(State(state): State<AppState>, format: FeedFormat) -> Result<OPDSResponse> {
+    let series_list = CatalogRepository::new(state.db()).series_counts().await?;
     let base = base_url(&state);
+    let root = format!("{}{}", base, format.root());
 
-    // Group by series
-    let mut series_counts: HashMap<String, usize> = HashMap::new();
-    for book in &books {
-        if let Some(ref series) = book.series {
-            *series_counts.entry(series.clone()).or_insert(0) += 1;
-        }
-    }
-
-    let mut series_list: Vec<_> = series_counts.into_iter().collect();
-    series_list.sort_by(|a, b| a.0.cmp(&b.0));
-
-    let mut feed = OPDSFeed::navigation("Series", &format!("{}/opds/series", base));
-    feed.links.push(crate::opds::OPDSLink {
-        href: "/opds".to_string(),
-        rel: Some(crate::opds::rel::UP.to_string()),
-        link_type: Some(mime::ATOM_CATALOG.to_string()),
-        title: None,
-    });
+    let mut feed = OPDSFeed::navigation(format, "Series", &format!("{}/series", root));
+    feed.add_up_link(format.root());
 
     for (series, count) in series_list {
         let encoded = urlencoding::encode(&series);
         feed.add_navigation_entry(OPDSEntry::navigation(
+            format,
             &series,
             &format!("{} books", count),
-            &format!("{}/opds/series/{}", base, encoded),
+            &format!("{}/series/{}", root, encoded),
         ));
     }
 
-    let xml = serialize_feed(&feed)?;
-    Ok(OPDSResponse(xml))
+    Ok(OPDSResponse(feed))
 }
 
 /// Books in a specific series
 async fn series_books(
     State(state): State<AppState>,
-    axum::Extension(cache): axum::Extension<LibraryCache>,
     Path(name): Path<String>,
+    Query(query): Query<FeedQuery>,
+    format: FeedFormat,
 ) -> Result<OPDSResponse> {
-    let books = cache.get_books().await;
+    let catalog = CatalogRepository::new(state.db());
     let base = base_url(&state);
+    let path = format!("{}{}/series/{}", base, format.root(), urlencoding::encode(&name));
+
+    let mut feed = OPDSFeed::acquisition(format, &name, &query.href(&path));
+    feed.add_up_link(&format!("{}/series", format.root()));
+    let scope = CatalogFilter {
+        series: Some(name),
+        ..Default::default()
+    };
+    // Sorted by series index
+    let series_books = apply_facets(
+        &mut feed,
+        &catalog,
+        scope,
+        &query,
+        &path,
+        CatalogOrder::Series,
+    )
+    .await?;
+    add_books(&state, &mut feed, series_books, &base).await;
 
-    let mut series_books: Vec<_> = books
-        .iter()
-        .filter(|b| b.series.as_deref() == Some(&name))
-        .cloned()
-        .collect();
-
-    // Sort by series index
-    series_books.sort_by(|a, b| {
-        a.series_index
-            .unwrap_or(0.0)
-            .partial_cmp(&b.series_index.unwrap_or(0.0))
-            .unwrap_or(std::cmp::Ordering::Equal)
-    });
-
-    let mut feed = OPDSFeed::acquisition(&name, &format!("{}/opds/series/{}", base, urlencoding::encode(&name)));
-    feed.links.push(crate::opds::OPDSLink {
-        href: "/opds/series".to_string(),
-        rel: Some(crate::opds::rel::UP.to_string()),
-        link_type: Some(mime::ATOM_CATALOG.to_string()),
-        title: None,
-    });
-    feed.add_books(&series_books, &base);
-
-    let xml = serialize_feed(&feed)?;
-    Ok(OPDSResponse(xml))
+    Ok(OPDSResponse(feed))
 }
 
 /// Recently added books
-async fn recent_books(
-    State(state): State<AppState>,
-    axum::Extension(cache): axum::Extension<LibraryCache>,
-) -> Result<OPDSResponse> {
-    let mut books = cache.get_books().await;
+async fn recent_books(State(state): State<AppState>, format: FeedFormat) -> Result<OPDSResponse> {
+    // The 50 most recently added
+    let recent = CatalogRepository::new(state.db()).recent(50).await?;
     let base = base_url(&state);
 
-    // Sort by added date, most recent first
-    books.sort_by(|a, b| b.added_at.cmp(&a.added_at));
-
-    // Take the 50 most recent
-    let recent: Vec<_> = books.into_iter().take(50).collect();
-
-    let mut feed = OPDSFeed::acquisition("Recent Books", &format!("{}/opds/recent", base));
-    feed.links.push(crate::opds::OPDSLink {
-        href: "/opds".to_string(),
-        rel: Some(crate::opds::rel::UP.to_string()),
-        link_type: Some(mime::ATOM_CATALOG.to_string()),
-        title: None,
-    });
-    feed.add_books(&recent, &base);
-
-    let xml = serialize_feed(&feed)?;
-    Ok(OPDSResponse(xml))
-}
+    let mut feed = OPDSFeed::acquisition(
+        format,
+        "Recent Books",
+        &format!("{}{}/recent", base, format.root()),
+    );
+    feed.add_up_link(format.root());
+    add_books(&state, &mut feed, recent, &base).await;
 
-#[derive(Deserialize)]
-struct SearchQuery {
-    q: String,
+    Ok(OPDSResponse(feed))
 }
 
 /// Search books
+///
+/// Matches title, author, series or tags; `author:` and `title:`
+/// qualifiers narrow a term to one field.
 async fn search_books(
     State(state): State<AppState>,
-    axum::Extension(cache): axum::Extension<LibraryCache>,
-    Query(query): Query<SearchQuery>,
+    Query(query): Query<FeedQuery>,
+    format: FeedFormat,
 ) -> Result<OPDSResponse> {
-    let books = cache.get_books().await;
+    let terms = query.q.clone().unwrap_or_default();
+    let catalog = CatalogRepository::new(state.db());
     let base = base_url(&state);
-    let q = query.q.to_lowercase();
+    let path = format!("{}{}/search", base, format.root());
+
+    let mut feed = OPDSFeed::acquisition(format, &format!("Search: {}", terms), &query.href(&path));
+    feed.add_up_link(format.root());
+    let scope = CatalogFilter {
+        search: Some(terms),
+        ..Default::default()
+    };
+    let results = apply_facets(
+        &mut feed,
+        &catalog,
+        scope,
+        &query,
+        &path,
+        CatalogOrder::Title,
+    )
+    .await?;
+    add_books(&state, &mut feed, results, &base).await;
 
-    // Simple search: match title, author, or tags
-    let results: Vec<_> = books
-        .iter()
-        .filter(|b| {
-            b.title.to_lowercase().contains(&q)
-                || b.author.as_ref().map_or(false, |a| a.to_lowercase().contains(&q))
-                || b.tags.iter().any(|t| t.to_lowercase().contains(&q))
-                || b.series.as_ref().map_or(false, |s| s.to_lowercase().contains(&q))
-        })
-        .cloned()
-        .collect();
+    Ok(OPDSResponse(feed))
+}
 
-    let mut feed = OPDSFeed::acquisition(
-        &format!("Search: {}", query.q),
-        &format!("{}/opds/search?q={}", base, urlencoding::encode(&query.q)),
-    );
-    feed.links.push(crate::opds::OPDSLink {
-        href: "/opds".to_string(),
-        rel: Some(crate::opds::rel::UP.to_string()),
-        link_type: Some(mime::ATOM_CATALOG.to_string()),
-        title: None,
-    });
-    feed.add_books(&results, &base);
-
-    let xml = serialize_feed(&feed)?;
-    Ok(OPDSResponse(xml))
+/// OpenSearch description of the catalog search
+///
+/// GET /opds/opensearch.xml
+async fn opensearch_description(State(state): State<AppState>) -> Result<Response> {
+    let xml = serialize_opensearch(&base_url(&state))?;
+    Ok(([(header::CONTENT_TYPE, mime::OPENSEARCH)], xml).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageQuery {
+    max_width: Option<u32>,
 }
 
-/// Refresh library cache
-async fn refresh_library(
+/// Stream one page of a PDF or comic (OPDS-PSE), 0-based
+///
+/// GET /opds/pse/:id/:format/:page?maxWidth=
+async fn stream_page(
     State(state): State<AppState>,
-    axum::Extension(cache): axum::Extension<LibraryCache>,
-) -> Result<String> {
-    let scanner = LibraryScanner::new(state.s3_client().clone());
-    cache.refresh(&scanner).await?;
-    let count = cache.get_books().await.len();
-    Ok(format!("Library refreshed: {} books", count))
+    Path((id, format, page)): Path<(String, String, usize)>,
+    Query(query): Query<PageQuery>,
+) -> Result<Response> {
+    let book = CatalogRepository::new(state.db())
+        .get(&id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Book not found: {}", id)))?;
+    let book_format = book
+        .formats
+        .iter()
+        .find(|f| f.format.name() == format && LibraryDocuments::is_page_based(f))
+        .ok_or_else(|| AppError::NotFound(format!("No {} pages for book {}", format, id)))?;
+
+    let max_width = query
+        .max_width
+        .unwrap_or(DEFAULT_PAGE_WIDTH)
+        .clamp(1, MAX_PAGE_WIDTH);
+    let result = state
+        .library_documents()
+        .render_page(&book, book_format, page, max_width)
+        .await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, result.format.content_type()),
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+        ],
+        result.data,
+    )
+        .into_response())
+}
+
+/// Refresh the library catalog from storage
+///
+/// Only folders whose objects changed since the last refresh are re-read.
+async fn refresh_library(State(state): State<AppState>) -> Result<String> {
+    let changes = state.library_watcher().scan_now().await?;
+    let count = CatalogRepository::new(state.db()).count().await?;
+    if changes.is_empty() {
+        return Ok(format!("Library up to date: {} books", count));
+    }
+    Ok(format!(
+        "Library refreshed: {} books ({} added, {} updated, {} removed)",
+        count,
+        changes.added.len(),
+        changes.updated.len(),
+        changes.removed.len()
+    ))
 }
@@ -19,6 +19,10 @@
 //! - Render pages
 //! - Get text layers
 //! - Search content
+//! - Export PDFs with stored annotations written in
+//! - Fill form fields, saving the result as a new revision
+
+use std::collections::HashSet;
 
 use axum::{
     body::Body,
@@ -31,12 +35,17 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 
+use crate::annotations::{
+    import_id, Annotation, AnnotationQuery, AnnotationRepository, AnnotationType, Selector,
+};
 use crate::db::{CreateHighlight, Highlight, HighlightRepository, UpdateHighlight};
 use crate::document::TocEntry;
+use crate::library::RESERVED_PREFIX;
 use crate::ocr::{OcrRect, OcrRequest, OcrResult, OcrService, OcrServiceConfig};
 use crate::pdf::{
-    FormField, FormInfo, ImageFormat, PageRenderRequest, ParsedPdf, PdfMetadata, PdfSearchResult,
-    SignatureInfo, TextLayer,
+    ExtractionOptions, FillFormRequest, FillFormResult, FormField, FormInfo, ImageFormat,
+    NormalizedRect, PageRenderRequest, ParsedPdf, PdfMetadata, PdfSearchResult, SignatureInfo,
+    TextLayer, WrittenAnnotation, WrittenAnnotationType,
 };
 use crate::state::AppState;
 
@@ -238,10 +247,12 @@ pub fn router() -> Router<AppState> {
                 .put(update_annotation)
                 .delete(delete_annotation),
         )
+        .route("/:id/export", get(export_pdf))
         // Forms (Phase 9)
         .route("/:id/forms", get(get_form_info))
         .route("/:id/forms/fields", get(list_form_fields))
         .route("/:id/forms/signatures", get(list_signatures))
+        .route("/:id/forms/fill", post(fill_form))
         // Allow up to 200MB uploads for large PDFs
         .layer(DefaultBodyLimit::max(200 * 1024 * 1024))
         // Add deprecation headers to all responses
@@ -916,6 +927,230 @@ async fn delete_annotation(
     }
 }
 
+// ============================================================================
+// Export
+// ============================================================================
+
+/// Query parameters for PDF export
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportQuery {
+    /// Write stored highlights and notes into the PDF (default: false)
+    #[serde(default)]
+    pub annotations: bool,
+    /// Only export annotations of this user
+    pub user_id: Option<String>,
+}
+
+/// Export a copy of a PDF
+///
+/// With `annotations=true`, every stored PDF highlight and annotation for
+/// the book is written in as a native annotation: highlights and underlines
+/// with QuadPoints from their rects, notes as Text annotations. Records
+/// imported from this PDF's own annotations (see
+/// [`crate::annotations::import_annotations`]) are already in it and are
+/// not written again. The number written is returned in the
+/// `X-Annotation-Count` header.
+async fn export_pdf(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    if !state.pdf_cache().contains(&id).await {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(format!("PDF '{}' not found", id))),
+        ));
+    }
+
+    let mut annotations = Vec::new();
+    let mut users = HashSet::new();
+    if query.annotations {
+        let highlights = HighlightRepository::new(state.db())
+            .list_for_book(&id, query.user_id.as_deref())
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse::with_details(
+                        "Failed to list highlights",
+                        e.to_string(),
+                    )),
+                )
+            })?;
+        let highlights: Vec<_> = highlights.iter().filter(|h| h.is_pdf()).collect();
+        users.extend(highlights.iter().map(|h| h.user_id.clone()));
+        annotations.extend(highlights.into_iter().filter_map(highlight_to_written));
+
+        let stored = AnnotationRepository::new(state.db())
+            .list(&AnnotationQuery {
+                book_id: Some(id.clone()),
+                user_id: query.user_id.clone(),
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse::with_details(
+                        "Failed to list annotations",
+                        e.to_string(),
+                    )),
+                )
+            })?;
+        users.extend(stored.iter().map(|a| a.user_id.clone()));
+        annotations.extend(stored.iter().filter_map(annotation_to_written));
+    }
+
+    let book_id = id.clone();
+    let (data, written) = state
+        .pdf_cache()
+        .with_parser(&id, move |parser| {
+            if !annotations.is_empty() {
+                // Import IDs of the annotations already in the file
+                let existing = parser.extract_annotations(&ExtractionOptions {
+                    types: Vec::new(),
+                    pages: Vec::new(),
+                    include_text: true,
+                })?;
+                let imported: HashSet<String> = existing
+                    .iter()
+                    .flat_map(|extracted| {
+                        users
+                            .iter()
+                            .map(|user_id| import_id(&book_id, user_id.as_deref(), extracted))
+                    })
+                    .collect();
+                annotations.retain(|a| a.name.as_ref().is_none_or(|name| !imported.contains(name)));
+            }
+            parser.export_with_annotations(&annotations)
+        })
+        .await
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse::new(format!("PDF '{}' not found", id))),
+            )
+        })?
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::with_details(
+                    format!("Failed to export PDF '{}'", id),
+                    e.to_string(),
+                )),
+            )
+        })?;
+
+    let filename: String = id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/pdf")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.pdf\"", filename),
+        )
+        .header("X-Annotation-Count", written)
+        .body(Body::from(data))
+        .unwrap();
+
+    Ok(response)
+}
+
+/// Convert a stored PDF highlight for export
+///
+/// Uses the highlight's rects, or its region if it has none.
+fn highlight_to_written(highlight: &Highlight) -> Option<WrittenAnnotation> {
+    let page = u32::try_from(highlight.page?).ok()?;
+    let rects = match highlight.get_rects().filter(|rects| !rects.is_empty()) {
+        Some(rects) => rects
+            .iter()
+            .map(|r| NormalizedRect {
+                x: r.x,
+                y: r.y,
+                width: r.width,
+                height: r.height,
+            })
+            .collect(),
+        None => highlight
+            .get_region()
+            .map(|r| NormalizedRect {
+                x: r.x,
+                y: r.y,
+                width: r.width,
+                height: r.height,
+            })
+            .into_iter()
+            .collect(),
+    };
+    let annotation_type = match highlight.annotation_type.as_str() {
+        "underline" => WrittenAnnotationType::Underline,
+        "note" => WrittenAnnotationType::Text,
+        _ => WrittenAnnotationType::Highlight,
+    };
+
+    Some(WrittenAnnotation {
+        annotation_type,
+        page,
+        rects,
+        color: Some(highlight.color.clone()),
+        contents: highlight.annotation.clone(),
+        author: None,
+        name: Some(highlight.id.clone()),
+        modified: chrono::DateTime::parse_from_rfc3339(&highlight.updated_at)
+            .ok()
+            .map(|date| date.with_timezone(&chrono::Utc)),
+    })
+}
+
+/// Convert a stored PDF annotation for export
+///
+/// Uses the `PdfRegion` selectors on the annotation's page as its rects, and
+/// its style color. Bookmarks and EPUB annotations are skipped.
+fn annotation_to_written(annotation: &Annotation) -> Option<WrittenAnnotation> {
+    let annotation_type = match annotation.annotation_type {
+        AnnotationType::Highlight => WrittenAnnotationType::Highlight,
+        AnnotationType::Underline => WrittenAnnotationType::Underline,
+        AnnotationType::Note => WrittenAnnotationType::Text,
+        AnnotationType::Bookmark => return None,
+    };
+    let page = annotation.pdf_page()?;
+    let rects = annotation
+        .target
+        .selectors
+        .iter()
+        .filter_map(|selector| match selector {
+            Selector::PdfRegion { page: p, rect } if *p == page => Some(NormalizedRect {
+                x: rect.x,
+                y: rect.y,
+                width: rect.width,
+                height: rect.height,
+            }),
+            _ => None,
+        })
+        .collect();
+
+    Some(WrittenAnnotation {
+        annotation_type,
+        page: u32::try_from(page).ok()?,
+        rects,
+        color: annotation.style.as_ref().map(|style| style.color.clone()),
+        contents: annotation.body.as_ref().and_then(|body| body.value.clone()),
+        author: None,
+        name: Some(annotation.id.clone()),
+        modified: Some(annotation.updated_at),
+    })
+}
+
 // ============================================================================
 // Form Endpoints (Phase 9)
 // ============================================================================
@@ -1047,12 +1282,120 @@ async fn list_form_fields(
     }))
 }
 
+/// Request body for filling form fields
+#[derive(Debug, Deserialize)]
+pub struct FillFormBody {
+    /// Field values to set
+    pub fields: Vec<FillFormRequest>,
+    /// Draw the fields into the page content and remove the form
+    #[serde(default)]
+    pub flatten: bool,
+}
+
+/// Fill form fields in a PDF
+///
+/// Values are validated against each field's type and options; invalid
+/// values are reported in `errors` and leave the field unchanged. Calculated
+/// fields are recalculated. If any field was updated, the filled PDF is
+/// stored as a new revision under the reserved `.amnesia/revisions/` prefix
+/// and replaces the cached copy. The book's own file is left alone, so the
+/// catalog, OPDS and deduplication keep seeing the original; the revision is
+/// reached through the returned `revisionKey`, and the cache loses the filled
+/// values when the PDF is evicted.
+async fn fill_form(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(body): Json<FillFormBody>,
+) -> Result<Json<FillFormResult>, (StatusCode, Json<ErrorResponse>)> {
+    if !state.pdf_cache().contains(&id).await {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(format!("PDF '{}' not found", id))),
+        ));
+    }
+    let FillFormBody { fields, flatten } = body;
+    if fields.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new("No fields to fill")),
+        ));
+    }
+
+    let (mut result, filled) = state
+        .pdf_cache()
+        .with_parser(&id, move |parser| parser.fill_form(&fields, flatten))
+        .await
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse::new(format!("PDF '{}' not found", id))),
+            )
+        })?
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::with_details(
+                    "Failed to fill form",
+                    e.to_string(),
+                )),
+            )
+        })?;
+
+    let Some(filled) = filled else {
+        return Ok(Json(result));
+    };
+
+    // Revisions are kept out of the library scan, which would otherwise
+    // pick them up as extra formats of the book
+    let revision_key = format!(
+        "{}revisions/{}/{}.pdf",
+        RESERVED_PREFIX,
+        id,
+        chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    );
+
+    state
+        .storage()
+        .put_object(&revision_key, filled.clone(), "application/pdf")
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::with_details(
+                    "Failed to store filled PDF",
+                    e.to_string(),
+                )),
+            )
+        })?;
+
+    state.pdf_cache().remove(&id).await;
+    state
+        .pdf_cache()
+        .load_from_bytes(&filled, id.clone())
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::with_details(
+                    "Failed to load filled PDF",
+                    e.to_string(),
+                )),
+            )
+        })?;
+
+    result.revision_key = Some(revision_key);
+    Ok(Json(result))
+}
+
 /// List all digital signatures in a PDF
 ///
 /// Returns information about signature fields including:
 /// - Signer name
 /// - Signing time and reason
-/// - Validation status (note: full cryptographic validation not yet implemented)
+/// - Validation status: byte range digest, signature and certificate chain
+///   (against the configured trust anchors)
+/// - Whether the document was modified after signing
+/// - Signer certificate details
 async fn list_signatures(
     State(state): State<AppState>,
     Path(id): Path<String>,
@@ -1065,10 +1408,11 @@ async fn list_signatures(
         ));
     }
 
-    // Get signatures
+    // Get signatures, validated against the configured trust anchors
+    let trust_anchors = state.trust_anchors().clone();
     let signatures = state
         .pdf_cache()
-        .with_parser(&id, |parser| parser.get_signatures())
+        .with_parser(&id, move |parser| parser.get_signatures(&trust_anchors))
         .await
         .ok_or_else(|| {
             (
@@ -0,0 +1,413 @@
+//! tus Upload Routes
+//!
+//! tus 1.0 resumable uploads for third-party clients (Uppy, tus-js-client,
+//! tusd-compatible tools). Uploads share sessions, chunk storage and the
+//! ingest pipeline with the up2k endpoints in `upload.rs`.
+//!
+//! Endpoints:
+//! - OPTIONS /api/v1/tus - Server capabilities
+//! - POST /api/v1/tus - Create an upload (optionally with initial data)
+//! - HEAD /api/v1/tus/:id - Get the current offset
+//! - PATCH /api/v1/tus/:id - Append data at the current offset
+//! - DELETE /api/v1/tus/:id - Terminate an upload
+//!
+//! Once all bytes have arrived the upload is ingested as a book; the final
+//! PATCH response carries the new book ID in `X-Book-Id`.
+
+use axum::{
+    body::Body,
+    extract::{OriginalUri, Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{head, post},
+    Router,
+};
+use futures::StreamExt;
+
+use crate::routes::upload::{finalize_session, is_valid_file_type, UploadState};
+use crate::state::AppState;
+use crate::upload::chunk_store::compute_hash;
+use crate::upload::tus::{
+    creation_request, encode_metadata, parse_metadata, ChunkAppender, TusError, UploadChecksum,
+    MAX_CHECKSUM_BODY_SIZE, OFFSET_OCTET_STREAM, TUS_CHECKSUM_ALGORITHMS, TUS_EXTENSIONS,
+    TUS_VERSION,
+};
+use crate::upload::{SessionStatus, UploadError, UploadSession, MAX_FILE_SIZE};
+
+const TUS_RESUMABLE: &str = "tus-resumable";
+const UPLOAD_OFFSET: &str = "upload-offset";
+const UPLOAD_LENGTH: &str = "upload-length";
+const UPLOAD_METADATA: &str = "upload-metadata";
+const UPLOAD_CHECKSUM: &str = "upload-checksum";
+const UPLOAD_EXPIRES: &str = "upload-expires";
+
+// ============================================================================
+// Error Response
+// ============================================================================
+
+impl IntoResponse for TusError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        let mut response = (status, self.to_string()).into_response();
+
+        if matches!(self, TusError::UnsupportedVersion(_)) {
+            response
+                .headers_mut()
+                .insert("tus-version", HeaderValue::from_static(TUS_VERSION));
+        }
+
+        response
+    }
+}
+
+// ============================================================================
+// Router
+// ============================================================================
+
+/// Create the tus router
+pub fn router(state: UploadState) -> Router<AppState> {
+    Router::new()
+        .route("/", post(create_upload).options(options))
+        .route(
+            "/:id",
+            head(upload_offset)
+                .patch(append)
+                .delete(terminate)
+                .options(options),
+        )
+        .layer(middleware::map_response(add_tus_resumable))
+        .with_state(state)
+}
+
+/// Every tus response carries the protocol version
+async fn add_tus_resumable(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+    response
+}
+
+// ============================================================================
+// Handlers
+// ============================================================================
+
+/// OPTIONS /api/v1/tus
+///
+/// Advertise protocol version, extensions and limits.
+async fn options() -> impl IntoResponse {
+    (
+        StatusCode::NO_CONTENT,
+        [
+            ("tus-version", TUS_VERSION.to_string()),
+            ("tus-extension", TUS_EXTENSIONS.to_string()),
+            ("tus-max-size", MAX_FILE_SIZE.to_string()),
+            ("tus-checksum-algorithm", TUS_CHECKSUM_ALGORITHMS.to_string()),
+        ],
+    )
+}
+
+/// POST /api/v1/tus
+///
+/// Create an upload from `Upload-Length` and `Upload-Metadata`
+/// (`filename`, `filetype`). A body is appended right away
+/// (creation-with-upload).
+async fn create_upload(
+    State(state): State<UploadState>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, TusError> {
+    check_version(&headers)?;
+
+    let file_size: u64 = header_str(&headers, UPLOAD_LENGTH)
+        .and_then(|v| v.parse().ok())
+        .ok_or(TusError::InvalidHeader("Upload-Length"))?;
+
+    if file_size == 0 {
+        return Err(TusError::InvalidHeader("Upload-Length"));
+    }
+
+    if file_size > MAX_FILE_SIZE {
+        return Err(UploadError::FileTooLarge {
+            size: file_size,
+            max: MAX_FILE_SIZE,
+        }
+        .into());
+    }
+
+    let metadata = match header_str(&headers, UPLOAD_METADATA) {
+        Some(value) => parse_metadata(value)?,
+        None => Default::default(),
+    };
+
+    let request = creation_request(file_size, &metadata);
+    if !is_valid_file_type(&request.mime_type) {
+        return Err(UploadError::InvalidFileType(request.mime_type).into());
+    }
+
+    let session = state.session_manager.create_session(&request).await?;
+
+    tracing::info!(
+        session_id = %session.id,
+        file_name = %session.file_name,
+        file_size = file_size,
+        "tus upload created"
+    );
+
+    let location = format!("{}/{}", uri.path().trim_end_matches('/'), session.id);
+    let mut response_headers = vec![
+        (header::LOCATION.to_string(), location),
+        (UPLOAD_EXPIRES.to_string(), http_date(&session)),
+    ];
+
+    // creation-with-upload
+    if header_str(&headers, header::CONTENT_TYPE.as_str()) == Some(OFFSET_OCTET_STREAM) {
+        let (session, book_id) = receive(&state, session, &headers, body).await?;
+        response_headers.push((UPLOAD_OFFSET.to_string(), session.upload_offset.to_string()));
+        if let Some(book_id) = book_id {
+            response_headers.push(("x-book-id".to_string(), book_id));
+        }
+    }
+
+    Ok(with_headers(StatusCode::CREATED, response_headers))
+}
+
+/// HEAD /api/v1/tus/:id
+///
+/// Report how many bytes have been received.
+async fn upload_offset(
+    State(state): State<UploadState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, TusError> {
+    check_version(&headers)?;
+
+    let session = state.session_manager.get_session_by_str(&id).await?;
+    if session.is_expired() {
+        return Err(UploadError::SessionExpired(id).into());
+    }
+
+    Ok(with_headers(
+        StatusCode::OK,
+        vec![
+            (UPLOAD_OFFSET.to_string(), session.upload_offset.to_string()),
+            (UPLOAD_LENGTH.to_string(), session.file_size.to_string()),
+            (
+                UPLOAD_METADATA.to_string(),
+                encode_metadata(&[
+                    ("filename", session.file_name.as_str()),
+                    ("filetype", session.mime_type.as_str()),
+                ]),
+            ),
+            (header::CACHE_CONTROL.to_string(), "no-store".to_string()),
+        ],
+    ))
+}
+
+/// PATCH /api/v1/tus/:id
+///
+/// Append the body at `Upload-Offset`. Completing the upload ingests the book.
+async fn append(
+    State(state): State<UploadState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, TusError> {
+    check_version(&headers)?;
+
+    if header_str(&headers, header::CONTENT_TYPE.as_str()) != Some(OFFSET_OCTET_STREAM) {
+        return Err(TusError::InvalidContentType);
+    }
+
+    let offset: u64 = header_str(&headers, UPLOAD_OFFSET)
+        .and_then(|v| v.parse().ok())
+        .ok_or(TusError::InvalidHeader("Upload-Offset"))?;
+
+    let session = state.session_manager.get_session_by_str(&id).await?;
+
+    // Held until the response is built; a concurrent PATCH gets 409
+    let _lock = state
+        .session_manager
+        .lock_append(session.id)
+        .ok_or(TusError::Locked)?;
+    // Re-read under the lock so the offset check sees the latest append
+    let session = state.session_manager.get_session(session.id).await?;
+    if session.is_expired() {
+        return Err(UploadError::SessionExpired(id).into());
+    }
+
+    if offset != session.upload_offset {
+        return Err(TusError::OffsetMismatch {
+            expected: session.upload_offset,
+            actual: offset,
+        });
+    }
+
+    let (session, book_id) = receive(&state, session, &headers, body).await?;
+
+    let mut response_headers = vec![
+        (UPLOAD_OFFSET.to_string(), session.upload_offset.to_string()),
+        (UPLOAD_EXPIRES.to_string(), http_date(&session)),
+    ];
+    if let Some(book_id) = book_id {
+        response_headers.push(("x-book-id".to_string(), book_id));
+    }
+
+    Ok(with_headers(StatusCode::NO_CONTENT, response_headers))
+}
+
+/// DELETE /api/v1/tus/:id
+///
+/// Terminate an upload and discard its chunks.
+async fn terminate(
+    State(state): State<UploadState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, TusError> {
+    check_version(&headers)?;
+
+    let session = state.session_manager.get_session_by_str(&id).await?;
+    state.session_manager.cancel_session(session.id).await?;
+
+    // Clean up chunks
+    let _ = state.chunk_store.delete_session_chunks(session.id).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Append a request body to an upload and finalize it once complete
+///
+/// Without a checksum the body is streamed into chunks as it arrives, so an
+/// interrupted request keeps its progress. With `Upload-Checksum` the body is
+/// buffered and verified before anything is stored.
+///
+/// Returns the updated session and, if the upload was finalized, the book ID.
+async fn receive(
+    state: &UploadState,
+    session: UploadSession,
+    headers: &HeaderMap,
+    body: Body,
+) -> Result<(UploadSession, Option<String>), TusError> {
+    let checksum = header_str(headers, UPLOAD_CHECKSUM)
+        .map(UploadChecksum::parse)
+        .transpose()?;
+
+    let mut appender =
+        ChunkAppender::new(&state.session_manager, &state.chunk_store, session).await?;
+
+    let result = match checksum {
+        Some(checksum) => {
+            let data = axum::body::to_bytes(body, MAX_CHECKSUM_BODY_SIZE)
+                .await
+                .map_err(|_| TusError::BodyTooLarge(MAX_CHECKSUM_BODY_SIZE))?;
+
+            if !checksum.verify(&data) {
+                return Err(TusError::ChecksumMismatch);
+            }
+
+            appender.push(&data).await
+        }
+        None => {
+            let mut stream = body.into_data_stream();
+            let mut result = Ok(());
+            while let Some(frame) = stream.next().await {
+                result = match frame {
+                    Ok(data) => appender.push(&data).await,
+                    Err(e) => Err(TusError::Body(e.to_string())),
+                };
+                if result.is_err() {
+                    break;
+                }
+            }
+            result
+        }
+    };
+
+    // Keep whatever arrived, even if the request failed part way
+    let session = appender.finish().await?;
+    result?;
+
+    if session.upload_offset < session.file_size || session.status == SessionStatus::Complete {
+        return Ok((session, None));
+    }
+
+    let book_id = finalize(state, session.clone()).await?;
+    let session = state.session_manager.get_session(session.id).await?;
+
+    Ok((session, Some(book_id)))
+}
+
+/// Assemble a completed tus upload and ingest it
+///
+/// If the same file is already in the library, the upload completes with
+/// the existing book instead of creating a duplicate.
+async fn finalize(state: &UploadState, mut session: UploadSession) -> Result<String, UploadError> {
+    tracing::info!(
+        session_id = %session.id,
+        file_name = %session.file_name,
+        chunks = session.chunk_hashes.len(),
+        "Assembling tus upload"
+    );
+
+    let file_data = state
+        .chunk_store
+        .assemble_chunks(session.id, &session.chunk_hashes)
+        .await?;
+
+    // tus has no upfront file hash; record it now for deduplication
+    session.file_hash = compute_hash(&file_data);
+    state.session_manager.update_session(session.clone()).await?;
+
+    let dedup = state
+        .dedup_service
+        .check_deduplication(&session.file_hash, &[])
+        .await?;
+
+    if let Some(book_id) = dedup.existing_book_id {
+        state.session_manager.complete_session(session.id).await?;
+        let _ = state.chunk_store.delete_session_chunks(session.id).await;
+        return Ok(book_id);
+    }
+
+    let finalized = finalize_session(state, &session, file_data).await?;
+    Ok(finalized.book_id)
+}
+
+/// Reject requests for other protocol versions
+fn check_version(headers: &HeaderMap) -> Result<(), TusError> {
+    match header_str(headers, TUS_RESUMABLE) {
+        Some(TUS_VERSION) => Ok(()),
+        Some(other) => Err(TusError::UnsupportedVersion(other.to_string())),
+        None => Err(TusError::UnsupportedVersion("none".to_string())),
+    }
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Session expiry in RFC 7231 format
+fn http_date(session: &UploadSession) -> String {
+    session
+        .expires_at
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+fn with_headers(status: StatusCode, headers: Vec<(String, String)>) -> Response {
+    let mut response = status.into_response();
+    for (name, value) in headers {
+        if let (Ok(name), Ok(value)) = (
+            header::HeaderName::try_from(name),
+            HeaderValue::try_from(value),
+        ) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
@@ -8,6 +8,10 @@
 //! - POST /api/v1/upload/:session_id/finalize - Assemble and store file
 //! - DELETE /api/v1/upload/:session_id - Cancel upload
 //! - GET /api/v1/upload/:session_id - Get session status
+//!
+//! A handshake with `direct: true` (requires `presigned_urls`) returns a
+//! presigned PUT URL per chunk instead; chunks go straight to the bucket and
+//! finalize verifies their hashes there.
 
 use axum::{
     extract::{Path, State},
@@ -19,13 +23,17 @@ use axum::{
 use axum::body::Bytes;
 use axum::http::header;
 use serde::Serialize;
+use std::time::Duration;
 use uuid::Uuid;
 
+use crate::db::CatalogRepository;
 use crate::state::AppState;
+use crate::upload::direct;
+use crate::upload::ingest::{ingest_book, IngestRequest};
 use crate::upload::{
     ChunkStore, DeduplicationService, SessionManager,
     HandshakeRequest, HandshakeResponse, ChunkUploadResponse, FinalizeResponse,
-    UploadError, UploadSession, SessionStatus, MAX_FILE_SIZE,
+    DirectUploadInfo, UploadError, UploadSession, SessionStatus, MAX_FILE_SIZE,
 };
 
 // ============================================================================
@@ -66,6 +74,7 @@ impl IntoResponse for UploadError {
             UploadError::MissingChunks(_) => "MISSING_CHUNKS",
             UploadError::StorageError(_) => "STORAGE_ERROR",
             UploadError::DatabaseError(_) => "DATABASE_ERROR",
+            UploadError::DirectUpload(_) => "DIRECT_UPLOAD_ERROR",
             UploadError::InternalError(_) => "INTERNAL_ERROR",
         };
 
@@ -117,6 +126,15 @@ async fn handshake(
         return Err(UploadError::InvalidFileType(request.mime_type.clone()));
     }
 
+    if request.direct {
+        if !state.app_state.config().storage.presigned_urls {
+            return Err(UploadError::DirectUpload(
+                "presigned URLs are disabled".to_string(),
+            ));
+        }
+        direct::validate_request(&request)?;
+    }
+
     // Check for deduplication
     let dedup_result = state
         .dedup_service
@@ -133,12 +151,17 @@ async fn handshake(
             existing_chunks: dedup_result.existing_chunks,
             total_chunks: request.chunk_hashes.len(),
             expires_at: chrono::Utc::now(),
+            direct_upload: None,
         }));
     }
 
     // Create new upload session
     let session = state.session_manager.create_session(&request).await?;
 
+    if request.direct {
+        return start_direct_upload(&state, session).await.map(Json);
+    }
+
     tracing::info!(
         session_id = %session.id,
         file_name = %request.file_name,
@@ -156,9 +179,55 @@ async fn handshake(
         existing_chunks: dedup_result.existing_chunks,
         total_chunks: request.chunk_hashes.len(),
         expires_at: session.expires_at,
+        direct_upload: None,
     }))
 }
 
+/// Start the multipart upload for a `direct` handshake
+///
+/// Every chunk is needed: parts already on the server can't be copied into
+/// the bucket's multipart upload.
+async fn start_direct_upload(
+    state: &UploadState,
+    mut session: UploadSession,
+) -> Result<HandshakeResponse, UploadError> {
+    let storage = state.app_state.storage();
+    let expires_in = Duration::from_secs(state.app_state.config().storage.presign_expiry_secs);
+
+    let upload = match direct::start(storage, &session, expires_in).await {
+        Ok(upload) => upload,
+        Err(e) => {
+            let _ = state.session_manager.cancel_session(session.id).await;
+            return Err(e);
+        }
+    };
+
+    session.direct_upload_id = Some(upload.upload_id);
+    state.session_manager.update_session(session.clone()).await?;
+
+    tracing::info!(
+        session_id = %session.id,
+        file_name = %session.file_name,
+        parts = upload.part_urls.len(),
+        "Direct upload handshake complete"
+    );
+
+    Ok(HandshakeResponse {
+        session_id: session.id.to_string(),
+        is_duplicate: false,
+        existing_book_id: None,
+        needed_chunks: (0..session.chunk_hashes.len()).collect(),
+        existing_chunks: vec![],
+        total_chunks: session.chunk_hashes.len(),
+        expires_at: session.expires_at,
+        direct_upload: Some(DirectUploadInfo {
+            part_urls: upload.part_urls,
+            expires_at: chrono::Utc::now()
+                + chrono::Duration::seconds(expires_in.as_secs() as i64),
+        }),
+    })
+}
+
 /// POST /api/v1/upload/:session_id/chunks/:index
 ///
 /// Upload a single chunk. The chunk data is the raw request body.
@@ -180,6 +249,12 @@ async fn upload_chunk(
         return Err(UploadError::SessionComplete);
     }
 
+    if session.direct_upload_id.is_some() {
+        return Err(UploadError::DirectUpload(
+            "chunks must be sent to the presigned part URLs".to_string(),
+        ));
+    }
+
     // Validate chunk index
     if chunk_index >= session.chunk_hashes.len() {
         return Err(UploadError::ChunkIndexOutOfBounds {
@@ -258,6 +333,10 @@ async fn finalize(
         return Err(UploadError::SessionExpired(session_id));
     }
 
+    if session.direct_upload_id.is_some() {
+        return finalize_direct(&state, &session).await.map(Json);
+    }
+
     // Check all chunks received
     if !session.is_complete() {
         let missing = session.missing_chunks();
@@ -276,7 +355,7 @@ async fn finalize(
 
     let file_data = state
         .chunk_store
-        .assemble_chunks(session_uuid, session.chunk_hashes.len())
+        .assemble_chunks(session_uuid, &session.chunk_hashes)
         .await?;
 
     // Verify final file hash
@@ -288,43 +367,86 @@ async fn finalize(
         });
     }
 
-    // Store in S3
-    let book_id = Uuid::new_v4().to_string();
-    let storage_key = format!("books/{}/{}", book_id, session.file_name);
+    finalize_session(&state, &session, file_data).await.map(Json)
+}
 
-    state
-        .app_state
-        .s3_client()
-        .put_object(&storage_key, file_data.clone(), &session.mime_type)
-        .await
-        .map_err(|e| UploadError::StorageError(e.to_string()))?;
+/// Complete a direct upload, verify it against the handshake and ingest it
+///
+/// A file that fails verification is discarded; the client has to start
+/// a new upload.
+async fn finalize_direct(
+    state: &UploadState,
+    session: &UploadSession,
+) -> Result<FinalizeResponse, UploadError> {
+    let storage = state.app_state.storage();
+
+    let file_data = match direct::complete(storage, session).await {
+        Ok(data) => data,
+        Err(e @ (UploadError::ChunkHashMismatch { .. } | UploadError::DirectUpload(_))) => {
+            tracing::warn!(session_id = %session.id, "Direct upload failed verification: {}", e);
+            direct::discard(storage, session).await;
+            let _ = state.session_manager.cancel_session(session.id).await;
+            return Err(e);
+        }
+        Err(e) => return Err(e),
+    };
 
-    // Extract title from file (basic for now)
-    let title = extract_title(&session.file_name, &file_data, &session.mime_type);
+    let response = finalize_session(state, session, file_data).await?;
 
-    // Register file hash for future deduplication
-    // Note: This would typically also create a database record for the book
-    // For now, we just log it
-    tracing::info!(
-        book_id = %book_id,
-        file_hash = %session.file_hash,
-        title = %title,
-        size = file_data.len(),
-        "File stored successfully"
-    );
+    // The book now lives at its library key
+    let staging_key = direct::staging_key(session.id);
+    if let Err(e) = storage.delete_object(&staging_key).await {
+        tracing::warn!("Failed to remove staged upload {}: {}", staging_key, e);
+    }
+
+    Ok(response)
+}
+
+/// Ingest an assembled upload as a library book
+///
+/// Shared by the up2k and tus endpoints so both produce identical books:
+/// ingests the file, adds it to the OPDS cache, completes the session and
+/// removes its chunks.
+pub(crate) async fn finalize_session(
+    state: &UploadState,
+    session: &UploadSession,
+    file_data: Vec<u8>,
+) -> Result<FinalizeResponse, UploadError> {
+    // Store the file and create the book record
+    let book_id = Uuid::new_v4().to_string();
+    let ingested = ingest_book(
+        &state.app_state,
+        &state.dedup_service,
+        IngestRequest {
+            book_id: book_id.clone(),
+            file_name: session.file_name.clone(),
+            mime_type: session.mime_type.clone(),
+            file_hash: session.file_hash.clone(),
+            data: file_data,
+        },
+    )
+    .await?;
+
+    // Make the book visible in the catalog without waiting for a rescan
+    if let Err(e) = CatalogRepository::new(state.app_state.db())
+        .upsert(&ingested.library_book)
+        .await
+    {
+        tracing::warn!("Failed to add book {} to catalog: {}", book_id, e);
+    }
 
     // Mark session complete
-    state.session_manager.complete_session(session_uuid).await?;
+    state.session_manager.complete_session(session.id).await?;
 
     // Clean up chunks
-    let _ = state.chunk_store.delete_session_chunks(session_uuid).await;
+    let _ = state.chunk_store.delete_session_chunks(session.id).await;
 
-    Ok(Json(FinalizeResponse {
+    Ok(FinalizeResponse {
         book_id,
-        title,
+        title: ingested.record.title,
         size: session.file_size,
-        storage_key,
-    }))
+        storage_key: ingested.record.storage_key,
+    })
 }
 
 /// GET /api/v1/upload/:session_id
@@ -379,7 +501,11 @@ async fn cancel_session(
         .map_err(|_| UploadError::SessionNotFound(session_id.clone()))?;
 
     // Cancel session
-    state.session_manager.cancel_session(session_uuid).await?;
+    let session = state.session_manager.cancel_session(session_uuid).await?;
+
+    if session.direct_upload_id.is_some() {
+        direct::discard(state.app_state.storage(), &session).await;
+    }
 
     // Clean up chunks
     let _ = state.chunk_store.delete_session_chunks(session_uuid).await;
@@ -392,7 +518,7 @@ async fn cancel_session(
 // ============================================================================
 
 /// Check if file type is allowed
-fn is_valid_file_type(mime_type: &str) -> bool {
+pub(crate) fn is_valid_file_type(mime_type: &str) -> bool {
     matches!(
         mime_type,
         "application/epub+zip"
@@ -402,27 +528,16 @@ fn is_valid_file_type(mime_type: &str) -> bool {
     )
 }
 
-/// Extract title from file (basic implementation)
-fn extract_title(file_name: &str, _data: &[u8], _mime_type: &str) -> String {
-    // For now, just use filename without extension
-    // TODO: Parse EPUB/PDF metadata
-    file_name
-        .rsplit('.')
-        .skip(1)
-        .collect::<Vec<_>>()
-        .into_iter()
-        .rev()
-        .collect::<Vec<_>>()
-        .join(".")
-}
-
 // ============================================================================
 // Factory Function
 // ============================================================================
 
 /// Create upload state with default configuration
-pub fn create_upload_state(app_state: AppState, chunk_base_path: std::path::PathBuf) -> UploadState {
-    let session_manager = SessionManager::new();
+pub fn create_upload_state(
+    app_state: AppState,
+    chunk_base_path: std::path::PathBuf,
+) -> UploadState {
+    let session_manager = SessionManager::with_database(app_state.db().clone());
     let chunk_store = ChunkStore::with_local_storage(chunk_base_path);
     let dedup_service = DeduplicationService::new(
         app_state.db().clone(),
@@ -437,11 +552,14 @@ pub fn create_upload_state(app_state: AppState, chunk_base_path: std::path::Path
     }
 }
 
-/// Create upload state with S3 chunk storage
-pub fn create_upload_state_s3(app_state: AppState, chunk_prefix: String) -> UploadState {
-    let session_manager = SessionManager::new();
-    let chunk_store = ChunkStore::with_s3_storage(
-        app_state.s3_client().clone(),
+/// Create upload state with chunks in the storage backend
+pub fn create_upload_state_object_storage(
+    app_state: AppState,
+    chunk_prefix: String,
+) -> UploadState {
+    let session_manager = SessionManager::with_database(app_state.db().clone());
+    let chunk_store = ChunkStore::with_object_storage(
+        app_state.storage().clone(),
         chunk_prefix,
     );
     let dedup_service = DeduplicationService::new(
@@ -1,13 +1,15 @@
 //! Application state management
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use sqlx::SqlitePool;
 
 use crate::config::Config;
 use crate::document::{CacheConfig, DocumentCache};
-use crate::pdf::PdfCache;
-use crate::storage::S3Client;
+use crate::library::{CoverService, LibraryDocuments, LibraryWatcher};
+use crate::pdf::{PdfCache, TrustAnchors};
+use crate::storage::Storage;
 
 /// Shared application state
 #[derive(Clone)]
@@ -17,24 +19,44 @@ pub struct AppState {
 
 struct AppStateInner {
     pub config: Config,
-    pub s3_client: S3Client,
+    pub storage: Storage,
     pub db: SqlitePool,
     /// Unified document cache for EPUB and PDF (new architecture)
     pub document_cache: DocumentCache,
     /// Legacy PDF cache (for backward compatibility with routes/pdf.rs)
     pub pdf_cache: PdfCache,
+    /// Background catalog scans
+    pub library_watcher: LibraryWatcher,
+    /// Catalog books opened through the document cache
+    pub library_documents: LibraryDocuments,
+    /// Cover extraction and resized variants
+    pub covers: CoverService,
+    /// Trusted certificates for PDF signature validation
+    pub trust_anchors: TrustAnchors,
 }
 
 impl AppState {
     /// Create a new application state
-    pub async fn new(config: Config, s3_client: S3Client, db: SqlitePool) -> Self {
+    pub async fn new(config: Config, storage: Storage, db: SqlitePool) -> Self {
+        let document_cache = DocumentCache::new(CacheConfig::default());
+        let library_documents =
+            LibraryDocuments::new(storage.clone(), db.clone(), document_cache.clone());
+        let library_watcher =
+            LibraryWatcher::new(storage.clone(), db.clone(), library_documents.clone());
+        let covers = CoverService::new(storage.clone(), db.clone(), library_documents.clone());
+        let trust_anchors = load_trust_anchors(config.pdf.signature_trust_dir.as_deref());
+
         Self {
             inner: Arc::new(AppStateInner {
                 config,
-                s3_client,
+                storage,
                 db,
-                document_cache: DocumentCache::new(CacheConfig::default()),
+                document_cache,
                 pdf_cache: PdfCache::new(),
+                library_watcher,
+                library_documents,
+                covers,
+                trust_anchors,
             }),
         }
     }
@@ -44,9 +66,29 @@ impl AppState {
         &self.inner.config
     }
 
-    /// Get the S3 client
-    pub fn s3_client(&self) -> &S3Client {
-        &self.inner.s3_client
+    /// Get the storage backend (S3 or local directory)
+    pub fn storage(&self) -> &Storage {
+        &self.inner.storage
+    }
+
+    /// Presigned download URL for a stored object
+    ///
+    /// `None` unless `presigned_urls` is enabled and the backend can presign,
+    /// in which case callers fall back to `/files/{key}`.
+    pub async fn presigned_url(&self, key: &str) -> Option<String> {
+        let config = &self.inner.config.storage;
+        if !config.presigned_urls {
+            return None;
+        }
+
+        let expires_in = Duration::from_secs(config.presign_expiry_secs);
+        match self.inner.storage.presign_get(key, expires_in).await {
+            Ok(url) => url,
+            Err(e) => {
+                tracing::warn!("Failed to presign {}: {}", key, e);
+                None
+            }
+        }
     }
 
     /// Get the database pool
@@ -59,8 +101,49 @@ impl AppState {
         &self.inner.document_cache
     }
 
+    /// Get the library watcher
+    pub fn library_watcher(&self) -> &LibraryWatcher {
+        &self.inner.library_watcher
+    }
+
+    /// Get the catalog book opener
+    pub fn library_documents(&self) -> &LibraryDocuments {
+        &self.inner.library_documents
+    }
+
+    /// Get the cover service
+    pub fn covers(&self) -> &CoverService {
+        &self.inner.covers
+    }
+
     /// Get the PDF cache (legacy, for backward compatibility)
     pub fn pdf_cache(&self) -> &PdfCache {
         &self.inner.pdf_cache
     }
+
+    /// Get the trusted certificates for PDF signature validation
+    pub fn trust_anchors(&self) -> &TrustAnchors {
+        &self.inner.trust_anchors
+    }
+}
+
+/// Load the signature trust anchors, or none if unset or unreadable
+fn load_trust_anchors(dir: Option<&str>) -> TrustAnchors {
+    let Some(dir) = dir else {
+        return TrustAnchors::default();
+    };
+    match TrustAnchors::load_dir(dir) {
+        Ok(anchors) => {
+            tracing::info!(
+                "Loaded {} signature trust anchors from {}",
+                anchors.len(),
+                dir
+            );
+            anchors
+        }
+        Err(e) => {
+            tracing::warn!("Failed to load signature trust anchors from {}: {}", dir, e);
+            TrustAnchors::default()
+        }
+    }
 }
@@ -0,0 +1,132 @@
+//! Storage backend trait
+//!
+//! Object-store style interface shared by the S3 and local-directory
+//! backends. Keys are `/`-separated paths such as
+//! `Author/Title (42)/Title - Author.epub`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Bytes;
+use futures::stream::BoxStream;
+
+use crate::error::{Result, StorageError};
+
+use super::types::{ListOptions, ObjectList, ObjectMetadata, PresignedUpload, StorageObject};
+
+/// Byte stream of an object's contents
+pub type ObjectStream = BoxStream<'static, Result<Bytes>>;
+
+/// Shared handle to the configured storage backend
+pub type Storage = Arc<dyn StorageBackend>;
+
+/// Operations every storage backend provides
+#[async_trait::async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Backend name for logging
+    fn name(&self) -> &'static str;
+
+    /// List one page of objects (S3 `ListObjectsV2` semantics)
+    async fn list_objects(&self, options: ListOptions) -> Result<ObjectList>;
+
+    /// Get object metadata
+    async fn head_object(&self, key: &str) -> Result<ObjectMetadata>;
+
+    /// Get an object's data
+    async fn get_object(&self, key: &str) -> Result<StorageObject>;
+
+    /// Get object as a byte stream (for large files)
+    async fn get_object_stream(&self, key: &str) -> Result<ObjectStream>;
+
+    /// Get bytes `start..=end` of an object as a stream
+    async fn get_object_range(&self, key: &str, start: u64, end: u64) -> Result<ObjectStream>;
+
+    /// Store an object, replacing any existing one
+    async fn put_object(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<()>;
+
+    /// Delete an object (deleting a missing object succeeds)
+    async fn delete_object(&self, key: &str) -> Result<()>;
+
+    /// Presigned GET URL for direct client downloads
+    ///
+    /// Returns `None` when the backend cannot presign (e.g. local storage).
+    async fn presign_get(&self, _key: &str, _expires_in: Duration) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Start a multipart upload with a presigned PUT URL for each part
+    ///
+    /// Returns `None` when the backend cannot presign.
+    async fn presign_multipart_upload(
+        &self,
+        _key: &str,
+        _content_type: &str,
+        _part_count: usize,
+        _expires_in: Duration,
+    ) -> Result<Option<PresignedUpload>> {
+        Ok(None)
+    }
+
+    /// Complete a multipart upload from the parts clients have uploaded
+    async fn complete_multipart_upload(&self, _key: &str, _upload_id: &str) -> Result<()> {
+        Err(StorageError::Unsupported("multipart upload".to_string()).into())
+    }
+
+    /// Abort a multipart upload, discarding uploaded parts
+    async fn abort_multipart_upload(&self, _key: &str, _upload_id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Delete all objects with a given prefix
+    async fn delete_objects_with_prefix(&self, prefix: &str) -> Result<usize> {
+        let objects = self.list_all_objects(Some(prefix)).await?;
+        let count = objects.len();
+
+        for obj in objects {
+            self.delete_object(&obj.key).await?;
+        }
+
+        tracing::debug!("Deleted {} objects with prefix: {}", count, prefix);
+        Ok(count)
+    }
+
+    /// Check if an object exists
+    async fn object_exists(&self, key: &str) -> Result<bool> {
+        match self.head_object(key).await {
+            Ok(_) => Ok(true),
+            Err(crate::error::AppError::Storage(StorageError::ObjectNotFound(_))) => {
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// List all objects with a given prefix (handles pagination)
+    async fn list_all_objects(&self, prefix: Option<&str>) -> Result<Vec<ObjectMetadata>> {
+        let mut all_objects = Vec::new();
+        let mut continuation_token = None;
+
+        loop {
+            let mut options = ListOptions::new().with_max_keys(1000);
+
+            if let Some(p) = prefix {
+                options = options.with_prefix(p);
+            }
+
+            if let Some(token) = continuation_token.take() {
+                options = options.with_continuation_token(token);
+            }
+
+            let result = self.list_objects(options).await?;
+            all_objects.extend(result.objects);
+
+            if !result.is_truncated {
+                break;
+            }
+
+            continuation_token = result.continuation_token;
+        }
+
+        Ok(all_objects)
+    }
+}
@@ -0,0 +1,542 @@
+//! Local directory storage
+//!
+//! Stores objects as files under a root directory, one file per key, so a
+//! Calibre library folder (`Author/Title (id)/...`) can be used directly
+//! and `LibraryScanner` sees the same layout it would in a bucket.
+//!
+//! Hidden entries (names starting with `.`) are ignored, which skips
+//! Calibre's `.caltrash` and in-progress writes.
+
+use std::io::SeekFrom;
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::error::{AppError, Result, StorageError};
+
+use super::backend::{ObjectStream, StorageBackend};
+use super::types::{ListOptions, ObjectList, ObjectMetadata, StorageObject};
+
+/// Read size for streamed objects
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Default page size for listings
+const DEFAULT_MAX_KEYS: usize = 1000;
+
+/// Storage backed by a local directory
+#[derive(Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    /// Open (and create if needed) a storage root
+    pub async fn new(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        tokio::fs::create_dir_all(&root).await?;
+        tracing::info!("Using local storage at {}", root.display());
+        Ok(Self { root })
+    }
+
+    /// Get the root directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolve a key to a path under the root, rejecting escapes
+    fn key_path(&self, key: &str) -> Result<PathBuf> {
+        let relative = Path::new(key);
+        let valid = key
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..")
+            && relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)));
+
+        if !valid {
+            return Err(StorageError::InvalidKey(key.to_string()).into());
+        }
+
+        Ok(self.root.join(relative))
+    }
+
+    /// Build metadata from a file on disk
+    fn metadata_for(key: &str, meta: &std::fs::Metadata) -> ObjectMetadata {
+        let modified = meta.modified().ok();
+        let mtime = modified
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        ObjectMetadata {
+            key: key.to_string(),
+            size: meta.len() as i64,
+            last_modified: modified.map(DateTime::<Utc>::from),
+            content_type: Some(
+                mime_guess::from_path(key)
+                    .first_or_octet_stream()
+                    .to_string(),
+            ),
+            // Same scheme as nginx: mtime and size
+            etag: Some(format!("\"{:x}-{:x}\"", mtime, meta.len())),
+        }
+    }
+
+    /// Check a listing prefix the way `key_path` checks keys
+    ///
+    /// The last segment may be empty or a partial name.
+    fn check_prefix(prefix: &str) -> Result<()> {
+        let mut parts: Vec<&str> = prefix.split('/').collect();
+        let last = parts.pop().unwrap_or_default();
+        let valid = parts
+            .iter()
+            .all(|part| !part.is_empty() && *part != "." && *part != "..")
+            && last != "."
+            && last != "..";
+
+        if !valid {
+            return Err(StorageError::InvalidKey(prefix.to_string()).into());
+        }
+        Ok(())
+    }
+
+    /// Key for a path under the root (always `/`-separated)
+    fn path_key(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let parts: Option<Vec<&str>> = relative.components().map(|c| c.as_os_str().to_str()).collect();
+        Some(parts?.join("/"))
+    }
+
+    /// Stream `len` bytes of a file starting at `start`
+    async fn read_stream(&self, key: &str, start: u64, len: u64) -> Result<ObjectStream> {
+        let mut file = tokio::fs::File::open(self.key_path(key)?)
+            .await
+            .map_err(|e| not_found(key, e))?;
+        file.seek(SeekFrom::Start(start)).await?;
+
+        let stream = futures::stream::unfold(Some(file.take(len)), |file| async move {
+            let mut file = file?;
+            let mut buffer = vec![0u8; STREAM_BUFFER_SIZE];
+            match file.read(&mut buffer).await {
+                Ok(0) => None,
+                Ok(n) => {
+                    buffer.truncate(n);
+                    Some((Ok(Bytes::from(buffer)), Some(file)))
+                }
+                Err(e) => Some((Err(AppError::from(e)), None)),
+            }
+        });
+
+        Ok(stream.boxed())
+    }
+
+    /// Remove empty directories between a deleted file and the root
+    async fn prune_empty_dirs(&self, path: &Path) {
+        let mut current = path.parent();
+        while let Some(dir) = current {
+            if dir == self.root || !dir.starts_with(&self.root) {
+                break;
+            }
+            // Fails (and stops) on the first non-empty directory
+            if tokio::fs::remove_dir(dir).await.is_err() {
+                break;
+            }
+            current = dir.parent();
+        }
+    }
+}
+
+/// Depth-first walk that yields files in key order
+///
+/// Directories are sorted as `name/`, which puts them where the keys below
+/// them sort, so a listing can stop after one page and the next page can
+/// skip the directories before its continuation token.
+struct OrderedWalk<'a> {
+    storage: &'a LocalStorage,
+    /// Sorted entries (key, with `/` after directories) of each open directory
+    stack: Vec<std::vec::IntoIter<(String, PathBuf, std::fs::Metadata)>>,
+}
+
+impl<'a> OrderedWalk<'a> {
+    async fn new(storage: &'a LocalStorage, dir: &Path) -> Result<Self> {
+        let mut walk = Self {
+            storage,
+            stack: Vec::new(),
+        };
+        walk.open(dir).await?;
+        Ok(walk)
+    }
+
+    /// Read a directory's entries onto the stack
+    async fn open(&mut self, dir: &Path) -> Result<()> {
+        let mut entries = match tokio::fs::read_dir(dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut children = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            let meta = entry.metadata().await?;
+            let path = entry.path();
+            let Some(mut key) = self.storage.path_key(&path) else {
+                continue;
+            };
+            if meta.is_dir() {
+                key.push('/');
+            } else if !meta.is_file() {
+                continue;
+            }
+            children.push((key, path, meta));
+        }
+
+        children.sort_by(|a, b| a.0.cmp(&b.0));
+        self.stack.push(children.into_iter());
+        Ok(())
+    }
+
+    /// Next file, descending only into directories `keep_dir` accepts
+    async fn next(
+        &mut self,
+        keep_dir: impl Fn(&str) -> bool,
+    ) -> Result<Option<(String, std::fs::Metadata)>> {
+        while let Some(entries) = self.stack.last_mut() {
+            let Some((key, path, meta)) = entries.next() else {
+                self.stack.pop();
+                continue;
+            };
+            if !meta.is_dir() {
+                return Ok(Some((key, meta)));
+            }
+            if keep_dir(&key) {
+                self.open(&path).await?;
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn not_found(key: &str, e: std::io::Error) -> AppError {
+    if e.kind() == std::io::ErrorKind::NotFound {
+        StorageError::ObjectNotFound(key.to_string()).into()
+    } else {
+        e.into()
+    }
+}
+
+#[async_trait::async_trait]
+impl StorageBackend for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn list_objects(&self, options: ListOptions) -> Result<ObjectList> {
+        let prefix = options.prefix.unwrap_or_default();
+        Self::check_prefix(&prefix)?;
+        let max_keys = options
+            .max_keys
+            .filter(|n| *n > 0)
+            .map_or(DEFAULT_MAX_KEYS, |n| n as usize);
+
+        // Only walk the directory the prefix points into
+        let start_dir = match prefix.rfind('/') {
+            Some(idx) => self.root.join(&prefix[..idx]),
+            None => self.root.clone(),
+        };
+
+        // Skip directories that can't hold keys of this page
+        let token = options.continuation_token.as_deref();
+        let returned_prefix = token.filter(|token| {
+            options
+                .delimiter
+                .as_deref()
+                .is_some_and(|d| token.ends_with(d))
+        });
+        let keep_dir = |dir: &str| {
+            (dir.starts_with(&prefix) || prefix.starts_with(dir))
+                && token.is_none_or(|token| dir > token || token.starts_with(dir))
+                && returned_prefix.is_none_or(|returned| !dir.starts_with(returned))
+        };
+
+        let mut objects = Vec::new();
+        let mut prefixes: Vec<String> = Vec::new();
+        let mut last_key = None;
+        let mut is_truncated = false;
+
+        let mut walk = OrderedWalk::new(self, &start_dir).await?;
+        while let Some((key, meta)) = walk.next(&keep_dir).await? {
+            if !key.starts_with(&prefix) {
+                continue;
+            }
+
+            // Resume after the token (a key, or a common prefix already returned)
+            if let Some(ref token) = options.continuation_token {
+                let inside_returned_prefix = options
+                    .delimiter
+                    .as_deref()
+                    .is_some_and(|d| token.ends_with(d) && key.starts_with(token.as_str()));
+                if key.as_str() <= token.as_str() || inside_returned_prefix {
+                    continue;
+                }
+            }
+
+            let common_prefix = options.delimiter.as_deref().and_then(|delimiter| {
+                key[prefix.len()..]
+                    .find(delimiter)
+                    .map(|idx| key[..prefix.len() + idx + delimiter.len()].to_string())
+            });
+
+            if let Some(ref common) = common_prefix {
+                if prefixes.last() == Some(common) {
+                    continue;
+                }
+            }
+
+            if objects.len() + prefixes.len() >= max_keys {
+                is_truncated = true;
+                break;
+            }
+
+            match common_prefix {
+                Some(common) => {
+                    last_key = Some(common.clone());
+                    prefixes.push(common);
+                }
+                None => {
+                    last_key = Some(key.clone());
+                    objects.push(Self::metadata_for(&key, &meta));
+                }
+            }
+        }
+
+        Ok(ObjectList {
+            objects,
+            prefixes,
+            continuation_token: if is_truncated { last_key } else { None },
+            is_truncated,
+        })
+    }
+
+    async fn head_object(&self, key: &str) -> Result<ObjectMetadata> {
+        let path = self.key_path(key)?;
+        let meta = tokio::fs::metadata(&path)
+            .await
+            .map_err(|e| not_found(key, e))?;
+
+        if !meta.is_file() {
+            return Err(StorageError::ObjectNotFound(key.to_string()).into());
+        }
+
+        Ok(Self::metadata_for(key, &meta))
+    }
+
+    async fn get_object(&self, key: &str) -> Result<StorageObject> {
+        let metadata = self.head_object(key).await?;
+        let data = tokio::fs::read(self.key_path(key)?)
+            .await
+            .map_err(|e| not_found(key, e))?;
+
+        Ok(StorageObject { metadata, data })
+    }
+
+    async fn get_object_stream(&self, key: &str) -> Result<ObjectStream> {
+        let size = self.head_object(key).await?.size as u64;
+        self.read_stream(key, 0, size).await
+    }
+
+    async fn get_object_range(&self, key: &str, start: u64, end: u64) -> Result<ObjectStream> {
+        self.read_stream(key, start, end.saturating_sub(start) + 1).await
+    }
+
+    async fn put_object(&self, key: &str, data: Vec<u8>, _content_type: &str) -> Result<()> {
+        let path = self.key_path(key)?;
+        let parent = path
+            .parent()
+            .ok_or_else(|| StorageError::InvalidKey(key.to_string()))?;
+        tokio::fs::create_dir_all(parent).await?;
+
+        // Write to a hidden temp file first so readers never see partial data
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let temp = parent.join(format!(".{}.{}.partial", file_name, uuid::Uuid::new_v4()));
+
+        tokio::fs::write(&temp, data).await?;
+        if let Err(e) = tokio::fs::rename(&temp, &path).await {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(e.into());
+        }
+
+        tracing::debug!("Stored object: {}", key);
+        Ok(())
+    }
+
+    async fn delete_object(&self, key: &str) -> Result<()> {
+        let path = self.key_path(key)?;
+
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+
+        self.prune_empty_dirs(&path).await;
+
+        tracing::debug!("Deleted object: {}", key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn storage() -> (TempDir, LocalStorage) {
+        let dir = TempDir::new().unwrap();
+        let storage = LocalStorage::new(dir.path()).await.unwrap();
+        (dir, storage)
+    }
+
+    #[tokio::test]
+    async fn test_put_get_delete() {
+        let (dir, storage) = storage().await;
+        let key = "Frank Herbert/Dune (1)/Dune - Frank Herbert.epub";
+
+        storage.put_object(key, b"epub".to_vec(), "application/epub+zip").await.unwrap();
+        assert!(dir.path().join("Frank Herbert/Dune (1)/Dune - Frank Herbert.epub").is_file());
+
+        let object = storage.get_object(key).await.unwrap();
+        assert_eq!(object.data, b"epub");
+        assert_eq!(object.metadata.size, 4);
+        assert_eq!(object.metadata.content_type.as_deref(), Some("application/epub+zip"));
+        assert!(object.metadata.etag.is_some());
+
+        let streamed: Vec<u8> = storage
+            .get_object_stream(key)
+            .await
+            .unwrap()
+            .map(|chunk| chunk.unwrap().to_vec())
+            .concat()
+            .await;
+        assert_eq!(streamed, b"epub");
+
+        let range: Vec<u8> = storage
+            .get_object_range(key, 1, 2)
+            .await
+            .unwrap()
+            .map(|chunk| chunk.unwrap().to_vec())
+            .concat()
+            .await;
+        assert_eq!(range, b"pu");
+
+        storage.delete_object(key).await.unwrap();
+        assert!(!storage.object_exists(key).await.unwrap());
+        // Empty Author/Title folders are removed
+        assert!(!dir.path().join("Frank Herbert").exists());
+        // Deleting again is not an error
+        storage.delete_object(key).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rejects_escaping_keys() {
+        let (_dir, storage) = storage().await;
+        for key in ["../etc/passwd", "/etc/passwd", "a/./b", "a/", ""] {
+            assert!(matches!(
+                storage.head_object(key).await,
+                Err(AppError::Storage(StorageError::InvalidKey(_)))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejects_escaping_prefixes() {
+        let (_dir, storage) = storage().await;
+        for prefix in ["../", "../..", "a/../", "/etc/", "a//b", "./"] {
+            assert!(matches!(
+                storage
+                    .list_objects(ListOptions::new().with_prefix(prefix))
+                    .await,
+                Err(AppError::Storage(StorageError::InvalidKey(_)))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_in_key_order() {
+        let (_dir, storage) = storage().await;
+        // '-' sorts before '/', so "a-b" comes before the keys under "a/"
+        for key in ["a/b/c.txt", "a-b/c.txt", "a/a.txt", "ab.txt"] {
+            storage.put_object(key, b"x".to_vec(), "").await.unwrap();
+        }
+
+        let keys: Vec<String> = storage
+            .list_all_objects(None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|o| o.key)
+            .collect();
+        assert_eq!(keys, vec!["a-b/c.txt", "a/a.txt", "a/b/c.txt", "ab.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_with_delimiter_and_pagination() {
+        let (_dir, storage) = storage().await;
+        for key in [
+            "Author A/Book 1 (1)/book.epub",
+            "Author A/Book 1 (1)/metadata.opf",
+            "Author A/Book 2 (2)/book.pdf",
+            "Author B/Book 3 (3)/book.epub",
+            "notes.txt",
+            ".caltrash/old.epub",
+        ] {
+            storage.put_object(key, b"x".to_vec(), "").await.unwrap();
+        }
+
+        let all = storage.list_all_objects(None).await.unwrap();
+        assert_eq!(all.len(), 5);
+
+        let top = storage
+            .list_objects(ListOptions::new().with_delimiter("/"))
+            .await
+            .unwrap();
+        assert_eq!(top.prefixes, vec!["Author A/", "Author B/"]);
+        assert_eq!(top.objects.len(), 1);
+
+        let author = storage
+            .list_all_objects(Some("Author A/"))
+            .await
+            .unwrap();
+        assert_eq!(author.len(), 3);
+
+        // One key per page
+        let mut keys = Vec::new();
+        let mut token = None;
+        loop {
+            let mut options = ListOptions::new().with_prefix("Author").with_max_keys(1);
+            if let Some(t) = token.take() {
+                options = options.with_continuation_token(t);
+            }
+            let page = storage.list_objects(options).await.unwrap();
+            keys.extend(page.objects.into_iter().map(|o| o.key));
+            if !page.is_truncated {
+                break;
+            }
+            token = page.continuation_token;
+        }
+        assert_eq!(keys.len(), 4);
+
+        assert_eq!(storage.delete_objects_with_prefix("Author A/").await.unwrap(), 3);
+        assert_eq!(storage.list_all_objects(None).await.unwrap().len(), 2);
+    }
+}
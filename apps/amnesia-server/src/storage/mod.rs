@@ -1,9 +1,29 @@
-//! Storage module for S3-compatible backends
+//! Storage module
 //!
-//! Supports MinIO, Cloudflare R2, Backblaze B2, and AWS S3.
+//! Supports S3-compatible backends (MinIO, Cloudflare R2, Backblaze B2,
+//! AWS S3) and a local directory, behind the `StorageBackend` trait.
 
+mod backend;
+mod local;
 mod s3_client;
 mod types;
 
+use std::sync::Arc;
+
+pub use backend::{ObjectStream, Storage, StorageBackend};
+pub use local::LocalStorage;
 pub use s3_client::S3Client;
 pub use types::*;
+
+use crate::config::{StorageConfig, StorageProvider};
+use crate::error::Result;
+
+/// Create the storage backend selected by the configuration
+pub async fn connect(config: &StorageConfig) -> Result<Storage> {
+    match config.provider {
+        StorageProvider::Local => Ok(Arc::new(LocalStorage::new(&config.local_path).await?)),
+        StorageProvider::Minio | StorageProvider::R2 | StorageProvider::S3 | StorageProvider::B2 => {
+            Ok(Arc::new(S3Client::new(config).await?))
+        }
+    }
+}
@@ -2,18 +2,24 @@
 //!
 //! Wraps the AWS SDK for S3-compatible storage access.
 
+use std::time::Duration;
+
 use aws_config::BehaviorVersion;
 use aws_sdk_s3::{
     config::{Credentials, Region},
+    presigning::PresigningConfig,
     primitives::ByteStream,
+    types::{CompletedMultipartUpload, CompletedPart},
     Client,
 };
-use chrono::{DateTime, Utc};
+use chrono::DateTime;
+use futures::StreamExt;
 
 use crate::config::StorageConfig;
 use crate::error::{AppError, Result, StorageError};
 
-use super::types::{ListOptions, ObjectList, ObjectMetadata, StorageObject};
+use super::backend::{ObjectStream, StorageBackend};
+use super::types::{ListOptions, ObjectList, ObjectMetadata, PresignedUpload, StorageObject};
 
 /// S3-compatible storage client
 #[derive(Clone)]
@@ -71,8 +77,86 @@ impl S3Client {
         &self.bucket
     }
 
+    /// GET an object (optionally a `Range`) as a byte stream
+    async fn get_stream(&self, key: &str, range: Option<String>) -> Result<ObjectStream> {
+        let response = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_range(range)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.to_string().contains("404") || e.to_string().contains("NoSuchKey") {
+                    AppError::Storage(StorageError::ObjectNotFound(key.to_string()))
+                } else {
+                    AppError::Storage(StorageError::SdkError(format!("Failed to get object stream {}: {}", key, e)))
+                }
+            })?;
+
+        let stream = futures::stream::unfold(response.body, |mut body| async move {
+            body.next().await.map(|chunk| {
+                let chunk = chunk.map_err(|e| {
+                    AppError::Storage(StorageError::SdkError(format!("Failed to read object body: {}", e)))
+                });
+                (chunk, body)
+            })
+        });
+
+        Ok(stream.boxed())
+    }
+
+    /// Collect the uploaded parts of a multipart upload (handles pagination)
+    async fn list_uploaded_parts(&self, key: &str, upload_id: &str) -> Result<Vec<CompletedPart>> {
+        let mut parts = Vec::new();
+        let mut marker = None;
+
+        loop {
+            let response = self
+                .client
+                .list_parts()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .set_part_number_marker(marker.take())
+                .send()
+                .await
+                .map_err(|e| {
+                    StorageError::SdkError(format!("Failed to list parts of {}: {}", key, e))
+                })?;
+
+            parts.extend(response.parts().iter().map(|part| {
+                CompletedPart::builder()
+                    .set_e_tag(part.e_tag().map(|s| s.to_string()))
+                    .set_part_number(part.part_number())
+                    .build()
+            }));
+
+            if !response.is_truncated().unwrap_or(false) {
+                break;
+            }
+            marker = response.next_part_number_marker().map(|s| s.to_string());
+        }
+
+        Ok(parts)
+    }
+}
+
+fn presigning_config(expires_in: Duration) -> Result<PresigningConfig> {
+    PresigningConfig::expires_in(expires_in).map_err(|e| {
+        AppError::Storage(StorageError::SdkError(format!("Invalid presign expiry: {}", e)))
+    })
+}
+
+#[async_trait::async_trait]
+impl StorageBackend for S3Client {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
     /// List objects in the bucket
-    pub async fn list_objects(&self, options: ListOptions) -> Result<ObjectList> {
+    async fn list_objects(&self, options: ListOptions) -> Result<ObjectList> {
         let mut request = self.client.list_objects_v2().bucket(&self.bucket);
 
         if let Some(prefix) = options.prefix {
@@ -124,7 +208,7 @@ impl S3Client {
     }
 
     /// Get object metadata (HEAD request)
-    pub async fn head_object(&self, key: &str) -> Result<ObjectMetadata> {
+    async fn head_object(&self, key: &str) -> Result<ObjectMetadata> {
         let response = self
             .client
             .head_object()
@@ -152,7 +236,7 @@ impl S3Client {
     }
 
     /// Get an object's data
-    pub async fn get_object(&self, key: &str) -> Result<StorageObject> {
+    async fn get_object(&self, key: &str) -> Result<StorageObject> {
         let response = self
             .client
             .get_object()
@@ -190,36 +274,17 @@ impl S3Client {
     }
 
     /// Get object as a byte stream (for large files)
-    pub async fn get_object_stream(&self, key: &str) -> Result<ByteStream> {
-        let response = self
-            .client
-            .get_object()
-            .bucket(&self.bucket)
-            .key(key)
-            .send()
-            .await
-            .map_err(|e| {
-                if e.to_string().contains("404") || e.to_string().contains("NoSuchKey") {
-                    AppError::Storage(StorageError::ObjectNotFound(key.to_string()))
-                } else {
-                    AppError::Storage(StorageError::SdkError(format!("Failed to get object stream {}: {}", key, e)))
-                }
-            })?;
-
-        Ok(response.body)
+    async fn get_object_stream(&self, key: &str) -> Result<ObjectStream> {
+        self.get_stream(key, None).await
     }
 
-    /// Check if an object exists
-    pub async fn object_exists(&self, key: &str) -> Result<bool> {
-        match self.head_object(key).await {
-            Ok(_) => Ok(true),
-            Err(crate::error::AppError::Storage(StorageError::ObjectNotFound(_))) => Ok(false),
-            Err(e) => Err(e),
-        }
+    /// Get a byte range of an object (ranged GET)
+    async fn get_object_range(&self, key: &str, start: u64, end: u64) -> Result<ObjectStream> {
+        self.get_stream(key, Some(format!("bytes={}-{}", start, end))).await
     }
 
     /// Put an object into the bucket
-    pub async fn put_object(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<()> {
+    async fn put_object(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<()> {
         let body = ByteStream::from(data);
 
         self.client
@@ -242,7 +307,7 @@ impl S3Client {
     }
 
     /// Delete an object from the bucket
-    pub async fn delete_object(&self, key: &str) -> Result<()> {
+    async fn delete_object(&self, key: &str) -> Result<()> {
         self.client
             .delete_object()
             .bucket(&self.bucket)
@@ -260,46 +325,119 @@ impl S3Client {
         Ok(())
     }
 
-    /// Delete all objects with a given prefix
-    pub async fn delete_objects_with_prefix(&self, prefix: &str) -> Result<usize> {
-        let objects = self.list_all_objects(Some(prefix)).await?;
-        let count = objects.len();
-
-        for obj in objects {
-            self.delete_object(&obj.key).await?;
-        }
+    /// Presign a GET so clients download straight from the bucket
+    async fn presign_get(&self, key: &str, expires_in: Duration) -> Result<Option<String>> {
+        let request = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .presigned(presigning_config(expires_in)?)
+            .await
+            .map_err(|e| {
+                AppError::Storage(StorageError::SdkError(format!("Failed to presign {}: {}", key, e)))
+            })?;
 
-        tracing::debug!("Deleted {} objects with prefix: {}", count, prefix);
-        Ok(count)
+        Ok(Some(request.uri().to_string()))
     }
 
-    /// List all objects with a given prefix (handles pagination)
-    pub async fn list_all_objects(&self, prefix: Option<&str>) -> Result<Vec<ObjectMetadata>> {
-        let mut all_objects = Vec::new();
-        let mut continuation_token = None;
+    /// Create a multipart upload and presign an `UploadPart` PUT for each part
+    async fn presign_multipart_upload(
+        &self,
+        key: &str,
+        content_type: &str,
+        part_count: usize,
+        expires_in: Duration,
+    ) -> Result<Option<PresignedUpload>> {
+        let created = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(content_type)
+            .send()
+            .await
+            .map_err(|e| {
+                AppError::Storage(StorageError::SdkError(format!(
+                    "Failed to create multipart upload {}: {}",
+                    key, e
+                )))
+            })?;
 
-        loop {
-            let mut options = ListOptions::new().with_max_keys(1000);
+        let upload_id = created
+            .upload_id()
+            .ok_or_else(|| StorageError::SdkError(format!("No upload ID returned for {}", key)))?
+            .to_string();
+
+        let config = presigning_config(expires_in)?;
+        let mut part_urls = Vec::with_capacity(part_count);
+        for part_number in 1..=part_count as i32 {
+            let request = self
+                .client
+                .upload_part()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(&upload_id)
+                .part_number(part_number)
+                .presigned(config.clone())
+                .await
+                .map_err(|e| {
+                    AppError::Storage(StorageError::SdkError(format!(
+                        "Failed to presign part {} of {}: {}",
+                        part_number, key, e
+                    )))
+                })?;
+            part_urls.push(request.uri().to_string());
+        }
 
-            if let Some(p) = prefix {
-                options = options.with_prefix(p);
-            }
+        tracing::debug!("Started multipart upload {} ({} parts): {}", upload_id, part_count, key);
+        Ok(Some(PresignedUpload { upload_id, part_urls }))
+    }
 
-            if let Some(token) = continuation_token.take() {
-                options = options.with_continuation_token(token);
-            }
+    /// Complete a multipart upload from the parts stored in the bucket
+    async fn complete_multipart_upload(&self, key: &str, upload_id: &str) -> Result<()> {
+        let parts = self.list_uploaded_parts(key, upload_id).await?;
 
-            let result = self.list_objects(options).await?;
-            all_objects.extend(result.objects);
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|e| {
+                AppError::Storage(StorageError::SdkError(format!(
+                    "Failed to complete multipart upload {}: {}",
+                    key, e
+                )))
+            })?;
 
-            if !result.is_truncated {
-                break;
-            }
+        tracing::debug!("Completed multipart upload {}: {}", upload_id, key);
+        Ok(())
+    }
 
-            continuation_token = result.continuation_token;
-        }
+    /// Abort a multipart upload
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<()> {
+        self.client
+            .abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await
+            .map_err(|e| {
+                AppError::Storage(StorageError::SdkError(format!(
+                    "Failed to abort multipart upload {}: {}",
+                    key, e
+                )))
+            })?;
 
-        Ok(all_objects)
+        Ok(())
     }
 }
 
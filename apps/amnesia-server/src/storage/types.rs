@@ -20,6 +20,15 @@ pub struct StorageObject {
     pub data: Vec<u8>,
 }
 
+/// A multipart upload whose parts clients PUT directly to storage
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresignedUpload {
+    pub upload_id: String,
+    /// Presigned PUT URL for each part, in order (part number = index + 1)
+    pub part_urls: Vec<String>,
+}
+
 /// List of objects with optional continuation token
 #[derive(Debug, Clone, Serialize)]
 pub struct ObjectList {
@@ -1,9 +1,9 @@
 //! Chunk Store
 //!
 //! Temporary storage for uploaded chunks before assembly.
-//! Supports both local filesystem and S3 storage backends.
+//! Supports a local chunk directory or the configured storage backend.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -11,7 +11,7 @@ use chrono::Utc;
 use sha2::{Sha256, Digest};
 use uuid::Uuid;
 
-use crate::storage::S3Client;
+use crate::storage::Storage;
 use super::types::{ChunkMetadata, UploadError};
 
 // ============================================================================
@@ -30,6 +30,14 @@ pub trait ChunkStorage: Send + Sync {
         expected_hash: &str,
     ) -> Result<ChunkMetadata, UploadError>;
 
+    /// Store a chunk under its session and index only
+    async fn store_session_chunk(
+        &self,
+        session_id: Uuid,
+        chunk_index: usize,
+        data: &[u8],
+    ) -> Result<(), UploadError>;
+
     /// Get a chunk by session and index
     async fn get_chunk(
         &self,
@@ -52,6 +60,9 @@ pub trait ChunkStorage: Send + Sync {
         session_id: Uuid,
         chunk_count: usize,
     ) -> Result<Vec<u8>, UploadError>;
+
+    /// List sessions that have chunks in storage
+    async fn list_sessions(&self) -> Result<Vec<Uuid>, UploadError>;
 }
 
 // ============================================================================
@@ -87,11 +98,11 @@ impl ChunkStore {
         }
     }
 
-    /// Create with S3 storage
-    pub fn with_s3_storage(s3_client: S3Client, prefix: String) -> Self {
+    /// Create with chunks kept in the storage backend under `prefix`
+    pub fn with_object_storage(storage: Storage, prefix: String) -> Self {
         Self {
             inner: Arc::new(ChunkStoreInner {
-                backend: Box::new(S3ChunkStorage::new(s3_client, prefix)),
+                backend: Box::new(ObjectChunkStorage::new(storage, prefix)),
                 chunk_index: RwLock::new(HashMap::new()),
                 session_chunks: RwLock::new(HashMap::new()),
             }),
@@ -162,6 +173,29 @@ impl ChunkStore {
         Ok(metadata)
     }
 
+    /// Store an incomplete chunk (a tus upload's partial trailing chunk)
+    ///
+    /// Partial data is kept under the session only: it gets no hash-keyed
+    /// copy and isn't offered for deduplication.
+    pub async fn store_partial_chunk(
+        &self,
+        session_id: Uuid,
+        chunk_index: usize,
+        data: &[u8],
+    ) -> Result<(), UploadError> {
+        {
+            let mut session_chunks = self.inner.session_chunks.write().await;
+            if let Some(chunks) = session_chunks.get_mut(&session_id) {
+                chunks.remove(&chunk_index);
+            }
+        }
+
+        self.inner
+            .backend
+            .store_session_chunk(session_id, chunk_index, data)
+            .await
+    }
+
     /// Get a chunk for a session
     pub async fn get_chunk(
         &self,
@@ -215,12 +249,35 @@ impl ChunkStore {
     }
 
     /// Assemble chunks into final file
+    ///
+    /// `chunk_hashes` are the session's persisted chunk hashes (empty for
+    /// chunks whose hash isn't known). Chunks are read by hash, through the
+    /// in-memory mapping or else the persisted hash, so chunks reused from
+    /// other uploads (and never written under this session) are found after
+    /// a restart too. Chunks without a hash are read from the session.
     pub async fn assemble_chunks(
         &self,
         session_id: Uuid,
-        chunk_count: usize,
+        chunk_hashes: &[String],
     ) -> Result<Vec<u8>, UploadError> {
-        self.inner.backend.assemble_chunks(session_id, chunk_count).await
+        let mapped = {
+            let session_chunks = self.inner.session_chunks.read().await;
+            session_chunks.get(&session_id).cloned().unwrap_or_default()
+        };
+
+        let mut result = Vec::new();
+        for (i, persisted) in chunk_hashes.iter().enumerate() {
+            let hash = mapped
+                .get(&i)
+                .or(Some(persisted).filter(|hash| !hash.is_empty()));
+            let chunk = match hash {
+                Some(hash) => self.inner.backend.get_chunk_by_hash(hash).await?,
+                None => self.inner.backend.get_chunk(session_id, i).await?,
+            };
+            result.extend_from_slice(&chunk);
+        }
+
+        Ok(result)
     }
 
     /// Get total stored chunk count
@@ -229,6 +286,41 @@ impl ChunkStore {
         index.len()
     }
 
+    /// Sessions that have chunks in storage
+    pub async fn stored_sessions(&self) -> Result<Vec<Uuid>, UploadError> {
+        self.inner.backend.list_sessions().await
+    }
+
+    /// Delete the chunks of `stored` sessions that are not in `live_sessions`
+    ///
+    /// Catches chunks left behind by sessions that expired or were removed
+    /// while the server was down. List `stored` (see [`Self::stored_sessions`])
+    /// before the live sessions, so a session created in between counts as
+    /// live. Returns the number of sessions cleaned up.
+    pub async fn reconcile_sessions(
+        &self,
+        stored: Vec<Uuid>,
+        live_sessions: &HashSet<Uuid>,
+    ) -> Result<usize, UploadError> {
+        let mut count = 0;
+
+        for session_id in stored {
+            if live_sessions.contains(&session_id) {
+                continue;
+            }
+
+            let deleted = self.delete_session_chunks(session_id).await?;
+            tracing::debug!(
+                session_id = %session_id,
+                chunks = deleted,
+                "Deleted chunks of orphaned session"
+            );
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
     /// Cleanup orphaned chunks (not referenced by any session)
     pub async fn cleanup_orphaned(&self) -> usize {
         // Get all session chunk hashes
@@ -306,16 +398,9 @@ impl ChunkStorage for LocalChunkStorage {
         expected_hash: &str,
     ) -> Result<ChunkMetadata, UploadError> {
         // Store by session/index
+        self.store_session_chunk(session_id, chunk_index, data)
+            .await?;
         let session_path = self.chunk_path(session_id, chunk_index);
-        if let Some(parent) = session_path.parent() {
-            tokio::fs::create_dir_all(parent)
-                .await
-                .map_err(|e| UploadError::StorageError(e.to_string()))?;
-        }
-
-        tokio::fs::write(&session_path, data)
-            .await
-            .map_err(|e| UploadError::StorageError(e.to_string()))?;
 
         // Also store by hash (content-addressable)
         let hash_path = self.hash_path(expected_hash);
@@ -341,6 +426,24 @@ impl ChunkStorage for LocalChunkStorage {
         })
     }
 
+    async fn store_session_chunk(
+        &self,
+        session_id: Uuid,
+        chunk_index: usize,
+        data: &[u8],
+    ) -> Result<(), UploadError> {
+        let path = self.chunk_path(session_id, chunk_index);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| UploadError::StorageError(e.to_string()))?;
+        }
+
+        tokio::fs::write(&path, data)
+            .await
+            .map_err(|e| UploadError::StorageError(e.to_string()))
+    }
+
     async fn get_chunk(
         &self,
         session_id: Uuid,
@@ -405,21 +508,44 @@ impl ChunkStorage for LocalChunkStorage {
 
         Ok(result)
     }
+
+    async fn list_sessions(&self) -> Result<Vec<Uuid>, UploadError> {
+        let chunks_dir = self.base_path.join("chunks");
+
+        if !chunks_dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut sessions = Vec::new();
+        let mut entries = tokio::fs::read_dir(&chunks_dir)
+            .await
+            .map_err(|e| UploadError::StorageError(e.to_string()))?;
+
+        while let Some(entry) = entries.next_entry().await
+            .map_err(|e| UploadError::StorageError(e.to_string()))?
+        {
+            if let Some(id) = entry.file_name().to_str().and_then(|n| Uuid::parse_str(n).ok()) {
+                sessions.push(id);
+            }
+        }
+
+        Ok(sessions)
+    }
 }
 
 // ============================================================================
-// S3 Storage
+// Object Storage
 // ============================================================================
 
-/// S3-based chunk storage
-struct S3ChunkStorage {
-    client: S3Client,
+/// Chunk storage in the configured storage backend (S3 or local directory)
+struct ObjectChunkStorage {
+    storage: Storage,
     prefix: String,
 }
 
-impl S3ChunkStorage {
-    fn new(client: S3Client, prefix: String) -> Self {
-        Self { client, prefix }
+impl ObjectChunkStorage {
+    fn new(storage: Storage, prefix: String) -> Self {
+        Self { storage, prefix }
     }
 
     fn chunk_key(&self, session_id: Uuid, chunk_index: usize) -> String {
@@ -438,7 +564,7 @@ impl S3ChunkStorage {
 }
 
 #[async_trait::async_trait]
-impl ChunkStorage for S3ChunkStorage {
+impl ChunkStorage for ObjectChunkStorage {
     async fn store_chunk(
         &self,
         session_id: Uuid,
@@ -446,16 +572,13 @@ impl ChunkStorage for S3ChunkStorage {
         data: &[u8],
         expected_hash: &str,
     ) -> Result<ChunkMetadata, UploadError> {
+        self.store_session_chunk(session_id, chunk_index, data)
+            .await?;
         let key = self.chunk_key(session_id, chunk_index);
 
-        self.client
-            .put_object(&key, data.to_vec(), "application/octet-stream")
-            .await
-            .map_err(|e| UploadError::StorageError(e.to_string()))?;
-
         // Also store by hash for deduplication
         let hash_key = self.hash_key(expected_hash);
-        self.client
+        self.storage
             .put_object(&hash_key, data.to_vec(), "application/octet-stream")
             .await
             .map_err(|e| UploadError::StorageError(e.to_string()))?;
@@ -469,6 +592,19 @@ impl ChunkStorage for S3ChunkStorage {
         })
     }
 
+    async fn store_session_chunk(
+        &self,
+        session_id: Uuid,
+        chunk_index: usize,
+        data: &[u8],
+    ) -> Result<(), UploadError> {
+        let key = self.chunk_key(session_id, chunk_index);
+        self.storage
+            .put_object(&key, data.to_vec(), "application/octet-stream")
+            .await
+            .map_err(|e| UploadError::StorageError(e.to_string()))
+    }
+
     async fn get_chunk(
         &self,
         session_id: Uuid,
@@ -476,23 +612,23 @@ impl ChunkStorage for S3ChunkStorage {
     ) -> Result<Vec<u8>, UploadError> {
         let key = self.chunk_key(session_id, chunk_index);
 
-        let obj = self.client
+        let obj = self.storage
             .get_object(&key)
             .await
-            .map_err(|e| UploadError::StorageError(format!("Failed to get chunk from S3: {}", e)))?;
+            .map_err(|e| UploadError::StorageError(format!("Failed to get chunk from storage: {}", e)))?;
 
         Ok(obj.data)
     }
 
     async fn chunk_exists(&self, hash: &str) -> bool {
         let key = self.hash_key(hash);
-        self.client.object_exists(&key).await.unwrap_or(false)
+        self.storage.object_exists(&key).await.unwrap_or(false)
     }
 
     async fn get_chunk_by_hash(&self, hash: &str) -> Result<Vec<u8>, UploadError> {
         let key = self.hash_key(hash);
 
-        let obj = self.client
+        let obj = self.storage
             .get_object(&key)
             .await
             .map_err(|e| UploadError::StorageError(format!("Failed to get chunk by hash: {}", e)))?;
@@ -503,7 +639,7 @@ impl ChunkStorage for S3ChunkStorage {
     async fn delete_session_chunks(&self, session_id: Uuid) -> Result<usize, UploadError> {
         let prefix = format!("{}/chunks/{}/", self.prefix, session_id);
 
-        self.client
+        self.storage
             .delete_objects_with_prefix(&prefix)
             .await
             .map_err(|e| UploadError::StorageError(e.to_string()))
@@ -523,6 +659,24 @@ impl ChunkStorage for S3ChunkStorage {
 
         Ok(result)
     }
+
+    async fn list_sessions(&self) -> Result<Vec<Uuid>, UploadError> {
+        let prefix = format!("{}/chunks/", self.prefix);
+
+        let objects = self.storage
+            .list_all_objects(Some(&prefix))
+            .await
+            .map_err(|e| UploadError::StorageError(e.to_string()))?;
+
+        let sessions: HashSet<Uuid> = objects
+            .iter()
+            .filter_map(|obj| obj.key.strip_prefix(&prefix))
+            .filter_map(|rest| rest.split('/').next())
+            .filter_map(|id| Uuid::parse_str(id).ok())
+            .collect();
+
+        Ok(sessions.into_iter().collect())
+    }
 }
 
 // ============================================================================
@@ -606,10 +760,34 @@ mod tests {
         store.store_chunk(session_id, 1, chunk2, &hash2).await.unwrap();
 
         // Assemble
-        let assembled = store.assemble_chunks(session_id, 2).await.unwrap();
+        let assembled = store
+            .assemble_chunks(session_id, &[hash1, hash2])
+            .await
+            .unwrap();
         assert_eq!(assembled, b"Hello, World!");
     }
 
+    #[tokio::test]
+    async fn test_assembly_after_restart() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = ChunkStore::with_local_storage(temp_dir.path().to_path_buf());
+
+        let first = Uuid::new_v4();
+        let second = Uuid::new_v4();
+        let chunk = b"shared chunk";
+        let hash = compute_hash(chunk);
+
+        // The second session reuses the first one's chunk
+        store.store_chunk(first, 0, chunk, &hash).await.unwrap();
+        store.store_chunk(second, 0, chunk, &hash).await.unwrap();
+        store.delete_session_chunks(first).await.unwrap();
+
+        // A new store has no mappings; the persisted hashes locate the chunk
+        let restarted = ChunkStore::with_local_storage(temp_dir.path().to_path_buf());
+        let assembled = restarted.assemble_chunks(second, &[hash]).await.unwrap();
+        assert_eq!(assembled, chunk);
+    }
+
     #[tokio::test]
     async fn test_hash_mismatch() {
         let temp_dir = TempDir::new().unwrap();
@@ -624,4 +802,29 @@ mod tests {
 
         assert!(matches!(result, Err(UploadError::ChunkHashMismatch { .. })));
     }
+
+    #[tokio::test]
+    async fn test_reconcile_sessions() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = ChunkStore::with_local_storage(temp_dir.path().to_path_buf());
+
+        let live = Uuid::new_v4();
+        let orphan = Uuid::new_v4();
+        let live_data = b"live chunk";
+        let orphan_data = b"orphan chunk";
+
+        store.store_chunk(live, 0, live_data, &compute_hash(live_data)).await.unwrap();
+        store.store_chunk(orphan, 0, orphan_data, &compute_hash(orphan_data)).await.unwrap();
+
+        let stored = store.stored_sessions().await.unwrap();
+        let live_sessions: HashSet<Uuid> = [live].into_iter().collect();
+        let removed = store
+            .reconcile_sessions(stored, &live_sessions)
+            .await
+            .unwrap();
+        assert_eq!(removed, 1);
+
+        assert!(store.get_chunk(live, 0).await.is_ok());
+        assert!(!temp_dir.path().join("chunks").join(orphan.to_string()).exists());
+    }
 }
@@ -0,0 +1,214 @@
+//! Direct-to-storage uploads
+//!
+//! With presigned URLs enabled, a handshake can ask for a `direct` upload:
+//! the server starts a multipart upload in the bucket with one part per
+//! chunk and hands back a presigned PUT URL for each. Chunk bytes never
+//! pass through the server; at finalize it completes the multipart upload
+//! and verifies the chunk and file hashes before ingesting the book.
+//!
+//! Parts are staged under [`DIRECT_UPLOAD_PREFIX`] and removed once the
+//! book has been stored at its library key.
+
+use std::time::Duration;
+
+use uuid::Uuid;
+
+use crate::storage::{PresignedUpload, Storage};
+
+use super::chunk_store::compute_hash;
+use super::types::{HandshakeRequest, UploadError, UploadSession, DEFAULT_CHUNK_SIZE};
+
+/// Storage prefix for direct uploads awaiting finalization
+pub const DIRECT_UPLOAD_PREFIX: &str = "uploads/direct";
+
+/// Minimum size of every part but the last (S3 multipart limit)
+pub const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+/// Maximum number of parts in a multipart upload (S3 limit)
+pub const MAX_PARTS: usize = 10_000;
+
+/// Storage key a session's parts are assembled at
+pub fn staging_key(session_id: Uuid) -> String {
+    format!("{}/{}", DIRECT_UPLOAD_PREFIX, session_id)
+}
+
+/// Check a handshake can be served as a multipart upload
+pub fn validate_request(request: &HandshakeRequest) -> Result<(), UploadError> {
+    let parts = request.chunk_hashes.len();
+    let chunk_size = request.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+
+    if parts == 0 || parts > MAX_PARTS {
+        return Err(UploadError::DirectUpload(format!(
+            "{} chunks (must be 1 to {})",
+            parts, MAX_PARTS
+        )));
+    }
+
+    if parts > 1 && chunk_size < MIN_PART_SIZE {
+        return Err(UploadError::DirectUpload(format!(
+            "chunk size {} is below the {} byte storage minimum",
+            chunk_size, MIN_PART_SIZE
+        )));
+    }
+
+    Ok(())
+}
+
+/// Start the multipart upload for a session and presign its part URLs
+pub async fn start(
+    storage: &Storage,
+    session: &UploadSession,
+    expires_in: Duration,
+) -> Result<PresignedUpload, UploadError> {
+    storage
+        .presign_multipart_upload(
+            &staging_key(session.id),
+            &session.mime_type,
+            session.chunk_hashes.len(),
+            expires_in,
+        )
+        .await
+        .map_err(|e| UploadError::StorageError(e.to_string()))?
+        .ok_or_else(|| {
+            UploadError::DirectUpload(format!(
+                "{} storage does not support presigned uploads",
+                storage.name()
+            ))
+        })
+}
+
+/// Complete a session's multipart upload and return the verified file
+///
+/// Completion is skipped when the object already exists, so a finalize
+/// that failed after completing can be retried.
+pub async fn complete(storage: &Storage, session: &UploadSession) -> Result<Vec<u8>, UploadError> {
+    let upload_id = session
+        .direct_upload_id
+        .as_deref()
+        .ok_or_else(|| UploadError::DirectUpload("session is not a direct upload".to_string()))?;
+    let key = staging_key(session.id);
+
+    let exists = storage
+        .object_exists(&key)
+        .await
+        .map_err(|e| UploadError::StorageError(e.to_string()))?;
+    if !exists {
+        storage
+            .complete_multipart_upload(&key, upload_id)
+            .await
+            .map_err(|e| UploadError::StorageError(e.to_string()))?;
+    }
+
+    let data = storage
+        .get_object(&key)
+        .await
+        .map_err(|e| UploadError::StorageError(e.to_string()))?
+        .data;
+
+    verify(session, &data)?;
+    Ok(data)
+}
+
+/// Abort a session's multipart upload and remove its staged object
+pub async fn discard(storage: &Storage, session: &UploadSession) {
+    let key = staging_key(session.id);
+
+    if let Some(ref upload_id) = session.direct_upload_id {
+        if let Err(e) = storage.abort_multipart_upload(&key, upload_id).await {
+            tracing::debug!("Failed to abort multipart upload {}: {}", upload_id, e);
+        }
+    }
+
+    if let Err(e) = storage.delete_object(&key).await {
+        tracing::warn!("Failed to remove staged upload {}: {}", key, e);
+    }
+}
+
+/// Check an uploaded file against the session's chunk and file hashes
+pub fn verify(session: &UploadSession, data: &[u8]) -> Result<(), UploadError> {
+    if data.len() as u64 != session.file_size {
+        return Err(UploadError::DirectUpload(format!(
+            "expected {} bytes, storage has {}",
+            session.file_size,
+            data.len()
+        )));
+    }
+
+    let chunks: Vec<&[u8]> = data.chunks(session.chunk_size.max(1)).collect();
+    if chunks.len() != session.chunk_hashes.len() {
+        return Err(UploadError::DirectUpload(format!(
+            "expected {} chunks, storage has {}",
+            session.chunk_hashes.len(),
+            chunks.len()
+        )));
+    }
+
+    for (chunk, expected) in chunks.iter().zip(&session.chunk_hashes) {
+        let actual = compute_hash(chunk);
+        if &actual != expected {
+            return Err(UploadError::ChunkHashMismatch {
+                expected: expected.clone(),
+                actual,
+            });
+        }
+    }
+
+    let actual = compute_hash(data);
+    if actual != session.file_hash {
+        return Err(UploadError::ChunkHashMismatch {
+            expected: session.file_hash.clone(),
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(data: &[u8], chunk_size: usize) -> HandshakeRequest {
+        HandshakeRequest {
+            file_name: "book.epub".to_string(),
+            file_size: data.len() as u64,
+            file_hash: compute_hash(data),
+            chunk_hashes: data.chunks(chunk_size).map(compute_hash).collect(),
+            mime_type: "application/epub+zip".to_string(),
+            chunk_size: Some(chunk_size),
+            direct: true,
+        }
+    }
+
+    #[test]
+    fn test_validate_request() {
+        let data = vec![7u8; 100];
+        assert!(validate_request(&request(&data, 100)).is_ok());
+        assert!(validate_request(&request(&data, 10)).is_err());
+
+        let large = vec![7u8; MIN_PART_SIZE + 1];
+        assert!(validate_request(&request(&large, MIN_PART_SIZE)).is_ok());
+    }
+
+    #[test]
+    fn test_verify() {
+        let data: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        let session = UploadSession::new(&request(&data, 300));
+
+        assert!(verify(&session, &data).is_ok());
+
+        let mut tampered = data.clone();
+        tampered[450] ^= 1;
+        match verify(&session, &tampered) {
+            Err(UploadError::ChunkHashMismatch { expected, .. }) => {
+                assert_eq!(expected, session.chunk_hashes[1]);
+            }
+            other => panic!("expected chunk mismatch, got {:?}", other),
+        }
+
+        assert!(matches!(
+            verify(&session, &data[..999]),
+            Err(UploadError::DirectUpload(_))
+        ));
+    }
+}
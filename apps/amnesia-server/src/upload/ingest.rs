@@ -0,0 +1,395 @@
+//! Ingest Pipeline
+//!
+//! Turns an assembled upload into a library book:
+//! 1. Store the file under `books/{id}/` in object storage
+//! 2. Parse metadata with the document handlers and extract a cover (the
+//!    EPUB's cover image, or the first page rendered)
+//! 3. Write a Calibre-style `metadata.opf` and the cover beside the file
+//!    so library scans pick the book up with proper metadata
+//! 4. Insert the `books` row; callers upsert the returned `library_book`
+//!    into the library catalog
+//! 5. Register the file hash for deduplication and warm the `DocumentCache`
+
+use std::sync::Arc;
+
+use chrono::Utc;
+
+use super::deduplication::DeduplicationService;
+use super::types::UploadError;
+use crate::db::{BookRecord, BookRepository, CatalogRepository, CreateBook};
+use crate::document::{DocumentFormat, ParsedDocument};
+use crate::formats::open_document;
+use crate::library::{
+    epub_cover, BookFormat, CalibreMetadata, CoverImage, FormatType, LibraryBook, LibraryDocuments,
+    RENDERED_COVER_SIZE,
+};
+use crate::state::AppState;
+
+/// A file ready to be ingested
+#[derive(Debug, Clone)]
+pub struct IngestRequest {
+    /// Book ID (also the storage folder name)
+    pub book_id: String,
+    /// Original file name
+    pub file_name: String,
+    /// MIME type declared by the client
+    pub mime_type: String,
+    /// SHA-256 of the file (already verified)
+    pub file_hash: String,
+    /// File contents
+    pub data: Vec<u8>,
+}
+
+/// Result of a successful ingest
+#[derive(Debug, Clone)]
+pub struct IngestedBook {
+    /// The persisted book record
+    pub record: BookRecord,
+    /// Library entry for catalog views (OPDS)
+    pub library_book: LibraryBook,
+}
+
+/// Run the ingest pipeline for an uploaded file
+///
+/// Parsing and cover extraction are best-effort: unsupported or malformed
+/// files are still stored and recorded, with the title taken from the file name.
+/// If the database insert fails, everything written to storage is removed.
+pub async fn ingest_book(
+    app_state: &AppState,
+    dedup_service: &DeduplicationService,
+    request: IngestRequest,
+) -> Result<IngestedBook, UploadError> {
+    let IngestRequest {
+        book_id,
+        file_name,
+        mime_type,
+        file_hash,
+        data,
+    } = request;
+
+    let storage = app_state.storage();
+    let prefix = format!("books/{}/", book_id);
+    let storage_key = format!("{}{}", prefix, file_name);
+    let file_size = data.len() as i64;
+
+    // Parse with the matching handler, if any
+    let format = DocumentFormat::from_magic_bytes(&data)
+        .or_else(|| DocumentFormat::from_mime(&mime_type))
+        .or_else(|| {
+            file_name
+                .rsplit_once('.')
+                .and_then(|(_, ext)| DocumentFormat::from_extension(ext))
+        });
+
+    let opened = match format {
+        Some(format) => match open_document(data.clone(), book_id.clone(), format).await {
+            Ok(opened) => Some(opened),
+            Err(e) => {
+                tracing::warn!(
+                    book_id = %book_id,
+                    file_name = %file_name,
+                    "Failed to parse uploaded file, storing without metadata: {}",
+                    e
+                );
+                None
+            }
+        },
+        None => None,
+    };
+
+    let mut cover: Option<CoverImage> = match (format, opened.is_some()) {
+        (Some(DocumentFormat::Epub), true) => epub_cover(Arc::new(data.clone())),
+        _ => None,
+    };
+
+    storage.put_object(&storage_key, data, &mime_type)
+        .await
+        .map_err(|e| UploadError::StorageError(e.to_string()))?;
+
+    // Cover: the EPUB's cover image, else the first page/chapter rendered
+    // as a thumbnail
+    if let Some((_, ref renderer, ref parsed)) = opened {
+        if cover.is_none() && parsed.item_count > 0 {
+            match renderer.render_thumbnail(0, RENDERED_COVER_SIZE).await {
+                Ok(rendered) => cover = Some(rendered.into()),
+                Err(e) => tracing::warn!(book_id = %book_id, "Failed to render cover: {}", e),
+            }
+        }
+    }
+
+    let mut cover_key = None;
+    if let Some(cover) = cover {
+        let key = format!("{}{}", prefix, cover.file_name());
+        match storage.put_object(&key, cover.data, cover.format.content_type()).await {
+            Ok(()) => cover_key = Some(key),
+            Err(e) => tracing::warn!(book_id = %book_id, "Failed to store cover: {}", e),
+        }
+    }
+
+    let parsed = opened.as_ref().map(|(_, _, parsed)| parsed.clone());
+    let metadata = calibre_metadata(&book_id, &file_name, parsed.as_ref(), cover_key.as_deref());
+
+    store_opf(app_state, &book_id, &metadata).await;
+
+    let title = metadata.title.clone().unwrap_or_else(|| file_name.clone());
+    let authors = metadata.authors.join(", ");
+
+    let create = CreateBook {
+        id: book_id.clone(),
+        title: title.clone(),
+        authors: (!authors.is_empty()).then_some(authors),
+        file_name: file_name.clone(),
+        file_size,
+        file_hash: Some(file_hash.clone()),
+        mime_type: mime_type.clone(),
+        storage_key: storage_key.clone(),
+        cover_key: cover_key.clone(),
+        metadata: parsed.as_ref().and_then(|p| serde_json::to_string(p).ok()),
+    };
+
+    let record = match BookRepository::new(app_state.db()).create(&create).await {
+        Ok(record) => record,
+        Err(e) => {
+            if let Err(cleanup) = storage.delete_objects_with_prefix(&prefix).await {
+                tracing::warn!(book_id = %book_id, "Failed to remove stored files: {}", cleanup);
+            }
+            return Err(UploadError::DatabaseError(e.to_string()));
+        }
+    };
+
+    dedup_service.register_file(&file_hash, &book_id).await?;
+
+    // Already parsed, so make it available to the documents API right away
+    if let Some((parser, renderer, parsed)) = opened {
+        app_state
+            .document_cache()
+            .store_document_with_renderer(book_id.clone(), parsed, parser, renderer)
+            .await;
+    }
+
+    let pages = parsed.as_ref().map(|parsed| parsed.item_count);
+    let library_book = library_book(
+        &book_id,
+        &metadata,
+        &storage_key,
+        file_size,
+        pages,
+        cover_key,
+    );
+
+    tracing::info!(
+        book_id = %book_id,
+        title = %title,
+        size = file_size,
+        parsed = parsed.is_some(),
+        "Book ingested"
+    );
+
+    Ok(IngestedBook {
+        record,
+        library_book,
+    })
+}
+
+/// Add a document stored outside [`ingest_book`] to the library catalog
+///
+/// Writes `metadata.opf` beside the file, as an ingest does, and upserts the
+/// catalog entry so the document is searchable right away. Both steps are
+/// best-effort.
+pub async fn catalog_document(
+    app_state: &AppState,
+    book_id: &str,
+    file_name: &str,
+    storage_key: &str,
+    file_size: i64,
+    parsed: &ParsedDocument,
+) {
+    let metadata = calibre_metadata(book_id, file_name, Some(parsed), None);
+    store_opf(app_state, book_id, &metadata).await;
+
+    let pages = Some(parsed.item_count);
+    let book = library_book(book_id, &metadata, storage_key, file_size, pages, None);
+    if let Err(e) = CatalogRepository::new(app_state.db()).upsert(&book).await {
+        tracing::warn!(book_id = %book_id, "Failed to add document to catalog: {}", e);
+    }
+}
+
+/// Store `metadata.opf` in the book's folder
+///
+/// It keeps library scans in agreement with the database.
+async fn store_opf(app_state: &AppState, book_id: &str, metadata: &CalibreMetadata) {
+    match metadata.to_opf() {
+        Ok(opf) => {
+            let key = format!("books/{}/metadata.opf", book_id);
+            let stored = app_state
+                .storage()
+                .put_object(&key, opf.into_bytes(), "application/oebps-package+xml")
+                .await;
+            if let Err(e) = stored {
+                tracing::warn!(book_id = %book_id, "Failed to store metadata.opf: {}", e);
+            }
+        }
+        Err(e) => tracing::warn!(book_id = %book_id, "Failed to serialize metadata.opf: {}", e),
+    }
+}
+
+/// Build Calibre metadata from a parsed document, falling back to the file name
+fn calibre_metadata(
+    book_id: &str,
+    file_name: &str,
+    parsed: Option<&ParsedDocument>,
+    cover_key: Option<&str>,
+) -> CalibreMetadata {
+    let mut metadata = CalibreMetadata::default();
+
+    if let Some(parsed) = parsed {
+        let meta = &parsed.metadata;
+        if !meta.title.trim().is_empty() {
+            metadata.title = Some(meta.title.clone());
+        }
+        metadata.authors = meta.creators.iter().map(|c| c.name.clone()).collect();
+        metadata.author = metadata.authors.first().cloned();
+        metadata.author_sort = meta.creators.first().and_then(|c| c.file_as.clone());
+        metadata.publisher = meta.publisher.clone();
+        metadata.pubdate = meta.date.clone();
+        metadata.language = meta.language.clone();
+        metadata.description = meta.description.clone();
+        metadata.tags = meta.subjects.clone();
+        metadata.series = meta.series.clone();
+        metadata.series_index = meta.series_index;
+    }
+
+    if metadata.title.is_none() {
+        metadata.title = Some(title_from_file_name(file_name));
+    }
+
+    metadata
+        .identifiers
+        .insert("uuid".to_string(), book_id.to_string());
+    metadata.cover_path = cover_key
+        .and_then(|key| key.rsplit('/').next())
+        .map(str::to_string);
+
+    metadata
+}
+
+/// Build the library entry a scan of the stored folder would produce,
+/// plus the page count of a page-based format
+fn library_book(
+    book_id: &str,
+    metadata: &CalibreMetadata,
+    storage_key: &str,
+    file_size: i64,
+    pages: Option<usize>,
+    cover_key: Option<String>,
+) -> LibraryBook {
+    let title = metadata.title.clone().unwrap_or_default();
+    let mut book = LibraryBook::new(title, format!("books/{}", book_id));
+    book.id = book_id.to_string();
+    book.author = metadata.author.clone();
+    book.author_sort = metadata.author_sort.clone();
+    book.authors = metadata.authors.clone();
+    book.publisher = metadata.publisher.clone();
+    book.pubdate = metadata.pubdate.clone();
+    book.language = metadata.language.clone();
+    book.description = metadata.description.clone();
+    book.tags = metadata.tags.clone();
+    book.series = metadata.series.clone();
+    book.series_index = metadata.series_index;
+    book.identifiers = metadata.identifiers.clone();
+    book.cover_key = cover_key;
+
+    let ext = storage_key.rsplit('.').next().unwrap_or_default();
+    let mut format = BookFormat {
+        format: FormatType::from_extension(ext),
+        s3_key: storage_key.to_string(),
+        size: file_size,
+        pages: None,
+    };
+    if LibraryDocuments::is_page_based(&format) {
+        format.pages = pages;
+    }
+    book.formats = vec![format];
+    book.added_at = Utc::now();
+    book.updated_at = book.added_at;
+
+    book
+}
+
+/// Derive a title from a file name by dropping the extension
+pub fn title_from_file_name(file_name: &str) -> String {
+    match file_name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem.to_string(),
+        _ => file_name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{Creator, DocumentMetadata};
+
+    #[test]
+    fn test_title_from_file_name() {
+        assert_eq!(title_from_file_name("Dune.epub"), "Dune");
+        assert_eq!(title_from_file_name("my.book.v2.pdf"), "my.book.v2");
+        assert_eq!(title_from_file_name("README"), "README");
+        assert_eq!(title_from_file_name(".hidden"), ".hidden");
+    }
+
+    #[test]
+    fn test_calibre_metadata_from_parsed() {
+        let parsed = ParsedDocument {
+            id: "abc".to_string(),
+            format: DocumentFormat::Epub,
+            metadata: DocumentMetadata {
+                title: "Dune".to_string(),
+                creators: vec![Creator {
+                    name: "Frank Herbert".to_string(),
+                    role: Some("aut".to_string()),
+                    file_as: Some("Herbert, Frank".to_string()),
+                }],
+                language: Some("en".to_string()),
+                subjects: vec!["Science Fiction".to_string()],
+                ..Default::default()
+            },
+            toc: vec![],
+            item_count: 10,
+            item_labels: None,
+            has_text_layer: true,
+        };
+
+        let metadata =
+            calibre_metadata("abc", "dune.epub", Some(&parsed), Some("books/abc/cover.jpg"));
+        assert_eq!(metadata.title.as_deref(), Some("Dune"));
+        assert_eq!(metadata.author.as_deref(), Some("Frank Herbert"));
+        assert_eq!(metadata.author_sort.as_deref(), Some("Herbert, Frank"));
+        assert_eq!(metadata.tags, vec!["Science Fiction".to_string()]);
+        assert_eq!(metadata.identifiers.get("uuid").map(String::as_str), Some("abc"));
+        assert_eq!(metadata.cover_path.as_deref(), Some("cover.jpg"));
+    }
+
+    #[test]
+    fn test_calibre_metadata_falls_back_to_file_name() {
+        let metadata = calibre_metadata("abc", "Some Title.mobi", None, None);
+        assert_eq!(metadata.title.as_deref(), Some("Some Title"));
+        assert!(metadata.authors.is_empty());
+        assert!(metadata.cover_path.is_none());
+    }
+
+    #[test]
+    fn test_library_book_matches_storage_layout() {
+        let metadata = calibre_metadata("abc", "dune.pdf", None, None);
+        let book = library_book("abc", &metadata, "books/abc/dune.pdf", 42, Some(3), None);
+        assert_eq!(book.id, "abc");
+        assert_eq!(book.s3_prefix, "books/abc");
+        assert_eq!(book.formats.len(), 1);
+        assert_eq!(book.formats[0].format, FormatType::Pdf);
+        assert_eq!(book.formats[0].size, 42);
+        assert_eq!(book.formats[0].pages, Some(3));
+
+        // Chapter counts aren't page counts
+        let book = library_book("abc", &metadata, "books/abc/dune.epub", 42, Some(3), None);
+        assert!(book.formats[0].pages.is_none());
+    }
+}
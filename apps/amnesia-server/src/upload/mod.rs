@@ -9,11 +9,19 @@
 //! 1. Client sends handshake with file hash and chunk hashes
 //! 2. Server responds with which chunks are needed (deduplication)
 //! 3. Client uploads only needed chunks
-//! 4. Server reassembles file and returns book ID
+//! 4. Server reassembles file, ingests it as a book and returns its ID
+//!
+//! The tus 1.0 protocol (`tus` module) is served alongside and shares the
+//! session manager, chunk store and ingest pipeline. With presigned URLs
+//! enabled, `direct` handshakes send chunks straight to the bucket
+//! (`direct` module).
 
 pub mod chunk_store;
 pub mod deduplication;
+pub mod direct;
+pub mod ingest;
 pub mod session;
+pub mod tus;
 pub mod types;
 
 pub use chunk_store::{ChunkStore, compute_hash, verify_hash};
@@ -4,13 +4,21 @@
 //! - In-memory session storage with mutex protection
 //! - Automatic session expiry cleanup
 //! - Session state persistence to database
+//!
+//! When backed by a database, every change is written through to the
+//! `upload_sessions` table so uploads survive restarts. The in-memory maps
+//! act as a cache: sessions are reloaded on startup with [`SessionManager::restore`]
+//! and lazily fetched on a cache miss (e.g. created by another replica).
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use chrono::Utc;
+use parking_lot::Mutex;
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
 use uuid::Uuid;
 
+use super::chunk_store::ChunkStore;
 use super::types::{
     HandshakeRequest, UploadSession, SessionStatus, UploadError,
     MAX_CONCURRENT_UPLOADS,
@@ -35,31 +43,94 @@ struct SessionManagerInner {
 
     /// Maximum concurrent uploads (0 = unlimited)
     max_concurrent: usize,
+
+    /// Database for persistent sessions (None = in-memory only)
+    db: Option<SqlitePool>,
+
+    /// Sessions with an append in progress (see [`SessionManager::lock_append`])
+    appending: Mutex<HashSet<Uuid>>,
+}
+
+/// Exclusive right to append to a session, released on drop
+pub struct AppendLock {
+    manager: SessionManager,
+    id: Uuid,
+}
+
+impl Drop for AppendLock {
+    fn drop(&mut self) {
+        self.manager.inner.appending.lock().remove(&self.id);
+    }
 }
 
 impl SessionManager {
-    /// Create a new session manager
+    /// Create a new in-memory session manager
     pub fn new() -> Self {
-        Self {
-            inner: Arc::new(SessionManagerInner {
-                sessions: RwLock::new(HashMap::new()),
-                sessions_by_hash: RwLock::new(HashMap::new()),
-                max_concurrent: MAX_CONCURRENT_UPLOADS,
-            }),
-        }
+        Self::build(MAX_CONCURRENT_UPLOADS, None)
     }
 
     /// Create a new session manager with custom max concurrent limit
     pub fn with_max_concurrent(max: usize) -> Self {
+        Self::build(max, None)
+    }
+
+    /// Create a session manager that persists sessions to `upload_sessions`
+    pub fn with_database(db: SqlitePool) -> Self {
+        Self::build(MAX_CONCURRENT_UPLOADS, Some(db))
+    }
+
+    fn build(max_concurrent: usize, db: Option<SqlitePool>) -> Self {
         Self {
             inner: Arc::new(SessionManagerInner {
                 sessions: RwLock::new(HashMap::new()),
                 sessions_by_hash: RwLock::new(HashMap::new()),
-                max_concurrent: max,
+                max_concurrent,
+                db,
+                appending: Mutex::new(HashSet::new()),
             }),
         }
     }
 
+    /// Reload unexpired sessions from the database
+    ///
+    /// Returns the number of sessions restored.
+    pub async fn restore(&self) -> Result<usize, UploadError> {
+        let Some(ref db) = self.inner.db else {
+            return Ok(0);
+        };
+
+        let rows = sqlx::query_as::<_, SessionRow>(
+            r#"
+            SELECT id, file_name, file_size, file_hash, mime_type, chunk_hashes, chunk_size,
+                   received_chunks, status, user_id, created_at, expires_at, upload_offset,
+                   direct_upload_id
+            FROM upload_sessions
+            WHERE expires_at > ?
+            "#,
+        )
+        .bind(Utc::now().to_rfc3339())
+        .fetch_all(db)
+        .await
+        .map_err(|e| UploadError::DatabaseError(e.to_string()))?;
+
+        let mut restored = 0;
+        for row in rows {
+            match row.into_session() {
+                Ok(session) => {
+                    self.cache_session(session).await;
+                    restored += 1;
+                }
+                Err(e) => tracing::warn!("Skipping unreadable upload session: {}", e),
+            }
+        }
+
+        if restored > 0 {
+            tracing::info!(count = restored, "Restored upload sessions");
+        }
+
+        Ok(restored)
+    }
+
     // ========================================================================
     // Session Lifecycle
     // ========================================================================
@@ -88,19 +159,9 @@ impl SessionManager {
         // Create new session
         let session = UploadSession::new(request);
         let id = session.id;
-        let hash = session.file_hash.clone();
 
-        // Store session
-        {
-            let mut sessions = self.inner.sessions.write().await;
-            sessions.insert(id, session.clone());
-        }
-
-        // Index by hash for deduplication
-        {
-            let mut by_hash = self.inner.sessions_by_hash.write().await;
-            by_hash.entry(hash).or_default().push(id);
-        }
+        self.persist_new(&session).await?;
+        self.cache_session(session.clone()).await;
 
         tracing::info!(
             session_id = %id,
@@ -115,11 +176,17 @@ impl SessionManager {
 
     /// Get a session by ID
     pub async fn get_session(&self, id: Uuid) -> Result<UploadSession, UploadError> {
-        let sessions = self.inner.sessions.read().await;
-        sessions
-            .get(&id)
-            .cloned()
-            .ok_or_else(|| UploadError::SessionNotFound(id.to_string()))
+        if let Some(session) = self.inner.sessions.read().await.get(&id).cloned() {
+            return Ok(session);
+        }
+
+        // Not cached: it may have been created before a restart or by another replica
+        let session = self
+            .load_session(id)
+            .await?
+            .ok_or_else(|| UploadError::SessionNotFound(id.to_string()))?;
+        self.cache_session(session.clone()).await;
+        Ok(session)
     }
 
     /// Get a session by string ID
@@ -129,12 +196,44 @@ impl SessionManager {
         self.get_session(uuid).await
     }
 
+    /// Claim a session for appending
+    ///
+    /// Returns `None` while another request holds the lock, so concurrent
+    /// appends can't both pass the offset check and write the same chunks.
+    pub fn lock_append(&self, id: Uuid) -> Option<AppendLock> {
+        if !self.inner.appending.lock().insert(id) {
+            return None;
+        }
+        Some(AppendLock {
+            manager: self.clone(),
+            id,
+        })
+    }
+
     /// Update a session
     pub async fn update_session(&self, session: UploadSession) -> Result<(), UploadError> {
-        let mut sessions = self.inner.sessions.write().await;
-        if !sessions.contains_key(&session.id) {
-            return Err(UploadError::SessionNotFound(session.id.to_string()));
+        // Ensure the session is known (and cached) before replacing it
+        let previous = self.get_session(session.id).await?;
+        self.persist_progress(&session).await?;
+
+        // tus uploads only learn the file hash once all bytes have arrived
+        if previous.file_hash != session.file_hash {
+            let mut by_hash = self.inner.sessions_by_hash.write().await;
+            if let Some(ids) = by_hash.get_mut(&previous.file_hash) {
+                ids.retain(|id| *id != session.id);
+                if ids.is_empty() {
+                    by_hash.remove(&previous.file_hash);
+                }
+            }
+            if !session.file_hash.is_empty() {
+                by_hash
+                    .entry(session.file_hash.clone())
+                    .or_default()
+                    .push(session.id);
+            }
         }
+
+        let mut sessions = self.inner.sessions.write().await;
         sessions.insert(session.id, session);
         Ok(())
     }
@@ -145,6 +244,9 @@ impl SessionManager {
         session_id: Uuid,
         chunk_index: usize,
     ) -> Result<UploadSession, UploadError> {
+        // Make sure the session is cached
+        self.get_session(session_id).await?;
+
         let mut sessions = self.inner.sessions.write().await;
 
         let session = sessions
@@ -154,6 +256,9 @@ impl SessionManager {
         // Check if session is expired
         if session.is_expired() {
             session.status = SessionStatus::Expired;
+            let expired = session.clone();
+            drop(sessions);
+            self.persist_progress(&expired).await?;
             return Err(UploadError::SessionExpired(session_id.to_string()));
         }
 
@@ -182,11 +287,18 @@ impl SessionManager {
             session.status = SessionStatus::Ready;
         }
 
-        Ok(session.clone())
+        let updated = session.clone();
+        drop(sessions);
+
+        self.persist_progress(&updated).await?;
+
+        Ok(updated)
     }
 
     /// Complete a session (after file assembly)
     pub async fn complete_session(&self, session_id: Uuid) -> Result<(), UploadError> {
+        self.get_session(session_id).await?;
+
         let mut sessions = self.inner.sessions.write().await;
 
         let session = sessions
@@ -201,11 +313,17 @@ impl SessionManager {
             "Upload session completed"
         );
 
-        Ok(())
+        let completed = session.clone();
+        drop(sessions);
+
+        self.persist_progress(&completed).await
     }
 
     /// Cancel/delete a session
     pub async fn cancel_session(&self, session_id: Uuid) -> Result<UploadSession, UploadError> {
+        self.get_session(session_id).await?;
+        self.persist_delete(session_id).await?;
+
         let session = {
             let mut sessions = self.inner.sessions.write().await;
             sessions
@@ -283,7 +401,9 @@ impl SessionManager {
 
     /// Clean up expired sessions
     ///
-    /// Returns the number of sessions cleaned up
+    /// With a database, expired rows are found in `upload_sessions` (including
+    /// sessions this instance never loaded). Returns the number of sessions
+    /// cleaned up.
     pub async fn cleanup_expired(&self) -> usize {
         let now = Utc::now();
         let mut expired_ids = Vec::new();
@@ -298,6 +418,17 @@ impl SessionManager {
             }
         }
 
+        match self.expired_in_database(now).await {
+            Ok(ids) => {
+                for id in ids {
+                    if !expired_ids.contains(&id) {
+                        expired_ids.push(id);
+                    }
+                }
+            }
+            Err(e) => tracing::warn!("Failed to query expired upload sessions: {}", e),
+        }
+
         // Remove expired sessions
         let count = expired_ids.len();
         for id in expired_ids {
@@ -339,17 +470,273 @@ impl SessionManager {
         count
     }
 
+    /// IDs of all known sessions (database rows, or the cache when in-memory)
+    pub async fn session_ids(&self) -> Result<HashSet<Uuid>, UploadError> {
+        let Some(ref db) = self.inner.db else {
+            return Ok(self.inner.sessions.read().await.keys().copied().collect());
+        };
+
+        let ids: Vec<String> = sqlx::query_scalar("SELECT id FROM upload_sessions")
+            .fetch_all(db)
+            .await
+            .map_err(|e| UploadError::DatabaseError(e.to_string()))?;
+
+        Ok(ids.iter().filter_map(|id| Uuid::parse_str(id).ok()).collect())
+    }
+
     /// Start background cleanup task
-    pub fn start_cleanup_task(self) -> tokio::task::JoinHandle<()> {
+    ///
+    /// Removes expired sessions and their chunks, then deletes chunks whose
+    /// session no longer exists. Stored chunks are listed before the live
+    /// sessions, so chunks of a session created in between are kept.
+    pub fn start_cleanup_task(self, chunk_store: ChunkStore) -> tokio::task::JoinHandle<()> {
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(std::time::Duration::from_secs(300)); // 5 minutes
 
             loop {
                 interval.tick().await;
                 self.cleanup_expired().await;
+
+                let stored = match chunk_store.stored_sessions().await {
+                    Ok(stored) => stored,
+                    Err(e) => {
+                        tracing::warn!("Failed to list stored upload chunks: {}", e);
+                        continue;
+                    }
+                };
+                match self.session_ids().await {
+                    Ok(live) => match chunk_store.reconcile_sessions(stored, &live).await {
+                        Ok(count) if count > 0 => {
+                            tracing::info!(count = count, "Removed chunks of orphaned upload sessions");
+                        }
+                        Ok(_) => {}
+                        Err(e) => tracing::warn!("Failed to reconcile upload chunks: {}", e),
+                    },
+                    Err(e) => tracing::warn!("Failed to list upload sessions: {}", e),
+                }
             }
         })
     }
+
+    // ========================================================================
+    // Persistence
+    // ========================================================================
+
+    /// Add a session to the in-memory maps
+    async fn cache_session(&self, session: UploadSession) {
+        let id = session.id;
+        let hash = session.file_hash.clone();
+
+        {
+            let mut sessions = self.inner.sessions.write().await;
+            sessions.insert(id, session);
+        }
+
+        // Index by hash for deduplication (tus uploads learn theirs at the end)
+        if !hash.is_empty() {
+            let mut by_hash = self.inner.sessions_by_hash.write().await;
+            let ids = by_hash.entry(hash).or_default();
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+
+    async fn load_session(&self, id: Uuid) -> Result<Option<UploadSession>, UploadError> {
+        let Some(ref db) = self.inner.db else {
+            return Ok(None);
+        };
+
+        let row = sqlx::query_as::<_, SessionRow>(
+            r#"
+            SELECT id, file_name, file_size, file_hash, mime_type, chunk_hashes, chunk_size,
+                   received_chunks, status, user_id, created_at, expires_at, upload_offset,
+                   direct_upload_id
+            FROM upload_sessions
+            WHERE id = ?
+            "#,
+        )
+        .bind(id.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(|e| UploadError::DatabaseError(e.to_string()))?;
+
+        row.map(SessionRow::into_session).transpose()
+    }
+
+    async fn persist_new(&self, session: &UploadSession) -> Result<(), UploadError> {
+        let Some(ref db) = self.inner.db else {
+            return Ok(());
+        };
+
+        let chunk_hashes = serde_json::to_string(&session.chunk_hashes)
+            .map_err(|e| UploadError::InternalError(e.to_string()))?;
+        let received_chunks = serde_json::to_string(&session.received_chunks)
+            .map_err(|e| UploadError::InternalError(e.to_string()))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO upload_sessions (id, file_name, file_size, file_hash, mime_type,
+                                         chunk_hashes, chunk_size, received_chunks, status,
+                                         user_id, created_at, expires_at, upload_offset,
+                                         direct_upload_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(session.id.to_string())
+        .bind(&session.file_name)
+        .bind(session.file_size as i64)
+        .bind(&session.file_hash)
+        .bind(&session.mime_type)
+        .bind(chunk_hashes)
+        .bind(session.chunk_size as i64)
+        .bind(received_chunks)
+        .bind(status_to_str(session.status))
+        .bind(&session.user_id)
+        .bind(session.created_at.to_rfc3339())
+        .bind(session.expires_at.to_rfc3339())
+        .bind(session.upload_offset as i64)
+        .bind(&session.direct_upload_id)
+        .execute(db)
+        .await
+        .map_err(|e| UploadError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Write the progress (hashes, received chunks, offset) and status of a session
+    async fn persist_progress(&self, session: &UploadSession) -> Result<(), UploadError> {
+        let Some(ref db) = self.inner.db else {
+            return Ok(());
+        };
+
+        let chunk_hashes = serde_json::to_string(&session.chunk_hashes)
+            .map_err(|e| UploadError::InternalError(e.to_string()))?;
+        let received_chunks = serde_json::to_string(&session.received_chunks)
+            .map_err(|e| UploadError::InternalError(e.to_string()))?;
+
+        sqlx::query(
+            r#"
+            UPDATE upload_sessions
+            SET file_hash = ?, chunk_hashes = ?, received_chunks = ?, upload_offset = ?,
+                status = ?, expires_at = ?, direct_upload_id = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&session.file_hash)
+        .bind(chunk_hashes)
+        .bind(received_chunks)
+        .bind(session.upload_offset as i64)
+        .bind(status_to_str(session.status))
+        .bind(session.expires_at.to_rfc3339())
+        .bind(&session.direct_upload_id)
+        .bind(session.id.to_string())
+        .execute(db)
+        .await
+        .map_err(|e| UploadError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn persist_delete(&self, session_id: Uuid) -> Result<(), UploadError> {
+        let Some(ref db) = self.inner.db else {
+            return Ok(());
+        };
+
+        sqlx::query("DELETE FROM upload_sessions WHERE id = ?")
+            .bind(session_id.to_string())
+            .execute(db)
+            .await
+            .map_err(|e| UploadError::DatabaseError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn expired_in_database(&self, now: DateTime<Utc>) -> Result<Vec<Uuid>, UploadError> {
+        let Some(ref db) = self.inner.db else {
+            return Ok(Vec::new());
+        };
+
+        let ids: Vec<String> =
+            sqlx::query_scalar("SELECT id FROM upload_sessions WHERE expires_at < ?")
+                .bind(now.to_rfc3339())
+                .fetch_all(db)
+                .await
+                .map_err(|e| UploadError::DatabaseError(e.to_string()))?;
+
+        Ok(ids.iter().filter_map(|id| Uuid::parse_str(id).ok()).collect())
+    }
+}
+
+/// Row in the `upload_sessions` table
+#[derive(sqlx::FromRow)]
+struct SessionRow {
+    id: String,
+    file_name: String,
+    file_size: i64,
+    file_hash: String,
+    mime_type: String,
+    chunk_hashes: String,
+    chunk_size: i64,
+    received_chunks: String,
+    status: String,
+    user_id: Option<String>,
+    created_at: String,
+    expires_at: String,
+    upload_offset: i64,
+    direct_upload_id: Option<String>,
+}
+
+impl SessionRow {
+    fn into_session(self) -> Result<UploadSession, UploadError> {
+        let parse_time = |value: &str| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|dt| dt.with_timezone(&Utc))
+                .map_err(|e| UploadError::DatabaseError(format!("Invalid timestamp '{}': {}", value, e)))
+        };
+
+        Ok(UploadSession {
+            id: Uuid::parse_str(&self.id)
+                .map_err(|e| UploadError::DatabaseError(format!("Invalid session ID: {}", e)))?,
+            file_name: self.file_name,
+            file_size: self.file_size as u64,
+            file_hash: self.file_hash,
+            mime_type: self.mime_type,
+            chunk_hashes: serde_json::from_str(&self.chunk_hashes)
+                .map_err(|e| UploadError::DatabaseError(e.to_string()))?,
+            chunk_size: self.chunk_size as usize,
+            received_chunks: serde_json::from_str(&self.received_chunks)
+                .map_err(|e| UploadError::DatabaseError(e.to_string()))?,
+            created_at: parse_time(&self.created_at)?,
+            expires_at: parse_time(&self.expires_at)?,
+            status: status_from_str(&self.status),
+            user_id: self.user_id,
+            upload_offset: self.upload_offset as u64,
+            direct_upload_id: self.direct_upload_id,
+        })
+    }
+}
+
+fn status_to_str(status: SessionStatus) -> &'static str {
+    match status {
+        SessionStatus::Pending => "pending",
+        SessionStatus::Uploading => "uploading",
+        SessionStatus::Ready => "ready",
+        SessionStatus::Complete => "complete",
+        SessionStatus::Failed => "failed",
+        SessionStatus::Expired => "expired",
+    }
+}
+
+fn status_from_str(status: &str) -> SessionStatus {
+    match status {
+        "uploading" => SessionStatus::Uploading,
+        "ready" => SessionStatus::Ready,
+        "complete" => SessionStatus::Complete,
+        "failed" => SessionStatus::Failed,
+        "expired" => SessionStatus::Expired,
+        _ => SessionStatus::Pending,
+    }
 }
 
 impl Default for SessionManager {
@@ -374,6 +761,7 @@ mod tests {
             chunk_hashes: vec!["chunk1".to_string(), "chunk2".to_string()],
             mime_type: "application/epub+zip".to_string(),
             chunk_size: None,
+            direct: false,
         }
     }
 
@@ -422,6 +810,83 @@ mod tests {
         assert!(not_found.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_hash_learned_later() {
+        let manager = SessionManager::new();
+        let mut request = create_test_request();
+        request.file_hash = String::new();
+        let mut session = manager.create_session(&request).await.unwrap();
+        assert!(manager.find_by_hash("").await.is_empty());
+
+        session.file_hash = "def456".to_string();
+        manager.update_session(session.clone()).await.unwrap();
+        assert_eq!(manager.find_by_hash("def456").await.len(), 1);
+        assert!(manager.find_by_hash("").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_lock_append() {
+        let manager = SessionManager::new();
+        let request = create_test_request();
+        let session = manager.create_session(&request).await.unwrap();
+
+        let lock = manager.lock_append(session.id).unwrap();
+        assert!(manager.lock_append(session.id).is_none());
+
+        drop(lock);
+        assert!(manager.lock_append(session.id).is_some());
+    }
+
+    async fn test_pool() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        crate::db::initialize_schema(&pool).await.unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_sessions_survive_restart() {
+        let pool = test_pool().await;
+
+        let manager = SessionManager::with_database(pool.clone());
+        let session = manager.create_session(&create_test_request()).await.unwrap();
+        manager.mark_chunk_received(session.id, 1).await.unwrap();
+
+        // A fresh manager sees the session and its received chunks
+        let restarted = SessionManager::with_database(pool.clone());
+        assert_eq!(restarted.restore().await.unwrap(), 1);
+
+        let restored = restarted.get_session(session.id).await.unwrap();
+        assert_eq!(restored.received_chunks, vec![1]);
+        assert_eq!(restored.status, SessionStatus::Uploading);
+        assert_eq!(restored.chunk_hashes, session.chunk_hashes);
+        assert_eq!(restarted.find_by_hash("abc123").await.len(), 1);
+
+        // Lazily loaded without restore()
+        let lazy = SessionManager::with_database(pool);
+        let updated = lazy.mark_chunk_received(session.id, 0).await.unwrap();
+        assert_eq!(updated.status, SessionStatus::Ready);
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_expired_uses_table() {
+        let pool = test_pool().await;
+
+        let manager = SessionManager::with_database(pool.clone());
+        let session = manager.create_session(&create_test_request()).await.unwrap();
+
+        // Expire the row behind the cache's back
+        sqlx::query("UPDATE upload_sessions SET expires_at = ? WHERE id = ?")
+            .bind((Utc::now() - chrono::Duration::hours(1)).to_rfc3339())
+            .bind(session.id.to_string())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(manager.cleanup_expired().await, 1);
+        assert!(manager.get_session(session.id).await.is_err());
+        assert!(manager.session_ids().await.unwrap().is_empty());
+    }
+
     #[tokio::test]
     async fn test_cancel_session() {
         let manager = SessionManager::new();
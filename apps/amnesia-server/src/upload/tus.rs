@@ -0,0 +1,451 @@
+//! tus Resumable Upload Protocol (1.0)
+//!
+//! Maps tus uploads onto the same chunk store and session manager used by
+//! the up2k protocol:
+//! - Creation allocates an `UploadSession` sized from `Upload-Length`
+//!   (file and chunk hashes are filled in as bytes arrive)
+//! - PATCH bodies are cut into fixed-size chunks; a trailing partial chunk is
+//!   stored under its index and completed by the next PATCH
+//! - `upload_offset` on the session tracks the bytes received
+//!
+//! See <https://tus.io/protocols/resumable-upload> for the protocol.
+
+use std::collections::HashMap;
+
+use axum::http::StatusCode;
+use base64::Engine;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use super::chunk_store::{compute_hash, ChunkStore};
+use super::session::SessionManager;
+use super::types::{
+    HandshakeRequest, SessionStatus, UploadError, UploadSession, DEFAULT_CHUNK_SIZE,
+};
+
+// ============================================================================
+// Constants
+// ============================================================================
+
+/// Protocol version implemented by the server
+pub const TUS_VERSION: &str = "1.0.0";
+
+/// Supported protocol extensions
+pub const TUS_EXTENSIONS: &str = "creation,creation-with-upload,expiration,termination,checksum";
+
+/// Supported checksum algorithms
+pub const TUS_CHECKSUM_ALGORITHMS: &str = "sha1,sha256";
+
+/// Required content type of PATCH bodies
+pub const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+
+/// Largest PATCH body buffered for checksum verification: 64MB
+pub const MAX_CHECKSUM_BODY_SIZE: usize = 64 * 1024 * 1024;
+
+// ============================================================================
+// Errors
+// ============================================================================
+
+/// tus protocol errors
+#[derive(Debug, thiserror::Error)]
+pub enum TusError {
+    #[error("Unsupported tus version: {0}")]
+    UnsupportedVersion(String),
+
+    #[error("Missing or invalid header: {0}")]
+    InvalidHeader(&'static str),
+
+    #[error("Content-Type must be {OFFSET_OCTET_STREAM}")]
+    InvalidContentType,
+
+    #[error("Upload offset mismatch: expected {expected}, got {actual}")]
+    OffsetMismatch { expected: u64, actual: u64 },
+
+    #[error("Upload is already receiving data")]
+    Locked,
+
+    #[error("Upload exceeds declared length of {length} bytes")]
+    ExceedsLength { length: u64 },
+
+    #[error("Unsupported checksum algorithm: {0}")]
+    UnsupportedChecksum(String),
+
+    #[error("Checksum mismatch")]
+    ChecksumMismatch,
+
+    #[error("Request body too large (max: {0} bytes)")]
+    BodyTooLarge(usize),
+
+    #[error("Failed to read request body: {0}")]
+    Body(String),
+
+    #[error(transparent)]
+    Upload(#[from] UploadError),
+}
+
+impl TusError {
+    /// Get HTTP status code for this error
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::UnsupportedVersion(_) => StatusCode::PRECONDITION_FAILED,
+            Self::InvalidHeader(_) => StatusCode::BAD_REQUEST,
+            Self::InvalidContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::OffsetMismatch { .. } => StatusCode::CONFLICT,
+            Self::Locked => StatusCode::CONFLICT,
+            Self::ExceedsLength { .. } => StatusCode::BAD_REQUEST,
+            Self::UnsupportedChecksum(_) => StatusCode::BAD_REQUEST,
+            // 460 Checksum Mismatch (defined by the checksum extension)
+            Self::ChecksumMismatch => {
+                StatusCode::from_u16(460).unwrap_or(StatusCode::BAD_REQUEST)
+            }
+            Self::BodyTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Body(_) => StatusCode::BAD_REQUEST,
+            Self::Upload(e) => e.status_code(),
+        }
+    }
+}
+
+// ============================================================================
+// Headers
+// ============================================================================
+
+/// Parse an `Upload-Metadata` header (`key base64value, key2, ...`)
+pub fn parse_metadata(header: &str) -> Result<HashMap<String, String>, TusError> {
+    let mut metadata = HashMap::new();
+
+    for pair in header.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let mut parts = pair.splitn(2, ' ');
+        let key = parts.next().unwrap_or_default().to_string();
+        let value = match parts.next().map(str::trim) {
+            Some(encoded) if !encoded.is_empty() => {
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(encoded)
+                    .map_err(|_| TusError::InvalidHeader("Upload-Metadata"))?;
+                String::from_utf8(bytes).map_err(|_| TusError::InvalidHeader("Upload-Metadata"))?
+            }
+            _ => String::new(),
+        };
+        metadata.insert(key, value);
+    }
+
+    Ok(metadata)
+}
+
+/// Encode metadata for `Upload-Metadata` responses
+pub fn encode_metadata(metadata: &[(&str, &str)]) -> String {
+    metadata
+        .iter()
+        .map(|(key, value)| {
+            format!("{} {}", key, base64::engine::general_purpose::STANDARD.encode(value))
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Checksum algorithms of the checksum extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Sha1,
+    Sha256,
+}
+
+/// Parsed `Upload-Checksum` header
+#[derive(Debug, Clone)]
+pub struct UploadChecksum {
+    pub algorithm: ChecksumAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+impl UploadChecksum {
+    /// Parse `<algorithm> <base64 digest>`
+    pub fn parse(header: &str) -> Result<Self, TusError> {
+        let (name, encoded) = header
+            .trim()
+            .split_once(' ')
+            .ok_or(TusError::InvalidHeader("Upload-Checksum"))?;
+
+        let algorithm = match name.to_ascii_lowercase().as_str() {
+            "sha1" => ChecksumAlgorithm::Sha1,
+            "sha256" => ChecksumAlgorithm::Sha256,
+            other => return Err(TusError::UnsupportedChecksum(other.to_string())),
+        };
+
+        let digest = base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .map_err(|_| TusError::InvalidHeader("Upload-Checksum"))?;
+
+        Ok(Self { algorithm, digest })
+    }
+
+    /// Check the digest against a request body
+    pub fn verify(&self, data: &[u8]) -> bool {
+        let actual = match self.algorithm {
+            ChecksumAlgorithm::Sha1 => Sha1::digest(data).to_vec(),
+            ChecksumAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+        };
+        actual == self.digest
+    }
+}
+
+// ============================================================================
+// Sessions
+// ============================================================================
+
+/// Build the session request for a tus creation
+///
+/// The file hash is unknown until the upload completes, and chunk hashes
+/// are recorded as chunks arrive, so both start out empty.
+pub fn creation_request(file_size: u64, metadata: &HashMap<String, String>) -> HandshakeRequest {
+    let file_name = metadata
+        .get("filename")
+        .or_else(|| metadata.get("name"))
+        .filter(|name| !name.is_empty())
+        .cloned()
+        .unwrap_or_else(|| "upload".to_string());
+
+    let mime_type = metadata
+        .get("filetype")
+        .or_else(|| metadata.get("type"))
+        .filter(|mime| !mime.is_empty())
+        .cloned()
+        .unwrap_or_else(|| {
+            mime_guess::from_path(&file_name)
+                .first_or_octet_stream()
+                .to_string()
+        });
+
+    HandshakeRequest {
+        file_name,
+        file_size,
+        file_hash: String::new(),
+        chunk_hashes: vec![String::new(); chunk_count(file_size, DEFAULT_CHUNK_SIZE)],
+        mime_type,
+        chunk_size: Some(DEFAULT_CHUNK_SIZE),
+        direct: false,
+    }
+}
+
+/// Number of chunks for a file of the given size
+pub fn chunk_count(file_size: u64, chunk_size: usize) -> usize {
+    file_size.div_ceil(chunk_size as u64) as usize
+}
+
+// ============================================================================
+// Chunk Appender
+// ============================================================================
+
+/// Appends PATCH bytes to a tus upload
+///
+/// Bytes are buffered until a chunk is full, then stored and recorded on the
+/// session, so an interrupted request keeps every completed chunk.
+/// [`ChunkAppender::finish`] stores whatever is left as a partial chunk.
+pub struct ChunkAppender<'a> {
+    sessions: &'a SessionManager,
+    chunks: &'a ChunkStore,
+    session: UploadSession,
+    /// Index of the chunk being filled
+    chunk_index: usize,
+    /// Bytes of the chunk being filled (including a previously stored partial)
+    pending: Vec<u8>,
+}
+
+impl<'a> ChunkAppender<'a> {
+    /// Start appending at the session's current offset
+    pub async fn new(
+        sessions: &'a SessionManager,
+        chunks: &'a ChunkStore,
+        session: UploadSession,
+    ) -> Result<Self, UploadError> {
+        let chunk_size = session.chunk_size as u64;
+        let chunk_index = (session.upload_offset / chunk_size) as usize;
+        let partial_len = (session.upload_offset % chunk_size) as usize;
+
+        // Resume the partial chunk left by the previous PATCH
+        let pending = if partial_len > 0 {
+            let data = chunks.get_chunk(session.id, chunk_index).await?;
+            if data.len() != partial_len {
+                return Err(UploadError::StorageError(format!(
+                    "Partial chunk {} has {} bytes, expected {}",
+                    chunk_index,
+                    data.len(),
+                    partial_len
+                )));
+            }
+            data
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            sessions,
+            chunks,
+            session,
+            chunk_index,
+            pending,
+        })
+    }
+
+    /// Current offset including buffered bytes
+    pub fn offset(&self) -> u64 {
+        let offset =
+            self.chunk_index as u64 * self.session.chunk_size as u64 + self.pending.len() as u64;
+        // Past the (short) last chunk
+        offset.min(self.session.file_size)
+    }
+
+    /// Append bytes, storing each chunk as soon as it is complete
+    pub async fn push(&mut self, mut data: &[u8]) -> Result<(), TusError> {
+        if self.offset() + data.len() as u64 > self.session.file_size {
+            return Err(TusError::ExceedsLength {
+                length: self.session.file_size,
+            });
+        }
+
+        while !data.is_empty() {
+            let wanted = self.current_chunk_len() - self.pending.len();
+            let take = wanted.min(data.len());
+            self.pending.extend_from_slice(&data[..take]);
+            data = &data[take..];
+
+            if self.pending.len() == self.current_chunk_len() {
+                self.store_complete_chunk().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Store remaining bytes as a partial chunk and persist the offset
+    pub async fn finish(mut self) -> Result<UploadSession, UploadError> {
+        // A full pending chunk (the last one, on a retried final PATCH) is already stored
+        if !self.pending.is_empty() && self.pending.len() < self.current_chunk_len() {
+            self.chunks
+                .store_partial_chunk(self.session.id, self.chunk_index, &self.pending)
+                .await?;
+
+            self.session.upload_offset = self.offset();
+            self.session.status = SessionStatus::Uploading;
+            self.sessions.update_session(self.session.clone()).await?;
+        }
+
+        Ok(self.session)
+    }
+
+    /// Length of the chunk being filled (the last chunk may be short)
+    fn current_chunk_len(&self) -> usize {
+        let start = self.chunk_index as u64 * self.session.chunk_size as u64;
+        self.session.file_size.saturating_sub(start).min(self.session.chunk_size as u64) as usize
+    }
+
+    async fn store_complete_chunk(&mut self) -> Result<(), UploadError> {
+        let hash = compute_hash(&self.pending);
+        self.chunks
+            .store_chunk(self.session.id, self.chunk_index, &self.pending, &hash)
+            .await?;
+
+        self.session.chunk_hashes[self.chunk_index] = hash;
+        self.session.mark_chunk_received(self.chunk_index);
+        self.session.status = if self.session.is_complete() {
+            SessionStatus::Ready
+        } else {
+            SessionStatus::Uploading
+        };
+
+        self.chunk_index += 1;
+        self.pending.clear();
+        self.session.upload_offset = self.offset();
+
+        self.sessions.update_session(self.session.clone()).await
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_metadata() {
+        let metadata =
+            parse_metadata("filename ZHVuZS5lcHVi,filetype YXBwbGljYXRpb24vZXB1Yit6aXA=, is_confidential").unwrap();
+        assert_eq!(metadata.get("filename").map(String::as_str), Some("dune.epub"));
+        assert_eq!(metadata.get("filetype").map(String::as_str), Some("application/epub+zip"));
+        assert_eq!(metadata.get("is_confidential").map(String::as_str), Some(""));
+
+        assert!(parse_metadata("filename !!!").is_err());
+
+        let encoded = encode_metadata(&[("filename", "dune.epub")]);
+        assert_eq!(parse_metadata(&encoded).unwrap()["filename"], "dune.epub");
+    }
+
+    #[test]
+    fn test_checksum() {
+        // SHA-1 of "hello"
+        let checksum = UploadChecksum::parse("sha1 qvTGHdzF6KLavt4PO0gs2a6pQ00=").unwrap();
+        assert_eq!(checksum.algorithm, ChecksumAlgorithm::Sha1);
+        assert!(checksum.verify(b"hello"));
+        assert!(!checksum.verify(b"hello!"));
+
+        assert!(matches!(
+            UploadChecksum::parse("md5 XUFAKrxLKna5cZ2REBfFkg=="),
+            Err(TusError::UnsupportedChecksum(_))
+        ));
+        assert!(UploadChecksum::parse("sha1").is_err());
+        assert_eq!(TusError::ChecksumMismatch.status_code().as_u16(), 460);
+    }
+
+    #[test]
+    fn test_creation_request() {
+        let mut metadata = HashMap::new();
+        metadata.insert("filename".to_string(), "dune.pdf".to_string());
+
+        let request = creation_request(DEFAULT_CHUNK_SIZE as u64 * 2 + 1, &metadata);
+        assert_eq!(request.file_name, "dune.pdf");
+        assert_eq!(request.mime_type, "application/pdf");
+        assert_eq!(request.chunk_hashes.len(), 3);
+        assert!(request.file_hash.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_append_across_requests() {
+        let temp_dir = TempDir::new().unwrap();
+        let chunks = ChunkStore::with_local_storage(temp_dir.path().to_path_buf());
+        let sessions = SessionManager::new();
+
+        let data: Vec<u8> = (0..DEFAULT_CHUNK_SIZE * 2 + 100).map(|i| (i % 251) as u8).collect();
+        let request = creation_request(data.len() as u64, &HashMap::new());
+        let session = sessions.create_session(&request).await.unwrap();
+
+        // First PATCH ends in the middle of the second chunk
+        let split = DEFAULT_CHUNK_SIZE + 10;
+        let mut appender = ChunkAppender::new(&sessions, &chunks, session).await.unwrap();
+        appender.push(&data[..split]).await.unwrap();
+        let session = appender.finish().await.unwrap();
+        assert_eq!(session.upload_offset, split as u64);
+        assert_eq!(session.received_chunks, vec![0]);
+        // The partial chunk isn't offered for deduplication
+        let partial = compute_hash(&data[DEFAULT_CHUNK_SIZE..split]);
+        assert!(!chunks.chunk_exists(&partial).await);
+
+        // Second PATCH resumes the partial chunk and completes the upload
+        let mut appender = ChunkAppender::new(&sessions, &chunks, session).await.unwrap();
+        assert!(matches!(
+            appender.push(&[0; DEFAULT_CHUNK_SIZE * 2]).await,
+            Err(TusError::ExceedsLength { .. })
+        ));
+        appender.push(&data[split..]).await.unwrap();
+        let session = appender.finish().await.unwrap();
+        assert_eq!(session.upload_offset, data.len() as u64);
+        assert_eq!(session.status, SessionStatus::Ready);
+
+        let assembled = chunks
+            .assemble_chunks(session.id, &session.chunk_hashes)
+            .await
+            .unwrap();
+        assert_eq!(assembled, data);
+    }
+}
@@ -46,6 +46,10 @@ pub struct HandshakeRequest {
     /// Optional: Expected chunk size (defaults to 2MB)
     #[serde(default)]
     pub chunk_size: Option<usize>,
+
+    /// Upload chunks straight to storage via presigned URLs
+    #[serde(default)]
+    pub direct: bool,
 }
 
 /// Response to handshake request
@@ -73,6 +77,24 @@ pub struct HandshakeResponse {
 
     /// Session expiry time
     pub expires_at: DateTime<Utc>,
+
+    /// Presigned part URLs, for `direct` handshakes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direct_upload: Option<DirectUploadInfo>,
+}
+
+/// Where to send chunks of a direct upload
+///
+/// Chunk `i` is PUT as-is to `part_urls[i]`; every chunk must be sent
+/// (chunks already on the server cannot be reused by the bucket).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectUploadInfo {
+    /// Presigned PUT URL for each chunk, in order
+    pub part_urls: Vec<String>,
+
+    /// When the part URLs stop working
+    pub expires_at: DateTime<Utc>,
 }
 
 // ============================================================================
@@ -162,6 +184,14 @@ pub struct UploadSession {
 
     /// Optional: User ID if authenticated
     pub user_id: Option<String>,
+
+    /// Bytes received so far (tus uploads, which may end mid-chunk)
+    #[serde(default)]
+    pub upload_offset: u64,
+
+    /// Multipart upload ID when chunks go straight to storage
+    #[serde(default)]
+    pub direct_upload_id: Option<String>,
 }
 
 impl UploadSession {
@@ -183,6 +213,8 @@ impl UploadSession {
             expires_at: now + chrono::Duration::hours(SESSION_EXPIRY_HOURS),
             status: SessionStatus::Pending,
             user_id: None,
+            upload_offset: 0,
+            direct_upload_id: None,
         }
     }
 
@@ -278,6 +310,9 @@ pub enum UploadError {
     #[error("Database error: {0}")]
     DatabaseError(String),
 
+    #[error("Direct upload: {0}")]
+    DirectUpload(String),
+
     #[error("Internal error: {0}")]
     InternalError(String),
 }
@@ -298,6 +333,7 @@ impl UploadError {
             Self::MissingChunks(_) => StatusCode::BAD_REQUEST,
             Self::StorageError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::DirectUpload(_) => StatusCode::BAD_REQUEST,
             Self::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }